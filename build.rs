@@ -0,0 +1,23 @@
+//! Records the commit the binaries are built from as `AGENT_INBOX_GIT_SHA`
+//! for `agent-inbox version`: "unknown" outside a git checkout (e.g. a
+//! crates.io tarball) or without git installed
+
+use std::process::Command;
+
+fn main() {
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=AGENT_INBOX_GIT_SHA={}", sha);
+
+    // A new commit or checkout moves one of these
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+}
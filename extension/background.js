@@ -8,6 +8,11 @@ const NATIVE_HOST_NAME = "com.agent_tasks.bridge";
 // Connection to native messaging host
 let nativePort = null;
 
+// Sequence number stamped on every task update, so the bridge can drop
+// updates that arrive out of order. It restarts with the service worker,
+// which also gets a new bridge process.
+let nextSeq = 0;
+
 // Agent type display names
 const AGENT_NAMES = {
   "gemini_web": "Gemini",
@@ -114,10 +119,11 @@ chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
 
   if (message.type === "task_update") {
     // Forward to native host
-    sendToNativeHost(message);
+    nextSeq += 1;
+    sendToNativeHost({ ...message, seq: nextSeq });
 
-    // Show notification when task completes
-    if (message.status === "completed") {
+    // Show notification when a turn finishes
+    if (message.status === "completed" || message.status === "ready_for_reply") {
       showCompletionNotification(message.agent_type, message.title);
     }
 
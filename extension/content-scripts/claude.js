@@ -178,7 +178,7 @@ function checkConversationState() {
 
   // Simple 3-state tracking:
   // - isActive=true → running (generating)
-  // - isActive=false → completed (waiting for user input)
+  // - isActive=false → ready_for_reply (waiting for user input)
   tracker.checkState(conversationId, isActive, title);
 }
 
@@ -73,7 +73,8 @@ class ConversationTracker {
    *
    * Simple logic:
    * - If generating: ensure task is "running"
-   * - If not generating: ensure task is "completed"
+   * - If not generating: ensure task is "ready_for_reply" (turn finished,
+   *   waiting for the next prompt)
    * - Create task on first detection
    *
    * @param {string} conversationId - Current conversation ID
@@ -116,7 +117,7 @@ class ConversationTracker {
     }
 
     // Determine current status based on generation state
-    const currentStatus = isGenerating ? "running" : "completed";
+    const currentStatus = isGenerating ? "running" : "ready_for_reply";
 
     // Only send update if status changed
     if (this.activeConversation.lastStatus !== currentStatus) {
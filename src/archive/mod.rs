@@ -0,0 +1,133 @@
+//! Append-only record of tasks removed from the database
+//!
+//! With `archive_path` configured, every task that cleanup, eviction or a
+//! clear takes out of the live database is first appended to the archive as
+//! one JSON object per line. The database stays small and the history stays
+//! greppable.
+
+use crate::models::Task;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// A task as written to the archive, with when it was removed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedTask {
+    #[serde(flatten)]
+    pub task: Task,
+    pub archived_at: DateTime<Utc>,
+}
+
+/// JSON-lines file that removed tasks are appended to
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+}
+
+impl Archive {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Append `tasks`, stamped with the current time
+    pub fn append(&self, tasks: &[Task]) -> Result<()> {
+        if tasks.is_empty() {
+            return Ok(());
+        }
+
+        let archived_at = Utc::now();
+        let mut lines = String::new();
+        for task in tasks {
+            let entry = ArchivedTask {
+                task: task.clone(),
+                archived_at,
+            };
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        // One write per call so concurrent appenders don't interleave lines
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open archive {}", self.path.display()))?;
+        file.write_all(lines.as_bytes())
+            .with_context(|| format!("Failed to write archive {}", self.path.display()))
+    }
+
+    /// Archived tasks whose line contains `term` (case-insensitively), most
+    /// recently archived first. A missing archive has no entries.
+    pub fn search(&self, term: &str) -> Result<Vec<ArchivedTask>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read archive {}", self.path.display()))
+            }
+        };
+
+        let term = term.to_lowercase();
+        let mut found = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() || !line.to_lowercase().contains(&term) {
+                continue;
+            }
+            match serde_json::from_str::<ArchivedTask>(&line) {
+                Ok(entry) => found.push(entry),
+                // A torn last line from a crash shouldn't hide the rest
+                Err(e) => tracing::warn!(error = %e, "Skipping malformed archive line"),
+            }
+        }
+        found.reverse();
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, title: &str) -> Task {
+        Task::new(
+            id.to_string(),
+            "claude_code".to_string(),
+            title.to_string(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_append_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = Archive::new(dir.path().join("nested").join("archive.jsonl"));
+        assert!(archive.search("anything").unwrap().is_empty());
+
+        archive
+            .append(&[task("t1", "Fix the parser"), task("t2", "Write docs")])
+            .unwrap();
+        archive.append(&[task("t3", "Parser benchmarks")]).unwrap();
+
+        let ids = |term: &str| -> Vec<String> {
+            archive
+                .search(term)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.task.task_id)
+                .collect()
+        };
+        assert_eq!(ids("parser"), ["t3", "t1"]);
+        assert_eq!(ids("DOCS"), ["t2"]);
+        assert!(ids("nothing").is_empty());
+    }
+}
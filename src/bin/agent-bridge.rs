@@ -1,45 +1,61 @@
-/**
+/*
  * Native messaging host for browser extension
  * Receives task updates from extension and writes to agent-inbox database
  */
 
-use agent_inbox::db::{default_db_path, Database};
-use agent_inbox::models::{Task, TaskContext, TaskStatus};
+use agent_inbox::archive::Archive;
+use agent_inbox::config::{default_config_path, Config};
+use agent_inbox::db::{default_db_path, ensure_data_dir, Database};
+use agent_inbox::models::message::{BatchMessage, IncomingMessage, OutgoingMessage};
+use agent_inbox::models::{
+    AgentType, AttentionReason, Task, TaskContext, TaskStatus, DEFAULT_MAX_TITLE_LEN,
+};
+use agent_inbox::webhook::{default_dead_letter_path, Webhook};
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{self, Read, Write};
-
-#[derive(Debug, Deserialize)]
-struct IncomingMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    task_id: String,
-    agent_type: String,
-    status: String,
-    title: String,
-    context: MessageContext,
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// A single native messaging frame: either one task update or a batch of
+/// them queued by the extension (e.g. after the browser wakes from sleep)
+#[derive(Debug)]
+enum IncomingFrame {
+    Single(IncomingMessage),
+    Batch(Vec<IncomingMessage>),
 }
 
-#[derive(Debug, Deserialize)]
-struct MessageContext {
-    url: Option<String>,
-    conversation_id: Option<String>,
-    #[allow(dead_code)]
-    timestamp: Option<i64>,
-    duration_ms: Option<i64>,
+/// Last applied `seq` per task, for this browser session. Kept in memory
+/// rather than in the database: a reloaded extension starts counting again,
+/// and it gets a new bridge process with a fresh map too.
+#[derive(Debug, Default, Clone)]
+struct SeqTracker {
+    last: HashMap<String, u64>,
 }
 
-#[derive(Debug, Serialize)]
-struct OutgoingMessage {
-    status: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
+impl SeqTracker {
+    /// Whether `message` is older than one already applied to its task.
+    /// Messages without a `seq` are never considered stale.
+    fn is_stale(&self, message: &IncomingMessage) -> bool {
+        match (message.seq, self.last.get(&message.task_id)) {
+            (Some(seq), Some(&last)) => seq <= last,
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, task_id: &str, seq: Option<u64>) {
+        if let Some(seq) = seq {
+            self.last.insert(task_id.to_string(), seq);
+        }
+    }
 }
 
-// Read a message from stdin using Chrome native messaging protocol
+// Read a frame from stdin using Chrome native messaging protocol
 // Format: 4-byte length (little-endian) + JSON message
-fn read_message() -> Result<IncomingMessage> {
+fn read_frame() -> Result<IncomingFrame> {
     let mut length_bytes = [0u8; 4];
     io::stdin()
         .read_exact(&mut length_bytes)
@@ -57,10 +73,24 @@ fn read_message() -> Result<IncomingMessage> {
         .read_exact(&mut buffer)
         .context("Failed to read message body")?;
 
-    let message: IncomingMessage =
-        serde_json::from_slice(&buffer).context("Failed to parse JSON message")?;
+    parse_frame(&buffer)
+}
 
-    Ok(message)
+// A frame whose top-level "type" is "batch" carries a "messages" array;
+// anything else is a single task update
+fn parse_frame(bytes: &[u8]) -> Result<IncomingFrame> {
+    let value: serde_json::Value =
+        serde_json::from_slice(bytes).context("Failed to parse JSON message")?;
+
+    if value.get("type").and_then(|t| t.as_str()) == Some("batch") {
+        let batch: BatchMessage =
+            serde_json::from_value(value).context("Failed to parse batch message")?;
+        Ok(IncomingFrame::Batch(batch.messages))
+    } else {
+        let message: IncomingMessage =
+            serde_json::from_value(value).context("Failed to parse JSON message")?;
+        Ok(IncomingFrame::Single(message))
+    }
 }
 
 // Write a message to stdout using Chrome native messaging protocol
@@ -81,52 +111,124 @@ fn write_message(message: &OutgoingMessage) -> Result<()> {
     Ok(())
 }
 
-fn process_message(db: &Database, message: IncomingMessage) -> Result<()> {
-    eprintln!(
-        "Processing message: {} {} {}",
-        message.msg_type, message.status, message.task_id
+// Log to stderr (surfaced in the browser's native host logs) and to a
+// daily-rotated file under ~/.agent-tasks/logs. Verbosity follows RUST_LOG.
+fn init_logging() -> Result<WorkerGuard> {
+    let log_dir = ensure_data_dir()?.join("logs");
+    std::fs::create_dir_all(&log_dir).context("Failed to create log directory")?;
+
+    let file_appender = tracing_appender::rolling::daily(&log_dir, "agent-bridge.log");
+    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt::layer().with_writer(io::stderr).with_ansi(false))
+        .with(fmt::layer().with_writer(file_writer).with_ansi(false))
+        .init();
+
+    Ok(guard)
+}
+
+// Apply a frame and build the response. A batch is applied atomically in one
+// transaction and answered with a single aggregate response. Out-of-order
+// messages (see `SeqTracker`) are skipped; they don't count as processed.
+fn process_frame(
+    db: &Database,
+    seqs: &mut SeqTracker,
+    frame: IncomingFrame,
+    max_title_len: usize,
+) -> Result<OutgoingMessage> {
+    let mut message = None;
+    let processed = match frame {
+        IncomingFrame::Single(update) => {
+            if seqs.is_stale(&update) {
+                warn!(task_id = %update.task_id, seq = ?update.seq, "Ignoring out-of-order update");
+                message = Some("ignored out-of-order update".to_string());
+            } else {
+                let (task_id, seq) = (update.task_id.clone(), update.seq);
+                process_message(db, update, max_title_len)?;
+                seqs.record(&task_id, seq);
+            }
+            None
+        }
+        IncomingFrame::Batch(updates) => {
+            // Only remember the seqs once the whole batch has committed
+            let mut pending = seqs.clone();
+            let applied = db.transaction(|db| {
+                let mut applied = 0;
+                for update in updates {
+                    if pending.is_stale(&update) {
+                        warn!(
+                            task_id = %update.task_id,
+                            seq = ?update.seq,
+                            "Ignoring out-of-order update"
+                        );
+                        continue;
+                    }
+                    let (task_id, seq) = (update.task_id.clone(), update.seq);
+                    process_message(db, update, max_title_len)?;
+                    pending.record(&task_id, seq);
+                    applied += 1;
+                }
+                Ok(applied)
+            })?;
+            *seqs = pending;
+            Some(applied)
+        }
+    };
+
+    Ok(OutgoingMessage {
+        status: "ok".to_string(),
+        message,
+        processed,
+    })
+}
+
+fn process_message(db: &Database, message: IncomingMessage, max_title_len: usize) -> Result<()> {
+    let span = info_span!(
+        "message",
+        task_id = %message.task_id,
+        status = %message.status
     );
+    let _enter = span.enter();
+    debug!(msg_type = %message.msg_type, ?message.context, "Processing message");
 
     match message.status.as_str() {
         "running" => {
-            // Check if task already exists (for follow-up messages)
-            if let Some(mut existing_task) = db.get_task_by_id(&message.task_id)? {
-                // Task exists - update to running (for follow-ups)
-                existing_task.status = TaskStatus::Running;
-                existing_task.updated_at = chrono::Utc::now();
-                existing_task.completed_at = None; // Clear completion timestamp
-
-                db.update_task(&existing_task)?;
-                eprintln!("Updated existing task to running: {}", message.task_id);
-            } else {
-                // Task doesn't exist - create new one
-                let mut task = Task::new(
-                    message.task_id.clone(),
-                    message.agent_type,
-                    message.title,
-                    None, // No PID for web tasks
-                    None,
-                );
+            // New conversations create the task; follow-up turns flip the
+            // existing one back to running (clearing its completion)
+            let agent = AgentType::from(message.agent_type.as_str());
+            if !agent.is_known() {
+                warn!(agent_type = %agent, "Unknown agent type");
+            }
 
-                // Add context
-                let mut extra = HashMap::new();
-                if let Some(conv_id) = message.context.conversation_id {
-                    extra.insert("conversation_id".to_string(), serde_json::json!(conv_id));
-                }
-                if let Some(duration) = message.context.duration_ms {
-                    extra.insert("duration_ms".to_string(), serde_json::json!(duration));
-                }
+            let mut task = Task::with_max_title_len(
+                message.task_id.clone(),
+                agent.to_string(),
+                &message.title,
+                None, // No PID for web tasks
+                None,
+                max_title_len,
+            );
+
+            // Add context
+            let mut extra = HashMap::new();
+            if let Some(conv_id) = message.context.conversation_id {
+                extra.insert("conversation_id".to_string(), serde_json::json!(conv_id));
+            }
+            if let Some(duration) = message.context.duration_ms {
+                extra.insert("duration_ms".to_string(), serde_json::json!(duration));
+            }
 
-                task.context = Some(TaskContext {
-                    url: message.context.url,
-                    project_path: None,
-                    session_id: None,
-                    extra,
-                });
+            task.context = Some(TaskContext {
+                url: message.context.url,
+                project_path: None,
+                session_id: None,
+                extra,
+            });
 
-                db.insert_task(&task)?;
-                eprintln!("Created new task: {}", message.task_id);
-            }
+            db.upsert_task(&task)?;
+            info!("Task running");
         }
         "completed" => {
             // Update existing task to completed (finished generating, waiting for user)
@@ -134,81 +236,349 @@ fn process_message(db: &Database, message: IncomingMessage) -> Result<()> {
                 task.complete();
                 db.update_task(&task)?;
 
-                eprintln!("Completed task: {}", message.task_id);
+                info!("Completed task");
             } else {
-                eprintln!("Task not found: {}", message.task_id);
+                warn!("Task not found");
             }
         }
+        "ready_for_reply" => {
+            // Finished its turn and idles until the next prompt
+            flag_attention(db, &message.task_id, AttentionReason::ReadyForReply)?;
+        }
+        "needs_attention" => {
+            // Blocked mid-turn, e.g. on a permission prompt
+            let reason = AttentionReason::Custom("Waiting for user action".to_string());
+            flag_attention(db, &message.task_id, reason)?;
+        }
         "exited" => {
             // Update existing task to exited (tab closed / process terminated)
             if let Some(mut task) = db.get_task_by_id(&message.task_id)? {
                 task.set_exited(None);
                 db.update_task(&task)?;
 
-                eprintln!("Task exited: {}", message.task_id);
+                info!("Task exited");
             } else {
-                eprintln!("Task not found: {}", message.task_id);
+                warn!("Task not found");
             }
         }
         _ => {
-            eprintln!("Unknown status: {}", message.status);
+            warn!("Unknown status");
         }
     }
 
     Ok(())
 }
 
+/// Flag an existing task, bringing it back to running so the flag shows
+fn flag_attention(db: &Database, task_id: &str, reason: AttentionReason) -> Result<()> {
+    match db.get_task_by_id(task_id)? {
+        Some(mut task) => {
+            // Re-flagging keeps the task's attention_since, for escalation
+            if task.status != TaskStatus::Running {
+                task.set_running();
+            }
+            task.flag_attention(reason);
+            db.update_task(&task)?;
+            info!("Task needs attention");
+        }
+        None => warn!("Task not found"),
+    }
+    Ok(())
+}
+
+/// Minimum time between the bridge's WAL checkpoints
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+fn checkpoint(db: &Database) {
+    match db.checkpoint() {
+        Ok(result) if result.is_complete() => debug!(?result, "WAL checkpointed"),
+        Ok(result) => info!(?result, "WAL checkpoint incomplete, readers still active"),
+        Err(e) => warn!(error = %e, "WAL checkpoint failed"),
+    }
+}
+
 fn main() -> Result<()> {
     // Note: stderr output goes to browser console/logs
     // For debugging, check: chrome://extensions -> Agent Inbox -> background page -> console
+    // or ~/.agent-tasks/logs/agent-bridge.log.<date>
+
+    let _log_guard = init_logging()?;
 
-    eprintln!("agent-bridge started");
+    info!("agent-bridge started");
 
     // Open database
     let db_path = default_db_path();
-    let db = Database::open(&db_path).context("Failed to open database")?;
-
-    eprintln!("Database opened: {:?}", db_path);
+    let mut db = Database::open(&db_path).context("Failed to open database")?;
+
+    info!(path = %db_path.display(), "Database opened");
+
+    let mut max_title_len = DEFAULT_MAX_TITLE_LEN;
+    match Config::load(default_config_path()) {
+        Ok(config) => {
+            max_title_len = config.max_title_len();
+            db.set_notification_cooldown(config.notification_cooldown());
+            // Most tasks are inserted here, one per web conversation
+            if let Some(max) = config.max_tasks {
+                db.set_max_tasks(max);
+            }
+            if let Some(path) = &config.archive_path {
+                db.set_archive(Archive::new(path));
+            }
+            for (url, format) in config.webhook_targets() {
+                db.add_webhook(
+                    Webhook::new(url)
+                        .with_format(format)
+                        .with_dead_letter(default_dead_letter_path()),
+                );
+            }
+        }
+        Err(e) => warn!(error = %e, "Ignoring unreadable config"),
+    }
 
     // Main message loop
+    let mut last_checkpoint = Instant::now();
+    let mut seqs = SeqTracker::default();
     loop {
-        match read_message() {
-            Ok(message) => {
-                eprintln!("Received message: {:?}", message);
-
-                match process_message(&db, message) {
-                    Ok(()) => {
-                        let response = OutgoingMessage {
-                            status: "ok".to_string(),
-                            message: None,
-                        };
-                        if let Err(e) = write_message(&response) {
-                            eprintln!("Failed to write response: {}", e);
-                            break;
-                        }
+        // The bridge's connection lives as long as the browser; checkpoint
+        // now and then so CLI reads can't starve SQLite's own checkpoints
+        // and keep the WAL growing
+        if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            checkpoint(&db);
+            last_checkpoint = Instant::now();
+        }
+
+        match read_frame() {
+            Ok(frame) => match process_frame(&db, &mut seqs, frame, max_title_len) {
+                Ok(response) => {
+                    if let Err(e) = write_message(&response) {
+                        error!(error = %e, "Failed to write response");
+                        break;
                     }
-                    Err(e) => {
-                        eprintln!("Error processing message: {}", e);
-                        let response = OutgoingMessage {
-                            status: "error".to_string(),
-                            message: Some(e.to_string()),
-                        };
-                        if let Err(e) = write_message(&response) {
-                            eprintln!("Failed to write error response: {}", e);
-                            break;
-                        }
+                }
+                Err(e) => {
+                    error!(error = %e, "Error processing message");
+                    let response = OutgoingMessage {
+                        status: "error".to_string(),
+                        message: Some(e.to_string()),
+                        processed: None,
+                    };
+                    if let Err(e) = write_message(&response) {
+                        error!(error = %e, "Failed to write error response");
+                        break;
                     }
                 }
-            }
+            },
             Err(e) => {
-                eprintln!("Error reading message: {}", e);
+                info!(reason = %e, "Stopped reading messages");
                 // EOF or error, exit gracefully
                 break;
             }
         }
     }
 
-    eprintln!("agent-bridge exiting");
+    checkpoint(&db);
+    info!("agent-bridge exiting");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn create_test_db() -> (Database, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::open(temp_file.path()).unwrap();
+        (db, temp_file)
+    }
+
+    fn update_json(task_id: &str, status: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "task_update",
+            "task_id": task_id,
+            "agent_type": "claude_web",
+            "status": status,
+            "title": "Test conversation",
+            "context": { "url": "https://claude.ai/chat/abc" }
+        })
+    }
+
+    fn frame(value: serde_json::Value) -> IncomingFrame {
+        parse_frame(value.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_single_message_frame() {
+        let (db, _temp) = create_test_db();
+
+        let single = frame(update_json("task-1", "running"));
+        assert!(matches!(single, IncomingFrame::Single(_)));
+
+        let response = process_frame(
+            &db,
+            &mut SeqTracker::default(),
+            single,
+            DEFAULT_MAX_TITLE_LEN,
+        )
+        .unwrap();
+        assert_eq!(response.status, "ok");
+        assert!(response.processed.is_none());
+
+        let task = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Running);
+    }
+
+    #[test]
+    fn test_batch_frame() {
+        let (db, _temp) = create_test_db();
+
+        let batch = frame(serde_json::json!({
+            "type": "batch",
+            "messages": [
+                update_json("task-1", "running"),
+                update_json("task-2", "running"),
+                update_json("task-1", "completed"),
+            ]
+        }));
+        assert!(matches!(batch, IncomingFrame::Batch(ref m) if m.len() == 3));
+
+        let response = process_frame(
+            &db,
+            &mut SeqTracker::default(),
+            batch,
+            DEFAULT_MAX_TITLE_LEN,
+        )
+        .unwrap();
+        assert_eq!(response.status, "ok");
+        assert_eq!(response.processed, Some(3));
+
+        let task1 = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert_eq!(task1.status, TaskStatus::Completed);
+        let task2 = db.get_task_by_id("task-2").unwrap().unwrap();
+        assert_eq!(task2.status, TaskStatus::Running);
+    }
+
+    fn with_seq(mut update: serde_json::Value, seq: u64) -> serde_json::Value {
+        update["seq"] = seq.into();
+        update
+    }
+
+    #[test]
+    fn test_out_of_order_updates_are_ignored() {
+        let (db, _temp) = create_test_db();
+        let mut seqs = SeqTracker::default();
+        let status = |db: &Database| db.get_task_by_id("task-1").unwrap().unwrap().status;
+        let mut send =
+            |update| process_frame(&db, &mut seqs, frame(update), DEFAULT_MAX_TITLE_LEN).unwrap();
+
+        send(with_seq(update_json("task-1", "running"), 1));
+        send(with_seq(update_json("task-1", "completed"), 3));
+
+        // The delayed running (seq 2) must not revive the completed task
+        let response = send(with_seq(update_json("task-1", "running"), 2));
+        assert_eq!(response.status, "ok");
+        assert!(response.message.is_some());
+        // Nor may a replay of the same seq
+        send(with_seq(update_json("task-1", "running"), 3));
+        assert_eq!(status(&db), TaskStatus::Completed);
+
+        // Newer updates, and ones without a seq, still apply
+        send(with_seq(update_json("task-1", "running"), 4));
+        assert_eq!(status(&db), TaskStatus::Running);
+        send(update_json("task-1", "completed"));
+        assert_eq!(status(&db), TaskStatus::Completed);
+    }
+
+    #[test]
+    fn test_out_of_order_updates_within_a_batch() {
+        let (db, _temp) = create_test_db();
+        let mut seqs = SeqTracker::default();
+
+        let batch = frame(serde_json::json!({
+            "type": "batch",
+            "messages": [
+                with_seq(update_json("task-1", "running"), 1),
+                with_seq(update_json("task-2", "running"), 1),
+                with_seq(update_json("task-1", "completed"), 5),
+                with_seq(update_json("task-1", "running"), 4),
+            ]
+        }));
+        let response = process_frame(&db, &mut seqs, batch, DEFAULT_MAX_TITLE_LEN).unwrap();
+        assert_eq!(response.processed, Some(3));
+
+        let task1 = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert_eq!(task1.status, TaskStatus::Completed);
+        // Sequences are per task
+        assert_eq!(seqs.last.get("task-2"), Some(&1));
+    }
+
+    #[test]
+    fn test_ready_for_reply_and_needs_attention() {
+        let (db, _temp) = create_test_db();
+        let mut seqs = SeqTracker::default();
+        let mut send =
+            |update| process_frame(&db, &mut seqs, frame(update), DEFAULT_MAX_TITLE_LEN).unwrap();
+        send(update_json("task-1", "running"));
+
+        send(update_json("task-1", "ready_for_reply"));
+        let task = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert!(task.awaiting_reply());
+        assert!(!task.needs_attention());
+        assert_eq!(task.attention_reason, Some(AttentionReason::ReadyForReply));
+
+        send(update_json("task-1", "needs_attention"));
+        let task = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert!(task.needs_attention());
+        assert!(task.attention_reason.unwrap().is_blocking());
+
+        // The next turn clears the flag
+        send(update_json("task-1", "running"));
+        let task = db.get_task_by_id("task-1").unwrap().unwrap();
+        assert!(!task.needs_attention());
+    }
+
+    #[test]
+    fn test_messages_match_schema() {
+        let schema = agent_inbox::models::json_schema();
+        let defs = &schema["$defs"];
+        let update = with_seq(update_json("task-1", "running"), 1);
+        let properties = defs["IncomingMessage"]["properties"].as_object().unwrap();
+        for key in update.as_object().unwrap().keys() {
+            assert!(
+                properties.contains_key(key),
+                "{} is missing from the schema",
+                key
+            );
+        }
+        let context = defs["MessageContext"]["properties"].as_object().unwrap();
+        for key in update["context"].as_object().unwrap().keys() {
+            assert!(
+                context.contains_key(key),
+                "{} is missing from the schema",
+                key
+            );
+        }
+
+        let reply = serde_json::to_value(OutgoingMessage {
+            status: "ok".to_string(),
+            message: Some("Ignored stale update".to_string()),
+            processed: Some(2),
+        })
+        .unwrap();
+        let properties = defs["OutgoingMessage"]["properties"].as_object().unwrap();
+        for key in reply.as_object().unwrap().keys() {
+            assert!(
+                properties.contains_key(key),
+                "{} is missing from the schema",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_batch_is_rejected() {
+        let result = parse_frame(br#"{"type":"batch","messages":[{"task_id":"x"}]}"#);
+        assert!(result.is_err());
+    }
+}
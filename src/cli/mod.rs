@@ -1,13 +1,94 @@
-use clap::{Parser, Subcommand};
+use crate::monitor::DEFAULT_IDLE_WINDOW;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{BufRead, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
-#[command(name = "agent-inbox")]
+#[command(name = "agent-inbox", version)]
 #[command(about = "Track and monitor tasks across multiple LLM/coding agents", long_about = None)]
 pub struct Cli {
+    /// Output format for read commands (list, top, show, history, sql)
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Answer yes to every confirmation prompt. Either this or a command's
+    /// own --force skips that command's prompt.
+    #[arg(short = 'y', long, global = true)]
+    pub yes: bool,
+
+    /// When to use colors and other escape codes: auto (a terminal, unless
+    /// NO_COLOR is set), always (e.g. for `less -R`) or never
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Log diagnostics to stderr: -v info, -vv debug, -vvv trace. RUST_LOG
+    /// takes precedence; without either only warnings are shown.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable view (default)
+    Text,
+    /// One aligned row per task
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to style output going to a stream that is (or isn't) a terminal
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.resolve(is_terminal, no_color)
+    }
+
+    fn resolve(self, is_terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && !no_color,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// How `list` splits tasks into sections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// Running, paused, completed, exited (default)
+    #[default]
+    Status,
+    /// One section per agent type
+    Agent,
+    /// One section per project directory (context project_path)
+    Project,
+    /// A single recency-ordered list
+    None,
+}
+
+/// Browser to register the `agent-bridge` native messaging host with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Brave,
+    Firefox,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// List all tasks (default shows only tasks needing attention)
@@ -16,41 +97,285 @@ pub enum Commands {
         #[arg(short, long)]
         all: bool,
 
-        /// Filter by status: running, completed, exited
+        /// Filter by status: running, paused, completed, exited (or synonyms
+        /// such as active, done, dead), or all for --all
         #[arg(short, long)]
         status: Option<String>,
+
+        /// Print only the number of matching tasks
+        #[arg(long)]
+        count: bool,
+
+        /// Print nothing (a summary with -v) and exit 2 if any task needs
+        /// attention, 1 if any failed, 0 otherwise. Looks at every status
+        /// unless --status is given.
+        #[arg(long, conflicts_with_all = ["count", "follow", "compact", "tree", "plain"])]
+        check: bool,
+
+        /// One aligned row per task instead of the grouped view (same as --format table)
+        #[arg(long)]
+        compact: bool,
+
+        /// Keep the list on screen and redraw whenever the database changes
+        #[arg(long, conflicts_with = "count")]
+        follow: bool,
+
+        /// Group turns of the same web conversation under one entry
+        #[arg(long, conflicts_with_all = ["count", "compact"])]
+        tree: bool,
+
+        /// ASCII-only output without colors, boxes or icons (cron mail, grep)
+        #[arg(long, conflicts_with_all = ["count", "compact", "tree"])]
+        plain: bool,
+
+        /// Only the summary and the task rows: no header box or footer hints
+        #[arg(short, long, conflicts_with = "count")]
+        quiet: bool,
+
+        /// Sections of the list view
+        #[arg(
+            long,
+            value_enum,
+            default_value = "status",
+            conflicts_with_all = ["count", "compact"]
+        )]
+        group_by: GroupBy,
+
+        /// Also show the tasks in these databases (other users' or mounted
+        /// homes), opened read-only; each task is tagged with its source
+        #[arg(long, value_name = "PATH", num_args = 1.., conflicts_with = "follow")]
+        merge: Vec<PathBuf>,
+
+        /// Only tasks working in this directory or below it (relative paths
+        /// are taken from the current directory)
+        #[arg(long, value_name = "PATH")]
+        project: Option<PathBuf>,
+
+        /// Only tasks that exited with this code or in this range, e.g. 137
+        /// or 128-159 (repeatable or comma-separated). Shows exited tasks
+        /// unless --status is given.
+        #[arg(
+            long,
+            value_name = "CODE",
+            value_delimiter = ',',
+            value_parser = parse_exit_codes
+        )]
+        exit_code: Vec<RangeInclusive<i32>>,
+
+        /// With --format json or yaml, output only these task fields
+        /// (comma-separated, e.g. task_id,status,title)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["count", "check"])]
+        fields: Vec<String>,
+
+        /// Order within each section: updated or created (newest first),
+        /// title (A to Z) or duration (longest first). Numbers for `show`
+        /// still count in the default order.
+        #[arg(
+            long,
+            default_value = "updated",
+            value_parser = ["created", "updated", "title", "duration"]
+        )]
+        sort: String,
+
+        /// Reverse the --sort order
+        #[arg(long)]
+        reverse: bool,
+    },
+
+    /// Show the most recently active tasks across all statuses
+    Top {
+        /// Number of tasks to show
+        #[arg(default_value = "5")]
+        count: usize,
+    },
+
+    /// Find tasks whose title, agent, reason, context or metadata contain a
+    /// string (case-insensitive)
+    Search {
+        /// Text to look for
+        query: String,
+
+        /// Maximum number of results
+        #[arg(long, default_value = "20")]
+        limit: usize,
+
+        /// Result order: relevance (most matched fields first) or updated
+        #[arg(long, default_value = "updated", value_parser = ["relevance", "updated"])]
+        sort: String,
     },
 
     /// Show detailed information about a specific task
     Show {
-        /// Task ID to show
+        /// Task to show: ID, unique ID prefix, number from `list`, or `last`
+        task_id: String,
+
+        /// Print the task as pretty JSON (same as --format json)
+        #[arg(long)]
+        json: bool,
+
+        /// After the details, tail the agent's log (context log_path) until
+        /// Ctrl+C
+        #[arg(long, conflicts_with = "json")]
+        follow_logs: bool,
+
+        /// Lines of existing log output to show first
+        #[arg(long, default_value_t = 20, requires = "follow_logs")]
+        lines: usize,
+
+        /// Print only the task's context (URL, project path, session, extras)
+        /// as JSON; `{}` if it has none
+        #[arg(long, conflicts_with = "follow_logs")]
+        context_only: bool,
+
+        /// With --context-only, fail if the task has no context
+        #[arg(long, requires = "context_only")]
+        require: bool,
+
+        /// With --json (or --format json/yaml), output only these fields
+        /// (comma-separated)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["context_only", "follow_logs"])]
+        fields: Vec<String>,
+    },
+
+    /// Show the status-change history of a task
+    History {
+        /// Task to show history for (ID, prefix, list number or `last`)
         task_id: String,
     },
 
-    /// Clear/archive a task
-    Clear {
-        /// Task ID to clear
+    /// Pause a task so it isn't counted as running or flagged
+    Pause {
+        /// Task to pause (ID, prefix, list number or `last`)
         task_id: String,
     },
 
+    /// Resume a paused task
+    Resume {
+        /// Task to resume (ID, prefix, list number or `last`)
+        task_id: String,
+    },
+
+    /// Clear/archive a task, or every task with a status
+    Clear {
+        /// Task to clear (ID, prefix, list number or `last`)
+        #[arg(required_unless_present_any = ["status", "all_terminal"])]
+        task_id: Option<String>,
+
+        /// Clear every task with this status instead: running, paused,
+        /// completed, exited, or attention (flagged tasks)
+        #[arg(long, conflicts_with_all = ["task_id", "all_terminal"])]
+        status: Option<String>,
+
+        /// Clear every completed and exited task instead
+        #[arg(long, conflicts_with = "task_id")]
+        all_terminal: bool,
+    },
+
     /// Clear all completed and exited tasks
-    ClearAll,
+    ClearAll {
+        /// Only clear tasks last updated longer ago than this (e.g. 30m, 12h, 1d)
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<Duration>,
+
+        /// Only clear tasks with this status: running, paused, completed, exited
+        #[arg(long)]
+        status: Option<String>,
+    },
 
     /// Force clear ALL tasks regardless of status (use when stuck)
     Reset {
-        /// Skip confirmation prompt
+        /// Skip confirmation prompt (the global --yes does the same)
         #[arg(short, long)]
         force: bool,
     },
 
     /// Watch tasks in real-time (refreshes every 2 seconds)
-    Watch,
+    Watch {
+        /// Only show tasks with this status: running, paused, completed,
+        /// exited (or synonyms such as active, done, dead), or all
+        #[arg(short, long)]
+        status: Option<String>,
+
+        /// Only show tasks from this agent type (e.g. claude_code)
+        #[arg(long)]
+        agent: Option<String>,
+
+        /// Ring the terminal bell once whenever a task starts needing
+        /// attention (silent otherwise)
+        #[arg(long)]
+        bell: bool,
+
+        /// Only the summary and the task rows: no header box or footer hints
+        #[arg(short, long)]
+        quiet: bool,
+    },
+
+    /// Print a one-line count of running and flagged tasks (`2⚠ 1↩ 3●`) for
+    /// a shell prompt. Reads the database only: no config, no auto-cleanup.
+    Prompt,
+
+    /// Print the JSON Schema of tasks (as `--format json` writes them) and
+    /// of the messages the browser extension exchanges with agent-bridge
+    Schema,
+
+    /// Print the version, the commit it was built from, the database schema
+    /// version and the database path, for bug reports
+    Version {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run a read-only SQL query against the task database
+    Sql {
+        /// A single SELECT (or other read-only) statement
+        query: String,
+    },
+
+    /// Write task changes as JSON lines to stdout or a named pipe
+    Stream {
+        /// Create (if needed) and write to this FIFO, reopening it whenever
+        /// the reader disconnects
+        #[arg(long, value_name = "PATH")]
+        fifo: Option<PathBuf>,
+    },
+
+    /// Restore the most recently cleared task from the trash
+    Undo,
+
+    /// Check the database for tasks whose context or metadata can't be
+    /// read back; exits 1 if any are found
+    Doctor,
+
+    /// Inspect or empty the trash of cleared tasks
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+
+    /// Look through the tasks that left the database (needs
+    /// `archive_path` in the config)
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
 
-    /// Manually trigger cleanup of old completed tasks
+    /// Manually trigger cleanup of old completed tasks, and of other
+    /// statuses with a configured retention (and of trash older than the
+    /// completed retention)
     Cleanup {
-        /// Retention period in seconds (default: 3600)
-        #[arg(short, long, default_value = "3600")]
-        retention_secs: i64,
+        /// Retention period for completed tasks in seconds (default: the
+        /// configured one, 3600 unless set)
+        #[arg(short, long)]
+        retention_secs: Option<i64>,
+    },
+
+    /// Remove completed and exited tasks that repeat a newer one with the
+    /// same agent, title and project, keeping the most recent of each set
+    PruneDuplicates {
+        /// Only list the tasks that would be removed
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Report task status (internal command used by wrappers)
@@ -59,13 +384,217 @@ pub enum Commands {
         action: ReportAction,
     },
 
+    /// Track processes another launcher started (tmux, pm2, ...): one
+    /// running task per row of a JSON or CSV list of task_id, agent_type,
+    /// title and pid, each with a monitor watching its process
+    ImportProcesses {
+        /// JSON array, JSON lines or CSV with a header row; `-` for stdin
+        file: PathBuf,
+
+        /// Only record the tasks, without starting monitors
+        #[arg(long)]
+        no_monitor: bool,
+    },
+
+    /// Relate a web conversation to the code task that carries it on: the
+    /// code task gets its URL and conversation id, and each shows the other
+    Link {
+        /// The claude.ai/Gemini task (ID, prefix, list number or `last`)
+        web_task_id: String,
+
+        /// The CLI agent task continuing it
+        code_task_id: String,
+    },
+
+    /// Fold a duplicate task into another: the kept task takes the latest
+    /// status and both tasks' context and metadata, and the other moves to
+    /// the trash
+    Merge {
+        /// Task that remains (ID, prefix, list number or `last`)
+        keep_task_id: String,
+
+        /// Duplicate to fold in and remove
+        drop_task_id: String,
+
+        /// Where both tasks set a field, take the more recently updated
+        /// one's instead of the kept one's
+        #[arg(long)]
+        prefer_newer: bool,
+    },
+
+    /// Read or attach arbitrary key/value metadata on a task
+    Meta {
+        #[command(subcommand)]
+        action: MetaAction,
+    },
+
+    /// Register agent-bridge as the browser extension's native messaging host
+    InstallHost {
+        #[arg(long, value_enum)]
+        browser: Browser,
+
+        /// ID of the loaded extension (chrome://extensions, or the add-on ID
+        /// on Firefox)
+        #[arg(long)]
+        extension_id: String,
+
+        /// agent-bridge binary to launch (default: next to this executable)
+        #[arg(long, value_name = "PATH")]
+        bridge_path: Option<PathBuf>,
+
+        /// Print the manifest path and contents without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Remove the native messaging host manifest written by install-host
+    UninstallHost {
+        #[arg(long, value_enum)]
+        browser: Browser,
+
+        /// Print the manifest path without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Run the daemon in the background at login: a systemd user unit on
+    /// Linux, a launchd agent on macOS
+    InstallService {
+        /// Print the file path and contents without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Stop the daemon service and remove what install-service wrote
+    UninstallService {
+        /// Print the file path without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Manage webhook deliveries
+    Webhook {
+        #[command(subcommand)]
+        action: WebhookAction,
+    },
+
     /// Monitor a process for completion or attention needs (internal command)
     Monitor {
         /// Task ID to monitor
         task_id: String,
 
-        /// Process ID to monitor
-        pid: i32,
+        /// Process IDs to monitor; the task exits once all of them have
+        #[arg(required = true, num_args = 1..)]
+        pids: Vec<i32>,
+
+        /// Flag the task when resident memory exceeds this many MB
+        #[arg(long, value_name = "MB")]
+        mem_threshold: Option<u64>,
+
+        /// Consecutive polls without CPU progress before the process counts
+        /// as idle; higher values tolerate longer network waits
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_IDLE_WINDOW)]
+        idle_window: u32,
+
+        /// Also stop once the task needs attention, and exit with a code
+        /// describing the outcome: 0 = completed or exited cleanly,
+        /// 1 = exited non-zero or task missing, 2 = needs attention,
+        /// 3 = exited but its exit code couldn't be seen (the pid isn't a
+        /// child of the monitor; a wrapper's `report` can still record it)
+        #[arg(long)]
+        exit_on_idle: bool,
+    },
+
+    /// Find tasks whose monitor died (no heartbeat for 3 polls) and watch
+    /// them again, or mark them exited if the agent is gone too
+    Reap {
+        /// Only report what would be done
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Stay in the foreground and, every --interval, reap tasks whose monitor
+    /// died and apply the retention cleanup. Only one daemon runs at a time
+    /// (lock in ~/.agent-tasks/daemon.lock); Ctrl+C stops it.
+    Daemon {
+        /// Time between passes (e.g. 30s, 5m)
+        #[arg(long, default_value = "30s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+
+    /// Run an agent command as a child and track it under a task, recording
+    /// its exact exit status the moment it ends; exits with the same code
+    Exec {
+        /// Task ID (report start it first)
+        task_id: String,
+
+        /// Flag the task when resident memory exceeds this many MB
+        #[arg(long, value_name = "MB")]
+        mem_threshold: Option<u64>,
+
+        /// Consecutive polls without CPU progress before the process counts
+        /// as idle
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_IDLE_WINDOW)]
+        idle_window: u32,
+
+        /// Command to run, after `--`
+        #[arg(required = true, last = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MetaAction {
+    /// Set a metadata key; the value must be JSON (quote strings: '"abc"')
+    Set {
+        /// Task (ID, prefix, list number or `last`)
+        task_id: String,
+
+        /// Metadata key
+        key: String,
+
+        /// JSON value, e.g. 42, true, '"PROJ-123"' or '{"job": 7}'
+        #[arg(value_parser = parse_json_value)]
+        value: serde_json::Value,
+    },
+
+    /// Print one metadata value, or all metadata when no key is given
+    Get {
+        /// Task (ID, prefix, list number or `last`)
+        task_id: String,
+
+        /// Metadata key
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TrashAction {
+    /// List cleared tasks, most recently deleted first
+    List,
+
+    /// Permanently delete everything in the trash
+    Empty,
+}
+
+#[derive(Subcommand)]
+pub enum ArchiveAction {
+    /// Archived tasks containing a term anywhere (title, ID, context...),
+    /// most recently archived first
+    Search {
+        /// Text to look for, case-insensitively
+        term: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WebhookAction {
+    /// Re-send deliveries that failed and were saved to the dead-letter file
+    Replay {
+        /// Send everything to this URL instead of where each delivery was
+        /// originally going
+        #[arg(long)]
+        url: Option<String>,
     },
 }
 
@@ -82,8 +611,13 @@ pub enum ReportAction {
         /// Working directory
         cwd: String,
 
-        /// Task title/description
-        title: String,
+        /// Task title/description; pass `-` to read it from stdin
+        #[arg(required_unless_present = "title_file")]
+        title: Option<String>,
+
+        /// Read the title/description from a file instead
+        #[arg(long, conflicts_with = "title")]
+        title_file: Option<PathBuf>,
 
         /// Process ID
         #[arg(long)]
@@ -92,6 +626,47 @@ pub enum ReportAction {
         /// Parent process ID
         #[arg(long)]
         ppid: Option<i32>,
+
+        /// Reuse an active task with the same agent, directory and title
+        /// instead of creating a duplicate
+        #[arg(long)]
+        dedup: bool,
+
+        /// Record this environment variable in the task's metadata (repeat
+        /// for more); values of secrets such as *_TOKEN or *_KEY are redacted
+        #[arg(long = "env", value_name = "KEY")]
+        env: Vec<String>,
+
+        /// Record the whole environment, secrets redacted
+        #[arg(long, conflicts_with = "env")]
+        env_all: bool,
+
+        /// Remove the task this long after it starts (e.g. 30m, 2d), whatever
+        /// its status
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<Duration>,
+    },
+
+    /// Change a task's title, directory or URL without touching its status
+    Update {
+        /// Task ID
+        task_id: String,
+
+        /// New title; pass `-` to read it from stdin
+        #[arg(long, required_unless_present_any = ["cwd", "url", "ttl"])]
+        title: Option<String>,
+
+        /// New working directory
+        #[arg(long)]
+        cwd: Option<String>,
+
+        /// New URL (e.g. the conversation the task continues)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Remove the task this long from now, whatever its status
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<Duration>,
     },
 
     /// Report task completion
@@ -119,4 +694,224 @@ pub enum ReportAction {
         #[arg(long)]
         exit_code: Option<i32>,
     },
+
+    /// Flag a task as needing the user's attention
+    NeedsAttention {
+        /// Task ID
+        task_id: String,
+
+        /// Why the task needs attention, shown in the list
+        reason: String,
+    },
+
+    /// Report task has failed (exited with an error)
+    Failed {
+        /// Task ID
+        task_id: String,
+
+        /// Exit code
+        #[arg(long, default_value = "1")]
+        exit_code: i32,
+    },
+}
+
+/// Resolve the title given to `report start`: a literal argument, `-` for
+/// `stdin`, or the contents of `title_file`. The full text is returned;
+/// `Task::new` applies the stored-title truncation.
+pub fn read_title<R: Read>(
+    title: Option<String>,
+    title_file: Option<PathBuf>,
+    mut stdin: R,
+) -> Result<String> {
+    let text = match (title, title_file) {
+        (_, Some(path)) => std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read title file {}", path.display()))?,
+        (Some(title), None) if title == "-" => {
+            let mut text = String::new();
+            stdin
+                .read_to_string(&mut text)
+                .context("Failed to read title from stdin")?;
+            text
+        }
+        (Some(title), None) => return Ok(title),
+        (None, None) => anyhow::bail!("A title or --title-file is required"),
+    };
+
+    Ok(text.trim_end().to_string())
+}
+
+/// Ask `prompt` on stdout and wait for the user to type "yes". With
+/// `assume_yes` (the global `--yes`) the prompt is skipped.
+pub fn confirm(prompt: &str, assume_yes: bool) -> Result<bool> {
+    confirm_with(
+        prompt,
+        assume_yes,
+        std::io::stdin().lock(),
+        std::io::stdout(),
+    )
+}
+
+fn confirm_with<R: BufRead, W: Write>(
+    prompt: &str,
+    assume_yes: bool,
+    mut input: R,
+    mut output: W,
+) -> Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+
+    write!(output, "{} (yes/no): ", prompt)?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+/// Parse a command-line argument as a JSON value
+pub fn parse_json_value(s: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(s).map_err(|e| {
+        format!(
+            "Invalid JSON ({}); strings must be quoted, e.g. '\"{}\"'",
+            e, s
+        )
+    })
+}
+
+/// Parse a human duration such as `90s`, `15m`, `12h`, `1d` or `2w`. A bare
+/// number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}': expected e.g. 30m, 12h, 1d", s))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit '{}': use s, m, h, d or w",
+                unit
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs(value * multiplier))
+}
+
+/// Parse an exit code such as `137`, or an inclusive range such as
+/// `128-159`. Negative codes (signals on some platforms) are allowed.
+pub fn parse_exit_codes(s: &str) -> Result<RangeInclusive<i32>, String> {
+    let s = s.trim();
+    let code = |part: &str| {
+        part.trim()
+            .parse::<i32>()
+            .map_err(|_| format!("Invalid exit code '{}': expected e.g. 137 or 128-159", s))
+    };
+    // Skip a leading minus so `-1` and `-2-2` split at the range dash
+    let range = match s.get(1..).and_then(|rest| rest.find('-')) {
+        Some(dash) => code(&s[..=dash])?..=code(&s[dash + 2..])?,
+        None => code(s)?..=code(s)?,
+    };
+    if range.is_empty() {
+        return Err(format!(
+            "Invalid exit code range '{}': the start is above the end",
+            s
+        ));
+    }
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_exit_codes() {
+        assert_eq!(parse_exit_codes("137").unwrap(), 137..=137);
+        assert_eq!(parse_exit_codes(" 128-159 ").unwrap(), 128..=159);
+        assert_eq!(parse_exit_codes("-1").unwrap(), -1..=-1);
+        assert_eq!(parse_exit_codes("-2-2").unwrap(), -2..=2);
+        assert!(parse_exit_codes("159-128")
+            .unwrap_err()
+            .contains("start is above"));
+        assert!(parse_exit_codes("killed").is_err());
+        assert!(parse_exit_codes("1-").is_err());
+        assert!(parse_exit_codes("").is_err());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut prompt = Vec::new();
+        assert!(confirm_with("Delete?", false, Cursor::new("yes\n"), &mut prompt).unwrap());
+        assert_eq!(String::from_utf8(prompt).unwrap(), "Delete? (yes/no): ");
+
+        assert!(!confirm_with("Delete?", false, Cursor::new("y\n"), Vec::new()).unwrap());
+
+        // --yes never reads stdin or prints the prompt
+        let mut prompt = Vec::new();
+        assert!(confirm_with("Delete?", true, Cursor::new(""), &mut prompt).unwrap());
+        assert!(prompt.is_empty());
+    }
+
+    #[test]
+    fn test_parse_json_value() {
+        assert_eq!(parse_json_value("42").unwrap(), serde_json::json!(42));
+        assert_eq!(
+            parse_json_value(r#"{"job": "ci-7"}"#).unwrap(),
+            serde_json::json!({"job": "ci-7"})
+        );
+        assert!(parse_json_value("PROJ-123").is_err());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Auto.resolve(true, false));
+        assert!(!ColorChoice::Auto.resolve(false, false));
+        assert!(!ColorChoice::Auto.resolve(true, true));
+
+        // Explicit choices win over the terminal check and NO_COLOR
+        assert!(ColorChoice::Always.resolve(false, true));
+        assert!(!ColorChoice::Never.resolve(true, false));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86_400));
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(1_209_600)
+        );
+        assert!(parse_duration("1y").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
+    #[test]
+    fn test_read_title_from_stdin() {
+        let stdin = Cursor::new("Refactor the parser\n\nKeep the old API working\n");
+        let title = read_title(Some("-".to_string()), None, stdin).unwrap();
+        assert_eq!(title, "Refactor the parser\n\nKeep the old API working");
+    }
+
+    #[test]
+    fn test_read_title_literal_and_file() {
+        let title = read_title(Some("Fix bug".to_string()), None, Cursor::new("unused")).unwrap();
+        assert_eq!(title, "Fix bug");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prompt.txt");
+        std::fs::write(&path, "From a file\n").unwrap();
+        let title = read_title(None, Some(path), Cursor::new("")).unwrap();
+        assert_eq!(title, "From a file");
+    }
 }
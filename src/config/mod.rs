@@ -0,0 +1,354 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::models::{TaskStatus, DEFAULT_MAX_TITLE_LEN};
+use crate::monitor::{DetectorSpec, EscalationStep};
+use crate::webhook::WebhookFormat;
+
+/// User configuration read from `~/.agent-tasks/config.toml`
+///
+/// Every section is optional; a missing file yields the defaults.
+///
+/// ```toml
+/// webhook_url = "https://hooks.example.com/agent-inbox"
+/// slack_webhook_url = "https://hooks.slack.com/services/..."
+/// max_tasks = 500
+/// auto_cleanup_retention_secs = 3600
+/// archive_path = "/home/me/.agent-tasks/archive.jsonl"
+/// sla_secs = 7200
+/// list_title_width = 100
+///
+/// [retention]
+/// exited = 86400
+///
+/// [agents.aider]
+/// color = "yellow"
+/// badge = "aider"
+/// sla_secs = 600
+///
+/// [[detectors]]
+/// kind = "log_matches"
+/// pattern = "(?i)rate limit"
+///
+/// [[escalation]]
+/// after_secs = 1800
+/// bump = true
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Display overrides keyed by agent_type
+    pub agents: HashMap<String, AgentStyle>,
+    /// URL that receives a JSON POST whenever a task changes status
+    pub webhook_url: Option<String>,
+    /// Slack incoming webhook that gets a formatted message per change
+    pub slack_webhook_url: Option<String>,
+    /// Discord webhook that gets a formatted message per change
+    pub discord_webhook_url: Option<String>,
+    /// Upper bound on stored tasks; the oldest finished ones are evicted
+    pub max_tasks: Option<usize>,
+    /// Age at which completed tasks, and web chats left waiting for a reply,
+    /// are removed by the cleanup every command runs on startup (default:
+    /// one hour)
+    pub auto_cleanup_retention_secs: Option<i64>,
+    /// Per-status retention in seconds for that cleanup and `cleanup`, e.g.
+    /// `exited = 86400`. A `completed` entry overrides
+    /// `auto_cleanup_retention_secs`; other statuses are only cleaned when
+    /// listed.
+    #[serde(deserialize_with = "deserialize_retention")]
+    pub retention: HashMap<TaskStatus, i64>,
+    /// Seconds during which a long-lived process (the bridge, a monitor)
+    /// holds back webhook notifications repeating a task's last status
+    /// (default 60; 0 sends every one)
+    pub notification_cooldown_secs: Option<u64>,
+    /// JSON-lines file that tasks removed by cleanup, eviction or a purge of
+    /// the trash are appended to before they leave the database
+    pub archive_path: Option<PathBuf>,
+    /// Running tasks older than this are marked "over SLA" in the list,
+    /// unless their agent sets its own `sla_secs`
+    pub sla_secs: Option<u64>,
+    /// Extra attention detectors for monitored processes (`[[detectors]]`),
+    /// run after the built-in ones, replacing those of the same kind
+    pub detectors: Vec<DetectorSpec>,
+    /// What the daemon does about tasks left flagged for attention
+    /// (`[[escalation]]`, see `monitor::escalation`)
+    pub escalation: Vec<EscalationStep>,
+    /// Tasks listed above a confirmation prompt (default 20); longer lists
+    /// are summarized there and shown in full through `$PAGER` first
+    pub confirm_preview: Option<usize>,
+    /// Characters of a task title kept when it is reported (default 100)
+    pub max_title_len: Option<usize>,
+    /// Characters of a title shown per row of the text list (default 60)
+    pub list_title_width: Option<usize>,
+}
+
+/// Status names as keys, accepting the legacy names `TaskStatus::from_str` does
+fn deserialize_retention<'de, D>(deserializer: D) -> Result<HashMap<TaskStatus, i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<String, i64>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(status, secs)| {
+            let status = TaskStatus::from_str(&status).map_err(serde::de::Error::custom)?;
+            Ok((status, secs))
+        })
+        .collect()
+}
+
+/// Set to anything but `0` or empty to skip the startup cleanup
+pub const NO_AUTOCLEAN_ENV: &str = "AGENT_INBOX_NO_AUTOCLEAN";
+
+const DEFAULT_AUTO_CLEANUP_RETENTION_SECS: i64 = 3600;
+
+const DEFAULT_NOTIFICATION_COOLDOWN_SECS: u64 = 60;
+
+const DEFAULT_CONFIRM_PREVIEW: usize = 20;
+
+/// Title characters per row of the text list unless `list_title_width` is set
+pub const DEFAULT_LIST_TITLE_WIDTH: usize = 60;
+
+/// How an agent's badge is rendered in the task list
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AgentStyle {
+    /// Color name, e.g. "magenta" or "bright_blue"
+    pub color: Option<String>,
+    /// Text shown inside the `[...]` badge
+    pub badge: Option<String>,
+    /// Expected longest run for this agent, overriding the global `sla_secs`
+    pub sla_secs: Option<u64>,
+}
+
+impl Config {
+    /// Every configured notification target with its body format
+    pub fn webhook_targets(&self) -> Vec<(String, WebhookFormat)> {
+        [
+            (&self.webhook_url, WebhookFormat::Generic),
+            (&self.slack_webhook_url, WebhookFormat::Slack),
+            (&self.discord_webhook_url, WebhookFormat::Discord),
+        ]
+        .into_iter()
+        .filter_map(|(url, format)| Some((url.clone()?, format)))
+        .collect()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Cooldown for repeated notifications from one task
+    pub fn notification_cooldown(&self) -> Duration {
+        Duration::from_secs(
+            self.notification_cooldown_secs
+                .unwrap_or(DEFAULT_NOTIFICATION_COOLDOWN_SECS),
+        )
+    }
+
+    /// How many tasks a confirmation prompt lists before summarizing
+    pub fn confirm_preview(&self) -> usize {
+        self.confirm_preview.unwrap_or(DEFAULT_CONFIRM_PREVIEW)
+    }
+
+    /// Longest title stored for a task
+    pub fn max_title_len(&self) -> usize {
+        self.max_title_len.unwrap_or(DEFAULT_MAX_TITLE_LEN)
+    }
+
+    /// Width of the title column in the text list
+    pub fn list_title_width(&self) -> usize {
+        self.list_title_width.unwrap_or(DEFAULT_LIST_TITLE_WIDTH)
+    }
+
+    /// How long tasks are kept by cleanup, per status: completed ones for
+    /// `auto_cleanup_retention_secs` unless `[retention]` says otherwise,
+    /// other statuses only when `[retention]` lists them
+    pub fn retention(&self) -> HashMap<TaskStatus, i64> {
+        let completed = self
+            .auto_cleanup_retention_secs
+            .unwrap_or(DEFAULT_AUTO_CLEANUP_RETENTION_SECS);
+        let mut retention = HashMap::from([(TaskStatus::Completed, completed)]);
+        retention.extend(
+            self.retention
+                .iter()
+                .map(|(status, secs)| (status.clone(), *secs)),
+        );
+        retention
+    }
+
+    /// Retention for the implicit startup cleanup, or None when it is
+    /// disabled through `NO_AUTOCLEAN_ENV` (whose value is passed in)
+    pub fn auto_cleanup_retention(&self, no_autoclean: Option<&OsStr>) -> Option<i64> {
+        let disabled = no_autoclean.is_some_and(|v| !v.is_empty() && v != "0");
+        if disabled {
+            return None;
+        }
+        Some(
+            self.auto_cleanup_retention_secs
+                .unwrap_or(DEFAULT_AUTO_CLEANUP_RETENTION_SECS),
+        )
+    }
+}
+
+pub fn default_config_path() -> PathBuf {
+    crate::db::data_dir().join("config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_agent_styles() {
+        let config = Config::parse(
+            r#"
+            webhook_url = "http://localhost:8080/hook"
+            max_tasks = 200
+
+            [agents.aider]
+            color = "yellow"
+            badge = "aider"
+
+            [agents.cursor]
+            color = "bright_magenta"
+            sla_secs = 600
+            "#,
+        )
+        .unwrap();
+
+        let aider = &config.agents["aider"];
+        assert_eq!(aider.color.as_deref(), Some("yellow"));
+        assert_eq!(aider.badge.as_deref(), Some("aider"));
+        assert!(config.agents["cursor"].badge.is_none());
+        assert_eq!(config.agents["cursor"].sla_secs, Some(600));
+        assert!(config.sla_secs.is_none());
+        assert_eq!(
+            config.webhook_url.as_deref(),
+            Some("http://localhost:8080/hook")
+        );
+        assert_eq!(config.max_tasks, Some(200));
+    }
+
+    #[test]
+    fn test_no_autoclean_env_disables_startup_cleanup() {
+        let config = Config::default();
+        assert_eq!(config.auto_cleanup_retention(None), Some(3600));
+        assert_eq!(config.auto_cleanup_retention(Some(OsStr::new("1"))), None);
+        // Explicitly off, or set but empty, keeps it enabled
+        assert_eq!(
+            config.auto_cleanup_retention(Some(OsStr::new("0"))),
+            Some(3600)
+        );
+        assert_eq!(
+            config.auto_cleanup_retention(Some(OsStr::new(""))),
+            Some(3600)
+        );
+
+        let config = Config::parse("auto_cleanup_retention_secs = 86400").unwrap();
+        assert_eq!(config.auto_cleanup_retention(None), Some(86400));
+    }
+
+    #[test]
+    fn test_notification_cooldown() {
+        assert_eq!(
+            Config::default().notification_cooldown(),
+            Duration::from_secs(60)
+        );
+        let config = Config::parse("notification_cooldown_secs = 0").unwrap();
+        assert_eq!(config.notification_cooldown(), Duration::ZERO);
+
+        assert_eq!(Config::default().confirm_preview(), 20);
+        assert_eq!(
+            Config::parse("confirm_preview = 5")
+                .unwrap()
+                .confirm_preview(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_title_lengths() {
+        let config = Config::default();
+        assert_eq!(config.max_title_len(), 100);
+        assert_eq!(config.list_title_width(), 60);
+
+        let config = Config::parse("max_title_len = 300\nlist_title_width = 120").unwrap();
+        assert_eq!(config.max_title_len(), 300);
+        assert_eq!(config.list_title_width(), 120);
+    }
+
+    #[test]
+    fn test_retention_per_status() {
+        let config = Config::default();
+        assert_eq!(
+            config.retention(),
+            HashMap::from([(TaskStatus::Completed, 3600)])
+        );
+
+        let config = Config::parse(
+            r#"
+            auto_cleanup_retention_secs = 600
+
+            [retention]
+            failed = 86400
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.retention(),
+            HashMap::from([(TaskStatus::Completed, 600), (TaskStatus::Exited, 86400)])
+        );
+
+        let config = Config::parse("[retention]\ncompleted = 60").unwrap();
+        assert_eq!(config.retention()[&TaskStatus::Completed], 60);
+        assert!(Config::parse("[retention]\nbogus = 60").is_err());
+    }
+
+    #[test]
+    fn test_webhook_targets() {
+        let config = Config::parse(
+            r#"
+            slack_webhook_url = "https://hooks.slack.com/services/x"
+            discord_webhook_url = "https://discord.com/api/webhooks/y"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.webhook_targets(),
+            vec![
+                (
+                    "https://hooks.slack.com/services/x".to_string(),
+                    WebhookFormat::Slack
+                ),
+                (
+                    "https://discord.com/api/webhooks/y".to_string(),
+                    WebhookFormat::Discord
+                ),
+            ]
+        );
+        assert!(Config::default().webhook_targets().is_empty());
+    }
+
+    #[test]
+    fn test_missing_config_is_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::load(dir.path().join("config.toml")).unwrap();
+        assert!(config.agents.is_empty());
+    }
+}
@@ -1,31 +1,388 @@
 use anyhow::{Context, Result};
-use chrono::{TimeZone, Utc};
-use rusqlite::{params, Connection, OptionalExtension};
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{
+    params, params_from_iter, Connection, DatabaseName, ErrorCode, OpenFlags, OptionalExtension,
+    Transaction, TransactionBehavior,
+};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::archive::Archive;
+use crate::models::{
+    normalize_project_path, AttentionReason, Task, TaskContext, TaskEvent, TaskStatus,
+};
+use crate::webhook::{StatusChange, Throttle, Webhook};
+
+/// Schema migrations applied in order on top of the version 1 schema.
+/// `MIGRATIONS[i]` upgrades the database to version `i + 2`.
+const MIGRATIONS: &[&str] = &[
+    // v2: history of task status changes
+    "CREATE TABLE task_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        task_id TEXT NOT NULL,
+        from_status TEXT,
+        to_status TEXT NOT NULL,
+        reason TEXT,
+        at INTEGER NOT NULL
+    );
+
+    CREATE INDEX idx_events_task_id ON task_events(task_id);
+    ",
+    // v3: fingerprint for `report start --dedup`
+    "ALTER TABLE tasks ADD COLUMN dedup_key TEXT;
+
+    CREATE INDEX idx_dedup_key ON tasks(dedup_key);
+    ",
+    // v4: trash for cleared tasks, so `undo` can bring them back. Same
+    // columns as `tasks` (keep them in step), plus when each was deleted.
+    "CREATE TABLE deleted_tasks (
+        trash_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        id INTEGER NOT NULL,
+        task_id TEXT NOT NULL,
+        agent_type TEXT NOT NULL,
+        title TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        completed_at INTEGER,
+        pid INTEGER,
+        ppid INTEGER,
+        monitor_pid INTEGER,
+        attention_reason TEXT,
+        exit_code INTEGER,
+        context TEXT,
+        metadata TEXT,
+        dedup_key TEXT,
+        deleted_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX idx_deleted_at ON deleted_tasks(deleted_at);
+    ",
+    // v5: context.project_path in its own column, for `list --project`
+    "ALTER TABLE tasks ADD COLUMN project_path TEXT;
+    ALTER TABLE deleted_tasks ADD COLUMN project_path TEXT;
+
+    UPDATE tasks SET project_path = rtrim(json_extract(context, '$.project_path'), '/')
+    WHERE json_valid(context);
+
+    CREATE INDEX idx_project_path ON tasks(project_path);
+    ",
+    // v6: liveness of the monitor watching a task, for `reap`
+    "ALTER TABLE tasks ADD COLUMN last_heartbeat INTEGER;
+    ALTER TABLE deleted_tasks ADD COLUMN last_heartbeat INTEGER;
+    ",
+    // v7: per-task expiry (`--ttl`), independent of status retention
+    "ALTER TABLE tasks ADD COLUMN expires_at INTEGER;
+    ALTER TABLE deleted_tasks ADD COLUMN expires_at INTEGER;
+
+    CREATE INDEX idx_expires_at ON tasks(expires_at);
+    ",
+    // v8: timestamps in milliseconds instead of seconds, so changes within
+    // the same second still order
+    "UPDATE tasks SET created_at = created_at * 1000, updated_at = updated_at * 1000,
+        completed_at = completed_at * 1000, last_heartbeat = last_heartbeat * 1000,
+        expires_at = expires_at * 1000;
+    UPDATE deleted_tasks SET created_at = created_at * 1000, updated_at = updated_at * 1000,
+        completed_at = completed_at * 1000, last_heartbeat = last_heartbeat * 1000,
+        expires_at = expires_at * 1000, deleted_at = deleted_at * 1000;
+    UPDATE task_events SET at = at * 1000;
+    ",
+    // v9: when a task was flagged, so the daemon can escalate long-ignored
+    // flags; already flagged tasks count from their last update
+    "ALTER TABLE tasks ADD COLUMN attention_since INTEGER;
+    ALTER TABLE deleted_tasks ADD COLUMN attention_since INTEGER;
+
+    UPDATE tasks SET attention_since = updated_at WHERE attention_reason IS NOT NULL;
+    ",
+    // v10: redo the v5 backfill the way `normalize_project_path` does it,
+    // which keeps a bare `/`, and fill in the trash it skipped
+    "UPDATE tasks SET project_path = CASE
+        WHEN json_extract(context, '$.project_path') LIKE '/%'
+            AND rtrim(json_extract(context, '$.project_path'), '/') = '' THEN '/'
+        ELSE rtrim(json_extract(context, '$.project_path'), '/')
+    END
+    WHERE json_valid(context);
+    UPDATE deleted_tasks SET project_path = CASE
+        WHEN json_extract(context, '$.project_path') LIKE '/%'
+            AND rtrim(json_extract(context, '$.project_path'), '/') = '' THEN '/'
+        ELSE rtrim(json_extract(context, '$.project_path'), '/')
+    END
+    WHERE json_valid(context);
+    ",
+];
+
+/// Version of the database layout this build reads and writes
+pub const SCHEMA_VERSION: i32 = 1 + MIGRATIONS.len() as i32;
+
+/// Bytes the `-wal` file is truncated to when SQLite resets it
+const WAL_SIZE_LIMIT: i64 = 1024 * 1024;
+
+/// How long a connection waits for another one's write lock before SQLite
+/// reports the database busy
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of `Database::checkpoint`, in WAL frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Frames in the WAL
+    pub log_frames: i64,
+    /// Frames copied back into the database
+    pub checkpointed_frames: i64,
+}
+
+impl Checkpoint {
+    /// Everything in the WAL made it into the database, so the next write
+    /// can restart (and truncate) it
+    pub fn is_complete(&self) -> bool {
+        self.checkpointed_frames >= self.log_frames
+    }
+}
+
+/// Column list matching the layout `row_to_task` expects
+const TASK_COLUMNS: &str = "id, task_id, agent_type, title, status, created_at, updated_at,
+    completed_at, pid, ppid, monitor_pid, attention_reason,
+    exit_code, context, metadata, dedup_key, last_heartbeat, expires_at, attention_since";
+
+/// Stored columns derived from others, not read back into a `Task`; copied
+/// along with `TASK_COLUMNS` when a task moves to and from the trash
+const DERIVED_COLUMNS: &str = "project_path";
+
+/// Finished tasks that aren't the latest of their (agent_type, title,
+/// project_path) set. Ties on updated_at go to the later insert.
+const DUPLICATES_CONDITION: &str = "id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (
+            PARTITION BY agent_type, title, project_path
+            ORDER BY updated_at DESC, id DESC
+        ) AS position
+        FROM tasks WHERE status IN ('completed', 'exited')
+    ) WHERE position > 1
+)";
+
+/// A cleared task waiting in the trash
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashedTask {
+    #[serde(flatten)]
+    pub task: Task,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Result order for `Database::search_tasks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSort {
+    /// Most matching fields first, then earliest match in the title
+    Relevance,
+    /// Most recently updated first
+    Updated,
+}
+
+impl SearchSort {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "relevance" => Ok(SearchSort::Relevance),
+            "updated" => Ok(SearchSort::Updated),
+            _ => Err(format!(
+                "Invalid sort order: {} (use relevance or updated)",
+                s
+            )),
+        }
+    }
+}
+
+/// Which task `Database::latest_task` picks: the most recently updated one,
+/// or the most recent of those flagged or failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latest {
+    Any,
+    Attention,
+    Failed,
+}
+
+impl Latest {
+    /// `last`, `last-attention` or `last-failed`, as typed for a task
+    pub fn from_selector(selector: &str) -> Option<Self> {
+        match selector {
+            "last" => Some(Latest::Any),
+            "last-attention" => Some(Latest::Attention),
+            "last-failed" => Some(Latest::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// What `Database::start_task` did, with the id of the task now running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Started {
+    /// A new task was created
+    Created(String),
+    /// A task with the same id already existed and was updated
+    Existing(String),
+    /// Folded into the active task with the same dedup key
+    Reused(String),
+}
+
+impl Started {
+    pub fn task_id(&self) -> &str {
+        match self {
+            Started::Created(id) | Started::Existing(id) | Started::Reused(id) => id,
+        }
+    }
+}
 
-use crate::models::{Task, TaskContext, TaskStatus};
+/// Row order for `list --sort`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskSort {
+    /// Most recently updated first
+    Updated,
+    /// Most recently created first
+    Created,
+    /// Title A to Z, ignoring case
+    Title,
+    /// Longest running first (see `Task::duration`)
+    Duration,
+}
+
+impl TaskSort {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "updated" => Ok(TaskSort::Updated),
+            "created" => Ok(TaskSort::Created),
+            "title" => Ok(TaskSort::Title),
+            "duration" => Ok(TaskSort::Duration),
+            _ => Err(format!(
+                "Invalid sort order: {} (use created, updated, title or duration)",
+                s
+            )),
+        }
+    }
+
+    /// `ORDER BY` terms for orders the database can apply; `None` for ones
+    /// computed after loading
+    fn order_by(self, reverse: bool) -> Option<String> {
+        let (column, ascending) = match self {
+            TaskSort::Updated => ("updated_at", false),
+            TaskSort::Created => ("created_at", false),
+            TaskSort::Title => ("title COLLATE NOCASE", true),
+            TaskSort::Duration => return None,
+        };
+        let direction = if ascending != reverse { "ASC" } else { "DESC" };
+        Some(format!("{} {}, id {}", column, direction, direction))
+    }
+}
 
-const SCHEMA_VERSION: i32 = 1;
+/// Put `tasks` in `sort` order (reversed with `reverse`), as
+/// `Database::list_tasks_sorted` returns them
+pub fn sort_tasks(tasks: &mut [Task], sort: TaskSort, reverse: bool, now: DateTime<Utc>) {
+    use std::cmp::Reverse;
+    match sort {
+        TaskSort::Updated => tasks.sort_by_key(|task| Reverse(task.updated_at)),
+        TaskSort::Created => tasks.sort_by_key(|task| Reverse(task.created_at)),
+        TaskSort::Title => tasks.sort_by_cached_key(|task| task.title.to_lowercase()),
+        TaskSort::Duration => tasks.sort_by_key(|task| Reverse(task.duration(now))),
+    }
+    if reverse {
+        tasks.reverse();
+    }
+}
 
 pub struct Database {
     conn: Connection,
+    webhooks: Vec<Webhook>,
+    max_tasks: Option<usize>,
+    archive: Option<Archive>,
+    throttle: Option<Mutex<Throttle>>,
+    /// Notifications for changes made inside `transaction`, sent once it
+    /// commits; `None` outside one
+    held_changes: Mutex<Option<Vec<StatusChange>>>,
 }
 
 impl Database {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let conn = Connection::open(path).context("Failed to open database")?;
 
-        // Enable WAL mode for better concurrent access
+        // Enable WAL mode for better concurrent access. SQLite checkpoints
+        // the WAL back into the database as it grows, but never shrinks the
+        // file; the size limit truncates it whenever it is reset, so a
+        // long-lived connection (the bridge) can't leave it large forever.
         conn.execute_batch("PRAGMA journal_mode=WAL;")
             .context("Failed to set WAL mode")?;
-
-        let mut db = Database { conn };
+        conn.pragma_update(None, "journal_size_limit", WAL_SIZE_LIMIT)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let mut db = Database {
+            conn,
+            webhooks: Vec::new(),
+            max_tasks: None,
+            archive: None,
+            throttle: None,
+            held_changes: Mutex::new(None),
+        };
         db.initialize()?;
         Ok(db)
     }
 
+    /// Open someone else's database for reading only: no schema changes, no
+    /// WAL setup and no locks held for writing
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .context("Failed to open database read-only")?;
+        conn.pragma_update(None, "query_only", true)?;
+
+        Ok(Database {
+            conn,
+            webhooks: Vec::new(),
+            max_tasks: None,
+            archive: None,
+            throttle: None,
+            held_changes: Mutex::new(None),
+        })
+    }
+
+    /// Fail unless the database is at the schema this build reads. A
+    /// read-only connection can't migrate it, and older versions lack
+    /// columns or store timestamps in other units.
+    fn check_schema_version(&self) -> Result<()> {
+        let version: Option<i32> = self
+            .conn
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .optional()
+            .context("Not an agent-inbox database")?;
+        match version {
+            Some(SCHEMA_VERSION) => Ok(()),
+            Some(v) if v < SCHEMA_VERSION => anyhow::bail!(
+                "Schema version {} is older than this agent-inbox's {}; migrate this database \
+                 first by running any agent-inbox command as its owner",
+                v,
+                SCHEMA_VERSION
+            ),
+            Some(v) => anyhow::bail!(
+                "Schema version {} is newer than this agent-inbox's {}; upgrade agent-inbox",
+                v,
+                SCHEMA_VERSION
+            ),
+            None => anyhow::bail!("Not an agent-inbox database: no schema version"),
+        }
+    }
+
     fn initialize(&mut self) -> Result<()> {
+        // Hold the write lock from reading the version until the last step
+        // commits: two processes opening an old database at once (the bridge
+        // and a shell hook after an upgrade) would otherwise both migrate it.
+        // The second one waits here, then finds it up to date.
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+
         // Create schema_version table if it doesn't exist
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS schema_version (
@@ -42,22 +399,79 @@ impl Database {
 
         match current_version {
             None => {
-                // Fresh database, create schema
+                // Fresh database, create the base schema and migrate it up
                 self.create_schema()?;
                 self.conn.execute(
                     "INSERT INTO schema_version (version) VALUES (?1)",
-                    params![SCHEMA_VERSION],
+                    params![1],
                 )?;
+                self.migrate(1, MIGRATIONS)?;
             }
             Some(v) if v < SCHEMA_VERSION => {
-                // Future: handle migrations
-                anyhow::bail!("Database schema migration not yet implemented");
+                self.migrate_with_backup(v, MIGRATIONS)?;
             }
             Some(_) => {
                 // Up to date
             }
         }
 
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Migrate a database that already holds tasks, inside the caller's
+    /// transaction. It is copied to `<db>.pre-migration.bak` first, for the
+    /// user to keep; if any step fails, rolling back the transaction leaves
+    /// the database at `from_version` instead of stopping halfway.
+    fn migrate_with_backup(&self, from_version: i32, steps: &[&str]) -> Result<()> {
+        let Some(path) = self
+            .conn
+            .path()
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+        else {
+            // In-memory: nothing on disk to protect
+            return self.migrate(from_version, steps);
+        };
+        let mut backup = path.clone().into_os_string();
+        backup.push(".pre-migration.bak");
+        let backup = PathBuf::from(backup);
+
+        // Copied through a second connection: this one holds the write lock,
+        // which SQLite's backup can't read past, and hasn't changed anything
+        // yet, so the copy is of the same version
+        let source = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        retry_busy(|| Ok(source.backup(DatabaseName::Main, &backup, None)?))
+            .with_context(|| format!("Failed to back up the database to {}", backup.display()))?;
+        tracing::info!(from_version, backup = %backup.display(), "Migrating database");
+
+        self.migrate(from_version, steps).map_err(|err| {
+            tracing::warn!(error = %err, "Migration failed, rolling back");
+            err.context(format!(
+                "Migration failed; rolled the database back to version {} (a copy is in {})",
+                from_version,
+                backup.display()
+            ))
+        })
+    }
+
+    /// Apply every step newer than `from_version`, each together with the
+    /// version bump. `steps[i]` upgrades to version `i + 2`. Runs inside the
+    /// caller's transaction, so a failed step undoes the earlier ones too.
+    fn migrate(&self, from_version: i32, steps: &[&str]) -> Result<()> {
+        for (idx, sql) in steps.iter().enumerate() {
+            let version = idx as i32 + 2;
+            if version <= from_version {
+                continue;
+            }
+
+            self.conn
+                .execute_batch(sql)
+                .with_context(|| format!("Failed to migrate database to version {}", version))?;
+            self.conn
+                .execute("UPDATE schema_version SET version = ?1", params![version])?;
+        }
+
         Ok(())
     }
 
@@ -91,93 +505,449 @@ impl Database {
         Ok(())
     }
 
+    /// Copy committed WAL frames back into the database without waiting on
+    /// other connections (a PASSIVE checkpoint). Frames a reader still needs
+    /// are left for a later checkpoint, so under constant reads a
+    /// long-running process should call this periodically.
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        let (log_frames, checkpointed_frames) =
+            self.conn
+                .query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+                    Ok((row.get(1)?, row.get(2)?))
+                })?;
+        Ok(Checkpoint {
+            log_frames,
+            checkpointed_frames,
+        })
+    }
+
+    /// Run `f` inside a single transaction, committing only if it succeeds.
+    /// Webhooks hear about the changes it made after the commit, and not at
+    /// all if it rolls back.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        let tx = self.conn.unchecked_transaction()?;
+        *self.held_changes() = Some(Vec::new());
+        let result = f(self);
+        let held = self.held_changes().take().unwrap_or_default();
+        let result = result?;
+        tx.commit()?;
+
+        for change in held {
+            self.notify(change);
+        }
+        Ok(result)
+    }
+
+    fn held_changes(&self) -> std::sync::MutexGuard<'_, Option<Vec<StatusChange>>> {
+        self.held_changes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Store a new task and return its row id. The `task_id` must not be in
+    /// use yet; `upsert_task` and `start_task` handle existing ones.
+    ///
+    /// ```
+    /// # use agent_inbox::{Database, Task};
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = tempfile::tempdir()?;
+    /// let db = Database::open(dir.path().join("tasks.db"))?;
+    /// let task = Task::new("t1".into(), "aider".into(), "Add tests".into(), None, None);
+    /// db.insert_task(&task)?;
+    ///
+    /// let stored = db.get_task_by_id("t1")?.expect("just inserted");
+    /// assert_eq!(stored.agent_type, "aider");
+    /// assert!(db.insert_task(&task).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn insert_task(&self, task: &Task) -> Result<i64> {
-        let context_json = task
-            .context
-            .as_ref()
-            .map(serde_json::to_string)
-            .transpose()?;
-
-        let metadata_json = task
-            .metadata
-            .as_ref()
-            .map(serde_json::to_string)
-            .transpose()?;
+        let (context_json, metadata_json) = json_columns(task)?;
 
         self.conn.execute(
             "INSERT INTO tasks (
                 task_id, agent_type, title, status, created_at, updated_at,
                 completed_at, pid, ppid, monitor_pid, attention_reason,
-                exit_code, context, metadata
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                exit_code, context, metadata, dedup_key, project_path, expires_at,
+                attention_since
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18
+            )",
             params![
                 task.task_id,
                 task.agent_type,
                 task.title,
                 task.status.as_str(),
-                task.created_at.timestamp(),
-                task.updated_at.timestamp(),
-                task.completed_at.map(|dt| dt.timestamp()),
+                task.created_at.timestamp_millis(),
+                task.updated_at.timestamp_millis(),
+                task.completed_at.map(|dt| dt.timestamp_millis()),
                 task.pid,
                 task.ppid,
                 task.monitor_pid,
-                task.attention_reason,
+                task.attention_reason
+                    .as_ref()
+                    .map(AttentionReason::to_column),
                 task.exit_code,
                 context_json,
                 metadata_json,
+                task.dedup_key,
+                task.project_path(),
+                task.expires_at.map(|dt| dt.timestamp_millis()),
+                task.attention_since.map(|dt| dt.timestamp_millis()),
             ],
         )?;
+        let id = self.conn.last_insert_rowid();
+
+        self.status_changed(task, None);
+        self.evict_over_cap()?;
 
-        Ok(self.conn.last_insert_rowid())
+        Ok(id)
     }
 
     pub fn update_task(&self, task: &Task) -> Result<()> {
-        let context_json = task
-            .context
-            .as_ref()
-            .map(serde_json::to_string)
-            .transpose()?;
-
-        let metadata_json = task
-            .metadata
-            .as_ref()
-            .map(serde_json::to_string)
-            .transpose()?;
+        let previous_status = self.current_status(&task.task_id);
+
+        let (context_json, metadata_json) = json_columns(task)?;
 
         self.conn.execute(
             "UPDATE tasks SET
                 agent_type = ?1, title = ?2, status = ?3, updated_at = ?4,
                 completed_at = ?5, pid = ?6, ppid = ?7, monitor_pid = ?8,
-                attention_reason = ?9, exit_code = ?10, context = ?11, metadata = ?12
-            WHERE task_id = ?13",
+                attention_reason = ?9, exit_code = ?10, context = ?11, metadata = ?12,
+                dedup_key = ?13, project_path = ?14, expires_at = ?15, attention_since = ?16
+            WHERE task_id = ?17",
             params![
                 task.agent_type,
                 task.title,
                 task.status.as_str(),
-                task.updated_at.timestamp(),
-                task.completed_at.map(|dt| dt.timestamp()),
+                task.updated_at.timestamp_millis(),
+                task.completed_at.map(|dt| dt.timestamp_millis()),
                 task.pid,
                 task.ppid,
                 task.monitor_pid,
-                task.attention_reason,
+                task.attention_reason
+                    .as_ref()
+                    .map(AttentionReason::to_column),
                 task.exit_code,
                 context_json,
                 metadata_json,
+                task.dedup_key,
+                task.project_path(),
+                task.expires_at.map(|dt| dt.timestamp_millis()),
+                task.attention_since.map(|dt| dt.timestamp_millis()),
                 task.task_id,
             ],
         )?;
 
+        if let Some(previous) = previous_status {
+            if previous != task.status {
+                self.status_changed(task, Some(&previous));
+            }
+        }
+
         Ok(())
     }
 
-    pub fn get_task_by_id(&self, task_id: &str) -> Result<Option<Task>> {
+    /// Insert the task, or update the existing row with the same task_id.
+    ///
+    /// State fields (status, timestamps, exit code, attention) always take the
+    /// new values; descriptive fields (pids, context, metadata) only replace
+    /// the stored ones when set, so a follow-up report doesn't erase them.
+    /// `created_at` is preserved for existing rows.
+    pub fn upsert_task(&self, task: &Task) -> Result<()> {
+        let previous_status = self.current_status(&task.task_id);
+
+        let (context_json, metadata_json) = json_columns(task)?;
+
+        self.conn.execute(
+            "INSERT INTO tasks (
+                task_id, agent_type, title, status, created_at, updated_at,
+                completed_at, pid, ppid, monitor_pid, attention_reason,
+                exit_code, context, metadata, dedup_key, project_path, expires_at,
+                attention_since
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18
+            )
+            ON CONFLICT(task_id) DO UPDATE SET
+                agent_type = excluded.agent_type,
+                title = excluded.title,
+                status = excluded.status,
+                updated_at = excluded.updated_at,
+                completed_at = excluded.completed_at,
+                pid = COALESCE(excluded.pid, tasks.pid),
+                ppid = COALESCE(excluded.ppid, tasks.ppid),
+                monitor_pid = COALESCE(excluded.monitor_pid, tasks.monitor_pid),
+                attention_reason = excluded.attention_reason,
+                exit_code = excluded.exit_code,
+                context = COALESCE(excluded.context, tasks.context),
+                metadata = COALESCE(excluded.metadata, tasks.metadata),
+                dedup_key = COALESCE(excluded.dedup_key, tasks.dedup_key),
+                project_path = COALESCE(excluded.project_path, tasks.project_path),
+                expires_at = COALESCE(excluded.expires_at, tasks.expires_at),
+                attention_since = excluded.attention_since",
+            params![
+                task.task_id,
+                task.agent_type,
+                task.title,
+                task.status.as_str(),
+                task.created_at.timestamp_millis(),
+                task.updated_at.timestamp_millis(),
+                task.completed_at.map(|dt| dt.timestamp_millis()),
+                task.pid,
+                task.ppid,
+                task.monitor_pid,
+                task.attention_reason
+                    .as_ref()
+                    .map(AttentionReason::to_column),
+                task.exit_code,
+                context_json,
+                metadata_json,
+                task.dedup_key,
+                task.project_path(),
+                task.expires_at.map(|dt| dt.timestamp_millis()),
+                task.attention_since.map(|dt| dt.timestamp_millis()),
+            ],
+        )?;
+
+        if previous_status.as_ref() != Some(&task.status) {
+            self.status_changed(task, previous_status.as_ref());
+        }
+        if previous_status.is_none() {
+            self.evict_over_cap()?;
+        }
+
+        Ok(())
+    }
+
+    /// Note that the task's monitor is alive and watching, without touching
+    /// `updated_at` (so the task doesn't re-sort)
+    pub fn record_heartbeat(&self, task_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE tasks SET last_heartbeat = ?1 WHERE task_id = ?2",
+            params![Utc::now().timestamp_millis(), task_id],
+        )?;
+        Ok(())
+    }
+
+    /// Make `pid` the task's monitor if no monitor owns it, `pid` already
+    /// does, or the owner is `stale` (a monitor the caller found dead). One
+    /// conditional update, so of two monitors racing for a task only one
+    /// wins, and nothing else in the row is touched. Returns whether `pid`
+    /// owns the task now.
+    pub fn claim_monitor(&self, task_id: &str, pid: i32, stale: Option<i32>) -> Result<bool> {
+        let claimed = self.conn.execute(
+            "UPDATE tasks SET monitor_pid = ?1
+             WHERE task_id = ?2
+               AND (monitor_pid IS NULL OR monitor_pid = ?1 OR monitor_pid = ?3)",
+            params![pid, task_id, stale],
+        )?;
+        Ok(claimed == 1)
+    }
+
+    /// Clear `monitor_pid` if `pid` still owns the task
+    pub fn release_monitor(&self, task_id: &str, pid: i32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE tasks SET monitor_pid = NULL WHERE task_id = ?1 AND monitor_pid = ?2",
+            params![task_id, pid],
+        )?;
+        Ok(())
+    }
+
+    /// Running or paused tasks that name a monitor, oldest heartbeat first
+    /// (a monitor that never beat sorts first); `reap` decides which are stale
+    pub fn monitored_tasks(&self) -> Result<Vec<Task>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM tasks
+             WHERE status IN ('running', 'paused') AND monitor_pid IS NOT NULL
+             ORDER BY last_heartbeat ASC, id ASC",
+            TASK_COLUMNS
+        ))?;
+        let tasks = stmt
+            .query_map([], |row| self.row_to_task(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tasks)
+    }
+
+    /// Record a task start. A task carrying a `dedup_key` is folded into an
+    /// active (running or paused) task with the same key, which is brought
+    /// back to running, instead of creating a second row. Starting an id
+    /// that already exists (a wrapper reporting twice) updates that row as
+    /// `upsert_task` does rather than failing.
+    pub fn start_task(&self, task: &Task) -> Result<Started> {
+        if let Some(key) = &task.dedup_key {
+            if let Some(mut existing) = self.find_active_by_dedup_key(key)? {
+                existing.pid = task.pid.or(existing.pid);
+                existing.ppid = task.ppid.or(existing.ppid);
+                existing.expires_at = task.expires_at.or(existing.expires_at);
+                existing.set_running();
+                self.update_task(&existing)?;
+                return Ok(Started::Reused(existing.task_id));
+            }
+        }
+
+        let existed = self.current_status(&task.task_id).is_some();
+        self.upsert_task(task)?;
+        Ok(if existed {
+            Started::Existing(task.task_id.clone())
+        } else {
+            Started::Created(task.task_id.clone())
+        })
+    }
+
+    fn find_active_by_dedup_key(&self, key: &str) -> Result<Option<Task>> {
+        let query = format!(
+            "SELECT {} FROM tasks
+             WHERE dedup_key = ?1 AND status IN ('running', 'paused')
+             ORDER BY updated_at DESC LIMIT 1",
+            TASK_COLUMNS
+        );
+        let task = self
+            .conn
+            .query_row(&query, params![key], |row| self.row_to_task(row))
+            .optional()?;
+        Ok(task)
+    }
+
+    fn current_status(&self, task_id: &str) -> Option<TaskStatus> {
+        self.conn
+            .query_row(
+                "SELECT status FROM tasks WHERE task_id = ?1",
+                params![task_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .ok()
+            .flatten()
+            .and_then(|s| TaskStatus::from_str(&s).ok())
+    }
+
+    /// Also send status-change notifications to `webhook`
+    pub fn add_webhook(&mut self, webhook: Webhook) {
+        self.webhooks.push(webhook);
+    }
+
+    /// Hold back webhook notifications that repeat a task's last status
+    /// within `cooldown`. The window is per connection, so it only has an
+    /// effect in long-lived processes (the bridge, monitors).
+    pub fn set_notification_cooldown(&mut self, cooldown: Duration) {
+        self.throttle = Some(Mutex::new(Throttle::new(cooldown)));
+    }
+
+    /// Append every task removed from here on to `archive` first
+    pub fn set_archive(&mut self, archive: Archive) {
+        self.archive = Some(archive);
+    }
+
+    /// Keep at most `max` tasks, evicting finished ones after every insert
+    pub fn set_max_tasks(&mut self, max: usize) {
+        self.max_tasks = Some(max);
+    }
+
+    fn evict_over_cap(&self) -> Result<()> {
+        if let Some(max) = self.max_tasks {
+            let evicted = self.enforce_max_tasks(max)?;
+            if evicted > 0 {
+                tracing::info!(evicted, max, "Evicted tasks over max_tasks");
+            }
+        }
+        Ok(())
+    }
+
+    /// Side effects of a status change: append it to the task's history and
+    /// notify the webhooks. Best-effort: failures are logged and never fail
+    /// the write that triggered them.
+    fn status_changed(&self, task: &Task, from: Option<&TaskStatus>) {
+        let result = self.conn.execute(
+            "INSERT INTO task_events (task_id, from_status, to_status, reason, at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                task.task_id,
+                from.map(|s| s.as_str()),
+                task.status.as_str(),
+                task.attention_reason.as_ref().map(|r| r.to_string()),
+                Utc::now().timestamp_millis(),
+            ],
+        );
+
+        if let Err(e) = result {
+            tracing::warn!(task_id = %task.task_id, error = %e, "Failed to record task event");
+        }
+
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let change = StatusChange::from_task(task);
+        match self.held_changes().as_mut() {
+            Some(held) => held.push(change),
+            None => self.notify(change),
+        }
+    }
+
+    /// Send `change` to the webhooks, unless the throttle holds it back
+    fn notify(&self, mut change: StatusChange) {
+        if let Some(throttle) = &self.throttle {
+            let mut throttle = throttle.lock().unwrap_or_else(|e| e.into_inner());
+            let task_id = change.task_id.clone();
+            match throttle.admit(change, Instant::now()) {
+                Some(admitted) => change = admitted,
+                None => {
+                    tracing::debug!(%task_id, "Notification throttled");
+                    return;
+                }
+            }
+        }
+        for webhook in &self.webhooks {
+            webhook.send(change.clone());
+        }
+    }
+
+    /// Send the task's attention flag to the webhooks again, marked as
+    /// escalated after being up for `flagged_for`. Not throttled: the
+    /// escalation steps set their own pace.
+    pub fn notify_escalation(&self, task: &Task, flagged_for: Duration) {
+        let mut change = StatusChange::from_task(task);
+        change.escalated_after_secs = Some(flagged_for.as_secs());
+        for webhook in &self.webhooks {
+            webhook.send(change.clone());
+        }
+    }
+
+    /// Status changes recorded for a task, oldest first
+    pub fn task_history(&self, task_id: &str) -> Result<Vec<TaskEvent>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, task_id, agent_type, title, status, created_at, updated_at,
-                    completed_at, pid, ppid, monitor_pid, attention_reason,
-                    exit_code, context, metadata
-             FROM tasks WHERE task_id = ?1",
+            "SELECT task_id, from_status, to_status, reason, at
+             FROM task_events WHERE task_id = ?1 ORDER BY at ASC, id ASC",
         )?;
 
+        let events = stmt
+            .query_map(params![task_id], |row| {
+                let from_status: Option<String> = row.get(1)?;
+                let to_status: String = row.get(2)?;
+                let at: i64 = row.get(4)?;
+                Ok(TaskEvent {
+                    task_id: row.get(0)?,
+                    from_status: from_status.and_then(|s| TaskStatus::from_str(&s).ok()),
+                    to_status: TaskStatus::from_str(&to_status).map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(
+                            2,
+                            rusqlite::types::Type::Text,
+                            e.into(),
+                        )
+                    })?,
+                    reason: row.get(3)?,
+                    at: Utc.timestamp_millis_opt(at).unwrap(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    pub fn get_task_by_id(&self, task_id: &str) -> Result<Option<Task>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM tasks WHERE task_id = ?1",
+            TASK_COLUMNS
+        ))?;
+
         let task = stmt
             .query_row(params![task_id], |row| self.row_to_task(row))
             .optional()?;
@@ -188,18 +958,15 @@ impl Database {
     pub fn list_tasks(&self, status_filter: Option<TaskStatus>) -> Result<Vec<Task>> {
         let query = if let Some(status) = status_filter {
             format!(
-                "SELECT id, task_id, agent_type, title, status, created_at, updated_at,
-                        completed_at, pid, ppid, monitor_pid, attention_reason,
-                        exit_code, context, metadata
-                 FROM tasks WHERE status = '{}' ORDER BY updated_at DESC",
+                "SELECT {} FROM tasks WHERE status = '{}' ORDER BY updated_at DESC",
+                TASK_COLUMNS,
                 status.as_str()
             )
         } else {
-            "SELECT id, task_id, agent_type, title, status, created_at, updated_at,
-                    completed_at, pid, ppid, monitor_pid, attention_reason,
-                    exit_code, context, metadata
-             FROM tasks ORDER BY updated_at DESC"
-                .to_string()
+            format!(
+                "SELECT {} FROM tasks ORDER BY updated_at DESC",
+                TASK_COLUMNS
+            )
         };
 
         let mut stmt = self.conn.prepare(&query)?;
@@ -210,102 +977,1079 @@ impl Database {
         Ok(tasks)
     }
 
-    pub fn delete_task(&self, task_id: &str) -> Result<bool> {
-        let affected = self
+    /// Most recently updated tasks first, `limit` rows starting at `offset`
+    pub fn list_tasks_paged(
+        &self,
+        status_filter: Option<TaskStatus>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Task>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM tasks WHERE (?1 IS NULL OR status = ?1)
+             ORDER BY updated_at DESC LIMIT ?2 OFFSET ?3",
+            TASK_COLUMNS
+        ))?;
+
+        let tasks = stmt
+            .query_map(
+                params![
+                    status_filter.as_ref().map(|s| s.as_str()),
+                    limit as i64,
+                    offset as i64
+                ],
+                |row| self.row_to_task(row),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tasks)
+    }
+
+    /// The most recently updated task of the kind `latest` picks
+    pub fn latest_task(&self, latest: Latest) -> Result<Option<Task>> {
+        let condition =
+            match latest {
+                Latest::Any => "1".to_string(),
+                // Chats waiting for a reply aren't blocked
+                Latest::Attention => format!(
+                "status = 'running' AND attention_reason IS NOT NULL AND attention_reason != '{}'",
+                AttentionReason::ReadyForReply.to_column().replace('\'', "''")
+            ),
+                Latest::Failed => {
+                    "status = 'exited' AND exit_code IS NOT NULL AND exit_code != 0".to_string()
+                }
+            };
+        let query = format!(
+            "SELECT {} FROM tasks WHERE {} ORDER BY updated_at DESC, id DESC LIMIT 1",
+            TASK_COLUMNS, condition
+        );
+        let task = self
             .conn
-            .execute("DELETE FROM tasks WHERE task_id = ?1", params![task_id])?;
+            .query_row(&query, [], |row| self.row_to_task(row))
+            .optional()?;
+        Ok(task)
+    }
+
+    /// The task whose ID starts with `prefix` (like a git short hash). None
+    /// if nothing matches; an error naming the candidates if several do.
+    pub fn get_task_by_id_prefix(&self, prefix: &str) -> Result<Option<Task>> {
+        if prefix.is_empty() {
+            return Ok(None);
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM tasks WHERE task_id LIKE ?1 || '%' ESCAPE '\\'
+             ORDER BY task_id LIMIT 6",
+            TASK_COLUMNS
+        ))?;
+        let mut matches = stmt
+            .query_map(params![escape_like(prefix)], |row| self.row_to_task(row))?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(affected > 0)
+        match matches.len() {
+            0 => Ok(None),
+            1 => Ok(matches.pop()),
+            n => {
+                let mut candidates: Vec<String> = matches
+                    .iter()
+                    .take(5)
+                    .map(|t| format!("  {}  {}", t.task_id, t.title))
+                    .collect();
+                if n > 5 {
+                    candidates.push("  ...".to_string());
+                }
+                anyhow::bail!(
+                    "Task ID prefix '{}' is ambiguous; candidates:\n{}",
+                    prefix,
+                    candidates.join("\n")
+                )
+            }
+        }
     }
 
-    pub fn cleanup_old_completed(&self, older_than_secs: i64) -> Result<usize> {
-        let cutoff = Utc::now().timestamp() - older_than_secs;
+    /// Case-insensitive substring search over title, agent, attention
+    /// reason, context and metadata
+    pub fn search_tasks(
+        &self,
+        query: &str,
+        sort: SearchSort,
+        limit: Option<usize>,
+    ) -> Result<Vec<Task>> {
+        let pattern = format!("%{}%", escape_like(query));
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM tasks
+             WHERE title LIKE ?1 ESCAPE '\\'
+                OR agent_type LIKE ?1 ESCAPE '\\'
+                OR attention_reason IS NOT NULL
+                OR context LIKE ?1 ESCAPE '\\'
+                OR metadata LIKE ?1 ESCAPE '\\'
+             ORDER BY updated_at DESC",
+            TASK_COLUMNS
+        ))?;
+        let needle = query.to_lowercase();
+        // The reason column holds JSON, so flagged rows are matched against
+        // the human text here rather than in SQL
+        let mut tasks = stmt
+            .query_map(params![pattern], |row| self.row_to_task(row))?
+            .filter(|task| !matches!(task, Ok(task) if matched_fields(task, &needle) == 0))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let affected = self.conn.execute(
-            "DELETE FROM tasks WHERE status = 'completed' AND completed_at < ?1",
-            params![cutoff],
-        )?;
+        if sort == SearchSort::Relevance {
+            // Stable sort keeps recency order among equally relevant tasks
+            tasks.sort_by_cached_key(|task| search_rank(task, &needle));
+        }
+        if let Some(limit) = limit {
+            tasks.truncate(limit);
+        }
 
-        Ok(affected)
+        Ok(tasks)
     }
 
-    fn row_to_task(&self, row: &rusqlite::Row) -> rusqlite::Result<Task> {
-        let created_ts: i64 = row.get(5)?;
-        let updated_ts: i64 = row.get(6)?;
-        let completed_ts: Option<i64> = row.get(7)?;
+    /// Count tasks matching the optional status and agent filters without
+    /// loading the rows
+    pub fn count(&self, status: Option<TaskStatus>, agent: Option<&str>) -> Result<usize> {
+        let (filter, values) = filter_clause(status.as_ref(), agent, None, &[]);
+        let query = format!("SELECT COUNT(*) FROM tasks{}", filter);
 
-        let context_json: Option<String> = row.get(13)?;
-        let context: Option<TaskContext> = context_json
-            .and_then(|s| serde_json::from_str(&s).ok());
+        let count: i64 = self
+            .conn
+            .query_row(&query, params_from_iter(values.iter()), |row| row.get(0))?;
 
-        let metadata_json: Option<String> = row.get(14)?;
-        let metadata: Option<HashMap<String, serde_json::Value>> = metadata_json
-            .and_then(|s| serde_json::from_str(&s).ok());
+        Ok(count as usize)
+    }
 
-        let status_str: String = row.get(4)?;
-        let status = TaskStatus::from_str(&status_str)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e,
-            ))))?;
+    /// Tasks matching the optional status and agent filters, most recently
+    /// updated first
+    pub fn list_tasks_filtered(
+        &self,
+        status: Option<&TaskStatus>,
+        agent: Option<&str>,
+    ) -> Result<Vec<Task>> {
+        self.query_filtered(status, agent, None, &[], "updated_at DESC")
+    }
 
-        Ok(Task {
-            id: Some(row.get(0)?),
-            task_id: row.get(1)?,
-            agent_type: row.get(2)?,
-            title: row.get(3)?,
-            status,
-            created_at: Utc.timestamp_opt(created_ts, 0).unwrap(),
-            updated_at: Utc.timestamp_opt(updated_ts, 0).unwrap(),
-            completed_at: completed_ts.map(|ts| Utc.timestamp_opt(ts, 0).unwrap()),
-            pid: row.get(8)?,
-            ppid: row.get(9)?,
-            monitor_pid: row.get(10)?,
-            attention_reason: row.get(11)?,
-            exit_code: row.get(12)?,
-            context,
-            metadata,
-        })
+    /// Tasks with the optional status, in or below the optional project and
+    /// with an exit code in one of `exit_codes` (any when empty), in `sort`
+    /// order (reversed with `reverse`)
+    pub fn list_tasks_sorted(
+        &self,
+        status: Option<&TaskStatus>,
+        project: Option<&str>,
+        exit_codes: &[RangeInclusive<i32>],
+        sort: TaskSort,
+        reverse: bool,
+    ) -> Result<Vec<Task>> {
+        match sort.order_by(reverse) {
+            Some(order) => self.query_filtered(status, None, project, exit_codes, &order),
+            None => {
+                let mut tasks =
+                    self.query_filtered(status, None, project, exit_codes, "updated_at DESC")?;
+                sort_tasks(&mut tasks, sort, reverse, Utc::now());
+                Ok(tasks)
+            }
+        }
     }
-}
 
-pub fn default_db_path() -> PathBuf {
-    let home = std::env::var("HOME").expect("HOME environment variable not set");
-    PathBuf::from(home)
-        .join(".agent-tasks")
-        .join("tasks.db")
-}
+    fn query_filtered(
+        &self,
+        status: Option<&TaskStatus>,
+        agent: Option<&str>,
+        project: Option<&str>,
+        exit_codes: &[RangeInclusive<i32>],
+        order: &str,
+    ) -> Result<Vec<Task>> {
+        let (filter, values) = filter_clause(status, agent, project, exit_codes);
+        let query = format!(
+            "SELECT {} FROM tasks{} ORDER BY {}",
+            TASK_COLUMNS, filter, order
+        );
 
-pub fn ensure_data_dir() -> Result<PathBuf> {
-    let home = std::env::var("HOME").context("HOME environment variable not set")?;
-    let data_dir = PathBuf::from(home).join(".agent-tasks");
+        let mut stmt = self.conn.prepare(&query)?;
+        let tasks = stmt
+            .query_map(params_from_iter(values.iter()), |row| self.row_to_task(row))?
+            .collect::<Result<Vec<_>, _>>()?;
 
-    if !data_dir.exists() {
-        std::fs::create_dir_all(&data_dir)
-            .context("Failed to create data directory")?;
+        Ok(tasks)
     }
 
-    Ok(data_dir)
-}
+    /// Move a task to the trash, keeping its history for `undo`. Call inside
+    /// a transaction so the task isn't left in both tables.
+    pub fn delete_task(&self, task_id: &str) -> Result<bool> {
+        let values = [Value::Text(task_id.to_string())];
+        Ok(self.trash_where("task_id = ?1", &values)? > 0)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
+    /// Move the tasks matching `condition` (with `values` bound to its
+    /// `?1`..`?n`) to the trash. Returns the number moved. They are archived
+    /// once purged from there, as `undo` may still bring them back.
+    fn trash_where(&self, condition: &str, values: &[Value]) -> Result<usize> {
+        let mut params: Vec<Value> = values.to_vec();
+        params.push(Value::Integer(Utc::now().timestamp_millis()));
+        self.conn.execute(
+            &format!(
+                "INSERT INTO deleted_tasks ({cols}, {derived}, deleted_at)
+                 SELECT {cols}, {derived}, ?{now} FROM tasks WHERE {condition}",
+                cols = TASK_COLUMNS,
+                derived = DERIVED_COLUMNS,
+                now = params.len(),
+                condition = condition,
+            ),
+            params_from_iter(params.iter()),
+        )?;
 
-    fn create_test_db() -> (Database, NamedTempFile) {
-        let temp_file = NamedTempFile::new().unwrap();
-        let db = Database::open(temp_file.path()).unwrap();
-        (db, temp_file)
+        let moved = self.conn.execute(
+            &format!("DELETE FROM tasks WHERE {}", condition),
+            params_from_iter(values.iter()),
+        )?;
+        Ok(moved)
     }
 
-    #[test]
-    fn test_database_creation() {
-        let (_db, _temp) = create_test_db();
-        // If we got here, database was created successfully
+    /// Append the tasks matching `condition` to the archive, when one is set,
+    /// ahead of removing them
+    fn archive_where(&self, condition: &str, values: &[Value]) -> Result<()> {
+        self.archive_from("tasks", condition, values)
     }
 
-    #[test]
+    /// Same as `archive_where`, for the rows of `table` (`tasks` or
+    /// `deleted_tasks`)
+    fn archive_from(&self, table: &str, condition: &str, values: &[Value]) -> Result<()> {
+        let Some(archive) = &self.archive else {
+            return Ok(());
+        };
+
+        let query = format!("SELECT {} FROM {} WHERE {}", TASK_COLUMNS, table, condition);
+        let mut stmt = self.conn.prepare(&query)?;
+        let tasks = stmt
+            .query_map(params_from_iter(values.iter()), |row| self.row_to_task(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+        archive.append(&tasks)
+    }
+
+    /// Restore the most recently deleted task from the trash. Returns None
+    /// when the trash is empty.
+    pub fn restore_last_deleted(&self) -> Result<Option<Task>> {
+        let last: Option<(i64, String)> = self
+            .conn
+            .query_row(
+                "SELECT trash_id, task_id FROM deleted_tasks ORDER BY trash_id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((trash_id, task_id)) = last else {
+            return Ok(None);
+        };
+
+        if self.get_task_by_id(&task_id)?.is_some() {
+            anyhow::bail!(
+                "Cannot restore task {}: a task with that ID exists again (clear it first)",
+                task_id
+            );
+        }
+
+        self.conn.execute(
+            &format!(
+                "INSERT INTO tasks ({cols}, {derived})
+                 SELECT {cols}, {derived} FROM deleted_tasks WHERE trash_id = ?1",
+                cols = TASK_COLUMNS,
+                derived = DERIVED_COLUMNS,
+            ),
+            params![trash_id],
+        )?;
+        self.conn.execute(
+            "DELETE FROM deleted_tasks WHERE trash_id = ?1",
+            params![trash_id],
+        )?;
+
+        self.get_task_by_id(&task_id)
+    }
+
+    /// Tasks in the trash, most recently deleted first
+    pub fn list_trash(&self) -> Result<Vec<TrashedTask>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, deleted_at FROM deleted_tasks ORDER BY trash_id DESC",
+            TASK_COLUMNS
+        ))?;
+        let trashed = stmt
+            .query_map([], |row| {
+                let deleted_ts: i64 = row.get("deleted_at")?;
+                Ok(TrashedTask {
+                    task: self.row_to_task(row)?,
+                    deleted_at: Utc.timestamp_millis_opt(deleted_ts).unwrap(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(trashed)
+    }
+
+    /// Permanently delete trashed tasks, all of them or only those deleted
+    /// more than `older_than_secs` ago, archiving them first. Returns the
+    /// number removed.
+    pub fn purge_trash(&self, older_than_secs: Option<i64>) -> Result<usize> {
+        let (condition, values) = match older_than_secs {
+            Some(secs) => (
+                "deleted_at < ?1",
+                vec![Value::Integer(
+                    Utc::now()
+                        .timestamp_millis()
+                        .saturating_sub(secs.saturating_mul(1000)),
+                )],
+            ),
+            None => ("1", Vec::new()),
+        };
+        self.archive_from("deleted_tasks", condition, &values)?;
+        let purged = self.conn.execute(
+            &format!("DELETE FROM deleted_tasks WHERE {}", condition),
+            params_from_iter(values.iter()),
+        )?;
+        self.prune_events()?;
+
+        Ok(purged)
+    }
+
+    /// Drop history rows whose task is gone from both the table and the trash
+    fn prune_events(&self) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM task_events WHERE task_id NOT IN (SELECT task_id FROM tasks)
+             AND task_id NOT IN (SELECT task_id FROM deleted_tasks)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Move tasks whose status is one of `statuses` and, when given, that
+    /// were last updated strictly before `updated_before` to the trash.
+    /// Returns the number of tasks removed.
+    pub fn delete_tasks(
+        &self,
+        statuses: &[TaskStatus],
+        updated_before: Option<DateTime<Utc>>,
+    ) -> Result<usize> {
+        if statuses.is_empty() {
+            return Ok(0);
+        }
+
+        let mut values: Vec<Value> = Vec::new();
+        let mut placeholders = Vec::new();
+        for status in statuses {
+            values.push(Value::Text(status.as_str().to_string()));
+            placeholders.push(format!("?{}", values.len()));
+        }
+
+        let mut condition = format!("status IN ({})", placeholders.join(", "));
+        if let Some(cutoff) = updated_before {
+            values.push(Value::Integer(cutoff.timestamp_millis()));
+            condition.push_str(&format!(" AND updated_at < ?{}", values.len()));
+        }
+
+        self.trash_where(&condition, &values)
+    }
+
+    /// Completed and exited tasks that share agent, title and project with a
+    /// more recently updated one, newest first. `prune_duplicates` removes
+    /// exactly these.
+    pub fn duplicate_tasks(&self) -> Result<Vec<Task>> {
+        let query = format!(
+            "SELECT {} FROM tasks WHERE {} ORDER BY updated_at DESC",
+            TASK_COLUMNS, DUPLICATES_CONDITION
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let tasks = stmt
+            .query_map([], |row| self.row_to_task(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tasks)
+    }
+
+    /// Move every task `duplicate_tasks` returns to the trash. Returns the
+    /// number of tasks removed.
+    pub fn prune_duplicates(&self) -> Result<usize> {
+        self.trash_where(DUPLICATES_CONDITION, &[])
+    }
+
+    /// Move every task flagged for attention to the trash, leaving chats
+    /// that are only ready for a reply. Returns the number of tasks removed.
+    pub fn delete_flagged(&self) -> Result<usize> {
+        let ready = AttentionReason::ReadyForReply.to_column();
+        self.trash_where(
+            "attention_reason IS NOT NULL AND attention_reason != ?1",
+            &[Value::Text(ready)],
+        )
+    }
+
+    /// Delete the least recently updated completed and exited tasks until at
+    /// most `max` remain. Running and paused tasks are never evicted, so the
+    /// table can stay above the cap if they alone exceed it. Returns the
+    /// number of tasks removed.
+    pub fn enforce_max_tasks(&self, max: usize) -> Result<usize> {
+        let total: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM tasks", [], |row| row.get(0))?;
+        let excess = total - max as i64;
+        if excess <= 0 {
+            return Ok(0);
+        }
+
+        let condition = "id IN (
+            SELECT id FROM tasks WHERE status IN ('completed', 'exited')
+            ORDER BY updated_at ASC, id ASC LIMIT ?1
+        )";
+        let values = [Value::Integer(excess)];
+        self.archive_where(condition, &values)?;
+        let affected = self.conn.execute(
+            &format!("DELETE FROM tasks WHERE {}", condition),
+            params_from_iter(values.iter()),
+        )?;
+        self.prune_events()?;
+
+        Ok(affected)
+    }
+
+    /// Delete tasks that have been in a status longer than its retention
+    /// (seconds) in `retention`, counting from when they finished, or from
+    /// their last update for tasks that never did. Statuses missing from
+    /// `retention` are kept. Chats waiting for a reply have finished their
+    /// turn and go with the completed ones. Ages are taken at `now`. Returns
+    /// the number of tasks removed.
+    pub fn cleanup(
+        &self,
+        retention: &HashMap<TaskStatus, i64>,
+        now: DateTime<Utc>,
+    ) -> Result<usize> {
+        let now = now.timestamp_millis();
+
+        let condition = "(status = ?1
+                OR (?1 = 'completed' AND status = 'running' AND attention_reason = ?3))
+            AND COALESCE(completed_at, updated_at) < ?2";
+        let ready = AttentionReason::ReadyForReply.to_column();
+        let mut affected = 0;
+        for (status, older_than_secs) in retention {
+            let values = [
+                Value::Text(status.as_str().to_string()),
+                Value::Integer(now.saturating_sub(older_than_secs.saturating_mul(1000))),
+                Value::Text(ready.clone()),
+            ];
+            self.archive_where(condition, &values)?;
+            affected += self.conn.execute(
+                &format!("DELETE FROM tasks WHERE {}", condition),
+                params_from_iter(values.iter()),
+            )?;
+        }
+        self.prune_events()?;
+
+        Ok(affected)
+    }
+
+    /// Remove every task whose `expires_at` has passed by `now`, whatever
+    /// its status. They go to the archive, like retention cleanup, not the
+    /// trash.
+    pub fn expire_tasks(&self, now: DateTime<Utc>) -> Result<usize> {
+        let condition = "expires_at IS NOT NULL AND expires_at <= ?1";
+        let values = [Value::Integer(now.timestamp_millis())];
+        self.transaction(|db| {
+            db.archive_where(condition, &values)?;
+            let expired = db.conn.execute(
+                &format!("DELETE FROM tasks WHERE {}", condition),
+                params_from_iter(values.iter()),
+            )?;
+            db.prune_events()?;
+            Ok(expired)
+        })
+    }
+
+    fn row_to_task(&self, row: &rusqlite::Row) -> rusqlite::Result<Task> {
+        let created_ts: i64 = row.get(5)?;
+        let updated_ts: i64 = row.get(6)?;
+        let completed_ts: Option<i64> = row.get(7)?;
+
+        let task_id: String = row.get(1)?;
+        let (context, raw_context) =
+            parse_json_column::<TaskContext>(&task_id, "context", row.get(13)?);
+        let (metadata, raw_metadata) = parse_json_column::<HashMap<String, serde_json::Value>>(
+            &task_id,
+            "metadata",
+            row.get(14)?,
+        );
+
+        let status_str: String = row.get(4)?;
+        let status = TaskStatus::from_str(&status_str).map_err(|e| {
+            rusqlite::Error::ToSqlConversionFailure(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                e,
+            )))
+        })?;
+
+        Ok(Task {
+            id: Some(row.get(0)?),
+            task_id,
+            agent_type: row.get(2)?,
+            title: row.get(3)?,
+            status,
+            created_at: Utc.timestamp_millis_opt(created_ts).unwrap(),
+            updated_at: Utc.timestamp_millis_opt(updated_ts).unwrap(),
+            completed_at: completed_ts.map(|ts| Utc.timestamp_millis_opt(ts).unwrap()),
+            pid: row.get(8)?,
+            ppid: row.get(9)?,
+            monitor_pid: row.get(10)?,
+            attention_reason: row
+                .get::<_, Option<String>>(11)?
+                .map(|s| AttentionReason::from_column(&s)),
+            exit_code: row.get(12)?,
+            context,
+            metadata,
+            dedup_key: row.get(15)?,
+            last_heartbeat: row
+                .get::<_, Option<i64>>(16)?
+                .map(|ts| Utc.timestamp_millis_opt(ts).unwrap()),
+            expires_at: row
+                .get::<_, Option<i64>>(17)?
+                .map(|ts| Utc.timestamp_millis_opt(ts).unwrap()),
+            attention_since: row
+                .get::<_, Option<i64>>(18)?
+                .map(|ts| Utc.timestamp_millis_opt(ts).unwrap()),
+            source: None,
+            raw_context,
+            raw_metadata,
+        })
+    }
+
+    /// Rows whose context or metadata isn't valid JSON for its type, with
+    /// the column and the parse error
+    pub fn corrupt_rows(&self) -> Result<Vec<CorruptRow>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT task_id, context, metadata FROM tasks ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+
+        let mut corrupt = Vec::new();
+        for row in rows {
+            let (task_id, context, metadata) = row?;
+            let errors = [
+                (
+                    "context",
+                    context.and_then(|s| json_error::<TaskContext>(&s)),
+                ),
+                (
+                    "metadata",
+                    metadata.and_then(|s| json_error::<HashMap<String, serde_json::Value>>(&s)),
+                ),
+            ];
+            for (column, error) in errors {
+                if let Some(error) = error {
+                    corrupt.push(CorruptRow {
+                        task_id: task_id.clone(),
+                        column,
+                        error,
+                    });
+                }
+            }
+        }
+        Ok(corrupt)
+    }
+}
+
+/// `task`'s context and metadata as stored: serialized, or the raw text read
+/// back when it didn't parse, so saving doesn't erase it
+fn json_columns(task: &Task) -> Result<(Option<String>, Option<String>)> {
+    let context = match &task.context {
+        Some(context) => Some(serde_json::to_string(context)?),
+        None => task.raw_context.clone(),
+    };
+    let metadata = match &task.metadata {
+        Some(metadata) => Some(serde_json::to_string(metadata)?),
+        None => task.raw_metadata.clone(),
+    };
+    Ok((context, metadata))
+}
+
+/// A task whose stored JSON doesn't parse, found by `Database::corrupt_rows`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRow {
+    pub task_id: String,
+    /// `context` or `metadata`
+    pub column: &'static str,
+    pub error: String,
+}
+
+fn json_error<T: serde::de::DeserializeOwned>(json: &str) -> Option<String> {
+    serde_json::from_str::<T>(json).err().map(|e| e.to_string())
+}
+
+/// Parse a JSON column of `task_id`. On failure the value is returned raw
+/// (second element) and a warning logged, rather than dropped silently.
+fn parse_json_column<T: serde::de::DeserializeOwned>(
+    task_id: &str,
+    column: &str,
+    json: Option<String>,
+) -> (Option<T>, Option<String>) {
+    let Some(json) = json else {
+        return (None, None);
+    };
+    match serde_json::from_str(&json) {
+        Ok(value) => (Some(value), None),
+        Err(e) => {
+            tracing::warn!(task_id, column, error = %e, "Unparseable JSON column, kept raw");
+            (None, Some(json))
+        }
+    }
+}
+
+/// `WHERE` clause (empty when unfiltered) and its parameters for the status,
+/// agent, project and exit code filters shared by `count`,
+/// `list_tasks_filtered` and `list_tasks_sorted`
+fn filter_clause(
+    status: Option<&TaskStatus>,
+    agent: Option<&str>,
+    project: Option<&str>,
+    exit_codes: &[RangeInclusive<i32>],
+) -> (String, Vec<Value>) {
+    let mut clauses = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+
+    if let Some(status) = status {
+        values.push(Value::Text(status.as_str().to_string()));
+        clauses.push(format!("status = ?{}", values.len()));
+    }
+    if let Some(agent) = agent {
+        values.push(Value::Text(agent.to_string()));
+        clauses.push(format!("agent_type = ?{}", values.len()));
+    }
+    if let Some(project) = project.map(normalize_project_path) {
+        // The project itself or anything below it, but not `/repo2` for `/repo`
+        let below = match project {
+            "/" => "/%".to_string(),
+            _ => format!("{}/%", escape_like(project)),
+        };
+        values.push(Value::Text(project.to_string()));
+        values.push(Value::Text(below));
+        clauses.push(format!(
+            "(project_path = ?{} OR project_path LIKE ?{} ESCAPE '\\')",
+            values.len() - 1,
+            values.len()
+        ));
+    }
+    if !exit_codes.is_empty() {
+        // Single codes go in one IN list, ranges each get a BETWEEN
+        let mut alternatives = Vec::new();
+        let mut singles = Vec::new();
+        for range in exit_codes {
+            values.push(Value::Integer(i64::from(*range.start())));
+            if range.start() == range.end() {
+                singles.push(format!("?{}", values.len()));
+            } else {
+                values.push(Value::Integer(i64::from(*range.end())));
+                alternatives.push(format!(
+                    "exit_code BETWEEN ?{} AND ?{}",
+                    values.len() - 1,
+                    values.len()
+                ));
+            }
+        }
+        if !singles.is_empty() {
+            alternatives.insert(0, format!("exit_code IN ({})", singles.join(", ")));
+        }
+        clauses.push(format!("({})", alternatives.join(" OR ")));
+    }
+
+    if clauses.is_empty() {
+        (String::new(), values)
+    } else {
+        (format!(" WHERE {}", clauses.join(" AND ")), values)
+    }
+}
+
+/// Number of searchable fields containing `needle`, which must already be
+/// lowercase
+fn matched_fields(task: &Task, needle: &str) -> usize {
+    let reason = task.attention_reason.as_ref().map(|r| r.to_string());
+    let context = task
+        .context
+        .as_ref()
+        .and_then(|c| serde_json::to_string(c).ok());
+    let metadata = task
+        .metadata
+        .as_ref()
+        .and_then(|m| serde_json::to_string(m).ok());
+    let fields = [
+        Some(task.title.as_str()),
+        Some(task.agent_type.as_str()),
+        reason.as_deref(),
+        context.as_deref(),
+        metadata.as_deref(),
+    ];
+
+    fields
+        .iter()
+        .flatten()
+        .filter(|field| field.to_lowercase().contains(needle))
+        .count()
+}
+
+/// Sort key for relevance: more matching fields first, then an earlier match
+/// in the title
+fn search_rank(task: &Task, needle: &str) -> (std::cmp::Reverse<usize>, usize) {
+    let title_pos = task.title.to_lowercase().find(needle).unwrap_or(usize::MAX);
+    (std::cmp::Reverse(matched_fields(task, needle)), title_pos)
+}
+
+/// Escape `%`, `_` and `\` for a LIKE pattern using `ESCAPE '\'`
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Active tasks by how much they want the user, for `agent-inbox prompt`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PromptCounts {
+    /// Running and not flagged
+    pub running: u64,
+    /// Flagged for something that needs the user to step in
+    pub attention: u64,
+    /// Flagged only because a chat turn finished
+    pub ready: u64,
+}
+
+/// Count the running tasks in the database at `path` with a single grouped
+/// query, for shell prompts. Opens read-only and sets nothing up: a missing
+/// database counts as empty rather than being created.
+pub fn prompt_counts(path: &Path) -> Result<PromptCounts> {
+    if !path.exists() {
+        return Ok(PromptCounts::default());
+    }
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .context("Failed to open database read-only")?;
+
+    let mut stmt = conn.prepare(
+        "SELECT CASE WHEN attention_reason IS NULL THEN 0
+                     WHEN attention_reason = ?2 THEN 1 ELSE 2 END AS flag,
+                COUNT(*)
+         FROM tasks WHERE status = ?1 GROUP BY flag",
+    )?;
+    let ready = AttentionReason::ReadyForReply.to_column();
+    let rows = stmt.query_map(params![TaskStatus::Running.as_str(), ready], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, u64>(1)?))
+    })?;
+
+    let mut counts = PromptCounts::default();
+    for row in rows {
+        match row? {
+            (0, n) => counts.running = n,
+            (1, n) => counts.ready = n,
+            (_, n) => counts.attention = n,
+        }
+    }
+    Ok(counts)
+}
+
+/// Rows returned by `query_read_only`, values in column order
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+impl QueryResult {
+    /// Rows as objects keyed by column name
+    pub fn records(&self) -> Vec<serde_json::Map<String, serde_json::Value>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .cloned()
+                    .zip(row.iter().cloned())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Tasks from several databases in one list, most recently updated first,
+/// each tagged with the path it was read from. Every database is opened
+/// read-only, so other users' inboxes are never locked or migrated.
+pub fn list_merged(
+    paths: &[PathBuf],
+    status_filter: Option<TaskStatus>,
+    project: Option<&str>,
+    exit_codes: &[RangeInclusive<i32>],
+) -> Result<Vec<Task>> {
+    let mut merged = Vec::new();
+    for path in paths {
+        let tasks = Database::open_read_only(path)
+            .and_then(|db| {
+                db.check_schema_version()?;
+                let status = status_filter.as_ref();
+                db.list_tasks_sorted(status, project, exit_codes, TaskSort::Updated, false)
+            })
+            .with_context(|| format!("Failed to read tasks from {}", path.display()))?;
+        let source = path.display().to_string();
+        merged.extend(tasks.into_iter().map(|mut task| {
+            task.source = Some(source.clone());
+            task
+        }));
+    }
+
+    merged.sort_by_key(|task| std::cmp::Reverse(task.updated_at));
+    Ok(merged)
+}
+
+/// Run one ad-hoc SQL statement against the database at `path`. It gets its
+/// own connection opened read-only with `query_only` set, so a stray UPDATE
+/// or DROP fails instead of touching the data.
+pub fn query_read_only(path: &Path, sql: &str) -> Result<QueryResult> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .context("Failed to open database read-only")?;
+    conn.pragma_update(None, "query_only", true)?;
+
+    let mut batch = rusqlite::Batch::new(&conn, sql);
+    let mut stmt = batch
+        .next()
+        .context("Invalid query")?
+        .context("Empty query")?;
+    if batch.next().context("Invalid query")?.is_some() {
+        anyhow::bail!("Only one statement can be run at a time");
+    }
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let mut rows = Vec::new();
+    let mut cursor = stmt.query([]).context("Query failed")?;
+    while let Some(row) = cursor.next().context("Query failed")? {
+        let values = (0..columns.len())
+            .map(|i| Ok(json_value(row.get_ref(i)?)))
+            .collect::<Result<Vec<_>>>()?;
+        rows.push(values);
+    }
+
+    Ok(QueryResult { columns, rows })
+}
+
+fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()).into(),
+    }
+}
+
+/// Attempts made by `retry_busy` before the error is returned
+const BUSY_ATTEMPTS: u32 = 6;
+/// First backoff delay; doubles on every retry (50ms .. 1.6s)
+const BUSY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Run `f`, retrying with exponential backoff while it fails because another
+/// process holds the database lock. Other errors are returned immediately.
+pub fn retry_busy<T>(mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = BUSY_BACKOFF;
+    for _ in 1..BUSY_ATTEMPTS {
+        match f() {
+            Err(e) if is_busy(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    f()
+}
+
+/// Whether `err` is SQLite reporting a locked or busy database
+pub fn is_busy(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<rusqlite::Error>(),
+            Some(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
+/// Directory holding the database, config and logs: `~/.agent-tasks`
+pub fn data_dir() -> PathBuf {
+    data_dir_from(std::env::var_os("HOME").as_deref())
+}
+
+/// Resolve the data directory for a given HOME. Minimal containers and CI
+/// jobs may have no HOME at all; fall back to a per-user temp directory
+/// rather than refusing to run.
+pub fn data_dir_from(home: Option<&OsStr>) -> PathBuf {
+    match home {
+        Some(home) if !home.is_empty() => PathBuf::from(home).join(".agent-tasks"),
+        _ => temp_data_dir(),
+    }
+}
+
+/// `<tmp>/agent-tasks-<uid>`: the temp dir is shared by every user, so each
+/// gets their own
+fn temp_data_dir() -> PathBuf {
+    #[cfg(unix)]
+    let name = format!("agent-tasks-{}", unsafe { libc::getuid() });
+    #[cfg(not(unix))]
+    let name = "agent-tasks".to_string();
+    std::env::temp_dir().join(name)
+}
+
+pub fn default_db_path() -> PathBuf {
+    data_dir().join("tasks.db")
+}
+
+pub fn ensure_data_dir() -> Result<PathBuf> {
+    let data_dir = data_dir();
+
+    if data_dir == temp_data_dir() {
+        ensure_private_dir(&data_dir)?;
+    } else if !data_dir.exists() {
+        std::fs::create_dir_all(&data_dir).context("Failed to create data directory")?;
+    }
+
+    Ok(data_dir)
+}
+
+/// Create `dir` accessible to this user only, or make sure the one already
+/// there is a real directory of theirs. Anyone can create names in the temp
+/// dir, so another user could otherwise plant one (or a symlink) to read or
+/// forge the tasks kept in it.
+fn ensure_private_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+        match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                return Err(e).context("Failed to create data directory");
+            }
+            _ => {}
+        }
+        let meta = std::fs::symlink_metadata(dir)
+            .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+        if !meta.is_dir() || meta.uid() != unsafe { libc::getuid() } {
+            anyhow::bail!(
+                "Refusing to use {}: it is not a directory owned by you (set HOME instead)",
+                dir.display()
+            );
+        }
+        if meta.mode() & 0o077 != 0 {
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+                .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+        }
+    }
+    #[cfg(not(unix))]
+    std::fs::create_dir_all(dir).context("Failed to create data directory")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn busy_error() -> anyhow::Error {
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        )
+        .into()
+    }
+
+    #[test]
+    fn test_retry_busy_survives_transient_lock() {
+        let mut calls = 0;
+        let result = retry_busy(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(busy_error())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_retry_busy_gives_up_and_skips_other_errors() {
+        let mut calls = 0;
+        let result: Result<()> = retry_busy(|| {
+            calls += 1;
+            Err(anyhow::anyhow!("not a lock"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: Result<()> = retry_busy(|| {
+            calls += 1;
+            Err(busy_error().context("Failed to update task"))
+        });
+        assert!(is_busy(&result.unwrap_err()));
+        assert_eq!(calls, BUSY_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_data_dir_without_home() {
+        // No HOME (or an empty one) must not panic
+        let dir = data_dir_from(None);
+        assert_eq!(dir, temp_data_dir());
+        assert!(dir.starts_with(std::env::temp_dir()));
+        assert_ne!(dir, std::env::temp_dir().join("agent-tasks"));
+        assert_eq!(data_dir_from(Some(OsStr::new(""))), dir);
+
+        assert_eq!(
+            data_dir_from(Some(OsStr::new("/home/me"))),
+            PathBuf::from("/home/me/.agent-tasks")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempfile::tempdir().unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let dir = temp.path().join("agent-tasks-1000");
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        // An existing one left open to others is closed again
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+
+        // Planted by someone else as a link to their own directory
+        let planted = temp.path().join("planted");
+        std::os::unix::fs::symlink(&dir, &planted).unwrap();
+        let err = ensure_private_dir(&planted).unwrap_err();
+        assert!(err.to_string().contains("Refusing to use"), "{:#}", err);
+    }
+
+    fn create_test_db() -> (Database, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::open(temp_file.path()).unwrap();
+        (db, temp_file)
+    }
+
+    #[test]
+    fn test_database_creation() {
+        let (_db, _temp) = create_test_db();
+        // If we got here, database was created successfully
+    }
+
+    #[test]
     fn test_insert_and_retrieve_task() {
         let (db, _temp) = create_test_db();
 
@@ -350,6 +2094,81 @@ mod tests {
         assert_eq!(retrieved.status, TaskStatus::Completed);
     }
 
+    #[test]
+    fn test_upsert_task() {
+        let (db, _temp) = create_test_db();
+
+        let mut task = Task::new(
+            "test-123".to_string(),
+            "claude_web".to_string(),
+            "First prompt".to_string(),
+            None,
+            None,
+        );
+        task.metadata = Some(HashMap::from([(
+            "ticket".to_string(),
+            serde_json::json!(7),
+        )]));
+        task.created_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        db.upsert_task(&task).unwrap();
+
+        let mut follow_up = Task::new(
+            "test-123".to_string(),
+            "claude_web".to_string(),
+            "Second prompt".to_string(),
+            None,
+            None,
+        );
+        follow_up.complete();
+        db.upsert_task(&follow_up).unwrap();
+
+        let tasks = db.list_tasks(None).unwrap();
+        assert_eq!(tasks.len(), 1);
+        let stored = &tasks[0];
+        assert_eq!(stored.title, "Second prompt");
+        assert_eq!(stored.status, TaskStatus::Completed);
+        assert_eq!(stored.created_at, task.created_at);
+        // Unset descriptive fields don't clobber stored ones
+        assert_eq!(
+            stored.metadata.as_ref().unwrap()["ticket"],
+            serde_json::json!(7)
+        );
+    }
+
+    #[test]
+    fn test_concurrent_upserts_create_one_row() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let writers: Vec<Database> = (0..4)
+            .map(|_| Database::open(temp_file.path()).unwrap())
+            .collect();
+
+        let handles: Vec<_> = writers
+            .into_iter()
+            .enumerate()
+            .map(|(i, db)| {
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        let task = Task::new(
+                            "shared-task".to_string(),
+                            "claude_web".to_string(),
+                            format!("Writer {}", i),
+                            None,
+                            None,
+                        );
+                        db.upsert_task(&task).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let db = Database::open(temp_file.path()).unwrap();
+        assert_eq!(db.count(None, None).unwrap(), 1);
+    }
+
     #[test]
     fn test_list_tasks() {
         let (db, _temp) = create_test_db();
@@ -386,18 +2205,104 @@ mod tests {
     }
 
     #[test]
-    fn test_delete_task() {
+    fn test_list_tasks_paged() {
         let (db, _temp) = create_test_db();
 
-        let task = Task::new(
-            "test-123".to_string(),
-            "claude_code".to_string(),
-            "Test task".to_string(),
-            None,
-            None,
-        );
-
-        db.insert_task(&task).unwrap();
+        for i in 0..5 {
+            let mut task = Task::new(
+                format!("test-{}", i),
+                "claude_code".to_string(),
+                format!("Task {}", i),
+                None,
+                None,
+            );
+            task.updated_at = Utc.timestamp_opt(1_700_000_000 + i, 0).unwrap();
+            if i % 2 == 0 {
+                task.complete();
+                task.updated_at = Utc.timestamp_opt(1_700_000_000 + i, 0).unwrap();
+            }
+            db.insert_task(&task).unwrap();
+        }
+
+        let ids = |tasks: Vec<Task>| tasks.into_iter().map(|t| t.task_id).collect::<Vec<_>>();
+
+        assert_eq!(
+            ids(db.list_tasks_paged(None, 3, 0).unwrap()),
+            vec!["test-4", "test-3", "test-2"]
+        );
+        assert_eq!(
+            ids(db.list_tasks_paged(None, 3, 3).unwrap()),
+            vec!["test-1", "test-0"]
+        );
+        assert_eq!(
+            ids(db
+                .list_tasks_paged(Some(TaskStatus::Running), 5, 0)
+                .unwrap()),
+            vec!["test-3", "test-1"]
+        );
+    }
+
+    #[test]
+    fn test_count_matches_list() {
+        let (db, _temp) = create_test_db();
+
+        let task1 = Task::new(
+            "test-1".to_string(),
+            "claude_code".to_string(),
+            "Task 1".to_string(),
+            None,
+            None,
+        );
+        let mut task2 = Task::new(
+            "test-2".to_string(),
+            "opencode".to_string(),
+            "Task 2".to_string(),
+            None,
+            None,
+        );
+        task2.complete();
+        let task3 = Task::new(
+            "test-3".to_string(),
+            "opencode".to_string(),
+            "Task 3".to_string(),
+            None,
+            None,
+        );
+
+        db.insert_task(&task1).unwrap();
+        db.insert_task(&task2).unwrap();
+        db.insert_task(&task3).unwrap();
+
+        assert_eq!(
+            db.count(None, None).unwrap(),
+            db.list_tasks(None).unwrap().len()
+        );
+        assert_eq!(
+            db.count(Some(TaskStatus::Running), None).unwrap(),
+            db.list_tasks(Some(TaskStatus::Running)).unwrap().len()
+        );
+        assert_eq!(db.count(None, Some("opencode")).unwrap(), 2);
+        assert_eq!(
+            db.count(Some(TaskStatus::Running), Some("opencode"))
+                .unwrap(),
+            1
+        );
+        assert_eq!(db.count(Some(TaskStatus::Exited), None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_delete_task() {
+        let (db, _temp) = create_test_db();
+
+        let task = Task::new(
+            "test-123".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+
+        db.insert_task(&task).unwrap();
 
         let deleted = db.delete_task("test-123").unwrap();
         assert!(deleted);
@@ -406,6 +2311,680 @@ mod tests {
         assert!(retrieved.is_none());
     }
 
+    #[test]
+    fn test_delete_then_undo_round_trip() {
+        let (db, _temp) = create_test_db();
+
+        let mut task = Task::new(
+            "keep-me".to_string(),
+            "claude_code".to_string(),
+            "Still needed".to_string(),
+            None,
+            None,
+        );
+        task.set_metadata("ticket".to_string(), serde_json::json!("PROJ-1"));
+        db.insert_task(&task).unwrap();
+        task.complete();
+        db.update_task(&task).unwrap();
+        let before = db.get_task_by_id("keep-me").unwrap().unwrap();
+
+        db.transaction(|db| db.delete_task("keep-me")).unwrap();
+        assert!(db.get_task_by_id("keep-me").unwrap().is_none());
+        let trash = db.list_trash().unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].task.task_id, "keep-me");
+
+        let restored = db.restore_last_deleted().unwrap().unwrap();
+        assert_eq!(restored.id, before.id);
+        assert_eq!(restored.status, TaskStatus::Completed);
+        assert_eq!(restored.metadata, before.metadata);
+        assert_eq!(restored.completed_at, before.completed_at);
+        // History survives the trip through the trash
+        assert_eq!(db.task_history("keep-me").unwrap().len(), 2);
+
+        assert!(db.list_trash().unwrap().is_empty());
+        assert!(db.restore_last_deleted().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_undo_restores_most_recent_first() {
+        let (db, _temp) = create_test_db();
+        for id in ["first", "second"] {
+            let task = Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                id.to_string(),
+                None,
+                None,
+            );
+            db.insert_task(&task).unwrap();
+            db.delete_task(id).unwrap();
+        }
+
+        assert_eq!(
+            db.restore_last_deleted().unwrap().unwrap().task_id,
+            "second"
+        );
+        assert_eq!(db.restore_last_deleted().unwrap().unwrap().task_id, "first");
+    }
+
+    #[test]
+    fn test_undo_refuses_to_overwrite_a_recreated_task() {
+        let (db, _temp) = create_test_db();
+        let task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Old".to_string(),
+            None,
+            None,
+        );
+        db.insert_task(&task).unwrap();
+        db.delete_task("t1").unwrap();
+        db.insert_task(&task).unwrap();
+
+        assert!(db.restore_last_deleted().is_err());
+        assert_eq!(db.list_trash().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_purge_trash() {
+        let (db, _temp) = create_test_db();
+        let task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Gone".to_string(),
+            None,
+            None,
+        );
+        db.insert_task(&task).unwrap();
+        db.delete_task("t1").unwrap();
+
+        // Deleted just now: inside any retention
+        assert_eq!(db.purge_trash(Some(3600)).unwrap(), 0);
+        assert_eq!(db.purge_trash(None).unwrap(), 1);
+        assert!(db.list_trash().unwrap().is_empty());
+        assert!(db.task_history("t1").unwrap().is_empty());
+    }
+
+    fn in_project(id: &str, project_path: Option<&str>) -> Task {
+        let mut task = Task::new(
+            id.to_string(),
+            "claude_code".to_string(),
+            id.to_string(),
+            None,
+            None,
+        );
+        task.context = project_path.map(|path| TaskContext {
+            url: None,
+            project_path: Some(path.to_string()),
+            session_id: None,
+            extra: HashMap::new(),
+        });
+        task
+    }
+
+    #[test]
+    fn test_list_tasks_sorted() {
+        let (db, _temp) = create_test_db();
+        let now = Utc::now();
+        let minutes = chrono::Duration::minutes;
+        // (id, title, created, updated, completed) in minutes ago
+        for (id, title, created, updated, completed) in [
+            ("a", "beta", 30, 5, Some(20)),
+            ("b", "Alpha", 60, 10, None),
+            ("c", "gamma", 10, 1, Some(9)),
+        ] {
+            let mut task = in_project(id, Some("/repo"));
+            task.title = title.to_string();
+            task.created_at = now - minutes(created);
+            task.updated_at = now - minutes(updated);
+            task.completed_at = completed.map(|ago| now - minutes(ago));
+            db.insert_task(&task).unwrap();
+        }
+
+        let ids = |sort: TaskSort, reverse: bool| -> Vec<String> {
+            let tasks = db
+                .list_tasks_sorted(None, None, &[], sort, reverse)
+                .unwrap();
+            tasks.into_iter().map(|t| t.task_id).collect()
+        };
+        assert_eq!(ids(TaskSort::Updated, false), ["c", "a", "b"]);
+        assert_eq!(ids(TaskSort::Created, false), ["c", "a", "b"]);
+        assert_eq!(ids(TaskSort::Created, true), ["b", "a", "c"]);
+        // Case doesn't matter for titles
+        assert_eq!(ids(TaskSort::Title, false), ["b", "a", "c"]);
+        assert_eq!(ids(TaskSort::Title, true), ["c", "a", "b"]);
+        // b still runs (60m), a ran 10m, c 1m
+        assert_eq!(ids(TaskSort::Duration, false), ["b", "a", "c"]);
+        assert_eq!(ids(TaskSort::Duration, true), ["c", "a", "b"]);
+
+        // Sorting loaded tasks agrees with the database
+        for sort in [TaskSort::Updated, TaskSort::Created, TaskSort::Title] {
+            let mut tasks = db.list_tasks(None).unwrap();
+            sort_tasks(&mut tasks, sort, false, now);
+            let sorted: Vec<String> = tasks.into_iter().map(|t| t.task_id).collect();
+            assert_eq!(sorted, ids(sort, false), "{:?}", sort);
+        }
+        let other = db.list_tasks_sorted(None, Some("/other"), &[], TaskSort::Title, false);
+        assert!(other.unwrap().is_empty());
+        assert!(TaskSort::from_str("size").is_err());
+    }
+
+    #[test]
+    fn test_list_tasks_by_project() {
+        let (db, _temp) = create_test_db();
+        for (id, path) in [
+            ("root", Some("/repo")),
+            ("sub", Some("/repo/crates/core/")),
+            ("sibling", Some("/repository")),
+            ("other", Some("/other/repo")),
+            ("web", None),
+        ] {
+            db.insert_task(&in_project(id, path)).unwrap();
+        }
+
+        let listed = |status: Option<&TaskStatus>, project: &str| {
+            db.list_tasks_sorted(status, Some(project), &[], TaskSort::Updated, false)
+                .unwrap()
+        };
+        let found = |project: &str| {
+            let mut tasks = listed(None, project);
+            tasks.sort_by(|a, b| a.task_id.cmp(&b.task_id));
+            tasks.into_iter().map(|t| t.task_id).collect::<Vec<_>>()
+        };
+        // Subdirectories count, prefixes of a longer name don't
+        assert_eq!(found("/repo"), ["root", "sub"]);
+        // With or without trailing slashes, on either side
+        assert_eq!(found("/repo/"), ["root", "sub"]);
+        assert_eq!(found("/repo/crates/core"), ["sub"]);
+        assert!(found("/rep").is_empty());
+        assert_eq!(found("/").len(), 4);
+
+        // Wildcards in the path are literal
+        db.insert_task(&in_project("odd", Some("/a_b"))).unwrap();
+        assert!(found("/a%").is_empty());
+        assert_eq!(found("/a_b"), ["odd"]);
+
+        let running = Some(&TaskStatus::Running);
+        assert_eq!(listed(running, "/other").len(), 1);
+        assert!(listed(Some(&TaskStatus::Exited), "/repo").is_empty());
+    }
+
+    #[test]
+    fn test_list_tasks_by_exit_code() {
+        let (db, _temp) = create_test_db();
+        for (id, code) in [("ok", 0), ("err", 1), ("killed", 137), ("segv", 139)] {
+            let mut task = in_project(id, Some("/repo"));
+            task.set_exited(Some(code));
+            db.insert_task(&task).unwrap();
+        }
+        db.insert_task(&in_project("running", Some("/repo")))
+            .unwrap();
+
+        let found = |exit_codes: &[RangeInclusive<i32>]| {
+            let tasks = db.list_tasks_sorted(None, None, exit_codes, TaskSort::Title, false);
+            tasks
+                .unwrap()
+                .into_iter()
+                .map(|t| t.task_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(&[137..=137]), ["killed"]);
+        assert_eq!(found(&[0..=0, 137..=137]), ["killed", "ok"]);
+        assert_eq!(found(&[1..=1, 128..=159]), ["err", "killed", "segv"]);
+        assert!(found(&[2..=2]).is_empty());
+        assert_eq!(found(&[]).len(), 5);
+
+        // Combined with the other filters
+        let exited = Some(&TaskStatus::Exited);
+        let in_repo = db.list_tasks_sorted(exited, Some("/repo"), &[0..=1], TaskSort::Title, false);
+        assert_eq!(in_repo.unwrap().len(), 2);
+        let running = Some(&TaskStatus::Running);
+        assert!(db
+            .list_tasks_sorted(running, None, &[137..=137], TaskSort::Updated, false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_heartbeat_is_owned_by_the_monitor() {
+        let (db, _temp) = create_test_db();
+        let mut task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Beating".to_string(),
+            None,
+            None,
+        );
+        task.updated_at = Utc::now() - chrono::Duration::hours(1);
+        db.insert_task(&task).unwrap();
+
+        db.record_heartbeat("t1").unwrap();
+        let beating = db.get_task_by_id("t1").unwrap().unwrap();
+        assert!(beating.last_heartbeat.is_some());
+        assert_eq!(beating.updated_at.timestamp(), task.updated_at.timestamp());
+
+        // Saving a copy read before the beat doesn't roll it back
+        db.update_task(&task).unwrap();
+        let saved = db.get_task_by_id("t1").unwrap().unwrap();
+        assert_eq!(saved.last_heartbeat, beating.last_heartbeat);
+    }
+
+    #[test]
+    fn test_claim_monitor() {
+        let (db, _temp) = create_test_db();
+        let task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Watched".to_string(),
+            None,
+            None,
+        );
+        db.insert_task(&task).unwrap();
+
+        assert!(db.claim_monitor("t1", 100, None).unwrap());
+        assert!(db.claim_monitor("t1", 100, None).unwrap());
+        // Only one of two monitors that both saw 100 dead takes over
+        assert!(db.claim_monitor("t1", 200, Some(100)).unwrap());
+        assert!(!db.claim_monitor("t1", 300, Some(100)).unwrap());
+        assert!(!db.claim_monitor("missing", 300, None).unwrap());
+
+        // Claiming leaves a status written meanwhile alone
+        let mut exited = task.clone();
+        exited.set_exited(Some(0));
+        db.update_task(&exited).unwrap();
+        assert!(db.claim_monitor("t1", 200, None).unwrap());
+        let claimed = db.get_task_by_id("t1").unwrap().unwrap();
+        assert_eq!(claimed.status, TaskStatus::Exited);
+        assert_eq!(claimed.monitor_pid, Some(200));
+
+        db.release_monitor("t1", 300).unwrap();
+        assert_eq!(
+            db.get_task_by_id("t1").unwrap().unwrap().monitor_pid,
+            Some(200)
+        );
+        db.release_monitor("t1", 200).unwrap();
+        assert_eq!(db.get_task_by_id("t1").unwrap().unwrap().monitor_pid, None);
+    }
+
+    #[test]
+    fn test_list_merged() {
+        let (mine, mine_file) = create_test_db();
+        let (theirs, theirs_file) = create_test_db();
+
+        let task = |id: &str| {
+            Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                id.to_string(),
+                None,
+                None,
+            )
+        };
+        let mut old = task("old");
+        old.updated_at = Utc::now() - chrono::Duration::hours(1);
+        mine.insert_task(&old).unwrap();
+        theirs.insert_task(&task("new")).unwrap();
+
+        let paths = [
+            mine_file.path().to_path_buf(),
+            theirs_file.path().to_path_buf(),
+        ];
+        let merged = list_merged(&paths, None, None, &[]).unwrap();
+        assert_eq!(ids(&merged), ["new", "old"]);
+        assert_eq!(
+            merged[0].source.as_deref(),
+            Some(&*theirs_file.path().to_string_lossy())
+        );
+        assert_eq!(
+            merged[1].source.as_deref(),
+            Some(&*mine_file.path().to_string_lossy())
+        );
+
+        assert!(list_merged(&paths, Some(TaskStatus::Exited), None, &[])
+            .unwrap()
+            .is_empty());
+        // Reading never writes to someone else's database
+        assert!(Database::open_read_only(theirs_file.path())
+            .unwrap()
+            .delete_task("new")
+            .is_err());
+
+        // A database on another schema is refused, not misread
+        theirs
+            .conn
+            .execute("UPDATE schema_version SET version = 7", [])
+            .unwrap();
+        let err = format!("{:#}", list_merged(&paths, None, None, &[]).unwrap_err());
+        assert!(
+            err.contains(&*theirs_file.path().to_string_lossy()),
+            "{}",
+            err
+        );
+        assert!(err.contains("older than this agent-inbox's"), "{}", err);
+        theirs
+            .conn
+            .execute("UPDATE schema_version SET version = 99", [])
+            .unwrap();
+        let err = format!("{:#}", list_merged(&paths, None, None, &[]).unwrap_err());
+        assert!(err.contains("upgrade agent-inbox"), "{}", err);
+    }
+
+    #[test]
+    fn test_trash_has_the_task_columns() {
+        let (db, _temp) = create_test_db();
+        let columns = |table: &str| -> Vec<String> {
+            let mut stmt = db
+                .conn
+                .prepare(&format!("PRAGMA table_info({})", table))
+                .unwrap();
+            stmt.query_map([], |row| row.get(1))
+                .unwrap()
+                .map(|c| c.unwrap())
+                .collect()
+        };
+
+        let mut tasks = columns("tasks");
+        let mut trash = columns("deleted_tasks");
+        trash.retain(|c| c != "trash_id" && c != "deleted_at");
+        tasks.sort();
+        trash.sort();
+        assert_eq!(trash, tasks);
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let (db, _temp) = create_test_db();
+
+        let result: Result<()> = db.transaction(|db| {
+            db.insert_task(&Task::new(
+                "test-1".to_string(),
+                "claude_code".to_string(),
+                "Task 1".to_string(),
+                None,
+                None,
+            ))?;
+            anyhow::bail!("boom");
+        });
+        assert!(result.is_err());
+        assert!(db.get_task_by_id("test-1").unwrap().is_none());
+
+        db.transaction(|db| {
+            db.insert_task(&Task::new(
+                "test-2".to_string(),
+                "claude_code".to_string(),
+                "Task 2".to_string(),
+                None,
+                None,
+            ))
+        })
+        .unwrap();
+        assert!(db.get_task_by_id("test-2").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_status_changes_are_recorded() {
+        let (db, _temp) = create_test_db();
+
+        let mut task = Task::new(
+            "test-123".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        db.insert_task(&task).unwrap();
+
+        // Same-status updates aren't events
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        db.update_task(&task).unwrap();
+
+        task.complete();
+        db.update_task(&task).unwrap();
+        task.set_exited(Some(1));
+        db.update_task(&task).unwrap();
+
+        let history = db.task_history("test-123").unwrap();
+        let transitions: Vec<_> = history
+            .iter()
+            .map(|e| (e.from_status.clone(), e.to_status.clone()))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (None, TaskStatus::Running),
+                (Some(TaskStatus::Running), TaskStatus::Completed),
+                (Some(TaskStatus::Completed), TaskStatus::Exited),
+            ]
+        );
+        assert_eq!(
+            history[1].reason.as_deref(),
+            Some("No CPU activity for 10m")
+        );
+
+        // History stays while the task is in the trash, and goes with it
+        db.delete_task("test-123").unwrap();
+        assert_eq!(db.task_history("test-123").unwrap().len(), 3);
+        db.purge_trash(None).unwrap();
+        assert!(db.task_history("test-123").unwrap().is_empty());
+    }
+
+    /// A version 1 database at `path` holding one task, created at 5s
+    fn version_1_database(path: &Path) {
+        let db = Database {
+            conn: Connection::open(path).unwrap(),
+            webhooks: Vec::new(),
+            max_tasks: None,
+            archive: None,
+            throttle: None,
+            held_changes: Mutex::new(None),
+        };
+        db.conn
+            .execute_batch(
+                "CREATE TABLE schema_version (version INTEGER PRIMARY KEY);
+                 INSERT INTO schema_version (version) VALUES (1);",
+            )
+            .unwrap();
+        db.create_schema().unwrap();
+        db.conn
+            .execute(
+                "INSERT INTO tasks (task_id, agent_type, title, status, created_at,
+                     updated_at, context)
+                 VALUES ('old', 'claude_code', 'Old', 'running', 5, 5,
+                     '{\"project_path\":\"/repo/\"}')",
+                [],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_migrates_version_1_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        version_1_database(temp_file.path());
+
+        let db = Database::open(temp_file.path()).unwrap();
+        let version: i32 = db
+            .conn
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        assert!(db.task_history("anything").unwrap().is_empty());
+        // Existing rows get their project column filled in from the context
+        let in_repo = db.list_tasks_sorted(None, Some("/repo"), &[], TaskSort::Updated, false);
+        assert_eq!(in_repo.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_migration_normalizes_project_paths() {
+        let (db, _temp) = create_test_db();
+        db.insert_task(&in_project("root", Some("/"))).unwrap();
+        db.insert_task(&in_project("trashed", Some("/repo/")))
+            .unwrap();
+        db.delete_task("trashed").unwrap();
+        // As the v5 backfill left them
+        db.conn
+            .execute_batch(
+                "UPDATE tasks SET project_path = '';
+                 UPDATE deleted_tasks SET project_path = NULL;
+                 UPDATE schema_version SET version = 9;",
+            )
+            .unwrap();
+        db.transaction(|db| db.migrate(9, MIGRATIONS)).unwrap();
+
+        let listed = |project| {
+            db.list_tasks_sorted(None, Some(project), &[], TaskSort::Updated, false)
+                .unwrap()
+        };
+        assert_eq!(listed("/").len(), 1);
+        db.restore_last_deleted().unwrap();
+        assert_eq!(listed("/repo")[0].task_id, "trashed");
+    }
+
+    #[test]
+    fn test_concurrent_opens_migrate_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.db");
+        version_1_database(&path);
+
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let openers: Vec<_> = (0..2)
+            .map(|_| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    Database::open(&path).map(drop)
+                })
+            })
+            .collect();
+        for opener in openers {
+            opener.join().unwrap().unwrap();
+        }
+
+        // Seconds became milliseconds exactly once
+        let db = Database::open(&path).unwrap();
+        let task = db.get_task_by_id("old").unwrap().unwrap();
+        assert_eq!(task.created_at.timestamp_millis(), 5000);
+        let version: i32 = db
+            .conn
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.db");
+        let db = Database::open(&path).unwrap();
+        db.insert_task(&in_project("t1", None)).unwrap();
+
+        // One good step, then one that fails
+        let mut steps = MIGRATIONS.to_vec();
+        steps.push("CREATE TABLE extra (x INTEGER);");
+        steps.push("ALTER TABLE missing ADD COLUMN y INTEGER;");
+        let err = db
+            .transaction(|db| db.migrate_with_backup(SCHEMA_VERSION, &steps))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("rolled the database back"),
+            "{:#}",
+            err
+        );
+        assert!(dir.path().join("tasks.db.pre-migration.bak").exists());
+
+        let version: i32 = db
+            .conn
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        let extra: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'extra'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(extra, 0);
+        assert!(db.get_task_by_id("t1").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_sub_second_updates_order() {
+        let (db, _temp) = create_test_db();
+        let base = Utc.timestamp_millis_opt(1_700_000_000_100).unwrap();
+
+        // Two turns 300ms apart, inserted newest first
+        let mut second = in_project("second", None);
+        second.updated_at = base + chrono::Duration::milliseconds(300);
+        db.insert_task(&second).unwrap();
+        let mut first = in_project("first", None);
+        first.updated_at = base;
+        db.insert_task(&first).unwrap();
+
+        let tasks = db.list_tasks(None).unwrap();
+        let ids: Vec<&str> = tasks.iter().map(|t| t.task_id.as_str()).collect();
+        assert_eq!(ids, ["second", "first"]);
+        assert_eq!(tasks[1].updated_at, base);
+        assert_eq!(
+            db.latest_task(Latest::Any).unwrap().unwrap().task_id,
+            "second"
+        );
+    }
+
+    #[test]
+    fn test_expire_tasks() {
+        let (db, _temp) = create_test_db();
+        let now = Utc::now();
+
+        // Expired, whatever the status; not yet; never
+        let mut running = in_project("expired-running", None);
+        running.expires_at = Some(now - chrono::Duration::seconds(1));
+        let mut completed = in_project("expired-completed", None);
+        completed.complete();
+        completed.expires_at = Some(now - chrono::Duration::hours(1));
+        let mut later = in_project("later", None);
+        later.expires_at = Some(now + chrono::Duration::hours(1));
+        let never = in_project("never", None);
+        for task in [&running, &completed, &later, &never] {
+            db.insert_task(task).unwrap();
+        }
+        assert_eq!(
+            db.get_task_by_id("later").unwrap().unwrap().expires_at,
+            Some(
+                Utc.timestamp_millis_opt(later.expires_at.unwrap().timestamp_millis())
+                    .unwrap()
+            )
+        );
+
+        assert_eq!(db.task_history("expired-running").unwrap().len(), 1);
+        assert_eq!(db.task_history("later").unwrap().len(), 1);
+
+        assert_eq!(db.expire_tasks(now).unwrap(), 2);
+        // Their history goes with them
+        let events: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM task_events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(events, 2);
+        assert!(db.task_history("expired-running").unwrap().is_empty());
+        let mut left: Vec<String> = db
+            .list_tasks(None)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.task_id)
+            .collect();
+        left.sort();
+        assert_eq!(left, ["later", "never"]);
+
+        // Status retention is separate: a fresh task with no expiry stays
+        assert_eq!(
+            db.expire_tasks(now + chrono::Duration::days(365)).unwrap(),
+            1
+        );
+        assert_eq!(db.list_tasks(None).unwrap()[0].task_id, "never");
+    }
+
     #[test]
     fn test_cleanup_old_completed() {
         let (db, _temp) = create_test_db();
@@ -419,15 +2998,836 @@ mod tests {
         );
 
         // Create a completed task
-        task.complete();
+        let completed = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        task.complete_at(completed);
         db.insert_task(&task).unwrap();
-
-        // Should not delete tasks completed less than 1 second ago
-        let deleted = db.cleanup_old_completed(1).unwrap();
+        let retention = HashMap::from([(TaskStatus::Completed, 60)]);
+
+        // A retention too long to count in milliseconds keeps everything
+        let forever = HashMap::from([(TaskStatus::Completed, i64::MAX)]);
+        assert_eq!(db.cleanup(&forever, Utc::now()).unwrap(), 0);
+        db.delete_task("test-123").unwrap();
+        assert_eq!(db.purge_trash(Some(i64::MAX)).unwrap(), 0);
+        db.restore_last_deleted().unwrap();
+
+        // Should not delete tasks completed within the retention
+        let deleted = db
+            .cleanup(&retention, completed + chrono::Duration::seconds(60))
+            .unwrap();
         assert_eq!(deleted, 0);
 
-        // But should delete if we look back far enough (negative time = future)
-        let deleted = db.cleanup_old_completed(-1).unwrap();
+        // But should delete once it has passed
+        let deleted = db
+            .cleanup(&retention, completed + chrono::Duration::seconds(61))
+            .unwrap();
+        assert_eq!(deleted, 1);
+    }
+
+    #[test]
+    fn test_removed_tasks_are_archived() {
+        let (mut db, temp) = create_test_db();
+        let path = temp.path().with_extension("archive.jsonl");
+        db.set_archive(Archive::new(&path));
+
+        for id in ["old", "cleared", "older"] {
+            let mut task = Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                format!("Task {}", id),
+                None,
+                None,
+            );
+            task.complete();
+            db.insert_task(&task).unwrap();
+        }
+
+        // Negative retention: everything completed counts as old
+        db.delete_task("cleared").unwrap();
+        let retention = HashMap::from([(TaskStatus::Completed, -1)]);
+        assert_eq!(db.cleanup(&retention, Utc::now()).unwrap(), 2);
+        // Cleared tasks go once they leave the trash, not before: undo
+        // could still bring them back
+        let archived = || std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(archived(), 2);
+        db.restore_last_deleted().unwrap();
+        db.delete_task("cleared").unwrap();
+        assert_eq!(db.purge_trash(None).unwrap(), 1);
+        assert_eq!(archived(), 3);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let archived: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let mut ids: Vec<&str> = archived
+            .iter()
+            .map(|entry| entry["task_id"].as_str().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["cleared", "old", "older"]);
+        assert!(archived.iter().all(|entry| entry["status"] == "completed"));
+        assert!(archived
+            .iter()
+            .all(|entry| entry["archived_at"].is_string()));
+
+        let found = Archive::new(&path).search("task older").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].task.task_id, "older");
+    }
+
+    #[test]
+    fn test_cleanup_per_status_retention() {
+        let (db, _temp) = create_test_db();
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let two_hours_ago = now - chrono::Duration::hours(2);
+
+        // Each finished two hours ago
+        for (id, status) in [
+            ("completed", TaskStatus::Completed),
+            ("exited", TaskStatus::Exited),
+            ("running", TaskStatus::Running),
+        ] {
+            let mut task = Task::new_at(
+                id.to_string(),
+                "claude_code".to_string(),
+                "Test task".to_string(),
+                None,
+                None,
+                two_hours_ago,
+            );
+            match status {
+                TaskStatus::Completed => task.complete_at(two_hours_ago),
+                TaskStatus::Exited => task.set_exited_at(Some(1), two_hours_ago),
+                _ => {}
+            }
+            db.insert_task(&task).unwrap();
+        }
+
+        // An hour for completed, a day for exited, running not listed
+        let retention =
+            HashMap::from([(TaskStatus::Completed, 3600), (TaskStatus::Exited, 86_400)]);
+        assert_eq!(db.cleanup(&retention, now).unwrap(), 1);
+        assert!(db.get_task_by_id("completed").unwrap().is_none());
+        assert!(db.get_task_by_id("exited").unwrap().is_some());
+        assert!(db.get_task_by_id("running").unwrap().is_some());
+
+        // Tasks that never finished count from their last update
+        let retention = HashMap::from([(TaskStatus::Running, 3600)]);
+        assert_eq!(db.cleanup(&retention, now).unwrap(), 1);
+        assert!(db.get_task_by_id("running").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cleanup_chats_waiting_for_reply() {
+        let (db, _temp) = create_test_db();
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        for (id, reason, minutes_ago) in [
+            ("old-chat", AttentionReason::ReadyForReply, 120),
+            ("new-chat", AttentionReason::ReadyForReply, 10),
+            (
+                "blocked",
+                AttentionReason::Custom("Approve?".to_string()),
+                120,
+            ),
+        ] {
+            let mut task = in_project(id, None);
+            task.flag_attention_at(reason, now - chrono::Duration::minutes(minutes_ago));
+            db.insert_task(&task).unwrap();
+        }
+
+        // Finished turns go with the completed tasks, blocked ones stay
+        let retention = HashMap::from([(TaskStatus::Completed, 3600)]);
+        assert_eq!(db.cleanup(&retention, now).unwrap(), 1);
+        assert!(db.get_task_by_id("old-chat").unwrap().is_none());
+        assert!(db.get_task_by_id("new-chat").unwrap().is_some());
+        assert!(db.get_task_by_id("blocked").unwrap().is_some());
+        assert_eq!(
+            db.cleanup(&HashMap::from([(TaskStatus::Exited, 0)]), now)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_metadata_survives_restart() {
+        let (db, _temp) = create_test_db();
+
+        let task = Task::new(
+            "test-123".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        db.upsert_task(&task).unwrap();
+
+        let mut stored = db.get_task_by_id("test-123").unwrap().unwrap();
+        stored.set_metadata("ticket".to_string(), serde_json::json!("PROJ-123"));
+        db.update_task(&stored).unwrap();
+
+        // A wrapper re-reporting start must not wipe script-set metadata
+        db.upsert_task(&task).unwrap();
+        let retrieved = db.get_task_by_id("test-123").unwrap().unwrap();
+        assert_eq!(
+            retrieved.metadata.unwrap()["ticket"],
+            serde_json::json!("PROJ-123")
+        );
+    }
+
+    #[test]
+    fn test_dedup_start_reuses_active_task() {
+        let (db, _temp) = create_test_db();
+        let key = crate::models::dedup_key("claude_code", "/repo", "Fix the build");
+
+        let mut first = Task::new(
+            "first".to_string(),
+            "claude_code".to_string(),
+            "Fix the build".to_string(),
+            Some(100),
+            None,
+        );
+        first.dedup_key = Some(key.clone());
+        let mut second = first.clone();
+        second.task_id = "second".to_string();
+        second.pid = Some(200);
+
+        assert_eq!(
+            db.start_task(&first).unwrap(),
+            Started::Created("first".to_string())
+        );
+        assert_eq!(
+            db.start_task(&second).unwrap(),
+            Started::Reused("first".to_string())
+        );
+
+        let tasks = db.list_tasks(None).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].pid, Some(200));
+
+        // Once the first task is done, the same start creates a new one
+        let mut done = db.get_task_by_id("first").unwrap().unwrap();
+        done.set_exited(Some(0));
+        db.update_task(&done).unwrap();
+        assert_eq!(db.start_task(&second).unwrap().task_id(), "second");
+        assert_eq!(db.list_tasks(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_start_existing_task_id() {
+        let (db, _temp) = create_test_db();
+        let mut task = in_project("t1", Some("/repo"));
+        task.pid = Some(100);
+        assert_eq!(
+            db.start_task(&task).unwrap(),
+            Started::Created("t1".to_string())
+        );
+
+        // A second start for the same id updates the row instead of failing
+        let mut again = in_project("t1", None);
+        again.title = "Retried".to_string();
+        assert_eq!(
+            db.start_task(&again).unwrap(),
+            Started::Existing("t1".to_string())
+        );
+
+        let tasks = db.list_tasks(None).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].title, "Retried");
+        // Fields the retry didn't set are kept
+        assert_eq!(tasks[0].pid, Some(100));
+        assert_eq!(tasks[0].project_path(), Some("/repo"));
+    }
+
+    #[test]
+    fn test_corrupt_json_is_kept() {
+        let (db, _temp) = create_test_db();
+        db.insert_task(&in_project("t1", Some("/repo"))).unwrap();
+        db.insert_task(&in_project("t2", None)).unwrap();
+        db.conn
+            .execute(
+                "UPDATE tasks SET context = '{bad', metadata = '[1]' WHERE task_id = 't1'",
+                [],
+            )
+            .unwrap();
+
+        // The row still loads, with the unreadable text kept aside
+        let mut task = db.get_task_by_id("t1").unwrap().unwrap();
+        assert!(task.context.is_none());
+        assert_eq!(task.raw_context.as_deref(), Some("{bad"));
+        assert_eq!(task.raw_metadata.as_deref(), Some("[1]"));
+
+        let corrupt = db.corrupt_rows().unwrap();
+        let found: Vec<_> = corrupt
+            .iter()
+            .map(|r| (r.task_id.as_str(), r.column))
+            .collect();
+        assert_eq!(found, [("t1", "context"), ("t1", "metadata")]);
+
+        // Saving the task doesn't overwrite what couldn't be read
+        task.title = "Renamed".to_string();
+        db.update_task(&task).unwrap();
+        let task = db.get_task_by_id("t1").unwrap().unwrap();
+        assert_eq!(task.title, "Renamed");
+        assert_eq!(task.raw_context.as_deref(), Some("{bad"));
+        assert_eq!(db.corrupt_rows().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_max_tasks_evicts_oldest_finished() {
+        let (mut db, _temp) = create_test_db();
+        db.set_max_tasks(3);
+        let now = Utc::now();
+
+        // The oldest task is still running and must survive eviction
+        for (id, status, age) in [
+            ("running-old", TaskStatus::Running, 50),
+            ("done-1", TaskStatus::Completed, 40),
+            ("exited-2", TaskStatus::Exited, 30),
+            ("done-3", TaskStatus::Completed, 20),
+            ("done-4", TaskStatus::Completed, 10),
+        ] {
+            let mut task = Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                "Test task".to_string(),
+                None,
+                None,
+            );
+            task.status = status;
+            task.updated_at = now - chrono::Duration::minutes(age);
+            db.insert_task(&task).unwrap();
+        }
+
+        let mut ids: Vec<_> = db
+            .list_tasks(None)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.task_id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["done-3", "done-4", "running-old"]);
+        assert!(db.task_history("done-1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_max_tasks_never_evicts_active() {
+        let (db, _temp) = create_test_db();
+        for id in ["a", "b", "c"] {
+            db.insert_task(&Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                "Test task".to_string(),
+                None,
+                None,
+            ))
+            .unwrap();
+        }
+
+        assert_eq!(db.enforce_max_tasks(1).unwrap(), 0);
+        assert_eq!(db.list_tasks(None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_list_tasks_filtered() {
+        let (db, _temp) = create_test_db();
+        for (id, agent, status) in [
+            ("code-running", "claude_code", TaskStatus::Running),
+            ("code-done", "claude_code", TaskStatus::Completed),
+            ("web-running", "claude_web", TaskStatus::Running),
+        ] {
+            let mut task = Task::new(
+                id.to_string(),
+                agent.to_string(),
+                "Test task".to_string(),
+                None,
+                None,
+            );
+            task.status = status;
+            db.insert_task(&task).unwrap();
+        }
+
+        let ids = |tasks: Vec<Task>| {
+            let mut ids: Vec<_> = tasks.into_iter().map(|t| t.task_id).collect();
+            ids.sort();
+            ids
+        };
+        let running = Some(&TaskStatus::Running);
+        assert_eq!(
+            ids(db
+                .list_tasks_filtered(running, Some("claude_code"))
+                .unwrap()),
+            ["code-running"]
+        );
+        assert_eq!(
+            ids(db.list_tasks_filtered(None, Some("claude_code")).unwrap()),
+            ["code-done", "code-running"]
+        );
+        assert_eq!(db.list_tasks_filtered(running, None).unwrap().len(), 2);
+        assert_eq!(db.list_tasks_filtered(None, None).unwrap().len(), 3);
+    }
+
+    fn seed_search_db() -> (Database, NamedTempFile) {
+        let (db, temp) = create_test_db();
+        let now = Utc::now();
+
+        // (task_id, title, project path, minutes since update)
+        for (id, title, path, age) in [
+            ("old-both", "Refactor parser", "/src/parser", 30),
+            ("mid-late", "Fix tests for the parser", "/src/app", 20),
+            ("new-title", "Parser error messages", "/src/app", 10),
+            ("unrelated", "Update README", "/docs", 0),
+        ] {
+            let mut task = Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                title.to_string(),
+                None,
+                None,
+            );
+            task.context = Some(TaskContext {
+                url: None,
+                project_path: Some(path.to_string()),
+                session_id: None,
+                extra: HashMap::new(),
+            });
+            task.updated_at = now - chrono::Duration::minutes(age);
+            db.insert_task(&task).unwrap();
+        }
+
+        (db, temp)
+    }
+
+    fn ids(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|t| t.task_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_sorted_by_updated() {
+        let (db, _temp) = seed_search_db();
+
+        let tasks = db
+            .search_tasks("PARSER", SearchSort::Updated, None)
+            .unwrap();
+        assert_eq!(ids(&tasks), ["new-title", "mid-late", "old-both"]);
+
+        let tasks = db
+            .search_tasks("parser", SearchSort::Updated, Some(2))
+            .unwrap();
+        assert_eq!(ids(&tasks), ["new-title", "mid-late"]);
+    }
+
+    #[test]
+    fn test_search_sorted_by_relevance() {
+        let (db, _temp) = seed_search_db();
+
+        // Title and project path beat title only; among those, an earlier
+        // match in the title wins
+        let tasks = db
+            .search_tasks("parser", SearchSort::Relevance, None)
+            .unwrap();
+        assert_eq!(ids(&tasks), ["old-both", "new-title", "mid-late"]);
+
+        let tasks = db
+            .search_tasks("parser", SearchSort::Relevance, Some(1))
+            .unwrap();
+        assert_eq!(ids(&tasks), ["old-both"]);
+    }
+
+    #[test]
+    fn test_search_escapes_wildcards() {
+        let (db, _temp) = seed_search_db();
+        assert!(db
+            .search_tasks("%", SearchSort::Updated, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_delete_tasks_age_boundary() {
+        let (db, _temp) = create_test_db();
+        let cutoff = Utc
+            .timestamp_opt(Utc::now().timestamp() - 86_400, 0)
+            .unwrap();
+
+        for (id, updated_at) in [
+            ("at-cutoff", cutoff),
+            ("just-older", cutoff - chrono::Duration::seconds(1)),
+            ("newer", cutoff + chrono::Duration::seconds(1)),
+        ] {
+            let mut task = Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                "Test task".to_string(),
+                None,
+                None,
+            );
+            task.set_exited(Some(0));
+            task.updated_at = updated_at;
+            db.insert_task(&task).unwrap();
+        }
+
+        // Only tasks updated strictly before the cutoff go
+        let deleted = db
+            .delete_tasks(&[TaskStatus::Exited], Some(cutoff))
+            .unwrap();
         assert_eq!(deleted, 1);
+        assert!(db.get_task_by_id("just-older").unwrap().is_none());
+        assert!(db.get_task_by_id("at-cutoff").unwrap().is_some());
+        assert!(db.get_task_by_id("newer").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_delete_tasks_by_status() {
+        let (db, _temp) = create_test_db();
+
+        let running = Task::new(
+            "running".to_string(),
+            "claude_code".to_string(),
+            "Running task".to_string(),
+            None,
+            None,
+        );
+        let mut completed = running.clone();
+        completed.task_id = "completed".to_string();
+        completed.complete();
+        db.insert_task(&running).unwrap();
+        db.insert_task(&completed).unwrap();
+
+        assert_eq!(db.delete_tasks(&[TaskStatus::Exited], None).unwrap(), 0);
+        assert_eq!(db.delete_tasks(&[TaskStatus::Completed], None).unwrap(), 1);
+        assert!(db.get_task_by_id("running").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_prune_duplicates() {
+        let (db, _temp) = create_test_db();
+        let base = Utc::now() - chrono::Duration::hours(1);
+        let seed = |id: &str, title: &str, project: Option<&str>, minutes: i64, done: bool| {
+            let mut task = in_project(id, project);
+            task.title = title.to_string();
+            if done {
+                task.set_exited(Some(0));
+            }
+            task.updated_at = base + chrono::Duration::minutes(minutes);
+            db.insert_task(&task).unwrap();
+        };
+
+        // Three finished copies in /repo: only the newest stays
+        seed("fix-1", "Fix build", Some("/repo"), 0, true);
+        seed("fix-2", "Fix build", Some("/repo"), 10, true);
+        seed("fix-3", "Fix build", Some("/repo"), 5, true);
+        // Same title elsewhere, or without a project, is a different set
+        seed("fix-other", "Fix build", Some("/other"), 0, true);
+        seed("fix-none-1", "Fix build", None, 2, true);
+        seed("fix-none-2", "Fix build", None, 3, true);
+        // A running copy is never pruned, nor counted as the one to keep
+        seed("fix-running", "Fix build", Some("/other"), 20, false);
+
+        let duplicates: Vec<String> = db
+            .duplicate_tasks()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.task_id)
+            .collect();
+        assert_eq!(duplicates, ["fix-3", "fix-none-1", "fix-1"]);
+
+        assert_eq!(db.prune_duplicates().unwrap(), 3);
+        let mut left: Vec<String> = db
+            .list_tasks(None)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.task_id)
+            .collect();
+        left.sort();
+        assert_eq!(left, ["fix-2", "fix-none-2", "fix-other", "fix-running"]);
+        assert!(db.duplicate_tasks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_webhooks_wait_for_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let letters = dir.path().join("deadletter.jsonl");
+        let mut db = Database::open(dir.path().join("tasks.db")).unwrap();
+        // Unreachable, so every notification sent lands in the dead letters
+        let retry = crate::webhook::RetryPolicy {
+            attempts: 1,
+            base_delay: Duration::ZERO,
+        };
+        db.add_webhook(
+            Webhook::new("http://127.0.0.1:9/hook".to_string())
+                .with_retry(retry)
+                .with_dead_letter(letters.clone()),
+        );
+        let sent = |db: &Database| {
+            db.webhooks[0].flush();
+            std::fs::read_to_string(&letters).map_or(0, |s| s.lines().count())
+        };
+
+        let result: Result<()> = db.transaction(|db| {
+            db.insert_task(&in_project("t1", None))?;
+            anyhow::bail!("the rest of the batch failed")
+        });
+        assert!(result.is_err());
+        assert_eq!(sent(&db), 0);
+
+        db.transaction(|db| db.insert_task(&in_project("t1", None)))
+            .unwrap();
+        assert_eq!(sent(&db), 1);
+    }
+
+    #[test]
+    fn test_delete_flagged() {
+        let (db, _temp) = create_test_db();
+
+        let running = Task::new(
+            "running".to_string(),
+            "claude_code".to_string(),
+            "Running task".to_string(),
+            None,
+            None,
+        );
+        let mut flagged = running.clone();
+        flagged.task_id = "flagged".to_string();
+        flagged.flag_attention(AttentionReason::Custom("Waiting for approval".to_string()));
+        let mut chat = running.clone();
+        chat.task_id = "chat".to_string();
+        chat.flag_attention(AttentionReason::ReadyForReply);
+        db.insert_task(&running).unwrap();
+        db.insert_task(&flagged).unwrap();
+        db.insert_task(&chat).unwrap();
+
+        assert_eq!(db.delete_flagged().unwrap(), 1);
+        assert!(db.get_task_by_id("flagged").unwrap().is_none());
+        assert!(db.get_task_by_id("running").unwrap().is_some());
+        // Waiting for a reply isn't a flag
+        assert!(db.get_task_by_id("chat").unwrap().is_some());
+        assert_eq!(db.list_trash().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_latest_task() {
+        let (db, _temp) = create_test_db();
+        assert!(db.latest_task(Latest::Any).unwrap().is_none());
+
+        let now = Utc::now();
+        let mut tasks: Vec<Task> = ["failed", "flagged", "chat", "newest"]
+            .iter()
+            .map(|id| in_project(id, None))
+            .collect();
+        tasks[0].set_exited(Some(2));
+        tasks[1].flag_attention(AttentionReason::Custom("Approve?".to_string()));
+        tasks[2].flag_attention(AttentionReason::ReadyForReply);
+        for (age, task) in tasks.iter_mut().rev().enumerate() {
+            task.updated_at = now - chrono::Duration::minutes(age as i64);
+            db.insert_task(task).unwrap();
+        }
+        // Exited cleanly: not a failure
+        let mut clean = in_project("clean", None);
+        clean.set_exited(Some(0));
+        clean.updated_at = now - chrono::Duration::hours(1);
+        db.insert_task(&clean).unwrap();
+
+        let latest = |which| db.latest_task(which).unwrap().unwrap().task_id;
+        assert_eq!(latest(Latest::Any), "newest");
+        assert_eq!(latest(Latest::Attention), "flagged");
+        assert_eq!(latest(Latest::Failed), "failed");
+
+        assert_eq!(Latest::from_selector("last"), Some(Latest::Any));
+        assert_eq!(Latest::from_selector("last-failed"), Some(Latest::Failed));
+        assert_eq!(Latest::from_selector("lastly"), None);
+    }
+
+    #[test]
+    fn test_get_task_by_id_prefix() {
+        let (db, _temp) = create_test_db();
+        for id in ["3f2a91c0", "3f2b04d1", "a1b2c3", "a_b"] {
+            db.insert_task(&Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                format!("Task {}", id),
+                None,
+                None,
+            ))
+            .unwrap();
+        }
+
+        // Unique
+        assert_eq!(
+            db.get_task_by_id_prefix("3f2a").unwrap().unwrap().task_id,
+            "3f2a91c0"
+        );
+        assert_eq!(
+            db.get_task_by_id_prefix("a1").unwrap().unwrap().task_id,
+            "a1b2c3"
+        );
+
+        // Ambiguous: the error lists every candidate
+        let err = db.get_task_by_id_prefix("3f2").unwrap_err().to_string();
+        assert!(err.contains("ambiguous"), "{}", err);
+        assert!(
+            err.contains("3f2a91c0  Task 3f2a91c0") && err.contains("3f2b04d1"),
+            "{}",
+            err
+        );
+
+        // No match; wildcards in the prefix are literal
+        assert!(db.get_task_by_id_prefix("ff").unwrap().is_none());
+        assert!(db.get_task_by_id_prefix("").unwrap().is_none());
+        assert_eq!(
+            db.get_task_by_id_prefix("a_").unwrap().unwrap().task_id,
+            "a_b"
+        );
+        assert!(db.get_task_by_id_prefix("%").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_bounds_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.db");
+        let wal_size = || {
+            std::fs::metadata(dir.path().join("tasks.db-wal"))
+                .unwrap()
+                .len()
+        };
+        let db = Database::open(&path).unwrap();
+
+        // A reader holding a snapshot keeps the WAL from being recycled, the
+        // way a lingering CLI read would while the bridge keeps writing
+        let reader = Connection::open(&path).unwrap();
+        reader
+            .execute_batch("BEGIN; SELECT COUNT(*) FROM tasks;")
+            .unwrap();
+
+        let mut task = Task::new(
+            "t0".to_string(),
+            "claude_web".to_string(),
+            "x".repeat(4000),
+            None,
+            None,
+        );
+        for i in 0..1000 {
+            task.task_id = format!("t{}", i);
+            db.insert_task(&task).unwrap();
+        }
+        let grown = wal_size();
+        assert!(
+            grown as i64 > WAL_SIZE_LIMIT,
+            "WAL only reached {} bytes",
+            grown
+        );
+
+        // While the reader is active the checkpoint can't finish
+        assert!(!db.checkpoint().unwrap().is_complete());
+
+        reader.execute_batch("COMMIT").unwrap();
+        let checkpoint = db.checkpoint().unwrap();
+        assert!(checkpoint.is_complete(), "{:?}", checkpoint);
+
+        // The next write restarts the WAL and truncates it to the limit
+        task.task_id = "after".to_string();
+        db.insert_task(&task).unwrap();
+        assert!(
+            (wal_size() as i64) <= WAL_SIZE_LIMIT,
+            "WAL still {} bytes",
+            wal_size()
+        );
+        assert_eq!(db.count(None, None).unwrap(), 1001);
+    }
+
+    #[test]
+    fn test_prompt_counts() {
+        let (db, temp) = create_test_db();
+        for id in ["run", "flagged", "ready", "done"] {
+            db.insert_task(&in_project(id, None)).unwrap();
+        }
+        let mut task = db.get_task_by_id("flagged").unwrap().unwrap();
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        db.update_task(&task).unwrap();
+        let mut task = db.get_task_by_id("ready").unwrap().unwrap();
+        task.flag_attention(AttentionReason::ReadyForReply);
+        db.update_task(&task).unwrap();
+        let mut task = db.get_task_by_id("done").unwrap().unwrap();
+        task.complete();
+        db.update_task(&task).unwrap();
+
+        let counts = prompt_counts(temp.path()).unwrap();
+        assert_eq!(
+            counts,
+            PromptCounts {
+                running: 1,
+                attention: 1,
+                ready: 1
+            }
+        );
+
+        let missing = temp.path().with_extension("missing");
+        assert_eq!(prompt_counts(&missing).unwrap(), PromptCounts::default());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_query_read_only() {
+        use serde_json::json;
+
+        let (db, temp) = create_test_db();
+        let mut task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        db.insert_task(&task).unwrap();
+        task.task_id = "t2".to_string();
+        task.agent_type = "opencode".to_string();
+        db.insert_task(&task).unwrap();
+
+        let result = query_read_only(
+            temp.path(),
+            "SELECT agent_type, COUNT(*) AS n, NULL AS empty FROM tasks
+             GROUP BY agent_type ORDER BY agent_type",
+        )
+        .unwrap();
+        assert_eq!(result.columns, ["agent_type", "n", "empty"]);
+        assert_eq!(
+            result.rows,
+            [
+                [json!("claude_code"), json!(1), json!(null)],
+                [json!("opencode"), json!(1), json!(null)]
+            ]
+        );
+        assert_eq!(result.records()[1]["agent_type"], "opencode");
+    }
+
+    #[test]
+    fn test_query_read_only_rejects_writes() {
+        let (db, temp) = create_test_db();
+        db.insert_task(&Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        ))
+        .unwrap();
+
+        for sql in [
+            "DELETE FROM tasks",
+            "UPDATE tasks SET title = 'x'",
+            "DROP TABLE tasks",
+            "CREATE TABLE scratch (x)",
+            "PRAGMA user_version = 7",
+            // Only the first statement of a batch would run; refuse the lot
+            "SELECT 1; DELETE FROM tasks",
+        ] {
+            assert!(
+                query_read_only(temp.path(), sql).is_err(),
+                "{} should fail",
+                sql
+            );
+        }
+
+        assert_eq!(db.count(None, None).unwrap(), 1);
+        assert_eq!(db.get_task_by_id("t1").unwrap().unwrap().title, "Test task");
     }
 }
@@ -1,10 +1,27 @@
-use crate::models::{Task, TaskStatus};
-use chrono::Utc;
+use crate::archive::ArchivedTask;
+use crate::cli::{ColorChoice, GroupBy, OutputFormat};
+use crate::config::{AgentStyle, DEFAULT_LIST_TITLE_WIDTH};
+use crate::db::{PromptCounts, QueryResult, TrashedTask};
+use crate::models::attention::format_idle;
+use crate::models::{
+    AgentType, AttentionReason, Task, TaskContext, TaskEvent, TaskStatus, ENV_KEY, RELATED_KEY,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use unicode_width::UnicodeWidthChar;
 
 // ANSI color codes
 const RESET: &str = "\x1b[0m";
 const BOLD: &str = "\x1b[1m";
 const DIM: &str = "\x1b[2m";
+const INVERSE: &str = "\x1b[7m";
 
 // Colors
 const RED: &str = "\x1b[31m";
@@ -17,236 +34,1727 @@ const WHITE: &str = "\x1b[37m";
 const GRAY: &str = "\x1b[90m";
 
 // Bright colors
+const BRIGHT_RED: &str = "\x1b[91m";
+const BRIGHT_GREEN: &str = "\x1b[92m";
+const BRIGHT_YELLOW: &str = "\x1b[93m";
 const BRIGHT_BLUE: &str = "\x1b[94m";
+const BRIGHT_MAGENTA: &str = "\x1b[95m";
 const BRIGHT_CYAN: &str = "\x1b[96m";
 
 // Icons (using Unicode)
 const ICON_RUNNING: &str = "▶️ ";
+const ICON_PAUSED: &str = "‖";
 const ICON_COMPLETED: &str = "✓";
 const ICON_FAILED: &str = "✗";
 const ICON_ARROW: &str = "→";
+const ICON_REPLY: &str = "↩";
 
-pub fn display_task_list(tasks: &[Task]) {
-    let mut running = Vec::new();
-    let mut completed = Vec::new();
-    let mut exited = Vec::new();
+/// Whether output carries ANSI styling, decided once from `--color`
+static COLOR: OnceLock<bool> = OnceLock::new();
 
+/// Apply the `--color` choice to everything printed from here on. Call once
+/// at startup; without it `auto` applies.
+pub fn set_color(choice: ColorChoice) {
+    let _ = COLOR.set(choice.enabled(std::io::stdout().is_terminal()));
+}
+
+fn color_enabled() -> bool {
+    *COLOR.get_or_init(|| ColorChoice::Auto.enabled(std::io::stdout().is_terminal()))
+}
+
+/// Rendering embeds escape codes unconditionally; this is where they are
+/// dropped when color is off
+fn gate(s: &str) -> Cow<'_, str> {
+    if color_enabled() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(strip_ansi(s))
+    }
+}
+
+/// `print!` through the color gate
+macro_rules! out {
+    ($($arg:tt)*) => {
+        print!("{}", gate(&format!($($arg)*)))
+    };
+}
+
+/// `println!` through the color gate
+macro_rules! outln {
+    () => {
+        println!()
+    };
+    ($($arg:tt)*) => {
+        println!("{}", gate(&format!($($arg)*)))
+    };
+}
+
+/// Per-agent badge overrides from the `[agents.<name>]` config tables
+static AGENT_STYLES: OnceLock<HashMap<String, AgentStyle>> = OnceLock::new();
+
+/// Install the configured agent styles. Call once at startup, before rendering.
+pub fn set_agent_styles(styles: HashMap<String, AgentStyle>) {
+    let _ = AGENT_STYLES.set(styles);
+}
+
+static SLA_SECS: OnceLock<u64> = OnceLock::new();
+
+/// Install the global `sla_secs`. Agents with their own take precedence.
+pub fn set_sla(sla_secs: Option<u64>) {
+    if let Some(sla_secs) = sla_secs {
+        let _ = SLA_SECS.set(sla_secs);
+    }
+}
+
+/// Map a color name from the config file to its ANSI escape code
+fn color_code(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().replace('-', "_").as_str() {
+        "red" => Some(RED),
+        "green" => Some(GREEN),
+        "yellow" => Some(YELLOW),
+        "blue" => Some(BLUE),
+        "magenta" | "purple" => Some(MAGENTA),
+        "cyan" => Some(CYAN),
+        "white" => Some(WHITE),
+        "gray" | "grey" => Some(GRAY),
+        "bright_red" => Some(BRIGHT_RED),
+        "bright_green" => Some(BRIGHT_GREEN),
+        "bright_yellow" => Some(BRIGHT_YELLOW),
+        "bright_blue" => Some(BRIGHT_BLUE),
+        "bright_magenta" => Some(BRIGHT_MAGENTA),
+        "bright_cyan" => Some(BRIGHT_CYAN),
+        _ => None,
+    }
+}
+
+/// Per-invocation rendering choices for the list views
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    /// Task ids whose rows are drawn highlighted because they just changed
+    pub highlight: HashSet<String>,
+    /// Collapse turns of the same conversation under one entry (text view)
+    pub tree: bool,
+    /// How the text view splits tasks into sections
+    pub group_by: GroupBy,
+    /// ASCII-only text view without colors, boxes or icons
+    pub plain: bool,
+    /// Text view without the header box and footer hints, styling kept
+    pub quiet: bool,
+    /// Keep only these task fields (JSON and YAML); empty keeps all
+    pub fields: Vec<String>,
+    /// Columns a title may take in the text view (default 60)
+    pub title_width: Option<usize>,
+}
+
+impl ListOptions {
+    fn title_width(&self) -> usize {
+        self.title_width.unwrap_or(DEFAULT_LIST_TITLE_WIDTH)
+    }
+}
+
+/// One entry of the grouped list: a lone task, or every turn of a
+/// conversation (oldest first)
+#[derive(Debug)]
+enum ListEntry<'a> {
+    Single(&'a Task),
+    Conversation(Vec<&'a Task>),
+}
+
+impl<'a> ListEntry<'a> {
+    /// The task whose status the entry is listed under
+    fn latest(&self) -> &'a Task {
+        match self {
+            ListEntry::Single(task) => task,
+            ListEntry::Conversation(turns) => turns[turns.len() - 1],
+        }
+    }
+}
+
+/// Heading of one block of the grouped list
+#[derive(Debug, Clone, PartialEq)]
+enum GroupKey {
+    Status(TaskStatus),
+    Agent(String),
+    Project(Option<String>),
+    /// Everything in one block, without a heading
+    All,
+}
+
+/// Split entries into blocks for `group_by`, keeping their order within each
+/// block. Status blocks come in a fixed order (running first); agent and
+/// project blocks in order of their most recent task. Empty blocks are
+/// dropped.
+fn group_entries<'a>(
+    entries: Vec<ListEntry<'a>>,
+    group_by: GroupBy,
+) -> Vec<(GroupKey, Vec<ListEntry<'a>>)> {
+    let mut groups: Vec<(GroupKey, Vec<ListEntry>)> = match group_by {
+        GroupBy::Status => [
+            TaskStatus::Running,
+            TaskStatus::Paused,
+            TaskStatus::Completed,
+            TaskStatus::Exited,
+        ]
+        .into_iter()
+        .map(|status| (GroupKey::Status(status), Vec::new()))
+        .collect(),
+        GroupBy::Agent | GroupBy::Project | GroupBy::None => Vec::new(),
+    };
+
+    for entry in entries {
+        let key = match group_by {
+            GroupBy::Status => GroupKey::Status(entry.latest().status.clone()),
+            GroupBy::Agent => GroupKey::Agent(entry.latest().agent_type.clone()),
+            GroupBy::Project => GroupKey::Project(entry.latest().project_path().map(String::from)),
+            GroupBy::None => GroupKey::All,
+        };
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(entry),
+            None => groups.push((key, vec![entry])),
+        }
+    }
+
+    groups.retain(|(_, group)| !group.is_empty());
+    groups
+}
+
+fn print_group_header(key: &GroupKey, group: &[ListEntry]) {
+    match key {
+        // Most important - agents actively generating
+        GroupKey::Status(TaskStatus::Running) => {
+            outln!("{}{}{} RUNNING{}", BOLD, BRIGHT_BLUE, ICON_RUNNING, RESET)
+        }
+        // Deliberately suspended
+        GroupKey::Status(TaskStatus::Paused) => {
+            outln!("{}{} {} PAUSED{}", BOLD, CYAN, ICON_PAUSED, RESET)
+        }
+        // Waiting for user input
+        GroupKey::Status(TaskStatus::Completed) => {
+            outln!("{}{} {} COMPLETED{}", BOLD, GREEN, ICON_COMPLETED, RESET)
+        }
+        // Closed/terminated
+        GroupKey::Status(TaskStatus::Exited) => {
+            outln!("{}{} {} EXITED{}", BOLD, GRAY, ICON_FAILED, RESET)
+        }
+        GroupKey::Agent(_) => {
+            let (color, badge) = agent_badge(group[0].latest(), AGENT_STYLES.get());
+            outln!(
+                "{}{}[{}]{} {}{}{}",
+                BOLD,
+                color,
+                badge,
+                RESET,
+                DIM,
+                group.len(),
+                RESET
+            );
+        }
+        GroupKey::Project(project) => {
+            let name = project.as_deref().unwrap_or("(no project)");
+            outln!(
+                "{}{}{}{} {}{}{}",
+                BOLD,
+                BLUE,
+                name,
+                RESET,
+                DIM,
+                group.len(),
+                RESET
+            );
+        }
+        GroupKey::All => return,
+    }
+    outln!("{}{}{}", GRAY, "─".repeat(50), RESET);
+}
+
+/// Group tasks sharing a conversation id into one entry, placed where its
+/// first turn appears in `tasks` (the newest, in list order). Tasks without
+/// an id, and conversations with a single turn, stay flat.
+fn group_conversations(tasks: &[Task]) -> Vec<ListEntry<'_>> {
+    let mut turns: HashMap<&str, Vec<&Task>> = HashMap::new();
     for task in tasks {
-        match task.status {
-            TaskStatus::Running => running.push(task),
-            TaskStatus::Completed => completed.push(task),
-            TaskStatus::Exited => exited.push(task),
+        if let Some(id) = task.conversation_id() {
+            turns.entry(id).or_default().push(task);
         }
     }
 
-    if running.is_empty() && completed.is_empty() && exited.is_empty() {
-        println!("{}{}No active tasks{}", DIM, GRAY, RESET);
-        println!("{}Start a conversation in Claude.ai or Gemini to create tasks{}", DIM, RESET);
+    let mut entries = Vec::new();
+    for task in tasks {
+        let Some(id) = task.conversation_id() else {
+            entries.push(ListEntry::Single(task));
+            continue;
+        };
+        let Some(mut group) = turns.remove(id) else {
+            // Already emitted with an earlier turn
+            continue;
+        };
+        if group.len() == 1 {
+            entries.push(ListEntry::Single(task));
+        } else {
+            group.sort_by_key(|t| t.updated_at);
+            entries.push(ListEntry::Conversation(group));
+        }
+    }
+
+    entries
+}
+
+/// Serialize `value` for the structured formats (JSON unless YAML was asked for)
+fn structured<T: Serialize + ?Sized>(value: &T, format: OutputFormat) -> Result<String> {
+    Ok(match format {
+        OutputFormat::Yaml => serde_yaml::to_string(value)?.trim_end().to_string(),
+        _ => serde_json::to_string_pretty(value)?,
+    })
+}
+
+/// Task fields `--fields` can select, named as in the JSON output
+pub const TASK_FIELDS: &[&str] = &[
+    "id",
+    "task_id",
+    "agent_type",
+    "title",
+    "status",
+    "created_at",
+    "updated_at",
+    "completed_at",
+    "pid",
+    "ppid",
+    "monitor_pid",
+    "attention_reason",
+    "exit_code",
+    "context",
+    "metadata",
+    "dedup_key",
+    "last_heartbeat",
+    "expires_at",
+    "attention_since",
+    "source",
+    "raw_context",
+    "raw_metadata",
+];
+
+/// Fail on any name in `fields` that isn't one of `TASK_FIELDS`
+pub fn validate_fields(fields: &[String]) -> Result<()> {
+    let unknown: Vec<&str> = fields
+        .iter()
+        .map(String::as_str)
+        .filter(|field| !TASK_FIELDS.contains(field))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(crate::error::invalid_argument(format!(
+        "Unknown field{}: {} (valid fields: {})",
+        if unknown.len() == 1 { "" } else { "s" },
+        unknown.join(", "),
+        TASK_FIELDS.join(", ")
+    )))
+}
+
+/// `task` as a JSON object with only `fields`. Fields the task doesn't have
+/// (an unset `source`) come out null.
+fn project(task: &Task, fields: &[String]) -> Result<Value> {
+    let Value::Object(mut all) = serde_json::to_value(task)? else {
+        unreachable!("a task serializes to an object");
+    };
+    Ok(Value::Object(
+        fields
+            .iter()
+            .map(|field| (field.clone(), all.remove(field).unwrap_or(Value::Null)))
+            .collect(),
+    ))
+}
+
+/// Print a task list in the chosen format
+pub fn display_tasks(tasks: &[Task], format: OutputFormat, opts: &ListOptions) -> Result<()> {
+    match format {
+        OutputFormat::Text if opts.plain => display_task_list_plain(tasks),
+        OutputFormat::Text => display_task_list(tasks, opts),
+        OutputFormat::Table => display_task_table(tasks, opts),
+        OutputFormat::Json | OutputFormat::Yaml if !opts.fields.is_empty() => {
+            let projected = tasks
+                .iter()
+                .map(|task| project(task, &opts.fields))
+                .collect::<Result<Vec<_>>>()?;
+            outln!("{}", structured(&projected, format)?)
+        }
+        OutputFormat::Json | OutputFormat::Yaml => outln!("{}", structured(tasks, format)?),
+    }
+    Ok(())
+}
+
+/// Print the `top` view in the chosen format
+pub fn display_top(tasks: &[Task], format: OutputFormat, opts: &ListOptions) -> Result<()> {
+    match format {
+        OutputFormat::Text => display_task_top(tasks, opts),
+        _ => display_tasks(tasks, format, opts)?,
+    }
+    Ok(())
+}
+
+/// Print a single task in the chosen format; `table` uses the detail view.
+/// `fields` narrows the structured formats as in `ListOptions::fields`.
+pub fn display_task(task: &Task, format: OutputFormat, fields: &[String]) -> Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => display_task_detail(task),
+        OutputFormat::Json | OutputFormat::Yaml if !fields.is_empty() => {
+            outln!("{}", structured(&project(task, fields)?, format)?)
+        }
+        OutputFormat::Json | OutputFormat::Yaml => outln!("{}", structured(task, format)?),
+    }
+    Ok(())
+}
+
+/// Print just a task's context as pretty JSON, or `{}` without one
+pub fn display_context(context: Option<&TaskContext>) -> Result<()> {
+    let value = match context {
+        Some(context) => serde_json::to_value(context)?,
+        None => serde_json::json!({}),
+    };
+    outln!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+/// Print a task's status-change history in the chosen format
+pub fn display_history(task: &Task, events: &[TaskEvent], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => display_task_history(task, events),
+        OutputFormat::Json | OutputFormat::Yaml => outln!("{}", structured(events, format)?),
+    }
+    Ok(())
+}
+
+/// Print the tasks in the trash, most recently deleted (next to `undo`) first
+/// The first `limit` of `lines`, then one "(and N more)" line for the rest,
+/// so a confirmation prompt stays on screen
+pub fn preview_lines(lines: &[String], limit: usize) -> Vec<String> {
+    if lines.len() <= limit {
+        return lines.to_vec();
+    }
+    let mut preview = lines[..limit].to_vec();
+    preview.push(format!("  (and {} more)", lines.len() - limit));
+    preview
+}
+
+/// Show `text` in `$PAGER` (`less` if unset) and wait for it to exit.
+/// Returns false if the pager couldn't be run.
+pub fn page(text: &str) -> bool {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    // Through the shell, so PAGER="less -R" works
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // A pager quit early closes the pipe; that's fine
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait().is_ok_and(|status| status.success())
+}
+
+pub fn display_trash(trashed: &[TrashedTask], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => display_trash_list(trashed),
+        OutputFormat::Json | OutputFormat::Yaml => outln!("{}", structured(trashed, format)?),
+    }
+    Ok(())
+}
+
+fn display_trash_list(trashed: &[TrashedTask]) {
+    if trashed.is_empty() {
+        outln!("{}Trash is empty{}", DIM, RESET);
         return;
     }
 
+    let now = Utc::now().timestamp();
+    for entry in trashed {
+        let task = &entry.task;
+        outln!(
+            "  {}{}{}  [{}] {}\"{}\"{}  {}deleted {}{}",
+            GRAY,
+            task.task_id,
+            RESET,
+            task.agent_type,
+            WHITE,
+            task.title,
+            RESET,
+            DIM,
+            format_ago(entry.deleted_at.timestamp(), now),
+            RESET
+        );
+    }
+}
+
+/// Print `archive search` results like the trash, by when each was archived
+pub fn display_archive(archived: &[ArchivedTask], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => {
+            if archived.is_empty() {
+                outln!("{}No archived tasks match{}", DIM, RESET);
+            }
+            let now = Utc::now().timestamp();
+            for entry in archived {
+                let task = &entry.task;
+                outln!(
+                    "  {}{}{}  [{}] {}\"{}\"{}  {}archived {}{}",
+                    GRAY,
+                    task.task_id,
+                    RESET,
+                    task.agent_type,
+                    WHITE,
+                    task.title,
+                    RESET,
+                    DIM,
+                    format_ago(entry.archived_at.timestamp(), now),
+                    RESET
+                );
+            }
+        }
+        OutputFormat::Json | OutputFormat::Yaml => outln!("{}", structured(archived, format)?),
+    }
+    Ok(())
+}
+
+/// Print the result of `agent-inbox sql`: an aligned table, or one object
+/// per row for json/yaml
+/// `2⚠ 1↩ 3●` for flagged, ready-for-reply and other running tasks, leaving
+/// out the zeros; empty when nothing runs. No colors, since prompts need
+/// escape sequences marked up by the shell.
+pub fn prompt_summary(counts: &PromptCounts) -> String {
+    [
+        (counts.attention, "⚠"),
+        (counts.ready, ICON_REPLY),
+        (counts.running, "●"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, glyph)| format!("{}{}", n, glyph))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// What `agent-inbox version` reports
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// Commit the binary was built from, or "unknown"
+    pub git_sha: &'static str,
+    /// Database layout this build expects (`PRAGMA user_version`)
+    pub schema_version: i32,
+    pub db_path: PathBuf,
+}
+
+pub fn display_version(info: &VersionInfo, json: bool) -> Result<()> {
+    if json {
+        outln!("{}", serde_json::to_string_pretty(info)?);
+    } else {
+        outln!("{}", version_text(info));
+    }
+    Ok(())
+}
+
+fn version_text(info: &VersionInfo) -> String {
+    format!(
+        "agent-inbox {} ({})\nschema version {}\ndatabase {}",
+        info.version,
+        info.git_sha,
+        info.schema_version,
+        info.db_path.display()
+    )
+}
+
+pub fn display_query(result: &QueryResult, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text | OutputFormat::Table => {
+            for row in query_rows(result) {
+                outln!("{}", row);
+            }
+            let count = result.rows.len();
+            let noun = if count == 1 { "row" } else { "rows" };
+            outln!("{}{}({} {}){}", DIM, GRAY, count, noun, RESET);
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            outln!("{}", structured(&result.records(), format)?)
+        }
+    }
+    Ok(())
+}
+
+/// Widest a query result column may grow before values are cut
+const QUERY_CELL_WIDTH: usize = 60;
+
+fn query_rows(result: &QueryResult) -> Vec<String> {
+    let cells: Vec<Vec<String>> = result
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| match value {
+                    serde_json::Value::Null => format!("{}NULL{}", DIM, RESET),
+                    serde_json::Value::String(s) => fit(s, QUERY_CELL_WIDTH),
+                    other => fit(&other.to_string(), QUERY_CELL_WIDTH),
+                })
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = result
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            cells
+                .iter()
+                .map(|row| visible_width(&row[i]))
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |values: Vec<String>| {
+        let padded: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| pad(v, *w))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let header = result
+        .columns
+        .iter()
+        .map(|name| format!("{}{}{}", BOLD, name, RESET))
+        .collect();
+    let rule = widths
+        .iter()
+        .map(|w| format!("{}{}{}", GRAY, "─".repeat(*w), RESET))
+        .collect();
+
+    [line(header), line(rule)]
+        .into_iter()
+        .chain(cells.into_iter().map(line))
+        .collect()
+}
+
+/// Tasks in the order the text list numbers them with `opts`: by section,
+/// then as given, a conversation under `tree` counting once as its latest
+/// turn
+pub fn numbered<'a>(tasks: &'a [Task], opts: &ListOptions) -> Vec<&'a Task> {
+    let entries = if opts.tree {
+        group_conversations(tasks)
+    } else {
+        tasks.iter().map(ListEntry::Single).collect()
+    };
+    group_entries(entries, opts.group_by)
+        .into_iter()
+        .flat_map(|(_, group)| group)
+        .map(|entry| entry.latest())
+        .collect()
+}
+
+/// Remembers what the previous frame of a live view showed so the next one
+/// can highlight rows whose status or update time moved
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    previous: Option<HashMap<String, (TaskStatus, DateTime<Utc>)>>,
+}
+
+impl ChangeTracker {
+    /// Record `tasks` as the current frame and return the ids that are new or
+    /// changed since the last call. The first frame highlights nothing, and a
+    /// highlight lasts one frame unless the task changes again.
+    pub fn update(&mut self, tasks: &[Task]) -> HashSet<String> {
+        let current: HashMap<_, _> = tasks
+            .iter()
+            .map(|t| (t.task_id.clone(), (t.status.clone(), t.updated_at)))
+            .collect();
+
+        let changed = match &self.previous {
+            Some(previous) => current
+                .iter()
+                .filter(|(id, state)| previous.get(*id) != Some(*state))
+                .map(|(id, _)| id.clone())
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        self.previous = Some(current);
+        changed
+    }
+}
+
+/// Remembers which tasks needed attention on the previous frame so a live
+/// view can alert only when one starts to
+#[derive(Debug, Default)]
+pub struct AttentionTracker {
+    previous: Option<HashSet<String>>,
+}
+
+impl AttentionTracker {
+    /// Record `tasks` as the current frame and return the ids that need
+    /// attention now but didn't last time. Tasks already flagged on the
+    /// first frame don't count as new.
+    pub fn update(&mut self, tasks: &[Task]) -> HashSet<String> {
+        let current: HashSet<String> = tasks
+            .iter()
+            .filter(|t| t.needs_attention())
+            .map(|t| t.task_id.clone())
+            .collect();
+
+        let added = match &self.previous {
+            Some(previous) => current.difference(previous).cloned().collect(),
+            None => HashSet::new(),
+        };
+
+        self.previous = Some(current);
+        added
+    }
+}
+
+pub fn display_task_list(tasks: &[Task], opts: &ListOptions) {
+    if tasks.is_empty() {
+        outln!("{}{}No active tasks{}", DIM, GRAY, RESET);
+        if !opts.quiet {
+            outln!(
+                "{}Start a conversation in Claude.ai or Gemini to create tasks{}",
+                DIM,
+                RESET
+            );
+        }
+        return;
+    }
+
+    // Numbered as `numbered` has them
+    let entries: Vec<ListEntry> = if opts.tree {
+        group_conversations(tasks)
+    } else {
+        tasks.iter().map(ListEntry::Single).collect()
+    };
+
     // Header with box drawing
-    println!();
-    println!("{}{}╭─────────────────────────────────────────────╮{}", BOLD, CYAN, RESET);
-    println!("{}{}│  {}Agent Inbox{}                              │{}", BOLD, CYAN, WHITE, CYAN, RESET);
-    println!("{}{}╰─────────────────────────────────────────────╯{}", BOLD, CYAN, RESET);
-    println!();
+    if !opts.quiet {
+        outln!();
+        outln!(
+            "{}{}╭─────────────────────────────────────────────╮{}",
+            BOLD,
+            CYAN,
+            RESET
+        );
+        outln!(
+            "{}{}│  {}Agent Inbox{}                              │{}",
+            BOLD,
+            CYAN,
+            WHITE,
+            CYAN,
+            RESET
+        );
+        outln!(
+            "{}{}╰─────────────────────────────────────────────╯{}",
+            BOLD,
+            CYAN,
+            RESET
+        );
+        outln!();
+    }
 
     // Summary line with colors
+    let count = |status: TaskStatus| {
+        entries
+            .iter()
+            .filter(|entry| entry.latest().status == status)
+            .count()
+    };
     let mut summary_parts = Vec::new();
 
-    if !running.is_empty() {
-        summary_parts.push(format!("{}{}{} running{}", BOLD, BRIGHT_BLUE, running.len(), RESET));
+    let running = count(TaskStatus::Running);
+    if running > 0 {
+        summary_parts.push(format!(
+            "{}{}{} running{}",
+            BOLD, BRIGHT_BLUE, running, RESET
+        ));
+    }
+    let paused = count(TaskStatus::Paused);
+    if paused > 0 {
+        summary_parts.push(format!("{}{} paused{}", CYAN, paused, RESET));
     }
-    if !completed.is_empty() {
-        summary_parts.push(format!("{}{} completed{}", GREEN, completed.len(), RESET));
+    let completed = count(TaskStatus::Completed);
+    if completed > 0 {
+        summary_parts.push(format!("{}{} completed{}", GREEN, completed, RESET));
     }
-    if !exited.is_empty() {
-        summary_parts.push(format!("{}{} exited{}", GRAY, exited.len(), RESET));
+    let exited = count(TaskStatus::Exited);
+    if exited > 0 {
+        summary_parts.push(format!("{}{} exited{}", GRAY, exited, RESET));
     }
 
     if !summary_parts.is_empty() {
-        println!("{}", summary_parts.join(&format!("{}  •  {}", GRAY, RESET)));
-        println!();
+        outln!("{}", summary_parts.join(&format!("{}  •  {}", GRAY, RESET)));
+        outln!();
     }
 
-    // Running section (most important - agents actively generating)
-    if !running.is_empty() {
-        println!("{}{}{} RUNNING{}", BOLD, BRIGHT_BLUE, ICON_RUNNING, RESET);
-        println!("{}{}{}", GRAY, "─".repeat(50), RESET);
-        for (idx, task) in running.iter().enumerate() {
-            print_task_summary(idx + 1, task);
+    let mut idx = 0;
+    for (key, group) in group_entries(entries, opts.group_by) {
+        print_group_header(&key, &group);
+        for entry in &group {
+            idx += 1;
+            print_entry(idx, entry, opts);
         }
-        println!();
+        outln!();
     }
 
-    // Completed section (waiting for user input)
-    if !completed.is_empty() {
-        println!("{}{} {} COMPLETED{}", BOLD, GREEN, ICON_COMPLETED, RESET);
-        println!("{}{}{}", GRAY, "─".repeat(50), RESET);
-        let start_idx = running.len();
-        for (idx, task) in completed.iter().enumerate() {
-            print_task_summary(start_idx + idx + 1, task);
-        }
-        println!();
+    // Footer with helpful info
+    if !opts.quiet {
+        outln!(
+            "{}{} Exited tasks auto-clear after 1 hour{}",
+            DIM,
+            GRAY,
+            RESET
+        );
+        outln!(
+            "{}{} Run {}agent-inbox show <id>{} for details{}",
+            DIM,
+            GRAY,
+            CYAN,
+            GRAY,
+            RESET
+        );
+        outln!();
     }
+}
 
-    // Exited section (closed/terminated)
-    if !exited.is_empty() {
-        println!("{}{} {} EXITED{}", BOLD, GRAY, ICON_FAILED, RESET);
-        println!("{}{}{}", GRAY, "─".repeat(50), RESET);
-        let start_idx = running.len() + completed.len();
-        for (idx, task) in exited.iter().enumerate() {
-            print_task_summary(start_idx + idx + 1, task);
-        }
-        println!();
+/// One ASCII line per task with no escape sequences, for dumb terminals,
+/// cron mail and grep
+pub fn display_task_list_plain(tasks: &[Task]) {
+    let now = Utc::now().timestamp();
+    for task in tasks {
+        outln!("{}", plain_row(task, now));
     }
-
-    // Footer with helpful info
-    println!("{}{} Exited tasks auto-clear after 1 hour{}", DIM, GRAY, RESET);
-    println!("{}{} Run {}agent-inbox show <id>{} for details{}", DIM, GRAY, CYAN, GRAY, RESET);
-    println!();
 }
 
-fn print_task_summary(idx: usize, task: &Task) {
-    // Agent badge with color
-    let agent_label = if let Some(pid) = task.pid {
-        format!("{}:{}", task.agent_type, pid)
-    } else {
-        task.agent_type.clone()
+/// `[ATTN] claude_code "title" (3m ago): reason`
+fn plain_row(task: &Task, now: i64) -> String {
+    let tag = match task.status {
+        TaskStatus::Running if task.awaiting_reply() => "REPLY".to_string(),
+        TaskStatus::Running if task.needs_attention() => "ATTN".to_string(),
+        TaskStatus::Running => "RUNNING".to_string(),
+        TaskStatus::Paused => "PAUSED".to_string(),
+        TaskStatus::Completed => "DONE".to_string(),
+        TaskStatus::Exited => match task.exit_code {
+            Some(code) => format!("EXITED {}", code),
+            None => "EXITED".to_string(),
+        },
     };
 
-    let (agent_color, badge): (&str, String) = match task.agent_type.as_str() {
-        "claude_web" => (MAGENTA, "claude.ai".to_string()),
-        "gemini_web" => (BLUE, "gemini".to_string()),
-        "claude_code" => (CYAN, "claude-code".to_string()),
-        "opencode" => (GREEN, "opencode".to_string()),
-        _ => (WHITE, agent_label.clone()),
-    };
+    let mut row = format!(
+        "[{}] {} \"{}\" ({})",
+        tag,
+        ascii(&task.agent_type),
+        ascii(&task.title),
+        format_ago(task.updated_at.timestamp(), now)
+    );
+    let flagged = task.needs_attention() || task.awaiting_reply();
+    if let Some(reason) = task.attention_reason.as_ref().filter(|_| flagged) {
+        row.push_str(": ");
+        row.push_str(&ascii(&reason.to_string()));
+    }
+    row
+}
 
-    let elapsed = format_elapsed(task.updated_at.timestamp());
+/// Single-line ASCII rendering: whitespace runs become one space and other
+/// non-ASCII or control characters become '?'
+fn ascii(s: &str) -> String {
+    s.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .map(|c| if c.is_ascii_graphic() { c } else { '?' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    // Status indicator
-    let status_indicator = match task.status {
+/// Dense overview: one aligned row per task (status glyph, agent badge,
+/// title, elapsed), with the title column sized to the terminal width
+pub fn display_task_table(tasks: &[Task], opts: &ListOptions) {
+    if tasks.is_empty() {
+        outln!("{}{}No active tasks{}", DIM, GRAY, RESET);
+        return;
+    }
+
+    for row in table_rows(tasks, terminal_width(), opts) {
+        outln!("{}", row);
+    }
+}
+
+fn table_rows(tasks: &[Task], width: usize, opts: &ListOptions) -> Vec<String> {
+    let now = Utc::now().timestamp();
+    let cells: Vec<(String, String, String)> = tasks
+        .iter()
+        .map(|task| {
+            let (agent_color, badge) = agent_badge(task, AGENT_STYLES.get());
+            (
+                status_glyph(task),
+                format!("{}{}[{}]{}", BOLD, agent_color, badge, RESET),
+                format!(
+                    "{}{}{}",
+                    DIM,
+                    format_ago(task.updated_at.timestamp(), now),
+                    RESET
+                ),
+            )
+        })
+        .collect();
+
+    let badge_width = cells.iter().map(|c| visible_width(&c.1)).max().unwrap_or(0);
+    let elapsed_width = cells.iter().map(|c| visible_width(&c.2)).max().unwrap_or(0);
+
+    // "  ● " + badge + "  " + title + "  " + elapsed
+    let fixed = 4 + badge_width + 2 + 2 + elapsed_width;
+    let title_width = width.saturating_sub(fixed).max(10);
+
+    tasks
+        .iter()
+        .zip(cells)
+        .map(|(task, (glyph, badge, elapsed))| {
+            format!(
+                "  {}{} {}  {}{}{}  {}",
+                glyph,
+                RESET,
+                pad(&badge, badge_width),
+                title_style(task, opts),
+                pad(&fit(&task.title, title_width), title_width),
+                RESET,
+                pad_left(&elapsed, elapsed_width)
+            )
+        })
+        .collect()
+}
+
+fn title_style(task: &Task, opts: &ListOptions) -> &'static str {
+    if opts.highlight.contains(&task.task_id) {
+        INVERSE
+    } else {
+        WHITE
+    }
+}
+
+/// Yellow for a task that is blocked, green for one that is only waiting for
+/// the user's next prompt
+fn attention_color(reason: &AttentionReason) -> &'static str {
+    if reason.is_blocking() {
+        YELLOW
+    } else {
+        BRIGHT_GREEN
+    }
+}
+
+fn status_glyph(task: &Task) -> String {
+    match task.status {
+        TaskStatus::Running if task.awaiting_reply() => format!("{}{}", BRIGHT_GREEN, ICON_REPLY),
+        TaskStatus::Running if task.needs_attention() => format!("{}{}", YELLOW, "●"),
         TaskStatus::Running => format!("{}{}", BRIGHT_BLUE, "●"),
+        TaskStatus::Paused => format!("{}{}", CYAN, ICON_PAUSED),
         TaskStatus::Completed => format!("{}{}", GREEN, "●"),
         TaskStatus::Exited => format!("{}{}", GRAY, "●"),
+    }
+}
+
+/// Terminal width in columns, or 80 when it can't be detected
+fn terminal_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| w as usize)
+        .unwrap_or(80)
+}
+
+/// Narrowest column budget wrapped text is squeezed into, however small the
+/// terminal
+const MIN_WRAP_WIDTH: usize = 20;
+
+/// Word-wrap plain text into lines of at most `width` terminal columns.
+/// Words longer than a line are split; whitespace runs (including newlines)
+/// collapse to single spaces. Always returns at least one line.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for mut word in text.split_whitespace() {
+        let mut word_width = text_width(word);
+        if line_width > 0 && line_width + 1 + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        while word_width > width {
+            let (head, rest) = split_at_width(word, width);
+            if line_width > 0 {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            lines.push(head.to_string());
+            word = rest;
+            word_width = text_width(word);
+        }
+        if line_width > 0 {
+            line.push(' ');
+            line_width += 1;
+        }
+        line_width += word_width;
+        line.push_str(word);
+    }
+
+    if line_width > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// `s` split after as many characters as fit in `width` columns (at least
+/// one, so a wide character still moves on in a one-column line)
+fn split_at_width(s: &str, width: usize) -> (&str, &str) {
+    let mut used = 0;
+    let end = s
+        .char_indices()
+        .find(|&(i, c)| {
+            used += char_width(c);
+            used > width && i > 0
+        })
+        .map_or(s.len(), |(i, _)| i);
+    s.split_at(end)
+}
+
+/// `text` wrapped to the terminal width for printing after a prefix of
+/// `indent` columns; continuation lines are indented to line up with the
+/// first
+fn wrap_indented(text: &str, indent: usize) -> String {
+    let width = terminal_width().saturating_sub(indent).max(MIN_WRAP_WIDTH);
+    wrap(text, width).join(&format!("\n{}", " ".repeat(indent)))
+}
+
+/// Remove ANSI escape sequences (SGR colors and OSC 8 links)
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: ESC [ ... final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: ESC ] ... terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Terminal columns `c` takes: 0 for controls and combining characters, 2
+/// for wide ones, else 1. Emoji ZWJ sequences count each part, as most
+/// terminals draw them when they can't join them.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Terminal columns of plain text
+fn text_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Display width of a string, ignoring escape sequences
+fn visible_width(s: &str) -> usize {
+    text_width(&strip_ansi(s))
+}
+
+/// Right-pad `s` with spaces to `width` visible columns
+fn pad(s: &str, width: usize) -> String {
+    let padding = width.saturating_sub(visible_width(s));
+    format!("{}{}", s, " ".repeat(padding))
+}
+
+/// Left-pad `s` with spaces to `width` visible columns
+fn pad_left(s: &str, width: usize) -> String {
+    let padding = width.saturating_sub(visible_width(s));
+    format!("{}{}", " ".repeat(padding), s)
+}
+
+/// Shorten plain text to at most `width` columns, marking the cut with "...".
+/// A wide character that would straddle the limit is dropped, so the result
+/// can be a column short.
+fn fit(s: &str, width: usize) -> String {
+    if text_width(s) <= width {
+        return s.to_string();
+    }
+    let budget = width.saturating_sub(3);
+    let mut used = 0;
+    let kept: String = s
+        .chars()
+        .take_while(|&c| {
+            used += char_width(c);
+            used <= budget
+        })
+        .collect();
+    format!("{}...", kept)
+}
+
+/// Compact recency-ordered listing with no status grouping or chrome
+pub fn display_task_top(tasks: &[Task], opts: &ListOptions) {
+    if tasks.is_empty() {
+        outln!("{}{}No tasks{}", DIM, GRAY, RESET);
+        return;
+    }
+
+    for (idx, task) in tasks.iter().enumerate() {
+        print_task_summary(idx + 1, task, opts);
+    }
+}
+
+/// Color and badge text for a task's agent. Configured styles take precedence
+/// over the built-in defaults; unset fields fall back individually.
+fn agent_badge(
+    task: &Task,
+    styles: Option<&HashMap<String, AgentStyle>>,
+) -> (&'static str, String) {
+    let (default_color, default_badge): (&str, String) = match task.agent() {
+        AgentType::ClaudeWeb => (MAGENTA, "claude.ai".to_string()),
+        AgentType::GeminiWeb => (BLUE, "gemini".to_string()),
+        AgentType::ClaudeCode => (CYAN, "claude-code".to_string()),
+        AgentType::OpenCode => (GREEN, "opencode".to_string()),
+        AgentType::Other(name) => {
+            let color = AGENT_PALETTE[agent_color_index(&name)];
+            let short = abbreviate_agent(&name);
+            let agent_label = if let Some(pid) = task.pid {
+                format!("{}:{}", short, pid)
+            } else {
+                short
+            };
+            (color, agent_label)
+        }
     };
 
+    match styles.and_then(|styles| styles.get(&task.agent_type)) {
+        Some(style) => (
+            style
+                .color
+                .as_deref()
+                .and_then(color_code)
+                .unwrap_or(default_color),
+            style.badge.clone().unwrap_or(default_badge),
+        ),
+        None => (default_color, default_badge),
+    }
+}
+
+/// Colors for agents without a built-in or configured one: none of the
+/// known agents' colors, and neither red (failures) nor gray (dimmed)
+const AGENT_PALETTE: [&str; 6] = [
+    YELLOW,
+    BRIGHT_GREEN,
+    BRIGHT_YELLOW,
+    BRIGHT_BLUE,
+    BRIGHT_MAGENTA,
+    BRIGHT_CYAN,
+];
+
+/// Index into `AGENT_PALETTE` for an agent name. FNV-1a, like `dedup_key`,
+/// so an agent keeps its color across runs and builds.
+fn agent_color_index(name: &str) -> usize {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % AGENT_PALETTE.len() as u64) as usize
+}
+
+/// Badge text for an unknown agent: its name, or for names longer than 12
+/// characters the initials of their words ("review-bot" stays,
+/// "internal_release_checker" becomes "IRC")
+fn abbreviate_agent(name: &str) -> String {
+    if name.chars().count() <= 12 {
+        return name.to_string();
+    }
+    let initials: String = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_uppercase)
+        .collect();
+    if initials.chars().count() > 1 {
+        initials
+    } else {
+        fit(name, 12)
+    }
+}
+
+fn print_entry(idx: usize, entry: &ListEntry, opts: &ListOptions) {
+    match entry {
+        ListEntry::Single(task) => print_task_summary(idx, task, opts),
+        ListEntry::Conversation(turns) => print_conversation(idx, turns, opts),
+    }
+}
+
+/// A conversation header carrying the latest turn's status, then one
+/// indented line per turn
+fn print_conversation(idx: usize, turns: &[&Task], opts: &ListOptions) {
+    let latest = turns[turns.len() - 1];
+    let (agent_color, badge) = agent_badge(latest, AGENT_STYLES.get());
+    let now = Utc::now().timestamp();
+
+    out!("  {}{}{:2}.{} ", GRAY, BOLD, idx, RESET);
+    out!("{}{} ", status_glyph(latest), RESET);
+    out!("{}{}[{}]{} ", BOLD, agent_color, badge, RESET);
+    // Narrower than single tasks, leaving room for the turn count
+    let width = opts.title_width().saturating_sub(10);
+    out!("{}\"{}\"{} ", WHITE, fit(&turns[0].title, width), RESET);
+    outln!(
+        "{}{} turns  {}{}",
+        DIM,
+        turns.len(),
+        summary_elapsed(latest, now),
+        RESET
+    );
+
+    for (i, turn) in turns.iter().enumerate() {
+        let branch = if i + 1 == turns.len() {
+            "└─"
+        } else {
+            "├─"
+        };
+        out!("      {}{}{} ", GRAY, branch, RESET);
+        out!("{}{} ", status_glyph(turn), RESET);
+        out!(
+            "{}\"{}\"{} ",
+            title_style(turn, opts),
+            fit(&turn.title, width),
+            RESET
+        );
+        outln!(
+            "{}{}{}",
+            DIM,
+            format_elapsed(turn.updated_at.timestamp(), now),
+            RESET
+        );
+    }
+
+    if latest.needs_attention() || latest.awaiting_reply() {
+        if let Some(reason) = &latest.attention_reason {
+            let color = attention_color(reason);
+            let reason = wrap_indented(&reason.to_string(), 8);
+            outln!("      {}{} {}{}", color, ICON_ARROW, reason, RESET);
+        }
+    }
+}
+
+fn print_task_summary(idx: usize, task: &Task, opts: &ListOptions) {
+    // Agent badge with color
+    let (agent_color, badge) = agent_badge(task, AGENT_STYLES.get());
+
+    let now = Utc::now().timestamp();
+    let elapsed = summary_elapsed(task, now);
+
+    // Status indicator
+    let status_indicator = status_glyph(task);
+
     // Print task line with colors
-    print!("  {}{}{:2}.{} ", GRAY, BOLD, idx, RESET);
-    print!("{}{} ", status_indicator, RESET);
-    print!("{}{}[{}]{} ", BOLD, agent_color, badge, RESET);
-    print!("{}\"{}\"{} ", WHITE, truncate(&task.title, 60), RESET);
-    println!("{}{}{}", DIM, elapsed, RESET);
+    out!("  {}{}{:2}.{} ", GRAY, BOLD, idx, RESET);
+    out!("{}{} ", status_indicator, RESET);
+    out!("{}{}[{}]{} ", BOLD, agent_color, badge, RESET);
+    if let Some(source) = &task.source {
+        out!("{}@{}{} ", DIM, source, RESET);
+    }
+    out!(
+        "{}\"{}\"{} ",
+        title_style(task, opts),
+        fit(&task.title, opts.title_width()),
+        RESET
+    );
+    let sla = task_sla(task, AGENT_STYLES.get(), SLA_SECS.get().copied());
+    if over_sla(task, sla, now) {
+        outln!("{}{}{} (over SLA){}", BOLD, RED, elapsed, RESET);
+    } else {
+        outln!(
+            "{}{}{}",
+            age_color(task.updated_at.timestamp(), now),
+            elapsed,
+            RESET
+        );
+    }
+
+    if task.needs_attention() || task.awaiting_reply() {
+        if let Some(reason) = &task.attention_reason {
+            let color = attention_color(reason);
+            let reason = wrap_indented(&reason.to_string(), 8);
+            outln!("      {}{} {}{}", color, ICON_ARROW, reason, RESET);
+        }
+    }
 
     // Additional info for exited tasks
     if task.status == TaskStatus::Exited {
         if let Some(code) = task.exit_code {
-            println!("      {}{} Exit code: {}{}", GRAY, ICON_ARROW, code, RESET);
+            outln!("      {}{} Exit code: {}{}", GRAY, ICON_ARROW, code, RESET);
         }
     }
 }
 
+/// What an exit code usually means: a few shell conventions, and 128 + the
+/// number of the signal that killed the process
+pub fn describe_exit_code(code: i32) -> &'static str {
+    match code {
+        0 => "success",
+        1 => "failed",
+        2 => "usage error",
+        126 => "not executable",
+        127 => "command not found",
+        129 => "hung up by SIGHUP",
+        130 => "interrupted by SIGINT",
+        131 => "quit by SIGQUIT",
+        134 => "aborted by SIGABRT",
+        136 => "arithmetic error, SIGFPE",
+        137 => "killed by SIGKILL, likely OOM",
+        139 => "segmentation fault, SIGSEGV",
+        141 => "broken pipe, SIGPIPE",
+        143 => "terminated by SIGTERM",
+        _ if (129..=192).contains(&code) => "killed by a signal",
+        _ => "failed",
+    }
+}
+
 pub fn display_task_detail(task: &Task) {
-    println!();
-    println!("{}{}╭─────────────────────────────────────────────╮{}", BOLD, CYAN, RESET);
-    println!("{}{}│  {}Task Details{}                            │{}", BOLD, CYAN, WHITE, CYAN, RESET);
-    println!("{}{}╰─────────────────────────────────────────────╯{}", BOLD, CYAN, RESET);
-    println!();
+    outln!();
+    outln!(
+        "{}{}╭─────────────────────────────────────────────╮{}",
+        BOLD,
+        CYAN,
+        RESET
+    );
+    outln!(
+        "{}{}│  {}Task Details{}                            │{}",
+        BOLD,
+        CYAN,
+        WHITE,
+        CYAN,
+        RESET
+    );
+    outln!(
+        "{}{}╰─────────────────────────────────────────────╯{}",
+        BOLD,
+        CYAN,
+        RESET
+    );
+    outln!();
 
     // Status badge
     let (status_color, status_text) = match task.status {
         TaskStatus::Running => (BRIGHT_BLUE, "RUNNING"),
+        TaskStatus::Paused => (CYAN, "PAUSED"),
         TaskStatus::Completed => (GREEN, "COMPLETED"),
         TaskStatus::Exited => (GRAY, "EXITED"),
     };
 
-    println!("{}{}Status:{} {}{}{}{}", BOLD, GRAY, RESET, BOLD, status_color, status_text, RESET);
-    println!();
+    outln!(
+        "{}{}Status:{} {}{}{}{}",
+        BOLD,
+        GRAY,
+        RESET,
+        BOLD,
+        status_color,
+        status_text,
+        RESET
+    );
+    outln!();
 
-    println!("{}{}ID:{} {}{}{}", BOLD, GRAY, RESET, CYAN, task.task_id, RESET);
-    println!("{}{}Agent:{} {}{}{}", BOLD, GRAY, RESET, MAGENTA, task.agent_type, RESET);
-    println!("{}{}Title:{} {}{}{}", BOLD, GRAY, RESET, WHITE, task.title, RESET);
-    println!();
+    outln!(
+        "{}{}ID:{} {}{}{}",
+        BOLD,
+        GRAY,
+        RESET,
+        CYAN,
+        task.task_id,
+        RESET
+    );
+    outln!(
+        "{}{}Agent:{} {}{}{}",
+        BOLD,
+        GRAY,
+        RESET,
+        MAGENTA,
+        task.agent_type,
+        RESET
+    );
+    let title = wrap_indented(&task.title, "Title: ".len());
+    outln!(
+        "{}{}Title:{} {}{}{}",
+        BOLD,
+        GRAY,
+        RESET,
+        WHITE,
+        title,
+        RESET
+    );
+    outln!();
 
-    println!("{}{}Timestamps:{}", BOLD, GRAY, RESET);
-    println!("  {}Created:  {}{}{}", GRAY, RESET, format_datetime(&task.created_at), RESET);
-    println!("  {}Updated:  {}{}{}", GRAY, RESET, format_datetime(&task.updated_at), RESET);
+    let now = Utc::now();
+    let ago = |at: &DateTime<Utc>| format_datetime_ago(at, now.timestamp());
+    outln!("{}{}Timestamps:{}", BOLD, GRAY, RESET);
+    outln!(
+        "  {}Created:  {}{}{}",
+        GRAY,
+        RESET,
+        ago(&task.created_at),
+        RESET
+    );
+    outln!(
+        "  {}Updated:  {}{}{}",
+        GRAY,
+        RESET,
+        ago(&task.updated_at),
+        RESET
+    );
     if let Some(completed) = task.completed_at {
-        println!("  {}Completed: {}{}{}", GRAY, GREEN, format_datetime(&completed), RESET);
+        outln!("  {}Completed: {}{}{}", GRAY, GREEN, ago(&completed), RESET);
+    }
+    if let (Some(expires), Some(ttl)) = (task.expires_at, task.time_to_live(now)) {
+        let left = match ttl.num_seconds() {
+            secs if secs > 0 => format!("in {}", format_idle(secs as u64)),
+            _ => "now (removed on the next run)".to_string(),
+        };
+        let at = format_datetime(&expires);
+        outln!(
+            "  {}Expires:  {}{} {}({}){}",
+            GRAY,
+            RESET,
+            left,
+            DIM,
+            at,
+            RESET
+        );
     }
-    println!();
+    outln!();
 
     if task.pid.is_some() || task.ppid.is_some() {
-        println!("{}{}Process Info:{}", BOLD, GRAY, RESET);
+        outln!("{}{}Process Info:{}", BOLD, GRAY, RESET);
         if let Some(pid) = task.pid {
-            println!("  {}PID:     {}{}{}", GRAY, RESET, pid, RESET);
+            outln!("  {}PID:     {}{}{}", GRAY, RESET, pid, RESET);
         }
         if let Some(ppid) = task.ppid {
-            println!("  {}Parent:  {}{}{}", GRAY, RESET, ppid, RESET);
+            outln!("  {}Parent:  {}{}{}", GRAY, RESET, ppid, RESET);
         }
         if let Some(monitor_pid) = task.monitor_pid {
-            println!("  {}Monitor: {}{}{}", GRAY, RESET, monitor_pid, RESET);
+            let beat = match task.last_heartbeat {
+                Some(beat) => {
+                    let ago = format_ago(beat.timestamp(), now.timestamp());
+                    format!(" {}(last beat {}){}", DIM, ago, RESET)
+                }
+                None => String::new(),
+            };
+            outln!(
+                "  {}Monitor: {}{}{}{}",
+                GRAY,
+                RESET,
+                monitor_pid,
+                beat,
+                RESET
+            );
         }
-        println!();
+        outln!();
     }
 
     if let Some(reason) = &task.attention_reason {
-        println!("{}{} Attention Reason:{} {}{}{}", BOLD, YELLOW, RESET, YELLOW, reason, RESET);
-        println!();
+        let color = attention_color(reason);
+        let reason = wrap_indented(&reason.to_string(), " Attention Reason: ".len());
+        outln!(
+            "{}{} Attention Reason:{} {}{}{}",
+            BOLD,
+            color,
+            RESET,
+            color,
+            reason,
+            RESET
+        );
+        outln!();
     }
 
     if let Some(code) = task.exit_code {
-        println!("{}{} Exit Code:{} {}{}{}", BOLD, RED, RESET, RED, code, RESET);
-        println!();
+        let meaning = describe_exit_code(code);
+        let color = match code {
+            0 => GREEN,
+            // Stopped on purpose rather than crashed
+            130 | 143 => YELLOW,
+            _ => RED,
+        };
+        outln!(
+            "{}{} Exit Code:{} {}{} ({}){}",
+            BOLD,
+            color,
+            RESET,
+            color,
+            code,
+            meaning,
+            RESET
+        );
+        outln!();
+    }
+
+    for (column, raw) in [
+        ("context", &task.raw_context),
+        ("metadata", &task.raw_metadata),
+    ] {
+        if let Some(raw) = raw {
+            outln!(
+                "{}{}Unreadable {} (kept as stored):{}",
+                BOLD,
+                RED,
+                column,
+                RESET
+            );
+            outln!("  {}{}{}", GRAY, fit(raw, 200), RESET);
+            outln!();
+        }
     }
 
     if let Some(context) = &task.context {
-        println!("{}{}Context:{}", BOLD, GRAY, RESET);
+        outln!("{}{}Context:{}", BOLD, GRAY, RESET);
         if let Some(url) = &context.url {
-            println!("  {}URL:        {}{}{}", GRAY, BRIGHT_CYAN, url, RESET);
+            outln!(
+                "  {}URL:        {}{}{}",
+                GRAY,
+                BRIGHT_CYAN,
+                hyperlink(url, url),
+                RESET
+            );
         }
         if let Some(path) = &context.project_path {
-            println!("  {}Project:    {}{}{}", GRAY, CYAN, path, RESET);
+            outln!("  {}Project:    {}{}{}", GRAY, CYAN, path, RESET);
         }
         if let Some(session) = &context.session_id {
-            println!("  {}Session ID: {}{}{}", GRAY, RESET, session, RESET);
+            outln!("  {}Session ID: {}{}{}", GRAY, RESET, session, RESET);
         }
         if !context.extra.is_empty() {
-            println!("  {}Extra:{}", GRAY, RESET);
+            outln!("  {}Extra:{}", GRAY, RESET);
             for (key, value) in &context.extra {
-                println!("    {}{}: {}{}", GRAY, key, RESET, value);
+                outln!("    {}{}: {}{}", GRAY, key, RESET, value);
+            }
+        }
+        outln!();
+    }
+
+    let related = task.related();
+    if !related.is_empty() {
+        outln!("{}{}Related:{}", BOLD, GRAY, RESET);
+        for task_id in related {
+            outln!("  {}{}{}", CYAN, task_id, RESET);
+        }
+        outln!();
+    }
+
+    let env = task
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get(ENV_KEY))
+        .and_then(Value::as_object);
+    if let Some(env) = env.filter(|env| !env.is_empty()) {
+        outln!("{}{}Environment:{}", BOLD, GRAY, RESET);
+        for (name, value) in env {
+            match value.as_str() {
+                Some(value) => outln!("  {}{}={}{}", GRAY, name, RESET, value),
+                None => outln!("  {}{}={}{}", GRAY, name, RESET, value),
             }
         }
-        println!();
+        outln!();
+    }
+
+    // Related tasks and the environment have their own sections above
+    let metadata = task
+        .metadata
+        .iter()
+        .flatten()
+        .filter(|(key, _)| *key != RELATED_KEY && *key != ENV_KEY);
+    let mut entries: Vec<_> = metadata.collect();
+    if !entries.is_empty() {
+        outln!("{}{}Metadata:{}", BOLD, GRAY, RESET);
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in entries {
+            outln!("  {}{}: {}{}", GRAY, key, RESET, value);
+        }
+        outln!();
+    }
+}
+
+/// Render `text` as a clickable OSC 8 hyperlink to `url`, or plain text when
+/// color is off (by default, when stdout isn't a terminal)
+fn hyperlink(url: &str, text: &str) -> String {
+    if color_enabled() {
+        osc8(url, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn osc8(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+pub fn display_task_history(task: &Task, events: &[TaskEvent]) {
+    outln!();
+    outln!(
+        "{}{}History:{} {}\"{}\"{} {}({}){}",
+        BOLD,
+        GRAY,
+        RESET,
+        WHITE,
+        task.title,
+        RESET,
+        DIM,
+        task.task_id,
+        RESET
+    );
+    outln!("{}{}{}", GRAY, "─".repeat(50), RESET);
+
+    if events.is_empty() {
+        outln!("  {}{}No recorded status changes{}", DIM, GRAY, RESET);
+    }
+
+    for event in events {
+        let from = event
+            .from_status
+            .as_ref()
+            .map(|s| s.as_str().to_string())
+            .unwrap_or_else(|| "created".to_string());
+        out!(
+            "  {}{}{}  {} {} {}{}{}",
+            GRAY,
+            format_datetime(&event.at),
+            RESET,
+            from,
+            ICON_ARROW,
+            BOLD,
+            event.to_status.as_str(),
+            RESET
+        );
+        match &event.reason {
+            Some(reason) => outln!("  {}{}{}", YELLOW, reason, RESET),
+            None => outln!(),
+        }
     }
+    outln!();
 }
 
 fn format_datetime(dt: &chrono::DateTime<Utc>) -> String {
     dt.format("%Y-%m-%d %H:%M:%S UTC").to_string()
 }
 
-fn format_elapsed(timestamp: i64) -> String {
-    let now = Utc::now().timestamp();
-    let elapsed = now - timestamp;
+/// Absolute time followed by the relative one as of `now`, e.g.
+/// "2024-05-01 10:00:00 UTC (2h ago)"
+fn format_datetime_ago(dt: &DateTime<Utc>, now: i64) -> String {
+    format!(
+        "{} {}{}",
+        format_datetime(dt),
+        DIM,
+        format_elapsed(dt.timestamp(), now)
+    )
+}
+
+fn format_elapsed(timestamp: i64, now: i64) -> String {
+    format!("({})", format_ago(timestamp, now))
+}
+
+/// Seconds from `timestamp` to `now`. The elapsed helpers take the current
+/// time from their caller, so tests can pin it.
+fn elapsed_secs(timestamp: i64, now: i64) -> i64 {
+    now - timestamp
+}
+
+/// Color for a row's elapsed text by how long ago the task last changed:
+/// green within a minute, yellow within an hour, red beyond, so
+/// long-outstanding tasks stand out in a long list
+fn age_color(timestamp: i64, now: i64) -> &'static str {
+    match elapsed_secs(timestamp, now) {
+        ..=59 => GREEN,
+        60..=3599 => YELLOW,
+        _ => RED,
+    }
+}
+
+/// The run time expected of a task: its agent's `sla_secs`, else the global one
+fn task_sla(
+    task: &Task,
+    styles: Option<&HashMap<String, AgentStyle>>,
+    global: Option<u64>,
+) -> Option<u64> {
+    styles
+        .and_then(|styles| styles.get(&task.agent_type))
+        .and_then(|style| style.sla_secs)
+        .or(global)
+}
+
+/// Whether a running task has been going longer than `sla` seconds since it
+/// was created. A chat waiting for a reply is idle, not late.
+fn over_sla(task: &Task, sla: Option<u64>, now: i64) -> bool {
+    match sla {
+        Some(sla) if task.status == TaskStatus::Running && !task.awaiting_reply() => {
+            now - task.created_at.timestamp() > sla as i64
+        }
+        _ => false,
+    }
+}
+
+fn format_ago(timestamp: i64, now: i64) -> String {
+    let elapsed = elapsed_secs(timestamp, now);
 
     if elapsed < 60 {
-        format!("({}s ago)", elapsed)
+        format!("{}s ago", elapsed)
     } else if elapsed < 3600 {
-        format!("({}m ago)", elapsed / 60)
+        format!("{}m ago", elapsed / 60)
     } else if elapsed < 86400 {
-        format!("({}h ago)", elapsed / 3600)
+        format!("{}h ago", elapsed / 3600)
     } else {
-        format!("({}d ago)", elapsed / 86400)
+        format!("{}d ago", elapsed / 86400)
     }
 }
 
-fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max_len - 3])
+/// Elapsed text for a list row. Running tasks show both when they started and
+/// when they last reported, so long-running work doesn't look fresh just
+/// because it updated recently. Finished tasks show time since completion.
+fn summary_elapsed(task: &Task, now: i64) -> String {
+    let ago = |at: DateTime<Utc>| format_ago(at.timestamp(), now);
+    match (&task.status, task.completed_at) {
+        (TaskStatus::Running, _) => format!(
+            "(started {}, updated {})",
+            ago(task.created_at),
+            ago(task.updated_at)
+        ),
+        (TaskStatus::Paused, _) => format!("(paused {})", ago(task.updated_at)),
+        (TaskStatus::Completed, Some(completed)) => format!("(completed {})", ago(completed)),
+        (TaskStatus::Exited, Some(completed)) => format!("(exited {})", ago(completed)),
+        _ => format_elapsed(task.updated_at.timestamp(), now),
     }
 }
 
@@ -254,13 +1762,665 @@ fn truncate(s: &str, max_len: usize) -> String {
 mod tests {
     use super::*;
 
+    fn web_turn(id: &str, conversation: Option<&str>, minutes_ago: i64) -> Task {
+        let mut task = Task::new(
+            id.to_string(),
+            "claude_web".to_string(),
+            format!("Turn {}", id),
+            None,
+            None,
+        );
+        task.updated_at = Utc::now() - chrono::Duration::minutes(minutes_ago);
+        task.context = conversation.map(|c| crate::models::TaskContext {
+            url: None,
+            project_path: None,
+            session_id: None,
+            extra: HashMap::from([("conversation_id".to_string(), serde_json::json!(c))]),
+        });
+        task
+    }
+
+    #[test]
+    fn test_group_conversations() {
+        // Newest first, as list_tasks returns them
+        let tasks = vec![
+            web_turn("c2", Some("conv"), 1),
+            web_turn("flat", None, 2),
+            web_turn("solo", Some("other"), 3),
+            web_turn("c1", Some("conv"), 4),
+        ];
+
+        let entries = group_conversations(&tasks);
+        assert_eq!(entries.len(), 3);
+        match &entries[0] {
+            ListEntry::Conversation(turns) => {
+                let ids: Vec<_> = turns.iter().map(|t| t.task_id.as_str()).collect();
+                assert_eq!(ids, ["c1", "c2"]);
+            }
+            other => panic!("expected a conversation, got {:?}", other),
+        }
+        assert_eq!(entries[0].latest().task_id, "c2");
+        assert!(matches!(entries[1], ListEntry::Single(t) if t.task_id == "flat"));
+        assert!(matches!(entries[2], ListEntry::Single(t) if t.task_id == "solo"));
+    }
+
+    fn keys(groups: &[(GroupKey, Vec<ListEntry>)]) -> Vec<GroupKey> {
+        groups.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    #[test]
+    fn test_group_entries() {
+        let mut tasks = [
+            web_turn("web", None, 1),
+            web_turn("code-done", None, 2),
+            web_turn("code", None, 3),
+        ];
+        tasks[1].agent_type = "claude_code".to_string();
+        tasks[1].complete();
+        tasks[2].agent_type = "claude_code".to_string();
+        tasks[2].context = Some(crate::models::TaskContext {
+            url: None,
+            project_path: Some("/repo/".to_string()),
+            session_id: None,
+            extra: HashMap::new(),
+        });
+        let entries = || tasks.iter().map(ListEntry::Single).collect::<Vec<_>>();
+
+        let by_status = group_entries(entries(), GroupBy::Status);
+        assert_eq!(
+            keys(&by_status),
+            [
+                GroupKey::Status(TaskStatus::Running),
+                GroupKey::Status(TaskStatus::Completed)
+            ]
+        );
+        assert_eq!(by_status[0].1.len(), 2);
+
+        // Agents appear in order of their most recent task
+        let by_agent = group_entries(entries(), GroupBy::Agent);
+        assert_eq!(
+            keys(&by_agent),
+            [
+                GroupKey::Agent("claude_web".to_string()),
+                GroupKey::Agent("claude_code".to_string())
+            ]
+        );
+        let code: Vec<_> = by_agent[1]
+            .1
+            .iter()
+            .map(|e| e.latest().task_id.as_str())
+            .collect();
+        assert_eq!(code, ["code-done", "code"]);
+
+        let by_project = group_entries(entries(), GroupBy::Project);
+        assert_eq!(
+            keys(&by_project),
+            [
+                GroupKey::Project(None),
+                GroupKey::Project(Some("/repo".to_string()))
+            ]
+        );
+
+        let flat = group_entries(entries(), GroupBy::None);
+        assert_eq!(keys(&flat), [GroupKey::All]);
+        assert_eq!(flat[0].1.len(), 3);
+    }
+
+    #[test]
+    fn test_numbered_follows_status_sections() {
+        let mut tasks = [
+            web_turn("done", None, 1),
+            web_turn("run", None, 2),
+            web_turn("turn-2", Some("chat"), 3),
+            web_turn("turn-1", Some("chat"), 4),
+        ];
+        tasks[0].complete();
+        let ids = |opts: &ListOptions| -> Vec<String> {
+            numbered(&tasks, opts)
+                .iter()
+                .map(|t| t.task_id.clone())
+                .collect()
+        };
+        assert_eq!(
+            ids(&ListOptions::default()),
+            ["run", "turn-2", "turn-1", "done"]
+        );
+        let flat = ListOptions {
+            group_by: GroupBy::None,
+            ..Default::default()
+        };
+        assert_eq!(ids(&flat), ["done", "run", "turn-2", "turn-1"]);
+        // A conversation is one entry, numbered as its latest turn
+        let tree = ListOptions {
+            tree: true,
+            ..Default::default()
+        };
+        assert_eq!(ids(&tree), ["run", "turn-2", "done"]);
+    }
+
+    #[test]
+    fn test_conversation_falls_back_to_session_id() {
+        let mut task = web_turn("a", None, 0);
+        assert_eq!(task.conversation_id(), None);
+
+        task.context = Some(crate::models::TaskContext {
+            url: None,
+            project_path: None,
+            session_id: Some("sess".to_string()),
+            extra: HashMap::new(),
+        });
+        assert_eq!(task.conversation_id(), Some("sess"));
+    }
+
+    /// A fixed "current time" for the elapsed helpers
+    fn fixed_now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_714_557_600, 0).unwrap()
+    }
+
     #[test]
     fn test_format_elapsed() {
-        let now = Utc::now().timestamp();
+        let now = fixed_now().timestamp();
+
+        assert_eq!(format_elapsed(now, now), "(0s ago)");
+        assert_eq!(format_elapsed(now - 30, now), "(30s ago)");
+        assert_eq!(format_elapsed(now - 59, now), "(59s ago)");
+        assert_eq!(format_elapsed(now - 120, now), "(2m ago)");
+        assert_eq!(format_elapsed(now - 3599, now), "(59m ago)");
+        assert_eq!(format_elapsed(now - 3660, now), "(1h ago)");
+        assert_eq!(format_elapsed(now - 90000, now), "(1d ago)");
+    }
+
+    #[test]
+    fn test_format_datetime_ago() {
+        let dt = fixed_now() - chrono::Duration::hours(2);
+        let text = strip_ansi(&format_datetime_ago(&dt, fixed_now().timestamp()));
+        assert_eq!(text, "2024-05-01 08:00:00 UTC (2h ago)");
+    }
+
+    #[test]
+    fn test_age_color() {
+        let now = fixed_now().timestamp();
+        assert_eq!(age_color(now - 5, now), GREEN);
+        assert_eq!(age_color(now - 60, now), YELLOW);
+        assert_eq!(age_color(now - 600, now), YELLOW);
+        assert_eq!(age_color(now - 3600, now), RED);
+        assert_eq!(age_color(now - 7200, now), RED);
+    }
+
+    #[test]
+    fn test_over_sla() {
+        let mut task = Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        let now = task.created_at.timestamp() + 600;
+        assert!(!over_sla(&task, None, now));
+        assert!(!over_sla(&task, Some(600), now));
+        assert!(over_sla(&task, Some(599), now));
+
+        // Blocked tasks still are, chats waiting for a reply aren't
+        task.flag_attention(AttentionReason::Custom("Approve?".to_string()));
+        assert!(over_sla(&task, Some(599), now));
+        task.flag_attention(AttentionReason::ReadyForReply);
+        assert!(!over_sla(&task, Some(599), now));
+
+        // Only running tasks can be over
+        task.complete();
+        assert!(!over_sla(&task, Some(1), now));
+
+        // The agent's own SLA wins over the global one
+        let mut styles = HashMap::new();
+        styles.insert(
+            "claude_code".to_string(),
+            AgentStyle {
+                sla_secs: Some(60),
+                ..Default::default()
+            },
+        );
+        assert_eq!(task_sla(&task, Some(&styles), Some(3600)), Some(60));
+        styles.clear();
+        assert_eq!(task_sla(&task, Some(&styles), Some(3600)), Some(3600));
+        assert_eq!(task_sla(&task, None, None), None);
+    }
+
+    #[test]
+    fn test_summary_elapsed() {
+        let now = fixed_now();
+        let mut task = Task::new_at(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+            now - chrono::Duration::hours(2),
+        );
+        task.updated_at = now - chrono::Duration::minutes(3);
+        assert_eq!(
+            summary_elapsed(&task, now.timestamp()),
+            "(started 2h ago, updated 3m ago)"
+        );
+
+        task.complete_at(now - chrono::Duration::seconds(30));
+        assert_eq!(
+            summary_elapsed(&task, now.timestamp()),
+            "(completed 30s ago)"
+        );
+    }
+
+    #[test]
+    fn test_color_code() {
+        assert_eq!(color_code("yellow"), Some(YELLOW));
+        assert_eq!(color_code("Bright-Blue"), Some(BRIGHT_BLUE));
+        assert_eq!(color_code("grey"), Some(GRAY));
+        assert_eq!(color_code("chartreuse"), None);
+    }
+
+    #[test]
+    fn test_agent_badge_overrides() {
+        let task = |agent: &str| {
+            Task::new(
+                "test-id".to_string(),
+                agent.to_string(),
+                "Test task".to_string(),
+                Some(42),
+                None,
+            )
+        };
+
+        let mut styles = HashMap::new();
+        styles.insert(
+            "aider".to_string(),
+            AgentStyle {
+                color: Some("yellow".to_string()),
+                badge: Some("aider".to_string()),
+                ..Default::default()
+            },
+        );
+        styles.insert(
+            "claude_code".to_string(),
+            AgentStyle {
+                color: Some("not-a-color".to_string()),
+                badge: Some("cc".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            agent_badge(&task("aider"), Some(&styles)),
+            (YELLOW, "aider".to_string())
+        );
+        // Unparseable colors keep the built-in default
+        assert_eq!(
+            agent_badge(&task("claude_code"), Some(&styles)),
+            (CYAN, "cc".to_string())
+        );
+        // Unknown agents without config get a color of their own
+        let cursor = AGENT_PALETTE[agent_color_index("cursor")];
+        assert_eq!(
+            agent_badge(&task("cursor"), Some(&styles)),
+            (cursor, "cursor:42".to_string())
+        );
+        assert_eq!(
+            agent_badge(&task("opencode"), None),
+            (GREEN, "opencode".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_agent_colors() {
+        for name in ["cursor", "aider", "internal_release_checker", ""] {
+            let index = agent_color_index(name);
+            assert!(index < AGENT_PALETTE.len());
+            assert_eq!(agent_color_index(name), index, "{} changed color", name);
+        }
+        // Pinned, so a custom agent keeps its color from one release to the next
+        assert_eq!(agent_color_index("cursor"), 3);
+        assert_eq!(agent_color_index("goose"), 4);
+        let colors: HashSet<usize> = ["cursor", "aider", "goose", "codex", "cline", "amp"]
+            .map(agent_color_index)
+            .into();
+        assert!(colors.len() > 1, "every agent got the same color");
+
+        assert_eq!(abbreviate_agent("review-bot"), "review-bot");
+        assert_eq!(abbreviate_agent("internal_release_checker"), "IRC");
+        assert_eq!(abbreviate_agent("supercalifragilistic"), "supercali...");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+        assert_eq!(wrap("short", 10), ["short"]);
+        assert_eq!(wrap("", 10), [""]);
+
+        // Newlines and runs of spaces collapse
+        assert_eq!(wrap("one\n\ntwo   three", 80), ["one two three"]);
+
+        // Words longer than a line are split across lines
+        assert_eq!(
+            wrap("see abcdefghijkl now", 5),
+            ["see", "abcde", "fghij", "kl", "now"]
+        );
+
+        // Widths count characters, not bytes
+        assert_eq!(wrap("ééé ééé", 3), ["ééé", "ééé"]);
+
+        // ...and wide ones take two columns
+        assert_eq!(wrap("修复 解析器", 6), ["修复", "解析器"]);
+        assert_eq!(
+            wrap("修复解析器的错误", 5),
+            ["修复", "解析", "器的", "错误"]
+        );
+        assert_eq!(wrap("🚀 ship it", 7), ["🚀 ship", "it"]);
+    }
+
+    #[test]
+    fn test_query_rows_align() {
+        let result = QueryResult {
+            columns: vec!["agent_type".to_string(), "n".to_string()],
+            rows: vec![
+                vec![serde_json::json!("claude_code"), serde_json::json!(12)],
+                vec![serde_json::json!("aider"), serde_json::Value::Null],
+            ],
+        };
+        let rows: Vec<String> = query_rows(&result).iter().map(|r| strip_ansi(r)).collect();
+        assert_eq!(
+            rows,
+            [
+                "agent_type   n",
+                "───────────  ────",
+                "claude_code  12",
+                "aider        NULL"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_lines() {
+        let lines: Vec<String> = (1..=140).map(|i| format!("  - task {}", i)).collect();
+        let preview = preview_lines(&lines, 3);
+        assert_eq!(
+            preview,
+            ["  - task 1", "  - task 2", "  - task 3", "  (and 137 more)"]
+        );
 
-        assert_eq!(format_elapsed(now - 30), "(30s ago)");
-        assert_eq!(format_elapsed(now - 120), "(2m ago)");
-        assert_eq!(format_elapsed(now - 3660), "(1h ago)");
-        assert_eq!(format_elapsed(now - 90000), "(1d ago)");
+        // Nothing to summarize at or under the limit
+        assert_eq!(preview_lines(&lines[..3], 3), &lines[..3]);
+        assert!(preview_lines(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_version_text() {
+        let info = VersionInfo {
+            version: "0.1.0",
+            git_sha: "0123456789ab",
+            schema_version: 8,
+            db_path: PathBuf::from("/home/me/.agent-tasks/tasks.db"),
+        };
+        assert_eq!(
+            version_text(&info),
+            "agent-inbox 0.1.0 (0123456789ab)\n\
+             schema version 8\n\
+             database /home/me/.agent-tasks/tasks.db"
+        );
+    }
+
+    #[test]
+    fn test_prompt_summary() {
+        assert_eq!(prompt_summary(&PromptCounts::default()), "");
+        let counts = PromptCounts {
+            running: 3,
+            attention: 2,
+            ready: 0,
+        };
+        assert_eq!(prompt_summary(&counts), "2⚠ 3●");
+    }
+
+    #[test]
+    fn test_plain_row() {
+        let now = fixed_now();
+        let three_minutes_ago = now - chrono::Duration::minutes(3);
+        let row = |task: &Task| plain_row(task, now.timestamp());
+        let mut task = web_turn("t1", None, 0);
+        task.updated_at = three_minutes_ago;
+        task.title = "Fix the\nparser — now".to_string();
+        assert_eq!(
+            row(&task),
+            "[RUNNING] claude_web \"Fix the parser ? now\" (3m ago)"
+        );
+
+        task.flag_attention_at(AttentionReason::Idle { idle_secs: 600 }, three_minutes_ago);
+        assert!(row(&task).starts_with("[ATTN] "));
+        assert!(row(&task).ends_with("(3m ago): No CPU activity for 10m"));
+
+        // A finished chat turn is flagged, but doesn't read as a problem
+        task.flag_attention_at(AttentionReason::ReadyForReply, three_minutes_ago);
+        assert!(row(&task).starts_with("[REPLY] "));
+        assert!(status_glyph(&task).ends_with(ICON_REPLY));
+
+        task.set_exited_at(Some(2), three_minutes_ago);
+        assert_eq!(
+            row(&task),
+            "[EXITED 2] claude_web \"Fix the parser ? now\" (3m ago)"
+        );
+        assert!(row(&task).is_ascii());
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(
+            strip_ansi(&format!("{}{}[claude-code]{}", BOLD, CYAN, RESET)),
+            "[claude-code]"
+        );
+        assert_eq!(strip_ansi(&osc8("https://claude.ai", "link")), "link");
+        assert_eq!(visible_width(&format!("{}●{}", GREEN, RESET)), 1);
+    }
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width("ascii"), 5);
+        assert_eq!(text_width("修复解析器"), 10);
+        assert_eq!(text_width("한국어"), 6);
+        assert_eq!(text_width("ｆｕｌｌ"), 8);
+        assert_eq!(text_width("🚀 ship"), 7);
+        // Combining accent and variation selector take no column
+        assert_eq!(text_width("e\u{301}"), 1);
+        assert_eq!(text_width("\u{2764}\u{fe0f}"), 1);
+        assert_eq!(visible_width(&format!("{}日本{}", BOLD, RESET)), 4);
+
+        assert_eq!(fit("修复解析器的错误", 9), "修复解...");
+        // The wide character that would cross the limit is dropped
+        assert_eq!(fit("a修复解析器的错误", 9), "a修复...");
+        assert_eq!(fit("修复", 4), "修复");
+    }
+
+    #[test]
+    fn test_table_rows_align() {
+        let mut tasks = vec![
+            Task::new(
+                "a".to_string(),
+                "claude_code".to_string(),
+                "Short".to_string(),
+                None,
+                None,
+            ),
+            Task::new(
+                "b".to_string(),
+                "gemini_web".to_string(),
+                "A considerably longer title that will not fit in the title column".to_string(),
+                None,
+                None,
+            ),
+        ];
+        tasks[1].complete();
+        tasks[1].updated_at = Utc::now() - chrono::Duration::hours(3);
+
+        let rows = table_rows(&tasks, 60, &ListOptions::default());
+        let widths: Vec<_> = rows.iter().map(|r| visible_width(r)).collect();
+        assert_eq!(widths[0], widths[1]);
+        assert!(widths[0] <= 60);
+
+        // Titles start in the same column regardless of badge length
+        let plain: Vec<_> = rows.iter().map(|r| strip_ansi(r)).collect();
+        assert_eq!(plain[0].find("Short"), plain[1].find("A considerably"));
+
+        // Double-width titles, long and short, pad to the same columns
+        let mut wide = tasks.clone();
+        wide[0].title = "修复 🚀".to_string();
+        wide[1].title = "修复解析器中的一个非常长的错误，它不适合标题栏的宽度".to_string();
+        let wide_rows = table_rows(&wide, 60, &ListOptions::default());
+        assert_eq!(visible_width(&wide_rows[0]), widths[0]);
+        assert_eq!(visible_width(&wide_rows[1]), widths[1]);
+
+        // Highlighting changes styling only, not alignment
+        let opts = ListOptions {
+            highlight: HashSet::from(["b".to_string()]),
+            ..Default::default()
+        };
+        let highlighted = table_rows(&tasks, 60, &opts);
+        assert!(highlighted[1].contains(INVERSE));
+        assert!(!highlighted[0].contains(INVERSE));
+        assert_eq!(visible_width(&highlighted[1]), widths[1]);
+    }
+
+    #[test]
+    fn test_structured_formats() {
+        let task = Task::new(
+            "a".to_string(),
+            "claude_code".to_string(),
+            "Task".to_string(),
+            None,
+            None,
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&structured(&task, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["task_id"], "a");
+        assert_eq!(json["status"], "running");
+
+        let yaml = structured(&[task], OutputFormat::Yaml).unwrap();
+        assert!(yaml.starts_with("- id: null"));
+        assert!(yaml.contains("status: running"));
+    }
+
+    #[test]
+    fn test_change_tracker() {
+        let mut tasks = vec![Task::new(
+            "a".to_string(),
+            "claude_code".to_string(),
+            "Task".to_string(),
+            None,
+            None,
+        )];
+        let mut tracker = ChangeTracker::default();
+
+        // Nothing is highlighted on the first frame
+        assert!(tracker.update(&tasks).is_empty());
+        assert!(tracker.update(&tasks).is_empty());
+
+        tasks[0].complete();
+        let mut added = tasks[0].clone();
+        added.task_id = "b".to_string();
+        tasks.push(added);
+        assert_eq!(
+            tracker.update(&tasks),
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+
+        // The highlight fades once the task stops changing
+        assert!(tracker.update(&tasks).is_empty());
+    }
+
+    #[test]
+    fn test_project_fields() {
+        let mut task = web_turn("t1", None, 0);
+        task.source = Some("other.db".to_string());
+        task.raw_context = Some("{".to_string());
+        task.raw_metadata = Some("[".to_string());
+        let Value::Object(all) = serde_json::to_value(&task).unwrap() else {
+            panic!("task is not an object");
+        };
+        // Every serialized field is selectable
+        let mut keys: Vec<&str> = all.keys().map(String::as_str).collect();
+        let mut known = TASK_FIELDS.to_vec();
+        keys.sort();
+        known.sort();
+        assert_eq!(keys, known);
+
+        let fields = [
+            "task_id".to_string(),
+            "status".to_string(),
+            "title".to_string(),
+        ];
+        assert_eq!(
+            project(&task, &fields).unwrap(),
+            serde_json::json!({"task_id": "t1", "status": "running", "title": "Turn t1"})
+        );
+        task.source = None;
+        assert_eq!(
+            project(&task, &["source".to_string()]).unwrap(),
+            serde_json::json!({"source": null})
+        );
+    }
+
+    #[test]
+    fn test_validate_fields() {
+        assert!(validate_fields(&["task_id".to_string(), "exit_code".to_string()]).is_ok());
+        let err = validate_fields(&["task_id".to_string(), "name".to_string()]).unwrap_err();
+        let message = err.to_string();
+        let expected = "Unknown field: name (valid fields: id, task_id,";
+        assert!(message.starts_with(expected), "{}", message);
+        assert_eq!(
+            crate::error::code_of(&err),
+            crate::error::ErrorCode::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_describe_exit_code() {
+        assert_eq!(describe_exit_code(0), "success");
+        assert_eq!(describe_exit_code(3), "failed");
+        assert_eq!(describe_exit_code(127), "command not found");
+        assert_eq!(describe_exit_code(130), "interrupted by SIGINT");
+        assert_eq!(describe_exit_code(137), "killed by SIGKILL, likely OOM");
+        assert_eq!(describe_exit_code(143), "terminated by SIGTERM");
+        // Other signals are still recognised as signals
+        assert_eq!(describe_exit_code(128 + 10), "killed by a signal");
+        assert_eq!(describe_exit_code(-1), "failed");
+    }
+
+    #[test]
+    fn test_attention_tracker() {
+        let mut tasks = vec![web_turn("a", None, 0), web_turn("b", None, 0)];
+        tasks[0].flag_attention(crate::models::AttentionReason::Custom(
+            "Approve?".to_string(),
+        ));
+        let mut tracker = AttentionTracker::default();
+
+        // Already flagged when watching starts: no alert
+        assert!(tracker.update(&tasks).is_empty());
+
+        tasks[1].flag_attention(crate::models::AttentionReason::Custom(
+            "Approve?".to_string(),
+        ));
+        assert_eq!(tracker.update(&tasks), HashSet::from(["b".to_string()]));
+        // Still flagged is not new
+        assert!(tracker.update(&tasks).is_empty());
+
+        // Cleared and flagged again alerts again
+        tasks[0].clear_attention();
+        assert!(tracker.update(&tasks).is_empty());
+        tasks[0].flag_attention(crate::models::AttentionReason::Custom("Again".to_string()));
+        assert_eq!(tracker.update(&tasks), HashSet::from(["a".to_string()]));
+    }
+
+    #[test]
+    fn test_osc8_hyperlink() {
+        assert_eq!(
+            osc8("https://claude.ai/chat/123", "link"),
+            "\x1b]8;;https://claude.ai/chat/123\x1b\\link\x1b]8;;\x1b\\"
+        );
     }
 }
@@ -0,0 +1,138 @@
+//! Stable failure codes for scripts
+//!
+//! Errors are plain `anyhow` throughout. The ones a caller may want to
+//! branch on carry a `CliError` with a code, and `main` prints any failure
+//! as `{"error": "...", "code": "..."}` on stderr when JSON output was asked
+//! for. Failures without a `CliError` get a code from their cause.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Kinds of failure, serialized as snake_case (`task_not_found`). Codes are
+/// only ever added, never renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// No task matches the ID, prefix or number given
+    TaskNotFound,
+    /// A status name that isn't one of running, paused, completed, exited
+    InvalidStatus,
+    /// Any other argument that doesn't parse or makes no sense
+    InvalidArgument,
+    /// The task exists but can't do this in its current state
+    InvalidState,
+    /// The database could not be read or written
+    Database,
+    /// A file could not be read or written
+    Io,
+    /// Anything else
+    Internal,
+}
+
+/// An error with a code attached, for the chain of an `anyhow::Error`
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+fn coded(code: ErrorCode, message: impl fmt::Display) -> anyhow::Error {
+    CliError {
+        code,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+pub fn task_not_found(selector: &str) -> anyhow::Error {
+    coded(
+        ErrorCode::TaskNotFound,
+        format!("Task not found: {}", selector),
+    )
+}
+
+pub fn invalid_status(message: impl fmt::Display) -> anyhow::Error {
+    coded(ErrorCode::InvalidStatus, message)
+}
+
+pub fn invalid_argument(message: impl fmt::Display) -> anyhow::Error {
+    coded(ErrorCode::InvalidArgument, message)
+}
+
+pub fn invalid_state(message: impl fmt::Display) -> anyhow::Error {
+    coded(ErrorCode::InvalidState, message)
+}
+
+/// The code of the outermost `CliError` in `err`'s chain, else one derived
+/// from a database or I/O cause, else `Internal`
+pub fn code_of(err: &anyhow::Error) -> ErrorCode {
+    if let Some(e) = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<CliError>())
+    {
+        return e.code;
+    }
+    for cause in err.chain() {
+        if cause.is::<rusqlite::Error>() {
+            return ErrorCode::Database;
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorCode::Io;
+        }
+    }
+    ErrorCode::Internal
+}
+
+/// `err` as the JSON object printed for scripts, with the full chain of
+/// messages in `error`
+pub fn to_json(err: &anyhow::Error) -> Value {
+    json!({
+        "error": format!("{:#}", err),
+        "code": code_of(err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_code_of() {
+        assert_eq!(code_of(&task_not_found("t1")), ErrorCode::TaskNotFound);
+        // Context added on top keeps the code
+        let wrapped = Err::<(), _>(invalid_status("bad"))
+            .context("Failed to list")
+            .unwrap_err();
+        assert_eq!(code_of(&wrapped), ErrorCode::InvalidStatus);
+
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        assert_eq!(
+            code_of(&anyhow::Error::from(io).context("Failed to read")),
+            ErrorCode::Io
+        );
+        let db = rusqlite::Error::InvalidQuery;
+        assert_eq!(code_of(&anyhow::Error::from(db)), ErrorCode::Database);
+        assert_eq!(code_of(&anyhow::anyhow!("boom")), ErrorCode::Internal);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let err = Err::<(), _>(task_not_found("t1"))
+            .context("Failed to show")
+            .unwrap_err();
+        assert_eq!(
+            to_json(&err),
+            json!({"error": "Failed to show: Task not found: t1", "code": "task_not_found"})
+        );
+    }
+}
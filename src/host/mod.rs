@@ -0,0 +1,197 @@
+//! Native messaging host registration for `agent-bridge`
+//!
+//! Browsers only launch a native host they find a manifest for: a JSON file
+//! named after the host, in a per-browser, per-OS directory, holding the
+//! absolute path of the binary and the extensions allowed to talk to it.
+
+use crate::cli::Browser;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Name the extension connects to (`chrome.runtime.connectNative`)
+pub const HOST_NAME: &str = "com.agent_tasks.bridge";
+
+/// Manifest contents for `browser`, launching the bridge at `bridge`
+pub fn manifest(browser: Browser, bridge: &Path, extension_id: &str) -> Value {
+    let mut manifest = json!({
+        "name": HOST_NAME,
+        "description": "Native messaging host for Agent Inbox extension",
+        "path": bridge,
+        "type": "stdio",
+    });
+    // Chromium browsers match on origin, Firefox on the add-on ID
+    let (key, allowed) = match browser {
+        Browser::Firefox => ("allowed_extensions", json!([extension_id])),
+        _ => (
+            "allowed_origins",
+            json!([format!("chrome-extension://{}/", extension_id)]),
+        ),
+    };
+    manifest[key] = allowed;
+    manifest
+}
+
+/// Reject IDs the browser would never match, so a typo doesn't install a
+/// manifest that silently does nothing
+pub fn validate_extension_id(browser: Browser, id: &str) -> Result<()> {
+    match browser {
+        // Chromium IDs are 32 letters from a to p
+        Browser::Chrome | Browser::Chromium | Browser::Brave => {
+            if id.len() != 32 || !id.bytes().all(|b| (b'a'..=b'p').contains(&b)) {
+                anyhow::bail!(
+                    "Invalid extension ID '{}': expected 32 letters a-p (see chrome://extensions)",
+                    id
+                );
+            }
+        }
+        // Firefox add-on IDs are an email-like string or a {GUID}
+        Browser::Firefox => {
+            let guid = id.starts_with('{') && id.ends_with('}');
+            if !id.contains('@') && !guid {
+                anyhow::bail!(
+                    "Invalid add-on ID '{}': expected name@domain or {{GUID}} \
+                     (see about:debugging)",
+                    id
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Directory `browser` reads per-user host manifests from on `os`
+/// (`std::env::consts::OS`)
+pub fn manifest_dir(browser: Browser, os: &str, home: &Path) -> Result<PathBuf> {
+    let relative = match (os, browser) {
+        ("linux", Browser::Chrome) => ".config/google-chrome/NativeMessagingHosts",
+        ("linux", Browser::Chromium) => ".config/chromium/NativeMessagingHosts",
+        ("linux", Browser::Brave) => ".config/BraveSoftware/Brave-Browser/NativeMessagingHosts",
+        ("linux", Browser::Firefox) => ".mozilla/native-messaging-hosts",
+        ("macos", Browser::Chrome) => {
+            "Library/Application Support/Google/Chrome/NativeMessagingHosts"
+        }
+        ("macos", Browser::Chromium) => "Library/Application Support/Chromium/NativeMessagingHosts",
+        ("macos", Browser::Brave) => {
+            "Library/Application Support/BraveSoftware/Brave-Browser/NativeMessagingHosts"
+        }
+        ("macos", Browser::Firefox) => "Library/Application Support/Mozilla/NativeMessagingHosts",
+        // Windows registers hosts in the registry rather than by location
+        _ => anyhow::bail!("install-host is not supported on {}", os),
+    };
+    Ok(home.join(relative))
+}
+
+/// Where the manifest for `browser` goes for the current user
+pub fn manifest_path(browser: Browser) -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME is not set")?;
+    let dir = manifest_dir(browser, std::env::consts::OS, Path::new(&home))?;
+    Ok(dir.join(format!("{}.json", HOST_NAME)))
+}
+
+/// `agent-bridge` installed next to the running `agent-inbox`
+pub fn default_bridge_path() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Failed to locate the agent-inbox binary")?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    Ok(exe.with_file_name(format!("agent-bridge{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// Write `manifest` to `path`, creating the directory if needed
+pub fn install(path: &Path, manifest: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let contents = serde_json::to_string_pretty(manifest)? + "\n";
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Remove the manifest at `path`. Returns false if there was none.
+pub fn uninstall(path: &Path) -> Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHROME_ID: &str = "abcdefghijklmnopabcdefghijklmnop";
+
+    #[test]
+    fn test_chromium_manifest() {
+        let value = manifest(
+            Browser::Brave,
+            Path::new("/opt/bin/agent-bridge"),
+            CHROME_ID,
+        );
+        assert_eq!(
+            value,
+            json!({
+                "name": "com.agent_tasks.bridge",
+                "description": "Native messaging host for Agent Inbox extension",
+                "path": "/opt/bin/agent-bridge",
+                "type": "stdio",
+                "allowed_origins": [format!("chrome-extension://{}/", CHROME_ID)],
+            })
+        );
+    }
+
+    #[test]
+    fn test_firefox_manifest() {
+        let value = manifest(
+            Browser::Firefox,
+            Path::new("/bin/agent-bridge"),
+            "inbox@example.com",
+        );
+        assert_eq!(value["allowed_extensions"], json!(["inbox@example.com"]));
+        assert!(value.get("allowed_origins").is_none());
+    }
+
+    #[test]
+    fn test_validate_extension_id() {
+        assert!(validate_extension_id(Browser::Chrome, CHROME_ID).is_ok());
+        assert!(validate_extension_id(Browser::Chrome, "abc").is_err());
+        assert!(validate_extension_id(Browser::Chrome, &CHROME_ID.replace("a", "z")).is_err());
+
+        assert!(validate_extension_id(Browser::Firefox, "inbox@example.com").is_ok());
+        assert!(validate_extension_id(Browser::Firefox, "{0b6d5c2e-1a2b}").is_ok());
+        assert!(validate_extension_id(Browser::Firefox, CHROME_ID).is_err());
+    }
+
+    #[test]
+    fn test_manifest_dir() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            manifest_dir(Browser::Chrome, "linux", home).unwrap(),
+            Path::new("/home/me/.config/google-chrome/NativeMessagingHosts")
+        );
+        assert_eq!(
+            manifest_dir(Browser::Firefox, "linux", home).unwrap(),
+            Path::new("/home/me/.mozilla/native-messaging-hosts")
+        );
+        assert_eq!(
+            manifest_dir(Browser::Firefox, "macos", home).unwrap(),
+            Path::new("/home/me/Library/Application Support/Mozilla/NativeMessagingHosts")
+        );
+        assert!(manifest_dir(Browser::Chrome, "windows", home).is_err());
+    }
+
+    #[test]
+    fn test_install_and_uninstall() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts").join(format!("{}.json", HOST_NAME));
+        let value = manifest(Browser::Chrome, Path::new("/bin/agent-bridge"), CHROME_ID);
+
+        install(&path, &value).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let written: Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(written, value);
+
+        assert!(uninstall(&path).unwrap());
+        assert!(!uninstall(&path).unwrap());
+    }
+}
@@ -0,0 +1,201 @@
+//! Process lists for `agent-inbox import-processes`
+//!
+//! Supervisors (tmux, pm2, a launcher script) already know which agents they
+//! started. They can hand the list over as JSON, an array of objects or one
+//! object per line, or as CSV with a header row naming the columns. Every
+//! row needs `task_id`, `agent_type`, `title` and `pid`; other keys and
+//! columns are ignored.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// One process to track
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProcessRow {
+    pub task_id: String,
+    pub agent_type: String,
+    pub title: String,
+    pub pid: i32,
+}
+
+const COLUMNS: [&str; 4] = ["task_id", "agent_type", "title", "pid"];
+
+/// Parse `text` as JSON when it starts like JSON, otherwise as CSV. Fails
+/// on the first invalid row, naming its line, and on a task_id repeated.
+pub fn parse_rows(text: &str) -> Result<Vec<ProcessRow>> {
+    let trimmed = text.trim_start();
+    let rows = if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed).context("Invalid JSON process list")?
+    } else if trimmed.starts_with('{') {
+        parse_json_lines(text)?
+    } else {
+        parse_csv(text)?
+    };
+
+    let mut seen = HashSet::new();
+    for row in &rows {
+        validate(row)?;
+        if !seen.insert(row.task_id.as_str()) {
+            bail!("Task {} is listed more than once", row.task_id);
+        }
+    }
+    Ok(rows)
+}
+
+fn validate(row: &ProcessRow) -> Result<()> {
+    if row.task_id.trim().is_empty() {
+        bail!("A row has an empty task_id");
+    }
+    if row.pid <= 0 {
+        bail!(
+            "Task {}: pid must be positive, not {}",
+            row.task_id,
+            row.pid
+        );
+    }
+    Ok(())
+}
+
+fn parse_json_lines(text: &str) -> Result<Vec<ProcessRow>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| format!("Line {}: invalid row", i + 1))
+        })
+        .collect()
+}
+
+fn parse_csv(text: &str) -> Result<Vec<ProcessRow>> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header = split_csv_line(header).context("Line 1: invalid header")?;
+    let mut index = [0; COLUMNS.len()];
+    for (slot, column) in index.iter_mut().zip(COLUMNS) {
+        *slot = header
+            .iter()
+            .position(|name| name.trim() == column)
+            .with_context(|| format!("The CSV header has no {} column", column))?;
+    }
+
+    lines
+        .map(|(i, line)| {
+            let line_no = i + 1;
+            let fields =
+                split_csv_line(line).with_context(|| format!("Line {}: invalid CSV", line_no))?;
+            let field = |slot: usize| -> Result<&str> {
+                fields
+                    .get(index[slot])
+                    .map(String::as_str)
+                    .with_context(|| format!("Line {}: no {} value", line_no, COLUMNS[slot]))
+            };
+            let (task_id, agent_type, title, pid) = (field(0)?, field(1)?, field(2)?, field(3)?);
+            Ok(ProcessRow {
+                task_id: task_id.to_string(),
+                agent_type: agent_type.to_string(),
+                title: title.to_string(),
+                pid: pid
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Line {}: pid {:?} is not a number", line_no, pid))?,
+            })
+        })
+        .collect()
+}
+
+/// Fields of one CSV record: comma-separated, each optionally in double
+/// quotes with `""` for a quote inside. Records can't span lines.
+fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unterminated quote");
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(task_id: &str, title: &str, pid: i32) -> ProcessRow {
+        ProcessRow {
+            task_id: task_id.to_string(),
+            agent_type: "claude_code".to_string(),
+            title: title.to_string(),
+            pid,
+        }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "pid,task_id,agent_type,title,pane\n\
+                   101,a,claude_code,Fix the build,%1\n\
+                   \n\
+                   102,b,claude_code,\"Review \"\"parser\"\", then merge\",%2\n";
+        assert_eq!(
+            parse_rows(csv).unwrap(),
+            [
+                row("a", "Fix the build", 101),
+                row("b", "Review \"parser\", then merge", 102)
+            ]
+        );
+        assert!(parse_rows("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let array = r#"[{"task_id": "a", "agent_type": "claude_code", "title": "Fix", "pid": 7,
+                         "session": "main"}]"#;
+        assert_eq!(parse_rows(array).unwrap(), [row("a", "Fix", 7)]);
+
+        let lines = r#"{"task_id": "a", "agent_type": "claude_code", "title": "Fix", "pid": 7}
+
+{"task_id": "b", "agent_type": "claude_code", "title": "Test", "pid": 8}"#;
+        assert_eq!(
+            parse_rows(lines).unwrap(),
+            [row("a", "Fix", 7), row("b", "Test", 8)]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| format!("{:#}", parse_rows(text).unwrap_err());
+        assert!(error("task_id,agent_type,title\n").contains("no pid column"));
+        assert!(
+            error("task_id,agent_type,title,pid\na,claude_code,Fix,abc\n")
+                .contains("Line 2: pid \"abc\" is not a number")
+        );
+        let short = "task_id,agent_type,title,pid\na,claude_code\n";
+        assert!(error(short).contains("Line 2: no title"));
+        assert!(
+            error("task_id,agent_type,title,pid\na,claude_code,\"Fix,1\n")
+                .contains("Unterminated quote")
+        );
+        let twice = "task_id,agent_type,title,pid\na,x,T,1\na,x,T,2\n";
+        assert!(error(twice).contains("more than once"));
+        assert!(error("task_id,agent_type,title,pid\na,x,T,0\n").contains("must be positive"));
+        assert!(error("{\"task_id\": \"a\"}").contains("Line 1: invalid row"));
+    }
+}
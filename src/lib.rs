@@ -1,3 +1,53 @@
-// Library exports for agent-inbox
+//! Track tasks across coding agents in a shared SQLite inbox
+//!
+//! This is the library behind the `agent-inbox` CLI and `agent-bridge`. The
+//! types re-exported at the crate root are the stable surface: open a
+//! [`Database`], record [`Task`]s in it and move them through their
+//! [`TaskStatus`]es, or watch an agent's processes with a [`TaskMonitor`].
+//!
+//! ```
+//! use agent_inbox::{Database, Task, TaskContext, TaskStatus};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let dir = tempfile::tempdir()?;
+//! # let path = dir.path().join("tasks.db");
+//! let db = Database::open(&path)?;
+//!
+//! let mut task = Task::new(
+//!     "build-42".to_string(),
+//!     "claude_code".to_string(),
+//!     "Fix the build".to_string(),
+//!     None,
+//!     None,
+//! );
+//! task.context = Some(TaskContext {
+//!     url: None,
+//!     project_path: Some("/home/me/src/parser".to_string()),
+//!     session_id: None,
+//!     extra: Default::default(),
+//! });
+//! db.insert_task(&task)?;
+//!
+//! task.complete();
+//! db.update_task(&task)?;
+//!
+//! let done = db.list_tasks(Some(TaskStatus::Completed))?;
+//! assert_eq!(done[0].title, "Fix the build");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The modules stay public for everything else (config, webhooks, the
+//! archive, detectors), but may change between releases.
+
+pub mod archive;
+pub mod config;
 pub mod db;
 pub mod models;
+pub mod monitor;
+pub mod webhook;
+
+pub use db::Database;
+pub use models::{AgentType, AttentionReason, Task, TaskContext, TaskEvent, TaskStatus};
+pub use monitor::detectors::AttentionDetector;
+pub use monitor::{Reaped, TaskMonitor};
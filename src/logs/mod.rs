@@ -0,0 +1,234 @@
+//! Tail an agent's log file for `show --follow-logs`
+//!
+//! Agents write their output wherever their wrapper points them; the path is
+//! recorded in the task context. The file may not exist yet, may be
+//! truncated, or may be rotated away under a running tail, and all three
+//! are followed rather than treated as errors.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often a followed file is checked for new output
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bytes read per step when scanning backwards for line starts
+const TAIL_CHUNK: u64 = 8192;
+
+/// The last `n` lines of `file` (fewer if it is shorter), leaving the file
+/// positioned at its end
+fn last_lines(file: &mut File, n: usize) -> io::Result<Vec<String>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut start = len;
+    let mut tail = Vec::new();
+
+    // Read backwards until the chunk holds n line breaks before the last line
+    while start > 0 {
+        let step = TAIL_CHUNK.min(start);
+        start -= step;
+        file.seek(SeekFrom::Start(start))?;
+        let mut chunk = vec![0; step as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+
+        let body = tail.strip_suffix(b"\n").unwrap_or(&tail);
+        if body.iter().filter(|&&b| b == b'\n').count() >= n {
+            break;
+        }
+    }
+    file.seek(SeekFrom::End(0))?;
+
+    let text = String::from_utf8_lossy(&tail);
+    let lines: Vec<&str> = text.lines().collect();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|line| line.to_string()).collect())
+}
+
+/// Identity of the file at a path, to notice it being replaced
+#[cfg(unix)]
+fn file_id(meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Incremental reader of a growing log file
+struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    id: Option<(u64, u64)>,
+    pos: u64,
+}
+
+impl Follower {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: None,
+            id: None,
+            pos: 0,
+        }
+    }
+
+    /// Open the file and return its last `n` lines; None if it doesn't exist
+    fn start(&mut self, n: usize) -> io::Result<Option<Vec<String>>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let lines = last_lines(&mut file, n)?;
+        self.attach(file)?;
+        Ok(Some(lines))
+    }
+
+    fn attach(&mut self, mut file: File) -> io::Result<()> {
+        self.id = file_id(&file.metadata()?);
+        self.pos = file.stream_position()?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Output written since the last call. A truncated file is read again
+    /// from the start, and so is a new file that replaced the old one
+    /// (after draining whatever was left in the old one).
+    fn poll(&mut self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+
+        if let Some(file) = self.file.as_mut() {
+            let len = file.metadata()?.len();
+            if len < self.pos {
+                file.seek(SeekFrom::Start(0))?;
+            }
+            file.read_to_end(&mut out)?;
+            self.pos = file.stream_position()?;
+        }
+
+        let current = match std::fs::metadata(&self.path) {
+            Ok(meta) => Some(meta),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let replaced = match &current {
+            Some(meta) => self.file.is_none() || file_id(meta) != self.id,
+            None => false,
+        };
+        if replaced {
+            if let Ok(mut file) = File::open(&self.path) {
+                file.read_to_end(&mut out)?;
+                self.attach(file)?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Print the last `lines` lines of `path`, then keep printing what gets
+/// appended until interrupted
+pub fn follow(path: &Path, lines: usize) -> Result<()> {
+    let mut follower = Follower::new(path);
+    let mut out = io::stdout();
+
+    match follower
+        .start(lines)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+    {
+        Some(tail) => {
+            for line in tail {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        None => eprintln!("Waiting for {} to appear...", path.display()),
+    }
+    out.flush()?;
+
+    loop {
+        thread::sleep(FOLLOW_INTERVAL);
+        let chunk = follower
+            .poll()
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if !chunk.is_empty() {
+            out.write_all(&chunk)?;
+            out.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.log");
+
+        append(&path, "one\ntwo\nthree\n");
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(last_lines(&mut file, 2).unwrap(), ["two", "three"]);
+        assert_eq!(last_lines(&mut file, 10).unwrap(), ["one", "two", "three"]);
+        assert!(last_lines(&mut file, 0).unwrap().is_empty());
+
+        // Lines spanning several backwards chunks
+        let long = "x".repeat(TAIL_CHUNK as usize * 2);
+        append(&path, &format!("{}\nlast", long));
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(last_lines(&mut file, 2).unwrap(), [long.as_str(), "last"]);
+    }
+
+    #[test]
+    fn test_follow_appends_and_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.log");
+        append(&path, "old\n");
+
+        let mut follower = Follower::new(&path);
+        assert_eq!(follower.start(5).unwrap().unwrap(), ["old"]);
+        assert!(follower.poll().unwrap().is_empty());
+
+        append(&path, "new\n");
+        assert_eq!(follower.poll().unwrap(), b"new\n");
+
+        // Truncated in place (e.g. `> agent.log`): start over
+        std::fs::write(&path, "fresh\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), b"fresh\n");
+    }
+
+    #[test]
+    fn test_follow_rotation_and_late_creation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.log");
+
+        let mut follower = Follower::new(&path);
+        assert!(follower.start(5).unwrap().is_none());
+        assert!(follower.poll().unwrap().is_empty());
+
+        append(&path, "hello\n");
+        assert_eq!(follower.poll().unwrap(), b"hello\n");
+
+        // Rotated: the tail of the old file, then the new one from the start
+        append(&path, "bye\n");
+        std::fs::rename(&path, dir.path().join("agent.log.1")).unwrap();
+        append(&path, "again\n");
+        assert_eq!(follower.poll().unwrap(), b"bye\nagain\n");
+    }
+}
@@ -1,76 +1,563 @@
 mod cli;
-mod db;
 mod display;
-mod models;
-mod monitor;
+mod error;
+mod host;
+mod import;
+mod logs;
+mod service;
+mod stream;
+mod watcher;
+
+use agent_inbox::{archive, config, db, models, monitor, webhook};
 
 use anyhow::{Context, Result};
+use chrono::Utc;
 use clap::Parser;
-use cli::{Cli, Commands, ReportAction};
-use db::Database;
-use models::{Task, TaskContext, TaskStatus};
+use cli::{
+    ArchiveAction, Cli, Commands, MetaAction, OutputFormat, ReportAction, TrashAction,
+    WebhookAction,
+};
+use db::{Database, SearchSort, TaskSort};
+use display::{AttentionTracker, ChangeTracker, ListOptions};
+use models::{AgentType, AttentionReason, Task, TaskContext, TaskStatus};
 use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing_subscriber::EnvFilter;
+
+/// Resolve a task argument typed by a user: an exact ID, `last` (or
+/// `last-attention`, `last-failed`) for the most recently updated one, the
+/// number the last `list` or `top` printed next to it, or a unique ID prefix
+/// (in that order of preference)
+fn find_task(db: &Database, selector: &str) -> Result<Option<Task>> {
+    if let Some(task) = db.get_task_by_id(selector)? {
+        return Ok(Some(task));
+    }
+    if let Some(latest) = db::Latest::from_selector(selector) {
+        return db.latest_task(latest);
+    }
+    if let Ok(index) = selector.parse::<usize>() {
+        if let Some(task_id) = numbered_task_id(index) {
+            return db.get_task_by_id(&task_id);
+        }
+    }
+    db.get_task_by_id_prefix(selector)
+}
+
+/// File next to the database holding the task IDs of the last numbered
+/// list, one per line in the order they were numbered
+static LIST_NUMBERS: OnceLock<PathBuf> = OnceLock::new();
+
+/// Record the numbers a text view just printed, so `show 2` means the task
+/// the user saw as 2 whatever filters and sort that view had
+fn remember_numbers(tasks: &[&Task]) {
+    let Some(path) = LIST_NUMBERS.get() else {
+        return;
+    };
+    let ids: String = tasks
+        .iter()
+        .map(|task| format!("{}\n", task.task_id))
+        .collect();
+    if let Err(e) = std::fs::write(path, ids) {
+        tracing::debug!(error = %e, path = %path.display(), "Could not save list numbers");
+    }
+}
+
+/// The task the last numbered view showed as `index` (counting from 1)
+fn numbered_task_id(index: usize) -> Option<String> {
+    let numbers = std::fs::read_to_string(LIST_NUMBERS.get()?).ok()?;
+    let task_id = numbers.lines().nth(index.checked_sub(1)?)?;
+    Some(task_id.to_string())
+}
+
+/// `find_task`, failing when nothing matches
+fn require_task(db: &Database, selector: &str) -> Result<Task> {
+    find_task(db, selector)?.ok_or_else(|| error::task_not_found(selector))
+}
 
-fn main() -> Result<()> {
+/// Reject `--fields` naming unknown fields or given for a text view
+fn check_fields(fields: &[String], format: OutputFormat) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    display::validate_fields(fields)?;
+    if !matches!(format, OutputFormat::Json | OutputFormat::Yaml) {
+        return Err(error::invalid_argument(
+            "--fields needs --format json or yaml",
+        ));
+    }
+    Ok(())
+}
+
+/// Set by SIGINT or SIGTERM so the daemon stops between passes and releases
+/// its lock
+static STOP: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Turn Ctrl+C and SIGTERM into a `STOP` request. Elsewhere they still kill
+/// the process, and the next daemon takes over the stale lock.
+fn install_stop_handler() {
+    #[cfg(unix)]
+    // SAFETY: the handler only stores to an atomic, which is signal-safe
+    unsafe {
+        let handler = request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// One daemon pass: recover tasks whose monitor died, drop expired tasks,
+/// then apply retention unless auto-cleanup is off. Failures are logged and
+/// retried next pass.
+fn daemon_pass(
+    db: &Database,
+    retention: Option<&HashMap<TaskStatus, i64>>,
+    escalation: &[monitor::EscalationStep],
+) {
+    match monitor::reap(db, false) {
+        Ok(reaped) => {
+            for (task, outcome) in reaped {
+                tracing::info!(task_id = %task.task_id, ?outcome, "Reaped task");
+            }
+        }
+        Err(e) => tracing::warn!(error = %e, "Reap failed"),
+    }
+    let now = Utc::now();
+    match db.expire_tasks(now) {
+        Ok(expired) => tracing::debug!(expired, "Expiry"),
+        Err(e) => tracing::warn!(error = %e, "Expiry failed"),
+    }
+    match monitor::escalation::escalate(db, escalation, now) {
+        Ok(escalated) => {
+            for task in escalated {
+                tracing::info!(task_id = %task.task_id, "Escalated attention flag");
+            }
+        }
+        Err(e) => tracing::warn!(error = %e, "Escalation failed"),
+    }
+    if let Some(retention) = retention {
+        match db.cleanup(retention, now) {
+            Ok(deleted) => tracing::debug!(deleted, "Cleanup"),
+            Err(e) => tracing::warn!(error = %e, "Cleanup failed"),
+        }
+    }
+}
+
+/// Send diagnostics to stderr at the level picked by `-v`, unless `RUST_LOG`
+/// says otherwise
+fn init_logging(verbose: u8, color: cli::ColorChoice) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(color.enabled(std::io::stderr().is_terminal()))
+        .without_time()
+        .with_target(false)
+        .init();
+}
+
+fn main() {
     let cli = Cli::parse();
+    // Scripts asking for JSON get failures as JSON too
+    let json_errors = cli.format == OutputFormat::Json
+        || matches!(
+            cli.command,
+            Some(Commands::Show { json: true, .. } | Commands::Version { json: true })
+        );
+
+    if let Err(err) = run(cli) {
+        if json_errors {
+            eprintln!("{}", error::to_json(&err));
+        } else {
+            eprintln!("Error: {:?}", err);
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    // Runs on every shell prompt: skip everything but the one query
+    if matches!(cli.command, Some(Commands::Prompt)) {
+        let counts = db::prompt_counts(&db::default_db_path())?;
+        println!("{}", display::prompt_summary(&counts));
+        return Ok(());
+    }
+    // Meant for bug reports, so it must work when config or database don't
+    if let Some(Commands::Version { json }) = cli.command {
+        let info = display::VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("AGENT_INBOX_GIT_SHA"),
+            schema_version: db::SCHEMA_VERSION,
+            db_path: db::default_db_path(),
+        };
+        return display::display_version(&info, json || cli.format == OutputFormat::Json);
+    }
+
+    init_logging(cli.verbose, cli.color);
+    display::set_color(cli.color);
 
     // Ensure data directory exists
     db::ensure_data_dir()?;
 
+    let config_path = config::default_config_path();
+    tracing::debug!(path = %config_path.display(), "Loading config");
+    let config = config::Config::load(config_path)?;
+    let auto_cleanup =
+        config.auto_cleanup_retention(std::env::var_os(config::NO_AUTOCLEAN_ENV).as_deref());
+    display::set_agent_styles(config.agents.clone());
+    display::set_sla(config.sla_secs);
+    let title_width = Some(config.list_title_width());
+
     // Open database
     let db_path = db::default_db_path();
-    let db = Database::open(&db_path).context("Failed to open database")?;
+    let _ = LIST_NUMBERS.set(db_path.with_file_name("list-numbers"));
+    let mut db = Database::open(&db_path).context("Failed to open database")?;
+    tracing::debug!(path = %db_path.display(), "Opened database");
+    for (url, format) in config.webhook_targets() {
+        tracing::debug!(%url, ?format, "Webhook target");
+        db.add_webhook(
+            webhook::Webhook::new(url)
+                .with_format(format)
+                .with_dead_letter(webhook::default_dead_letter_path()),
+        );
+    }
+    db.set_notification_cooldown(config.notification_cooldown());
+    if let Some(max) = config.max_tasks {
+        db.set_max_tasks(max);
+    }
+    if let Some(path) = &config.archive_path {
+        db.set_archive(archive::Archive::new(path));
+    }
+
+    // Tasks given a --ttl go once it runs out, auto-cleanup or not
+    match db.expire_tasks(Utc::now()) {
+        Ok(0) => {}
+        Ok(expired) => tracing::info!(expired, "Removed expired tasks"),
+        Err(e) => tracing::warn!(error = %e, "Expiry failed"),
+    }
 
-    // Run cleanup on every invocation
-    let _ = db.cleanup_old_completed(3600); // 1 hour default
+    // Run cleanup on every invocation unless disabled for interactive use
+    if let Some(retention) = auto_cleanup {
+        let cleaned = db
+            .cleanup(&config.retention(), Utc::now())
+            .and_then(|deleted| Ok((deleted, db.purge_trash(Some(retention))?)));
+        match cleaned {
+            Ok((deleted, purged)) => tracing::info!(deleted, purged, retention, "Auto-cleanup"),
+            Err(e) => tracing::warn!(error = %e, "Auto-cleanup failed"),
+        }
+    } else {
+        tracing::debug!("Auto-cleanup disabled");
+    }
 
     match cli.command {
         None => {
             // Default: show running tasks (actively generating)
             let tasks = db.list_tasks(Some(TaskStatus::Running))?;
-            display::display_task_list(&tasks);
-        }
-        Some(Commands::List { all, status }) => {
-            let tasks = if let Some(status_str) = status {
-                let status = TaskStatus::from_str(&status_str)
-                    .map_err(|e| anyhow::anyhow!(e))?;
-                db.list_tasks(Some(status))?
-            } else if all {
-                db.list_tasks(None)?
-            } else {
+            let opts = ListOptions {
+                title_width,
+                ..Default::default()
+            };
+            display::display_tasks(&tasks, cli.format, &opts)?;
+        }
+        Some(Commands::List {
+            all,
+            status,
+            count,
+            compact,
+            follow,
+            tree,
+            plain,
+            quiet,
+            group_by,
+            merge,
+            check,
+            project,
+            exit_code,
+            fields,
+            sort,
+            reverse,
+        }) => {
+            let sort = TaskSort::from_str(&sort).map_err(error::invalid_argument)?;
+            let status_filter = match status.as_deref() {
+                // `--status all` is the same as --all
+                Some("all") => None,
+                Some(status_str) => {
+                    Some(TaskStatus::from_str(status_str).map_err(error::invalid_status)?)
+                }
+                None if all || check => None,
+                // Only exited tasks have an exit code to match
+                None if !exit_code.is_empty() => Some(TaskStatus::Exited),
                 // Show running tasks by default
-                db.list_tasks(Some(TaskStatus::Running))?
+                None => Some(TaskStatus::Running),
             };
 
-            display::display_task_list(&tasks);
+            let project = match project {
+                // Collecting the components drops `.` segments
+                Some(path) => Some(
+                    std::env::current_dir()?
+                        .join(path)
+                        .components()
+                        .collect::<std::path::PathBuf>(),
+                ),
+                None => None,
+            };
+            let project = project.as_deref().map(|path| path.to_string_lossy());
+            let project = project.as_deref();
+
+            // Filters beyond status need the full list, even for --count
+            let merged = if !merge.is_empty() {
+                let mut paths = vec![db_path.clone()];
+                paths.extend(merge);
+                let mut tasks =
+                    db::list_merged(&paths, status_filter.clone(), project, &exit_code)?;
+                db::sort_tasks(&mut tasks, sort, reverse, Utc::now());
+                Some(tasks)
+            } else if project.is_some() || !exit_code.is_empty() {
+                let status = status_filter.as_ref();
+                Some(db.list_tasks_sorted(status, project, &exit_code, sort, reverse)?)
+            } else {
+                None
+            };
+
+            if count {
+                let count = match &merged {
+                    Some(tasks) => tasks.len(),
+                    None => db.count(status_filter, None)?,
+                };
+                println!("{}", count);
+                return Ok(());
+            }
+
+            if check {
+                let tasks = match merged {
+                    Some(tasks) => tasks,
+                    None => db.list_tasks(status_filter)?,
+                };
+                let health = models::Health::of(&tasks);
+                tracing::info!(
+                    tasks = tasks.len(),
+                    attention = health.attention,
+                    failed = health.failed,
+                    "Health check"
+                );
+                std::process::exit(health.exit_code());
+            }
+
+            let format = if compact {
+                OutputFormat::Table
+            } else {
+                cli.format
+            };
+            check_fields(&fields, format)?;
+            if !follow {
+                let tasks = match merged {
+                    Some(tasks) => tasks,
+                    None => {
+                        db.list_tasks_sorted(status_filter.as_ref(), None, &[], sort, reverse)?
+                    }
+                };
+                let opts = ListOptions {
+                    tree,
+                    group_by,
+                    plain,
+                    quiet,
+                    fields,
+                    title_width,
+                    ..Default::default()
+                };
+                if format == OutputFormat::Text && !plain {
+                    remember_numbers(&display::numbered(&tasks, &opts));
+                }
+                return display::display_tasks(&tasks, format, &opts);
+            }
+
+            // Fall back to polling when the platform watcher is unavailable
+            let db_watcher = match watcher::DbWatcher::new(&db_path) {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    tracing::warn!(error = %e, "File watching unavailable, polling every 2s");
+                    None
+                }
+            };
+
+            let mut tracker = ChangeTracker::default();
+            loop {
+                let status = status_filter.as_ref();
+                let tasks = db.list_tasks_sorted(status, project, &exit_code, sort, reverse)?;
+                let opts = ListOptions {
+                    highlight: tracker.update(&tasks),
+                    tree,
+                    group_by,
+                    plain,
+                    quiet,
+                    fields: fields.clone(),
+                    title_width,
+                };
+                if format == OutputFormat::Text && !plain {
+                    remember_numbers(&display::numbered(&tasks, &opts));
+                }
+
+                // Clear screen
+                print!("\x1B[2J\x1B[1;1H");
+                display::display_tasks(&tasks, format, &opts)?;
+
+                match &db_watcher {
+                    // Redraw at least every 30s so elapsed times stay current,
+                    // sooner while a highlight is waiting to fade
+                    Some(w) => {
+                        let timeout = if opts.highlight.is_empty() { 30 } else { 3 };
+                        w.wait(Duration::from_secs(timeout));
+                    }
+                    None => thread::sleep(Duration::from_secs(2)),
+                }
+            }
         }
-        Some(Commands::Show { task_id }) => {
-            let task = db
-                .get_task_by_id(&task_id)?
-                .ok_or_else(|| anyhow::anyhow!("Task not found: {}", task_id))?;
+        Some(Commands::Top { count }) => {
+            let tasks = db.list_tasks_paged(None, count, 0)?;
+            let opts = ListOptions {
+                title_width,
+                ..Default::default()
+            };
+            if cli.format == OutputFormat::Text {
+                remember_numbers(&tasks.iter().collect::<Vec<_>>());
+            }
+            display::display_top(&tasks, cli.format, &opts)?;
+        }
+        Some(Commands::Search { query, limit, sort }) => {
+            let sort = SearchSort::from_str(&sort).map_err(error::invalid_argument)?;
+            let tasks = db.search_tasks(&query, sort, Some(limit))?;
 
-            display::display_task_detail(&task);
+            // Grouping by status would undo the ranking, so text is one row each
+            let format = match cli.format {
+                OutputFormat::Text => OutputFormat::Table,
+                format => format,
+            };
+            display::display_tasks(&tasks, format, &ListOptions::default())?;
         }
-        Some(Commands::Clear { task_id }) => {
-            let deleted = db.delete_task(&task_id)?;
-            if deleted {
-                println!("Task {} cleared", task_id);
-            } else {
-                println!("Task not found: {}", task_id);
+        Some(Commands::Show {
+            task_id,
+            json,
+            follow_logs,
+            lines,
+            context_only,
+            require,
+            fields,
+        }) => {
+            let task = require_task(&db, &task_id)?;
+
+            if context_only {
+                if require && task.context.is_none() {
+                    let message = format!("Task {} has no context", task.task_id);
+                    return Err(error::invalid_state(message));
+                }
+                display::display_context(task.context.as_ref())?;
+                return Ok(());
+            }
+
+            let format = if json { OutputFormat::Json } else { cli.format };
+            check_fields(&fields, format)?;
+            display::display_task(&task, format, &fields)?;
+
+            if follow_logs {
+                let path = task
+                    .context
+                    .as_ref()
+                    .and_then(|context| context.log_path())
+                    .ok_or_else(|| {
+                        error::invalid_state(format!(
+                            "Task {} has no log_path in its context",
+                            task.task_id
+                        ))
+                    })?;
+                logs::follow(std::path::Path::new(path), lines)?;
             }
         }
-        Some(Commands::ClearAll) => {
-            let completed = db.list_tasks(Some(TaskStatus::Completed))?;
-            let exited = db.list_tasks(Some(TaskStatus::Exited))?;
+        Some(Commands::History { task_id }) => {
+            let task = require_task(&db, &task_id)?;
+            let events = db.task_history(&task.task_id)?;
 
-            let mut count = 0;
-            for task in completed.iter().chain(exited.iter()) {
-                db.delete_task(&task.task_id)?;
-                count += 1;
+            display::display_history(&task, &events, cli.format)?;
+        }
+        Some(Commands::Pause { task_id }) => {
+            let mut task = require_task(&db, &task_id)?;
+            if task.status != TaskStatus::Running {
+                return Err(error::invalid_state(format!(
+                    "Task {} is {}; only running tasks can be paused",
+                    task.task_id,
+                    task.status.as_str()
+                )));
             }
 
+            task.pause();
+            db.update_task(&task)?;
+            println!("Task {} paused", task.task_id);
+        }
+        Some(Commands::Resume { task_id }) => {
+            let mut task = require_task(&db, &task_id)?;
+            if task.status != TaskStatus::Paused {
+                return Err(error::invalid_state(format!(
+                    "Task {} is not paused",
+                    task.task_id
+                )));
+            }
+
+            task.set_running();
+            db.update_task(&task)?;
+            println!("Task {} resumed", task.task_id);
+        }
+        Some(Commands::Clear {
+            task_id: Some(task_id),
+            ..
+        }) => match find_task(&db, &task_id)? {
+            Some(task) => {
+                db.transaction(|db| db.delete_task(&task.task_id))?;
+                println!("Task {} cleared", task.task_id);
+            }
+            None => println!("Task not found: {}", task_id),
+        },
+        Some(Commands::Clear { status, .. }) => {
+            // Without a task or --status, clap guarantees --all-terminal
+            let count = match status.as_deref() {
+                Some("attention" | "needs-attention" | "needs_attention") => {
+                    db.transaction(|db| db.delete_flagged())?
+                }
+                Some(status_str) => {
+                    let status = TaskStatus::from_str(status_str).map_err(error::invalid_status)?;
+                    db.transaction(|db| db.delete_tasks(&[status], None))?
+                }
+                None => db.transaction(|db| {
+                    db.delete_tasks(&[TaskStatus::Completed, TaskStatus::Exited], None)
+                })?,
+            };
+            println!("Cleared {} tasks", count);
+        }
+        Some(Commands::ClearAll { older_than, status }) => {
+            let statuses = match status {
+                Some(status_str) => {
+                    vec![TaskStatus::from_str(&status_str).map_err(error::invalid_status)?]
+                }
+                None => vec![TaskStatus::Completed, TaskStatus::Exited],
+            };
+            let cutoff = older_than
+                .map(|age| chrono::Duration::from_std(age).map(|age| Utc::now() - age))
+                .transpose()?;
+
+            let count = db.transaction(|db| db.delete_tasks(&statuses, cutoff))?;
+
             println!("Cleared {} tasks", count);
         }
         Some(Commands::Reset { force }) => {
@@ -82,64 +569,200 @@ fn main() -> Result<()> {
                 return Ok(());
             }
 
-            // Show what will be cleared
+            // Show what will be cleared: all of it in the pager when it
+            // doesn't fit above the prompt
+            let lines: Vec<String> = all_tasks
+                .iter()
+                .map(|task| format!("  - [{}] {}", task.agent_type, task.title))
+                .collect();
+            let limit = config.confirm_preview();
+            let prompting = !force && !cli.yes && std::io::stdout().is_terminal();
+            if prompting && lines.len() > limit {
+                let full = format!("Tasks to delete ({}):\n{}\n", task_count, lines.join("\n"));
+                display::page(&full);
+            }
             println!("This will delete ALL {} tasks:", task_count);
-            for task in &all_tasks {
-                println!("  - [{}] {}", task.agent_type, task.title);
+            for line in display::preview_lines(&lines, limit) {
+                println!("{}", line);
             }
             println!();
 
-            // Confirm unless --force
-            if !force {
-                use std::io::{self, Write};
-                print!("Are you sure you want to delete ALL tasks? (yes/no): ");
-                io::stdout().flush()?;
-
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-
-                if input.trim().to_lowercase() != "yes" {
-                    println!("Aborted. No tasks were deleted.");
-                    return Ok(());
-                }
+            // Confirm unless --force or --yes
+            if !force && !cli::confirm("Are you sure you want to delete ALL tasks?", cli.yes)? {
+                println!("Aborted. No tasks were deleted.");
+                return Ok(());
             }
 
             // Delete all tasks
-            let mut count = 0;
-            for task in all_tasks {
-                db.delete_task(&task.task_id)?;
-                count += 1;
-            }
+            let count = db.transaction(|db| {
+                let mut count = 0;
+                for task in &all_tasks {
+                    db.delete_task(&task.task_id)?;
+                    count += 1;
+                }
+                Ok(count)
+            })?;
 
             println!("✓ Cleared all {} tasks", count);
         }
-        Some(Commands::Watch) => {
+        Some(Commands::Watch {
+            status,
+            agent,
+            bell,
+            quiet,
+        }) => {
+            let status_filter = status
+                .filter(|s| s != "all")
+                .map(|s| TaskStatus::from_str(&s).map_err(error::invalid_status))
+                .transpose()?;
+            // Match the canonical name stored by `report start`
+            let agent_filter = agent.map(|a| AgentType::from(a.as_str()).to_string());
+
             println!("Watching tasks (Ctrl+C to exit)...\n");
 
+            let mut tracker = ChangeTracker::default();
+            let mut attention = AttentionTracker::default();
             loop {
+                let tasks =
+                    db.list_tasks_filtered(status_filter.as_ref(), agent_filter.as_deref())?;
+                let opts = ListOptions {
+                    highlight: tracker.update(&tasks),
+                    quiet,
+                    title_width,
+                    ..Default::default()
+                };
+                let newly_flagged = attention.update(&tasks);
+
                 // Clear screen
                 print!("\x1B[2J\x1B[1;1H");
-
-                let tasks = db.list_tasks(None)?;
-                display::display_task_list(&tasks);
+                display::display_task_list(&tasks, &opts);
+                if bell && !newly_flagged.is_empty() {
+                    print!("\x07");
+                    std::io::stdout().flush()?;
+                }
 
                 thread::sleep(Duration::from_secs(2));
             }
         }
+        Some(Commands::Prompt | Commands::Version { .. }) => {
+            unreachable!("handled before the database is opened")
+        }
+        Some(Commands::Schema) => {
+            println!("{}", serde_json::to_string_pretty(&models::json_schema())?);
+        }
+        Some(Commands::Sql { query }) => {
+            let result = db::query_read_only(&db_path, &query)?;
+            display::display_query(&result, cli.format)?;
+        }
+        Some(Commands::Stream { fifo }) => {
+            stream::run(&db, &db_path, fifo.as_deref())?;
+        }
         Some(Commands::Cleanup { retention_secs }) => {
-            let deleted = db.cleanup_old_completed(retention_secs)?;
-            println!("Cleaned up {} old completed tasks", deleted);
+            let mut retention = config.retention();
+            if let Some(secs) = retention_secs {
+                retention.insert(TaskStatus::Completed, secs);
+            }
+            let deleted = db.cleanup(&retention, Utc::now())?;
+            let purged = db.purge_trash(Some(retention[&TaskStatus::Completed]))?;
+            println!(
+                "Cleaned up {} old completed tasks and {} trashed tasks",
+                deleted, purged
+            );
+        }
+        Some(Commands::PruneDuplicates { dry_run }) => {
+            if dry_run {
+                let duplicates = db.duplicate_tasks()?;
+                for task in &duplicates {
+                    println!(
+                        "  - [{}] {} ({})",
+                        task.agent_type, task.title, task.task_id
+                    );
+                }
+                println!("Would remove {} duplicate tasks", duplicates.len());
+            } else {
+                let count = db.transaction(|db| db.prune_duplicates())?;
+                println!("Removed {} duplicate tasks", count);
+            }
+        }
+        Some(Commands::Archive { action }) => match action {
+            ArchiveAction::Search { term } => {
+                let path = config.archive_path.as_ref().ok_or_else(|| {
+                    error::invalid_argument("No archive_path is set in the config")
+                })?;
+                let found = archive::Archive::new(path).search(&term)?;
+                display::display_archive(&found, cli.format)?;
+            }
+        },
+        Some(Commands::Doctor) => {
+            let corrupt = db.corrupt_rows()?;
+            for row in &corrupt {
+                println!(
+                    "Task {}: {} is not valid JSON ({})",
+                    row.task_id, row.column, row.error
+                );
+            }
+            if corrupt.is_empty() {
+                println!("No problems found");
+            } else {
+                std::process::exit(1);
+            }
         }
+        Some(Commands::Undo) => match db.transaction(|db| db.restore_last_deleted())? {
+            Some(task) => println!("Task {} restored: {}", task.task_id, task.title),
+            None => println!("Nothing to undo: the trash is empty"),
+        },
+        Some(Commands::Trash { action }) => match action {
+            TrashAction::List => display::display_trash(&db.list_trash()?, cli.format)?,
+            TrashAction::Empty => {
+                let count = db.list_trash()?.len();
+                if count == 0 {
+                    println!("Trash is already empty");
+                } else if cli::confirm(
+                    &format!("Permanently delete {} trashed tasks?", count),
+                    cli.yes,
+                )? {
+                    let purged = db.purge_trash(None)?;
+                    println!("Permanently deleted {} tasks", purged);
+                } else {
+                    println!("Aborted. The trash was left as it is.");
+                }
+            }
+        },
         Some(Commands::Report { action }) => match action {
             ReportAction::Start {
                 task_id,
                 agent_type,
                 cwd,
                 title,
+                title_file,
                 pid,
                 ppid,
+                dedup,
+                env,
+                env_all,
+                ttl,
             } => {
-                let mut task = Task::new(task_id, agent_type, title, pid, ppid);
+                let title = cli::read_title(title, title_file, std::io::stdin())?;
+                let agent = AgentType::from(agent_type.as_str());
+                if !agent.is_known() {
+                    tracing::warn!(
+                        "Unknown agent type '{}' (known: {})",
+                        agent,
+                        AgentType::KNOWN.join(", ")
+                    );
+                }
+
+                let mut task = Task::with_max_title_len(
+                    task_id,
+                    agent.to_string(),
+                    &title,
+                    pid,
+                    ppid,
+                    config.max_title_len(),
+                );
+                if dedup {
+                    task.dedup_key = Some(models::dedup_key(&task.agent_type, &cwd, &task.title));
+                }
 
                 // Add context
                 task.context = Some(TaskContext {
@@ -149,13 +772,62 @@ fn main() -> Result<()> {
                     extra: HashMap::new(),
                 });
 
-                db.insert_task(&task)?;
-                println!("Task started: {}", task.task_id);
+                if env_all || !env.is_empty() {
+                    let vars = std::env::vars_os().map(|(name, value)| {
+                        (
+                            name.to_string_lossy().into_owned(),
+                            value.to_string_lossy().into_owned(),
+                        )
+                    });
+                    let captured = models::capture_env(vars, &env, env_all);
+                    task.set_metadata(models::ENV_KEY.to_string(), serde_json::json!(captured));
+                }
+                if let Some(ttl) = ttl {
+                    task.expire_after(chrono::Duration::from_std(ttl)?);
+                }
+
+                let started = db.transaction(|db| db.start_task(&task))?;
+                // Scripts read stdout, so the difference only goes to stderr
+                match &started {
+                    db::Started::Created(_) => {}
+                    db::Started::Existing(id) => {
+                        eprintln!("Task {} already existed; updated it", id)
+                    }
+                    db::Started::Reused(id) => {
+                        eprintln!("Reusing active task {} with the same prompt", id)
+                    }
+                }
+                println!("Task started: {}", started.task_id());
+            }
+            ReportAction::Update {
+                task_id,
+                title,
+                cwd,
+                url,
+                ttl,
+            } => {
+                let mut task = db
+                    .get_task_by_id(&task_id)?
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
+
+                let title = match title {
+                    Some(title) => Some(cli::read_title(Some(title), None, std::io::stdin())?),
+                    None => None,
+                };
+                if let Some(title) = &title {
+                    task.set_title(title, config.max_title_len());
+                }
+                task.update_details(None, cwd, url);
+                if let Some(ttl) = ttl {
+                    task.expire_after(chrono::Duration::from_std(ttl)?);
+                }
+                db.update_task(&task)?;
+                println!("Task updated: {}", task_id);
             }
             ReportAction::Complete { task_id, exit_code } => {
                 let mut task = db
                     .get_task_by_id(&task_id)?
-                    .ok_or_else(|| anyhow::anyhow!("Task not found: {}", task_id))?;
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
 
                 // If exit_code is provided and non-zero, mark as exited (failed)
                 // Otherwise mark as completed (finished generating)
@@ -174,7 +846,7 @@ fn main() -> Result<()> {
             ReportAction::Running { task_id } => {
                 let mut task = db
                     .get_task_by_id(&task_id)?
-                    .ok_or_else(|| anyhow::anyhow!("Task not found: {}", task_id))?;
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
 
                 task.set_running();
                 db.update_task(&task)?;
@@ -183,17 +855,327 @@ fn main() -> Result<()> {
             ReportAction::Exited { task_id, exit_code } => {
                 let mut task = db
                     .get_task_by_id(&task_id)?
-                    .ok_or_else(|| anyhow::anyhow!("Task not found: {}", task_id))?;
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
 
                 task.set_exited(exit_code);
                 db.update_task(&task)?;
                 println!("Task exited: {}", task_id);
             }
+            ReportAction::NeedsAttention { task_id, reason } => {
+                let mut task = db
+                    .get_task_by_id(&task_id)?
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
+
+                // Attention is only shown on running tasks
+                if task.status != TaskStatus::Running {
+                    task.set_running();
+                }
+                task.flag_attention(AttentionReason::Custom(reason));
+                db.update_task(&task)?;
+                println!("Task needs attention: {}", task_id);
+            }
+            ReportAction::Failed { task_id, exit_code } => {
+                let mut task = db
+                    .get_task_by_id(&task_id)?
+                    .ok_or_else(|| error::task_not_found(&task_id))?;
+
+                task.set_exited(Some(exit_code));
+                db.update_task(&task)?;
+                println!("Task failed: {}", task_id);
+            }
+        },
+        Some(Commands::ImportProcesses { file, no_monitor }) => {
+            let text = if file.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?
+            } else {
+                std::fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?
+            };
+            let rows = import::parse_rows(&text)
+                .map_err(|e| error::invalid_argument(format!("{:#}", e)))?;
+
+            let tasks: Vec<Task> = rows
+                .into_iter()
+                .map(|row| {
+                    let agent = AgentType::from(row.agent_type.as_str());
+                    if !agent.is_known() {
+                        tracing::warn!(task_id = %row.task_id, "Unknown agent type '{}'", agent);
+                    }
+                    Task::with_max_title_len(
+                        row.task_id,
+                        agent.to_string(),
+                        &row.title,
+                        Some(row.pid),
+                        None,
+                        config.max_title_len(),
+                    )
+                })
+                .collect();
+            let started = db.transaction(|db| {
+                tasks
+                    .iter()
+                    .map(|task| db.start_task(task))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            let existed = started
+                .iter()
+                .filter(|started| !matches!(started, db::Started::Created(_)))
+                .count();
+            println!(
+                "Imported {} tasks ({} already existed)",
+                tasks.len(),
+                existed
+            );
+
+            if !no_monitor {
+                // A monitor whose process is already gone marks its task
+                // exited; tasks that already existed have their own monitor
+                let created = tasks
+                    .iter()
+                    .zip(&started)
+                    .filter(|(_, started)| matches!(started, db::Started::Created(_)));
+                for (task, _) in created {
+                    let pid = task.pid.expect("imported tasks have a pid");
+                    if let Err(e) = monitor::spawn_monitor(&task.task_id, pid) {
+                        tracing::warn!(task_id = %task.task_id, "Failed to spawn monitor: {:#}", e);
+                    }
+                }
+            }
+        }
+        Some(Commands::Link {
+            web_task_id,
+            code_task_id,
+        }) => {
+            let mut web = require_task(&db, &web_task_id)?;
+            let mut code = require_task(&db, &code_task_id)?;
+            if web.task_id == code.task_id {
+                return Err(error::invalid_argument("Cannot link a task to itself"));
+            }
+
+            models::link_tasks(&mut web, &mut code);
+            db.transaction(|db| {
+                db.update_task(&web)?;
+                db.update_task(&code)
+            })?;
+            println!("Linked {} to {}", web.task_id, code.task_id);
+        }
+        Some(Commands::Merge {
+            keep_task_id,
+            drop_task_id,
+            prefer_newer,
+        }) => {
+            let mut keep = require_task(&db, &keep_task_id)?;
+            let drop = require_task(&db, &drop_task_id)?;
+            if keep.task_id == drop.task_id {
+                return Err(error::invalid_argument("Cannot merge a task into itself"));
+            }
+
+            models::merge_tasks(&mut keep, &drop, prefer_newer);
+            db.transaction(|db| {
+                // Tasks linked to the duplicate now point at the kept one
+                for id in drop.related().into_iter().filter(|id| *id != keep.task_id) {
+                    if let Some(mut related) = db.get_task_by_id(&id)? {
+                        if related.replace_related(&drop.task_id, &keep.task_id) {
+                            db.update_task(&related)?;
+                        }
+                    }
+                }
+                db.delete_task(&drop.task_id)?;
+                db.update_task(&keep)
+            })?;
+            println!("Merged {} into {}", drop.task_id, keep.task_id);
+        }
+        Some(Commands::Meta { action }) => match action {
+            MetaAction::Set {
+                task_id,
+                key,
+                value,
+            } => {
+                let mut task = require_task(&db, &task_id)?;
+                task.set_metadata(key, value);
+                db.update_task(&task)?;
+            }
+            MetaAction::Get { task_id, key } => {
+                let task = require_task(&db, &task_id)?;
+                let metadata = task.metadata.unwrap_or_default();
+                match key {
+                    Some(key) => {
+                        let value = metadata.get(&key).ok_or_else(|| {
+                            error::invalid_argument(format!(
+                                "No metadata key '{}' on task {}",
+                                key, task.task_id
+                            ))
+                        })?;
+                        println!("{}", value);
+                    }
+                    None => println!("{}", serde_json::to_string_pretty(&metadata)?),
+                }
+            }
         },
-        Some(Commands::Monitor { task_id, pid }) => {
+        Some(Commands::InstallHost {
+            browser,
+            extension_id,
+            bridge_path,
+            dry_run,
+        }) => {
+            host::validate_extension_id(browser, &extension_id)?;
+            let bridge = match bridge_path {
+                Some(path) => std::path::absolute(path)?,
+                None => host::default_bridge_path()?,
+            };
+            if !bridge.exists() {
+                tracing::warn!("{} does not exist yet", bridge.display());
+            }
+
+            let path = host::manifest_path(browser)?;
+            let manifest = host::manifest(browser, &bridge, &extension_id);
+            if dry_run {
+                println!("Would write {}:", path.display());
+                println!("{}", serde_json::to_string_pretty(&manifest)?);
+            } else {
+                host::install(&path, &manifest)?;
+                println!("Installed native messaging host: {}", path.display());
+                println!("Reload the extension to connect");
+            }
+        }
+        Some(Commands::UninstallHost { browser, dry_run }) => {
+            let path = host::manifest_path(browser)?;
+            if dry_run {
+                println!("Would remove {}", path.display());
+            } else if host::uninstall(&path)? {
+                println!("Removed {}", path.display());
+            } else {
+                println!("No native messaging host installed at {}", path.display());
+            }
+        }
+        Some(Commands::InstallService { dry_run }) => {
+            let (kind, path) = service::current()?;
+            let exe = service::current_exe()?;
+            let definition = kind.definition(&exe, &db::data_dir().join("daemon.log"));
+            if dry_run {
+                println!("Would write {}:", path.display());
+                print!("{}", definition);
+            } else {
+                service::install(&path, &definition)?;
+                println!("Installed service: {}", path.display());
+                println!("Start it now and at every login with:");
+                println!("  {}", kind.enable_command(&path));
+            }
+        }
+        Some(Commands::UninstallService { dry_run }) => {
+            let (kind, path) = service::current()?;
+            if dry_run {
+                println!("Would remove {}", path.display());
+            } else if path.exists() {
+                // Stopped first: the service manager needs the definition for it
+                if let Err(e) = kind.disable(&path) {
+                    tracing::warn!(error = %e, "Could not stop the service; it may not be loaded");
+                }
+                service::uninstall(&path)?;
+                println!("Removed {}", path.display());
+            } else {
+                println!("No service installed at {}", path.display());
+            }
+        }
+        Some(Commands::Webhook { action }) => match action {
+            WebhookAction::Replay { url } => {
+                let summary = webhook::replay(
+                    &webhook::default_dead_letter_path(),
+                    url.as_deref(),
+                    webhook::RetryPolicy::default(),
+                )?;
+                println!(
+                    "Replayed {} deliveries, {} still failing",
+                    summary.delivered, summary.failed
+                );
+                if summary.failed > 0 {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Some(Commands::Monitor {
+            task_id,
+            pids,
+            mem_threshold,
+            idle_window,
+            exit_on_idle,
+        }) => {
             // Create a monitor and start monitoring
-            let monitor = monitor::TaskMonitor::new(db);
-            monitor.monitor_task(task_id, pid)?;
+            let monitor = monitor::TaskMonitor::new(db)
+                .with_detectors(monitor::build_detectors(&config.detectors)?)
+                .with_mem_threshold_kb(mem_threshold.map(|mb| mb * 1024))
+                .with_idle_window(idle_window)
+                .with_exit_on_idle(exit_on_idle);
+            monitor.monitor_task(task_id.clone(), pids)?;
+
+            if exit_on_idle {
+                let code = monitor::outcome_exit_code(monitor.task(&task_id)?.as_ref());
+                // Drop first so pending webhook deliveries are flushed
+                drop(monitor);
+                std::process::exit(code);
+            }
+        }
+        Some(Commands::Reap { dry_run }) => {
+            let reaped = monitor::reap(&db, dry_run)?;
+            if reaped.is_empty() {
+                println!("All monitors are alive");
+            }
+            for (task, outcome) in &reaped {
+                match outcome {
+                    monitor::Reaped::Restarted {
+                        monitor_pid: Some(pid),
+                    } => println!("Task {}: restarted monitoring (pid {})", task.task_id, pid),
+                    monitor::Reaped::Restarted { monitor_pid: None } => {
+                        println!("Task {}: would restart monitoring", task.task_id)
+                    }
+                    monitor::Reaped::Exited if dry_run => {
+                        println!("Task {}: agent is gone, would mark exited", task.task_id)
+                    }
+                    monitor::Reaped::Exited => {
+                        println!("Task {}: agent is gone, marked exited", task.task_id)
+                    }
+                }
+            }
+        }
+        Some(Commands::Daemon { interval }) => {
+            let _lock = monitor::lock::PidLock::acquire(monitor::lock::default_lock_path())?;
+            install_stop_handler();
+            tracing::info!(?interval, "Daemon started");
+            println!("Daemon running (Ctrl+C to stop)");
+
+            let retention = auto_cleanup.map(|_| config.retention());
+            let mut next_pass = Instant::now();
+            // Short sleeps so a stop request is noticed quickly
+            while !STOP.load(Ordering::Relaxed) {
+                if Instant::now() >= next_pass {
+                    daemon_pass(&db, retention.as_ref(), &config.escalation);
+                    next_pass = Instant::now() + interval;
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+            tracing::info!("Daemon stopped");
+        }
+        Some(Commands::Exec {
+            task_id,
+            mem_threshold,
+            idle_window,
+            command,
+        }) => {
+            // Ctrl-C is meant for the agent; dying here would take it down too
+            #[cfg(unix)]
+            // SAFETY: installing SIG_IGN has no handler to race with
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_IGN);
+                libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+            }
+
+            let monitor = monitor::TaskMonitor::new(db)
+                .with_detectors(monitor::build_detectors(&config.detectors)?)
+                .with_mem_threshold_kb(mem_threshold.map(|mb| mb * 1024))
+                .with_idle_window(idle_window);
+            let code = monitor.run_child(task_id, &command)?;
+            drop(monitor);
+            std::process::exit(code);
         }
     }
 
@@ -0,0 +1,75 @@
+use std::fmt;
+
+/// The agents agent-inbox knows how to badge
+///
+/// Tasks still store `agent_type` as a plain string; this is the parsed view
+/// of it. Anything unrecognised, including legacy rows, becomes `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentType {
+    ClaudeCode,
+    ClaudeWeb,
+    GeminiWeb,
+    OpenCode,
+    Other(String),
+}
+
+impl AgentType {
+    /// Canonical names of the known variants, in display order
+    pub const KNOWN: &'static [&'static str] =
+        &["claude_code", "claude_web", "gemini_web", "opencode"];
+
+    /// The string stored in the database
+    pub fn as_str(&self) -> &str {
+        match self {
+            AgentType::ClaudeCode => "claude_code",
+            AgentType::ClaudeWeb => "claude_web",
+            AgentType::GeminiWeb => "gemini_web",
+            AgentType::OpenCode => "opencode",
+            AgentType::Other(name) => name,
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        !matches!(self, AgentType::Other(_))
+    }
+}
+
+impl From<&str> for AgentType {
+    fn from(s: &str) -> Self {
+        // Accept hyphens and any case so "Claude-Code" lands on the same badge
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "claude_code" => AgentType::ClaudeCode,
+            "claude_web" => AgentType::ClaudeWeb,
+            "gemini_web" => AgentType::GeminiWeb,
+            "opencode" | "open_code" => AgentType::OpenCode,
+            _ => AgentType::Other(s.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AgentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_known_and_aliases() {
+        assert_eq!(AgentType::from("claude_code"), AgentType::ClaudeCode);
+        assert_eq!(AgentType::from("Claude-Code"), AgentType::ClaudeCode);
+        assert_eq!(AgentType::from("open-code"), AgentType::OpenCode);
+        assert_eq!(AgentType::from("gemini_web").to_string(), "gemini_web");
+    }
+
+    #[test]
+    fn test_unknown_is_other() {
+        let agent = AgentType::from("claud_code");
+        assert_eq!(agent, AgentType::Other("claud_code".to_string()));
+        assert!(!agent.is_known());
+        assert_eq!(agent.to_string(), "claud_code");
+    }
+}
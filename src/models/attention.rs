@@ -0,0 +1,234 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a running task was flagged for the user's attention
+///
+/// Stored as tagged JSON (`{"kind":"idle","idle_secs":150}`) so tools can
+/// route or theme on `kind`; `Display` gives the human text. Rows written
+/// before the column was structured hold free text, which reads back as
+/// `Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(into = "Tagged", from = "Stored")]
+pub enum AttentionReason {
+    /// No CPU activity across the process tree
+    Idle {
+        idle_secs: u64,
+    },
+    /// Sleeping on a terminal read
+    AwaitingInput {
+        idle_secs: u64,
+    },
+    /// Resident memory above the configured threshold
+    HighMemory {
+        rss_kb: u64,
+    },
+    /// Stuck in uninterruptible sleep (`D` state), usually on I/O
+    BlockedIo {
+        blocked_secs: u64,
+    },
+    /// A chat agent finished its turn and is waiting for the next prompt;
+    /// nothing is wrong, it's the user's move
+    ReadyForReply,
+    Custom(String),
+}
+
+impl AttentionReason {
+    /// Machine-readable name of the variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AttentionReason::Idle { .. } => "idle",
+            AttentionReason::AwaitingInput { .. } => "awaiting_input",
+            AttentionReason::HighMemory { .. } => "high_memory",
+            AttentionReason::BlockedIo { .. } => "blocked_io",
+            AttentionReason::ReadyForReply => "ready_for_reply",
+            AttentionReason::Custom(_) => "custom",
+        }
+    }
+
+    /// Whether the task is blocked on something, as opposed to simply
+    /// waiting for the user's next prompt
+    pub fn is_blocking(&self) -> bool {
+        !matches!(self, AttentionReason::ReadyForReply)
+    }
+
+    /// Same variant, ignoring the measured values
+    pub fn same_kind(&self, other: &AttentionReason) -> bool {
+        self.kind() == other.kind()
+    }
+
+    /// Value for the `attention_reason` column
+    pub fn to_column(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.to_string())
+    }
+
+    /// Parse the `attention_reason` column, accepting legacy free text
+    pub fn from_column(value: &str) -> Self {
+        serde_json::from_str(value).unwrap_or_else(|_| AttentionReason::Custom(value.to_string()))
+    }
+}
+
+impl fmt::Display for AttentionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttentionReason::Idle { idle_secs } => {
+                write!(f, "No CPU activity for {}", format_idle(*idle_secs))
+            }
+            AttentionReason::AwaitingInput { idle_secs } => {
+                write!(f, "Waiting for input (idle {})", format_idle(*idle_secs))
+            }
+            AttentionReason::HighMemory { rss_kb } => {
+                write!(f, "High memory usage ({} MB resident)", rss_kb / 1024)
+            }
+            AttentionReason::BlockedIo { blocked_secs } => {
+                write!(f, "Stuck on I/O for {}", format_idle(*blocked_secs))
+            }
+            AttentionReason::ReadyForReply => f.write_str("Ready for your reply"),
+            AttentionReason::Custom(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Compact human duration: "45s", "2m 30s", "1h 5m"
+pub fn format_idle(secs: u64) -> String {
+    let (hours, mins, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    match (hours, mins, secs) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// Serialized form: internally tagged by `kind`
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Tagged {
+    Idle { idle_secs: u64 },
+    AwaitingInput { idle_secs: u64 },
+    HighMemory { rss_kb: u64 },
+    BlockedIo { blocked_secs: u64 },
+    ReadyForReply,
+    Custom { message: String },
+}
+
+/// Anything that may be found where a reason is expected: the tagged form,
+/// or a bare string from before reasons were structured
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Stored {
+    Tagged(Tagged),
+    Legacy(String),
+}
+
+impl From<AttentionReason> for Tagged {
+    fn from(reason: AttentionReason) -> Self {
+        match reason {
+            AttentionReason::Idle { idle_secs } => Tagged::Idle { idle_secs },
+            AttentionReason::AwaitingInput { idle_secs } => Tagged::AwaitingInput { idle_secs },
+            AttentionReason::HighMemory { rss_kb } => Tagged::HighMemory { rss_kb },
+            AttentionReason::BlockedIo { blocked_secs } => Tagged::BlockedIo { blocked_secs },
+            AttentionReason::ReadyForReply => Tagged::ReadyForReply,
+            AttentionReason::Custom(message) => Tagged::Custom { message },
+        }
+    }
+}
+
+impl From<Stored> for AttentionReason {
+    fn from(stored: Stored) -> Self {
+        match stored {
+            Stored::Tagged(Tagged::Idle { idle_secs }) => AttentionReason::Idle { idle_secs },
+            Stored::Tagged(Tagged::AwaitingInput { idle_secs }) => {
+                AttentionReason::AwaitingInput { idle_secs }
+            }
+            Stored::Tagged(Tagged::HighMemory { rss_kb }) => AttentionReason::HighMemory { rss_kb },
+            Stored::Tagged(Tagged::BlockedIo { blocked_secs }) => {
+                AttentionReason::BlockedIo { blocked_secs }
+            }
+            Stored::Tagged(Tagged::ReadyForReply) => AttentionReason::ReadyForReply,
+            Stored::Tagged(Tagged::Custom { message }) | Stored::Legacy(message) => {
+                AttentionReason::Custom(message)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            AttentionReason::AwaitingInput { idle_secs: 45 }.to_string(),
+            "Waiting for input (idle 45s)"
+        );
+        assert_eq!(
+            AttentionReason::Idle { idle_secs: 150 }.to_string(),
+            "No CPU activity for 2m 30s"
+        );
+        assert_eq!(
+            AttentionReason::HighMemory { rss_kb: 2_097_152 }.to_string(),
+            "High memory usage (2048 MB resident)"
+        );
+        assert_eq!(
+            AttentionReason::BlockedIo { blocked_secs: 180 }.to_string(),
+            "Stuck on I/O for 3m"
+        );
+        assert_eq!(
+            AttentionReason::ReadyForReply.to_string(),
+            "Ready for your reply"
+        );
+        assert_eq!(
+            AttentionReason::Custom("Test".to_string()).to_string(),
+            "Test"
+        );
+    }
+
+    #[test]
+    fn test_format_idle() {
+        assert_eq!(format_idle(0), "0s");
+        assert_eq!(format_idle(600), "10m");
+        assert_eq!(format_idle(3900), "1h 5m");
+        assert_eq!(format_idle(7200), "2h");
+    }
+
+    #[test]
+    fn test_column_round_trip() {
+        let reason = AttentionReason::Idle { idle_secs: 150 };
+        let column = reason.to_column();
+        assert_eq!(column, r#"{"kind":"idle","idle_secs":150}"#);
+        assert_eq!(AttentionReason::from_column(&column), reason);
+
+        let custom = AttentionReason::Custom("Needs review".to_string());
+        assert_eq!(AttentionReason::from_column(&custom.to_column()), custom);
+
+        let ready = AttentionReason::ReadyForReply;
+        assert_eq!(ready.to_column(), r#"{"kind":"ready_for_reply"}"#);
+        assert_eq!(AttentionReason::from_column(&ready.to_column()), ready);
+        assert!(!ready.is_blocking());
+        assert!(custom.is_blocking());
+    }
+
+    #[test]
+    fn test_legacy_free_text_is_custom() {
+        let reason = AttentionReason::from_column("Process stalled (no activity)");
+        assert_eq!(
+            reason,
+            AttentionReason::Custom("Process stalled (no activity)".to_string())
+        );
+
+        // Same for JSON documents that predate the structured form
+        let reason: AttentionReason = serde_json::from_str(r#""Waiting for input""#).unwrap();
+        assert_eq!(reason.kind(), "custom");
+    }
+
+    #[test]
+    fn test_same_kind_ignores_values() {
+        let short = AttentionReason::Idle { idle_secs: 600 };
+        let long = AttentionReason::Idle { idle_secs: 900 };
+        assert!(short.same_kind(&long));
+        assert!(!short.same_kind(&AttentionReason::HighMemory { rss_kb: 1 }));
+    }
+}
@@ -0,0 +1,105 @@
+//! Environment snapshots recorded by `report start --env/--env-all`
+//!
+//! The snapshot lives in the task's metadata under `env`, as an object of
+//! variable name to value. Variables whose names look like credentials keep
+//! their name but not their value, whether they were asked for by name or
+//! came with `--env-all`.
+
+use std::collections::BTreeMap;
+
+/// Metadata key holding the captured environment
+pub const ENV_KEY: &str = "env";
+
+/// Stored in place of a secret's value
+pub const REDACTED: &str = "[redacted]";
+
+/// Name fragments that mark a variable as a secret (compared uppercase)
+const SECRET_PARTS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL"];
+
+/// Name suffixes that mark a variable as a secret; `KEY` alone would also
+/// catch names like `KEYBOARD_LAYOUT`
+const SECRET_SUFFIXES: &[&str] = &["_KEY", "_PASS", "_AUTH", "_PAT"];
+
+/// Whether the variable `name` probably holds a credential
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_uppercase();
+    name == "KEY"
+        || SECRET_PARTS.iter().any(|part| name.contains(part))
+        || SECRET_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// The variables of `vars` named in `keys` (or all of them with `all`), with
+/// secrets redacted. Named variables that aren't set are left out.
+pub fn capture_env(
+    vars: impl IntoIterator<Item = (String, String)>,
+    keys: &[String],
+    all: bool,
+) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(name, _)| all || keys.contains(name))
+        .map(|(name, value)| {
+            let value = if is_secret(&name) {
+                REDACTED.to_string()
+            } else {
+                value
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_secret() {
+        for name in [
+            "GITHUB_TOKEN",
+            "ANTHROPIC_API_KEY",
+            "AWS_SECRET_ACCESS_KEY",
+            "DB_PASSWORD",
+            "npm_token",
+            "GOOGLE_APPLICATION_CREDENTIALS",
+            "SMTP_PASS",
+        ] {
+            assert!(is_secret(name), "{}", name);
+        }
+        for name in [
+            "PATH",
+            "HOME",
+            "KEYBOARD_LAYOUT",
+            "SSH_AUTH_SOCK",
+            "MONKEY_MODE",
+        ] {
+            assert!(!is_secret(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_capture_env() {
+        let vars = || {
+            [
+                ("PATH", "/usr/bin"),
+                ("HOME", "/home/me"),
+                ("GITHUB_TOKEN", "ghp_123"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        };
+
+        let picked = capture_env(vars(), &["PATH".to_string(), "UNSET".to_string()], false);
+        assert_eq!(
+            picked,
+            BTreeMap::from([("PATH".to_string(), "/usr/bin".to_string())])
+        );
+
+        // Secrets are redacted even when asked for by name
+        let token = capture_env(vars(), &["GITHUB_TOKEN".to_string()], false);
+        assert_eq!(token["GITHUB_TOKEN"], REDACTED);
+
+        let all = capture_env(vars(), &[], true);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all["HOME"], "/home/me");
+        assert_eq!(all["GITHUB_TOKEN"], REDACTED);
+    }
+}
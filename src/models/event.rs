@@ -0,0 +1,14 @@
+use super::TaskStatus;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A recorded status change of a task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskEvent {
+    pub task_id: String,
+    /// None for the event that created the task
+    pub from_status: Option<TaskStatus>,
+    pub to_status: TaskStatus,
+    pub reason: Option<String>,
+    pub at: DateTime<Utc>,
+}
@@ -0,0 +1,52 @@
+//! Native messaging frames the browser extension exchanges with
+//! `agent-bridge`
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A task update the extension sends to agent-bridge
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct IncomingMessage {
+    #[serde(rename = "type")]
+    #[schemars(extend("examples" = ["task_update"]))]
+    pub msg_type: String,
+    pub task_id: String,
+    pub agent_type: String,
+    #[schemars(extend(
+        "enum" = ["running", "completed", "ready_for_reply", "needs_attention", "exited"]
+    ))]
+    pub status: String,
+    pub title: String,
+    pub context: MessageContext,
+    /// Increases with every update the extension sends; updates that arrive
+    /// after a later one for the same task are dropped
+    #[serde(default)]
+    pub seq: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MessageContext {
+    pub url: Option<String>,
+    pub conversation_id: Option<String>,
+    /// Milliseconds since the epoch
+    pub timestamp: Option<i64>,
+    pub duration_ms: Option<i64>,
+}
+
+/// Several queued updates in one frame (`"type": "batch"`), applied in order
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchMessage {
+    pub messages: Vec<IncomingMessage>,
+}
+
+/// agent-bridge's reply to every frame
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OutgoingMessage {
+    #[schemars(extend("enum" = ["ok", "error"]))]
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Number of messages applied, for batch frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processed: Option<usize>,
+}
@@ -1,3 +1,17 @@
+pub mod agent;
+pub mod attention;
+pub mod env;
+pub mod event;
+pub mod message;
+pub mod schema;
 pub mod task;
 
-pub use task::{Task, TaskContext, TaskStatus};
+pub use agent::AgentType;
+pub use attention::AttentionReason;
+pub use env::{capture_env, ENV_KEY};
+pub use event::TaskEvent;
+pub use schema::json_schema;
+pub use task::{
+    dedup_key, link_tasks, merge_tasks, normalize_project_path, Health, Task, TaskContext,
+    TaskStatus, DEFAULT_MAX_TITLE_LEN, RELATED_KEY,
+};
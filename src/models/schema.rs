@@ -0,0 +1,155 @@
+//! JSON Schema (draft 2020-12) for the data other programs read and write:
+//! tasks as `list --format json` and `show --json` print them, and the
+//! native messaging frames the browser extension exchanges with
+//! `agent-bridge`
+//!
+//! Generated by `schemars` from the types themselves, so it follows their
+//! serde attributes and doc comments.
+
+use super::message::{BatchMessage, IncomingMessage, OutgoingMessage};
+use super::Task;
+use schemars::generate::SchemaSettings;
+use serde_json::{json, Map, Value};
+
+/// The dialect every schema here is written in
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// One document holding every schema under `$defs`, referring to each other
+/// by `#/$defs/<Name>`
+pub fn json_schema() -> Value {
+    // Tasks and replies as agent-inbox writes them; updates as the bridge
+    // reads them, where optional fields may be left out
+    let mut output = SchemaSettings::draft2020_12()
+        .for_serialize()
+        .into_generator();
+    output.subschema_for::<Task>();
+    output.subschema_for::<OutgoingMessage>();
+    let mut input = SchemaSettings::draft2020_12()
+        .for_deserialize()
+        .into_generator();
+    input.subschema_for::<IncomingMessage>();
+    input.subschema_for::<BatchMessage>();
+
+    let mut defs = Map::new();
+    defs.extend(output.take_definitions(true));
+    defs.extend(input.take_definitions(true));
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": "agent-inbox",
+        "$defs": defs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AttentionReason, Task, TaskContext, TaskStatus};
+    use std::collections::HashMap;
+
+    fn def(name: &str) -> Value {
+        json_schema()["$defs"][name].clone()
+    }
+
+    /// Every key of `value` is a property of `schema` (unless it takes any),
+    /// and every required property is present
+    fn assert_matches(schema: &Value, value: &Value) {
+        let properties = schema["properties"].as_object().unwrap();
+        let object = value.as_object().unwrap();
+        if schema["additionalProperties"] != true {
+            for key in object.keys() {
+                assert!(
+                    properties.contains_key(key),
+                    "{} is missing from the schema",
+                    key
+                );
+            }
+        }
+        for key in schema["required"].as_array().unwrap() {
+            assert!(
+                object.contains_key(key.as_str().unwrap()),
+                "{} is not serialized",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_is_json_with_every_def() {
+        let text = serde_json::to_string_pretty(&json_schema()).unwrap();
+        let schema: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(schema["$schema"], SCHEMA_DIALECT);
+        for name in [
+            "Task",
+            "TaskContext",
+            "IncomingMessage",
+            "BatchMessage",
+            "OutgoingMessage",
+        ] {
+            assert!(schema["$defs"][name].is_object(), "no {}", name);
+        }
+        assert!(text.contains("#/$defs/TaskContext"));
+
+        // Updates are described as read: a missing seq is accepted
+        let required = &schema["$defs"]["IncomingMessage"]["required"];
+        assert!(required.as_array().unwrap().contains(&json!("task_id")));
+        assert!(!required.as_array().unwrap().contains(&json!("seq")));
+    }
+
+    #[test]
+    fn test_task_schema_matches_serialized_task() {
+        let mut task = Task::new(
+            "t1".to_string(),
+            "claude_code".to_string(),
+            "Fix the build".to_string(),
+            Some(1),
+            None,
+        );
+        let mut extra = HashMap::new();
+        extra.insert("log_path".to_string(), json!("/tmp/log"));
+        task.context = Some(TaskContext {
+            url: None,
+            project_path: Some("/repo".to_string()),
+            session_id: None,
+            extra,
+        });
+        task.source = Some("/other/tasks.db".to_string());
+        task.raw_metadata = Some("{".to_string());
+
+        let value = serde_json::to_value(&task).unwrap();
+        assert_matches(&def("Task"), &value);
+        assert_matches(&def("TaskContext"), &value["context"]);
+
+        let statuses = def("TaskStatus")["enum"].clone();
+        for status in [
+            TaskStatus::Running,
+            TaskStatus::Paused,
+            TaskStatus::Completed,
+            TaskStatus::Exited,
+        ] {
+            assert!(statuses
+                .as_array()
+                .unwrap()
+                .contains(&json!(status.as_str())));
+        }
+    }
+
+    #[test]
+    fn test_attention_schema_covers_every_kind() {
+        let variants = def("AttentionReason")["oneOf"].as_array().unwrap().clone();
+        for reason in [
+            AttentionReason::Idle { idle_secs: 1 },
+            AttentionReason::AwaitingInput { idle_secs: 1 },
+            AttentionReason::HighMemory { rss_kb: 1 },
+            AttentionReason::BlockedIo { blocked_secs: 1 },
+            AttentionReason::ReadyForReply,
+            AttentionReason::Custom("Approve?".to_string()),
+        ] {
+            let value = serde_json::to_value(&reason).unwrap();
+            let variant = variants
+                .iter()
+                .find(|v| v["properties"]["kind"]["const"] == value["kind"])
+                .unwrap_or_else(|| panic!("no variant for {}", value));
+            assert_matches(variant, &value);
+        }
+    }
+}
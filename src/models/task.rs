@@ -1,14 +1,22 @@
+use super::{AgentType, AttentionReason};
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-/// Task status - simplified to 3 states for reliability
+/// Characters of a title kept in the database unless `max_title_len` is set
+pub const DEFAULT_MAX_TITLE_LEN: usize = 100;
+
+/// Task status - simplified to 3 states for reliability, plus a manual pause
 /// - Running: Agent is actively generating output
+/// - Paused: Deliberately suspended by the user (e.g. rate-limited)
 /// - Completed: Agent finished generating, waiting for user input
 /// - Exited: Agent/tab closed or process terminated
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
 pub enum TaskStatus {
     Running,
+    Paused,
     Completed,
     Exited,
 }
@@ -17,25 +25,59 @@ impl TaskStatus {
     pub fn as_str(&self) -> &str {
         match self {
             TaskStatus::Running => "running",
+            TaskStatus::Paused => "paused",
             TaskStatus::Completed => "completed",
             TaskStatus::Exited => "exited",
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
+    /// Parse a canonical status name, a legacy one still found in old
+    /// databases, or a synonym people type (`active`, `done`, `dead`, in any
+    /// case)
     pub fn from_str(s: &str) -> Result<Self, String> {
-        match s {
-            "running" => Ok(TaskStatus::Running),
-            "completed" => Ok(TaskStatus::Completed),
-            "exited" => Ok(TaskStatus::Exited),
+        match s.to_ascii_lowercase().as_str() {
+            "running" | "active" | "working" => Ok(TaskStatus::Running),
+            "paused" | "suspended" => Ok(TaskStatus::Paused),
+            "completed" | "done" | "finished" | "complete" => Ok(TaskStatus::Completed),
+            "exited" | "dead" | "stopped" | "closed" => Ok(TaskStatus::Exited),
             // Legacy support
             "needs_attention" => Ok(TaskStatus::Completed),
             "failed" => Ok(TaskStatus::Exited),
-            _ => Err(format!("Invalid task status: {}", s)),
+            _ => Err(format!(
+                "Invalid task status: {} (expected running, paused, completed or exited)",
+                s
+            )),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A project path without trailing slashes, so `/repo/` and `/repo` are the
+/// same project (the root stays `/`)
+pub fn normalize_project_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// Stable fingerprint of what a task is doing, used to fold repeated starts
+/// of the same prompt in the same directory into one task. FNV-1a keeps it
+/// identical across builds, unlike `std`'s hashers.
+pub fn dedup_key(agent_type: &str, cwd: &str, title: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in [agent_type, cwd, title] {
+        // NUL separator so ("ab", "c") and ("a", "bc") differ
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Where the task runs; any other keys are kept as they are
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TaskContext {
     pub url: Option<String>,
     pub project_path: Option<String>,
@@ -44,10 +86,21 @@ pub struct TaskContext {
     pub extra: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl TaskContext {
+    /// File the agent's output goes to (`extra["log_path"]`), as set by its
+    /// wrapper
+    pub fn log_path(&self) -> Option<&str> {
+        self.extra.get("log_path")?.as_str()
+    }
+}
+
+/// A tracked agent task
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Task {
+    /// Row id in the database
     pub id: Option<i64>,
     pub task_id: String,
+    #[schemars(extend("examples" = ["claude_code", "claude_web"]))]
     pub agent_type: String,
     pub title: String,
     pub status: TaskStatus,
@@ -57,10 +110,31 @@ pub struct Task {
     pub pid: Option<i32>,
     pub ppid: Option<i32>,
     pub monitor_pid: Option<i32>,
-    pub attention_reason: Option<String>,
+    pub attention_reason: Option<AttentionReason>,
     pub exit_code: Option<i32>,
     pub context: Option<TaskContext>,
     pub metadata: Option<HashMap<String, serde_json::Value>>,
+    /// Set by `report start --dedup`; see `dedup_key`
+    pub dedup_key: Option<String>,
+    /// When the task's monitor last reported in; written only by the monitor
+    /// (`Database::record_heartbeat`), so saving a task never moves it
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// When cleanup removes the task whatever its status (`--ttl`); never
+    /// when `None`
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the current attention flag went up; `None` while unflagged
+    pub attention_since: Option<DateTime<Utc>>,
+    /// Database the task was read from, when several are shown together
+    /// (`list --merge`); never stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Stored context that isn't valid JSON for `context` (which is then
+    /// `None`), kept verbatim and written back on save until replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_context: Option<String>,
+    /// Same as `raw_context`, for `metadata`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_metadata: Option<String>,
 }
 
 impl Task {
@@ -71,12 +145,64 @@ impl Task {
         pid: Option<i32>,
         ppid: Option<i32>,
     ) -> Self {
-        let now = Utc::now();
+        Self::new_at(task_id, agent_type, title, pid, ppid, Utc::now())
+    }
+
+    /// Same as `new`, created at `now` rather than the current time
+    pub fn new_at(
+        task_id: String,
+        agent_type: String,
+        title: String,
+        pid: Option<i32>,
+        ppid: Option<i32>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self::build(
+            task_id,
+            agent_type,
+            &title,
+            pid,
+            ppid,
+            now,
+            DEFAULT_MAX_TITLE_LEN,
+        )
+    }
+
+    /// Same as `new`, with the title cut to `max_title_len` characters
+    /// (the configured `max_title_len`) instead of the default
+    pub fn with_max_title_len(
+        task_id: String,
+        agent_type: String,
+        title: &str,
+        pid: Option<i32>,
+        ppid: Option<i32>,
+        max_title_len: usize,
+    ) -> Self {
+        Self::build(
+            task_id,
+            agent_type,
+            title,
+            pid,
+            ppid,
+            Utc::now(),
+            max_title_len,
+        )
+    }
+
+    fn build(
+        task_id: String,
+        agent_type: String,
+        title: &str,
+        pid: Option<i32>,
+        ppid: Option<i32>,
+        now: DateTime<Utc>,
+        max_title_len: usize,
+    ) -> Self {
         Self {
             id: None,
             task_id,
             agent_type,
-            title: Self::truncate_title(&title, 100),
+            title: Self::truncate_title(title, max_title_len),
             status: TaskStatus::Running,
             created_at: now,
             updated_at: now,
@@ -88,44 +214,444 @@ impl Task {
             exit_code: None,
             context: None,
             metadata: None,
+            dedup_key: None,
+            last_heartbeat: None,
+            expires_at: None,
+            attention_since: None,
+            source: None,
+            raw_context: None,
+            raw_metadata: None,
+        }
+    }
+
+    /// Attach or replace a metadata entry
+    pub fn set_metadata(&mut self, key: String, value: serde_json::Value) {
+        self.metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(key, value);
+    }
+
+    /// Conversation a web task belongs to: `context.extra["conversation_id"]`,
+    /// falling back to the session id
+    pub fn conversation_id(&self) -> Option<&str> {
+        let context = self.context.as_ref()?;
+        context
+            .extra
+            .get("conversation_id")
+            .and_then(|id| id.as_str())
+            .or(context.session_id.as_deref())
+    }
+
+    /// IDs of the tasks `link_tasks` related this one to, oldest link first
+    pub fn related(&self) -> Vec<String> {
+        let related = self.metadata.as_ref().and_then(|m| m.get(RELATED_KEY));
+        related
+            .and_then(|ids| ids.as_array())
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| id.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn add_related(&mut self, task_id: &str) {
+        let mut related = self.related();
+        if !related.iter().any(|id| id == task_id) {
+            related.push(task_id.to_string());
+            self.set_metadata(RELATED_KEY.to_string(), serde_json::json!(related));
+        }
+    }
+
+    /// Point a relation at `to` instead of `from`, after `from` was merged
+    /// into it. Returns whether the task listed `from`.
+    pub fn replace_related(&mut self, from: &str, to: &str) -> bool {
+        let related = self.related();
+        if !related.iter().any(|id| id == from) {
+            return false;
+        }
+        let kept: Vec<String> = related.into_iter().filter(|id| id != from).collect();
+        self.set_metadata(RELATED_KEY.to_string(), serde_json::json!(kept));
+        if to != self.task_id {
+            self.add_related(to);
         }
+        true
+    }
+
+    /// Parsed view of `agent_type`
+    pub fn agent(&self) -> AgentType {
+        AgentType::from(self.agent_type.as_str())
+    }
+
+    /// The directory the agent works in (`context.project_path`), normalized
+    pub fn project_path(&self) -> Option<&str> {
+        let path = self.context.as_ref()?.project_path.as_deref()?;
+        Some(normalize_project_path(path))
+    }
+
+    /// Replace the title, cut to `max_len` characters (the last three an
+    /// ellipsis) when longer
+    pub fn set_title(&mut self, title: &str, max_len: usize) {
+        self.title = Self::truncate_title(title, max_len);
     }
 
     fn truncate_title(title: &str, max_len: usize) -> String {
-        if title.len() <= max_len {
+        if title.chars().count() <= max_len {
             title.to_string()
         } else {
-            format!("{}...", &title[..max_len.saturating_sub(3)])
+            let kept: String = title.chars().take(max_len.saturating_sub(3)).collect();
+            format!("{}...", kept)
         }
     }
 
+    /// Change what the task is about mid-run: any of a new title, working
+    /// directory or URL. The status stays as it is.
+    pub fn update_details(
+        &mut self,
+        title: Option<&str>,
+        project_path: Option<String>,
+        url: Option<String>,
+    ) {
+        if let Some(title) = title {
+            self.set_title(title, DEFAULT_MAX_TITLE_LEN);
+        }
+        if project_path.is_some() || url.is_some() {
+            let context = self.context.get_or_insert_with(|| TaskContext {
+                url: None,
+                project_path: None,
+                session_id: None,
+                extra: HashMap::new(),
+            });
+            if project_path.is_some() {
+                context.project_path = project_path;
+            }
+            if url.is_some() {
+                context.url = url;
+            }
+        }
+        self.updated_at = Utc::now();
+    }
+
+    /// Have the task expire `ttl` from now
+    pub fn expire_after(&mut self, ttl: chrono::Duration) {
+        self.expire_after_at(ttl, Utc::now());
+    }
+
+    /// Have the task expire `ttl` after `now`
+    pub fn expire_after_at(&mut self, ttl: chrono::Duration, now: DateTime<Utc>) {
+        self.expires_at = Some(now + ttl);
+        self.updated_at = now;
+    }
+
+    /// Time left until the task expires (negative once it has); `None` if it
+    /// never does
+    pub fn time_to_live(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        self.expires_at.map(|at| at - now)
+    }
+
     /// Mark task as completed (finished generating, waiting for user)
     pub fn complete(&mut self) {
+        self.complete_at(Utc::now());
+    }
+
+    /// Same as `complete`, finished at `now`
+    pub fn complete_at(&mut self, now: DateTime<Utc>) {
         self.status = TaskStatus::Completed;
-        self.completed_at = Some(Utc::now());
-        self.updated_at = Utc::now();
+        self.completed_at = Some(now);
+        self.updated_at = now;
     }
 
     /// Mark task as running (actively generating)
-    #[allow(dead_code)]
     pub fn set_running(&mut self) {
+        self.set_running_at(Utc::now());
+    }
+
+    /// Same as `set_running`, resumed at `now`
+    pub fn set_running_at(&mut self, now: DateTime<Utc>) {
         self.status = TaskStatus::Running;
         self.completed_at = None;
-        self.updated_at = Utc::now();
+        self.reset_attention();
+        self.updated_at = now;
+    }
+
+    /// Suspend the task on purpose. Paused tasks aren't checked by the
+    /// monitor's detectors and aren't cleaned up automatically.
+    pub fn pause(&mut self) {
+        self.pause_at(Utc::now());
+    }
+
+    /// Same as `pause`, paused at `now`
+    pub fn pause_at(&mut self, now: DateTime<Utc>) {
+        self.status = TaskStatus::Paused;
+        self.reset_attention();
+        self.updated_at = now;
+    }
+
+    /// Flag the task as needing attention without changing its status. A
+    /// task already flagged keeps its `attention_since`.
+    pub fn flag_attention(&mut self, reason: AttentionReason) {
+        self.flag_attention_at(reason, Utc::now());
+    }
+
+    /// Same as `flag_attention`, flagged at `now`
+    pub fn flag_attention_at(&mut self, reason: AttentionReason, now: DateTime<Utc>) {
+        if self.attention_reason.is_none() || self.attention_since.is_none() {
+            self.attention_since = Some(now);
+        }
+        self.attention_reason = Some(reason);
+        self.updated_at = now;
+    }
+
+    /// Withdraw a previously raised attention flag
+    pub fn clear_attention(&mut self) {
+        self.clear_attention_at(Utc::now());
+    }
+
+    /// Same as `clear_attention`, withdrawn at `now`
+    pub fn clear_attention_at(&mut self, now: DateTime<Utc>) {
+        self.reset_attention();
+        self.updated_at = now;
+    }
+
+    fn reset_attention(&mut self) {
+        self.attention_reason = None;
+        self.attention_since = None;
+        if let Some(metadata) = self.metadata.as_mut() {
+            metadata.remove(ESCALATIONS_KEY);
+        }
+    }
+
+    /// Escalation steps already taken for the current attention flag
+    pub fn escalations(&self) -> usize {
+        let escalations = self.metadata.as_ref().and_then(|m| m.get(ESCALATIONS_KEY));
+        escalations.and_then(|n| n.as_u64()).unwrap_or(0) as usize
+    }
+
+    /// Record that the first `count` escalation steps were taken
+    pub fn set_escalations(&mut self, count: usize) {
+        self.set_metadata(ESCALATIONS_KEY.to_string(), serde_json::json!(count));
+    }
+
+    /// How long the task ran: from creation until it completed or exited, or
+    /// until `now` while it is still active
+    pub fn duration(&self, now: DateTime<Utc>) -> chrono::Duration {
+        self.completed_at.unwrap_or(now) - self.created_at
+    }
+
+    /// Whether a still-active task is blocked on something the user has to
+    /// act on. A chat turn that's only ready for a reply doesn't count.
+    pub fn needs_attention(&self) -> bool {
+        self.status == TaskStatus::Running
+            && self
+                .attention_reason
+                .as_ref()
+                .is_some_and(AttentionReason::is_blocking)
+    }
+
+    /// Whether a still-active task finished its turn and is only waiting for
+    /// the user's next prompt
+    pub fn awaiting_reply(&self) -> bool {
+        self.status == TaskStatus::Running
+            && self
+                .attention_reason
+                .as_ref()
+                .is_some_and(|reason| !reason.is_blocking())
     }
 
     /// Mark task as exited (closed/terminated)
     pub fn set_exited(&mut self, exit_code: Option<i32>) {
+        self.set_exited_at(exit_code, Utc::now());
+    }
+
+    /// Same as `set_exited`, terminated at `now`
+    pub fn set_exited_at(&mut self, exit_code: Option<i32>, now: DateTime<Utc>) {
         self.status = TaskStatus::Exited;
         self.exit_code = exit_code;
-        self.completed_at = Some(Utc::now());
-        self.updated_at = Utc::now();
+        self.completed_at = Some(now);
+        self.updated_at = now;
+    }
+
+    /// Whether the task exited with a non-zero code. An exit without one
+    /// (a closed tab, a killed wrapper) isn't counted as a failure.
+    pub fn failed(&self) -> bool {
+        self.status == TaskStatus::Exited && self.exit_code.is_some_and(|code| code != 0)
+    }
+}
+
+/// Metadata key listing the tasks one was linked with
+pub const RELATED_KEY: &str = "related";
+
+/// Metadata key counting the escalation steps the daemon took for the
+/// task's current attention flag; dropped with the flag
+pub const ESCALATIONS_KEY: &str = "escalations";
+
+/// Carry a web conversation over to the code task that continues it. The
+/// code task gets the conversation's URL and id in its metadata (`web_url`,
+/// `conversation_id`), and both list each other under `related`.
+pub fn link_tasks(web: &mut Task, code: &mut Task) {
+    if let Some(url) = web.context.as_ref().and_then(|context| context.url.clone()) {
+        code.set_metadata("web_url".to_string(), serde_json::json!(url));
+    }
+    if let Some(id) = web.conversation_id().map(String::from) {
+        code.set_metadata("conversation_id".to_string(), serde_json::json!(id));
+    }
+    web.add_related(&code.task_id);
+    code.add_related(&web.task_id);
+}
+
+/// Fold `other` into `keep`, for two rows that turned out to be one task.
+/// `keep` takes the status (and the process and attention state with it)
+/// of whichever of the two changed last, the earlier creation time, and
+/// the union of both contexts and metadata. Where both set a field, `keep`
+/// wins, unless `prefer_newer` and `other` is the more recently updated.
+/// Relations to either task end up on `keep`.
+pub fn merge_tasks(keep: &mut Task, other: &Task, prefer_newer: bool) {
+    let other_is_newer = other.updated_at > keep.updated_at;
+    let other_wins = prefer_newer && other_is_newer;
+
+    if other_is_newer {
+        keep.status = other.status.clone();
+        keep.updated_at = other.updated_at;
+        keep.completed_at = other.completed_at;
+        keep.attention_reason = other.attention_reason.clone();
+        keep.attention_since = other.attention_since;
+        keep.exit_code = other.exit_code;
+        keep.pid = other.pid;
+        keep.ppid = other.ppid;
+        keep.monitor_pid = other.monitor_pid;
+        keep.last_heartbeat = other.last_heartbeat;
+    }
+    keep.created_at = keep.created_at.min(other.created_at);
+    if other_wins {
+        keep.title = other.title.clone();
+    }
+    keep.dedup_key = prefer(keep.dedup_key.take(), other.dedup_key.clone(), other_wins);
+    keep.expires_at = prefer(keep.expires_at, other.expires_at, other_wins);
+
+    keep.context = match (keep.context.take(), other.context.clone()) {
+        (Some(mut context), Some(theirs)) => {
+            context.url = prefer(context.url, theirs.url, other_wins);
+            context.project_path = prefer(context.project_path, theirs.project_path, other_wins);
+            context.session_id = prefer(context.session_id, theirs.session_id, other_wins);
+            union(&mut context.extra, theirs.extra, other_wins);
+            Some(context)
+        }
+        (context, theirs) => context.or(theirs),
+    };
+
+    let related: Vec<String> = keep
+        .related()
+        .into_iter()
+        .chain(other.related())
+        .filter(|id| *id != keep.task_id && *id != other.task_id)
+        .collect();
+    if let Some(theirs) = other.metadata.clone() {
+        union(
+            keep.metadata.get_or_insert_with(HashMap::new),
+            theirs,
+            other_wins,
+        );
+    }
+    if let Some(metadata) = keep.metadata.as_mut() {
+        metadata.remove(RELATED_KEY);
+    }
+    for id in related {
+        keep.add_related(&id);
+    }
+}
+
+fn prefer<T>(ours: Option<T>, theirs: Option<T>, theirs_wins: bool) -> Option<T> {
+    if theirs_wins {
+        theirs.or(ours)
+    } else {
+        ours.or(theirs)
+    }
+}
+
+fn union(
+    ours: &mut HashMap<String, serde_json::Value>,
+    theirs: HashMap<String, serde_json::Value>,
+    theirs_wins: bool,
+) {
+    for (key, value) in theirs {
+        if theirs_wins || !ours.contains_key(&key) {
+            ours.insert(key, value);
+        }
+    }
+}
+
+/// Roll-up of a set of tasks for `list --check`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    /// Tasks flagged for attention
+    pub attention: usize,
+    /// Tasks that exited with a non-zero code
+    pub failed: usize,
+}
+
+impl Health {
+    pub fn of(tasks: &[Task]) -> Self {
+        tasks.iter().fold(Health::default(), |mut health, task| {
+            health.attention += task.needs_attention() as usize;
+            health.failed += task.failed() as usize;
+            health
+        })
+    }
+
+    /// 2 if any task needs attention, otherwise 1 if any failed, otherwise 0.
+    /// A stuck agent is the more urgent of the two, so it wins.
+    pub fn exit_code(&self) -> i32 {
+        if self.attention > 0 {
+            2
+        } else if self.failed > 0 {
+            1
+        } else {
+            0
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_health_exit_code() {
+        let task = |id: &str| {
+            Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                id.to_string(),
+                None,
+                None,
+            )
+        };
+        let mut stuck = task("stuck");
+        stuck.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        let mut failed = task("failed");
+        failed.set_exited(Some(3));
+        let mut closed = task("closed");
+        closed.set_exited(None);
+        let mut done = task("done");
+        done.complete();
+
+        assert_eq!(Health::of(&[]).exit_code(), 0);
+        assert_eq!(
+            Health::of(&[task("busy"), closed.clone(), done.clone()]).exit_code(),
+            0
+        );
+        assert_eq!(Health::of(&[done.clone(), failed.clone()]).exit_code(), 1);
+        let all = [stuck, failed, closed, done];
+        assert_eq!(
+            Health::of(&all),
+            Health {
+                attention: 1,
+                failed: 1
+            }
+        );
+        assert_eq!(Health::of(&all).exit_code(), 2);
+    }
 
     #[test]
     fn test_task_creation() {
@@ -171,6 +697,43 @@ mod tests {
         task.complete();
         assert_eq!(task.status, TaskStatus::Completed);
         assert!(task.completed_at.is_some());
+        assert_eq!(task.completed_at, Some(task.updated_at));
+    }
+
+    #[test]
+    fn test_lifecycle_at_fixed_times() {
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let at = |minutes: i64| start + chrono::Duration::minutes(minutes);
+        let mut task = Task::new_at(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+            start,
+        );
+        assert_eq!((task.created_at, task.updated_at), (start, start));
+
+        task.flag_attention_at(AttentionReason::Idle { idle_secs: 60 }, at(1));
+        task.flag_attention_at(AttentionReason::Idle { idle_secs: 120 }, at(2));
+        assert_eq!(
+            (task.attention_since, task.updated_at),
+            (Some(at(1)), at(2))
+        );
+        task.clear_attention_at(at(3));
+        assert_eq!((task.attention_since, task.updated_at), (None, at(3)));
+
+        task.pause_at(at(4));
+        task.set_running_at(at(5));
+        assert_eq!(task.updated_at, at(5));
+        task.expire_after_at(chrono::Duration::hours(1), at(6));
+        assert_eq!(task.expires_at, Some(at(66)));
+
+        task.complete_at(at(10));
+        assert_eq!(task.duration(at(60)), chrono::Duration::minutes(10));
+        task.set_exited_at(Some(1), at(20));
+        assert_eq!((task.completed_at, task.updated_at), (Some(at(20)), at(20)));
+        assert_eq!(task.created_at, start);
     }
 
     #[test]
@@ -206,21 +769,329 @@ mod tests {
         assert!(task.completed_at.is_none());
     }
 
+    #[test]
+    fn test_update_details() {
+        let mut task = Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        task.complete();
+        let created_at = task.created_at;
+
+        task.update_details(
+            Some("Refined goal"),
+            None,
+            Some("https://example.com".to_string()),
+        );
+        assert_eq!(task.title, "Refined goal");
+        assert_eq!(task.status, TaskStatus::Completed);
+        assert_eq!(task.created_at, created_at);
+        let context = task.context.as_ref().unwrap();
+        assert_eq!(context.url.as_deref(), Some("https://example.com"));
+        assert!(context.project_path.is_none());
+
+        // Fields left out are kept
+        task.update_details(None, Some("/repo".to_string()), None);
+        assert_eq!(task.title, "Refined goal");
+        assert_eq!(task.project_path(), Some("/repo"));
+        assert_eq!(
+            task.context.unwrap().url.as_deref(),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    fn test_title_cap() {
+        let long = "x".repeat(150);
+        let mut task = Task::new(
+            "t".to_string(),
+            "claude_code".to_string(),
+            long.clone(),
+            None,
+            None,
+        );
+        assert_eq!(task.title.len(), DEFAULT_MAX_TITLE_LEN);
+        assert!(task.title.ends_with("..."));
+
+        task.set_title(&long, 200);
+        assert_eq!(task.title, long);
+        let roomy = Task::with_max_title_len(
+            "t".to_string(),
+            "claude_code".to_string(),
+            &long,
+            None,
+            None,
+            200,
+        );
+        assert_eq!(roomy.title, long);
+        task.set_title(&long, 20);
+        assert_eq!(task.title, format!("{}...", "x".repeat(17)));
+
+        // Counted in characters, never cut inside one
+        task.set_title(&"é".repeat(30), 10);
+        assert_eq!(task.title, format!("{}...", "é".repeat(7)));
+    }
+
+    #[test]
+    fn test_attention_flag() {
+        let mut task = Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        assert!(task.needs_attention());
+        assert_eq!(task.status, TaskStatus::Running);
+
+        // Resuming resolves the flag
+        task.set_running();
+        assert!(!task.needs_attention());
+        assert!(task.attention_reason.is_none());
+
+        // A finished chat turn is flagged, but isn't blocked
+        task.flag_attention(AttentionReason::ReadyForReply);
+        assert!(!task.needs_attention());
+        assert!(task.awaiting_reply());
+        assert_eq!(Health::of(std::slice::from_ref(&task)).exit_code(), 0);
+        task.complete();
+        assert!(!task.awaiting_reply());
+    }
+
+    #[test]
+    fn test_link_tasks() {
+        let mut web = Task::new(
+            "web".to_string(),
+            "claude_web".to_string(),
+            "Design the parser".to_string(),
+            None,
+            None,
+        );
+        web.context = Some(TaskContext {
+            url: Some("https://claude.ai/chat/abc".to_string()),
+            project_path: None,
+            session_id: None,
+            extra: HashMap::from([("conversation_id".to_string(), serde_json::json!("abc"))]),
+        });
+        let mut code = Task::new(
+            "code".to_string(),
+            "claude_code".to_string(),
+            "Implement the parser".to_string(),
+            None,
+            None,
+        );
+
+        link_tasks(&mut web, &mut code);
+        let metadata = code.metadata.as_ref().unwrap();
+        assert_eq!(metadata["web_url"], "https://claude.ai/chat/abc");
+        assert_eq!(metadata["conversation_id"], "abc");
+        assert_eq!(code.related(), ["web"]);
+        assert_eq!(web.related(), ["code"]);
+
+        // Linking again doesn't repeat the relation
+        link_tasks(&mut web, &mut code);
+        assert_eq!(code.related(), ["web"]);
+    }
+
+    #[test]
+    fn test_merge_tasks() {
+        let context = |url: Option<&str>, path: &str, key: &str| TaskContext {
+            url: url.map(String::from),
+            project_path: Some(path.to_string()),
+            session_id: None,
+            extra: HashMap::from([(key.to_string(), serde_json::json!(path))]),
+        };
+        let mut keep = Task::new(
+            "keep".to_string(),
+            "claude_code".to_string(),
+            "Old".to_string(),
+            None,
+            None,
+        );
+        keep.context = Some(context(None, "/keep", "log_path"));
+        keep.set_metadata("branch".to_string(), serde_json::json!("main"));
+        keep.set_metadata(RELATED_KEY.to_string(), serde_json::json!(["web", "drop"]));
+        keep.complete();
+
+        let mut drop = Task::new(
+            "drop".to_string(),
+            "claude_code".to_string(),
+            "New".to_string(),
+            None,
+            None,
+        );
+        drop.created_at = keep.created_at - chrono::Duration::minutes(5);
+        drop.updated_at = keep.updated_at + chrono::Duration::minutes(1);
+        drop.pid = Some(42);
+        drop.context = Some(context(Some("https://example.com"), "/drop", "pane"));
+        drop.set_metadata("branch".to_string(), serde_json::json!("fix"));
+        drop.set_metadata("pr".to_string(), serde_json::json!(7));
+        drop.set_metadata(
+            RELATED_KEY.to_string(),
+            serde_json::json!(["keep", "other"]),
+        );
+
+        let mut merged = keep.clone();
+        merge_tasks(&mut merged, &drop, false);
+        // The latest status and the process behind it, the earliest start
+        assert_eq!(merged.status, TaskStatus::Running);
+        assert!(merged.completed_at.is_none());
+        assert_eq!(merged.pid, Some(42));
+        assert_eq!(merged.updated_at, drop.updated_at);
+        assert_eq!(merged.created_at, drop.created_at);
+        // Both sides' fields, the kept one's where both set one
+        assert_eq!(merged.task_id, "keep");
+        assert_eq!(merged.title, "Old");
+        let merged_context = merged.context.as_ref().unwrap();
+        assert_eq!(merged_context.project_path.as_deref(), Some("/keep"));
+        assert_eq!(merged_context.url.as_deref(), Some("https://example.com"));
+        assert_eq!(merged_context.extra.len(), 2);
+        let metadata = merged.metadata.as_ref().unwrap();
+        assert_eq!(metadata["branch"], "main");
+        assert_eq!(metadata["pr"], 7);
+        assert_eq!(merged.related(), ["web", "other"]);
+
+        let mut newer = keep.clone();
+        merge_tasks(&mut newer, &drop, true);
+        assert_eq!(newer.title, "New");
+        assert_eq!(newer.project_path(), Some("/drop"));
+        assert_eq!(newer.metadata.as_ref().unwrap()["branch"], "fix");
+
+        // Preferring the newer one changes nothing when it's the kept one
+        let mut older = drop.clone();
+        older.updated_at = keep.updated_at - chrono::Duration::minutes(1);
+        let mut kept = keep.clone();
+        merge_tasks(&mut kept, &older, true);
+        assert_eq!(kept.status, TaskStatus::Completed);
+        assert_eq!(kept.title, "Old");
+        assert_eq!(kept.project_path(), Some("/keep"));
+    }
+
+    #[test]
+    fn test_replace_related() {
+        let mut web = Task::new(
+            "web".to_string(),
+            "claude_web".to_string(),
+            "W".to_string(),
+            None,
+            None,
+        );
+        web.set_metadata(RELATED_KEY.to_string(), serde_json::json!(["drop", "code"]));
+        assert!(!web.replace_related("missing", "keep"));
+        assert!(web.replace_related("drop", "keep"));
+        assert_eq!(web.related(), ["code", "keep"]);
+        // Never related to itself
+        assert!(web.replace_related("code", "web"));
+        assert_eq!(web.related(), ["keep"]);
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut task = Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+
+        task.pause();
+        assert_eq!(task.status, TaskStatus::Paused);
+        assert!(!task.needs_attention());
+        assert!(task.attention_reason.is_none());
+
+        task.set_running();
+        assert_eq!(task.status, TaskStatus::Running);
+    }
+
+    #[test]
+    fn test_normalize_project_path() {
+        assert_eq!(normalize_project_path("/repo/"), "/repo");
+        assert_eq!(normalize_project_path("/repo//"), "/repo");
+        assert_eq!(normalize_project_path("/repo"), "/repo");
+        assert_eq!(normalize_project_path("/"), "/");
+        assert_eq!(normalize_project_path("relative/dir/"), "relative/dir");
+    }
+
+    #[test]
+    fn test_dedup_key() {
+        let key = dedup_key("claude_code", "/repo", "Fix the build");
+        assert_eq!(key, dedup_key("claude_code", "/repo", "Fix the build"));
+        assert_eq!(key.len(), 16);
+        assert_ne!(key, dedup_key("claude_code", "/other", "Fix the build"));
+        assert_ne!(dedup_key("a", "bc", "t"), dedup_key("ab", "c", "t"));
+    }
+
     #[test]
     fn test_status_serialization() {
         assert_eq!(TaskStatus::Running.as_str(), "running");
         assert_eq!(TaskStatus::Completed.as_str(), "completed");
         assert_eq!(TaskStatus::Exited.as_str(), "exited");
+        assert_eq!(TaskStatus::Paused.as_str(), "paused");
+        assert_eq!(
+            serde_json::to_string(&TaskStatus::Paused).unwrap(),
+            "\"paused\""
+        );
     }
 
     #[test]
     fn test_status_deserialization() {
-        assert_eq!(TaskStatus::from_str("running").unwrap(), TaskStatus::Running);
-        assert_eq!(TaskStatus::from_str("completed").unwrap(), TaskStatus::Completed);
+        assert_eq!(
+            TaskStatus::from_str("running").unwrap(),
+            TaskStatus::Running
+        );
+        assert_eq!(
+            TaskStatus::from_str("completed").unwrap(),
+            TaskStatus::Completed
+        );
         assert_eq!(TaskStatus::from_str("exited").unwrap(), TaskStatus::Exited);
+        assert_eq!(TaskStatus::from_str("paused").unwrap(), TaskStatus::Paused);
+        assert_eq!(
+            serde_json::from_str::<TaskStatus>("\"paused\"").unwrap(),
+            TaskStatus::Paused
+        );
         // Legacy support
-        assert_eq!(TaskStatus::from_str("needs_attention").unwrap(), TaskStatus::Completed);
+        assert_eq!(
+            TaskStatus::from_str("needs_attention").unwrap(),
+            TaskStatus::Completed
+        );
         assert_eq!(TaskStatus::from_str("failed").unwrap(), TaskStatus::Exited);
         assert!(TaskStatus::from_str("invalid").is_err());
     }
+
+    #[test]
+    fn test_status_synonyms() {
+        for (synonym, status) in [
+            ("active", TaskStatus::Running),
+            ("working", TaskStatus::Running),
+            ("suspended", TaskStatus::Paused),
+            ("done", TaskStatus::Completed),
+            ("finished", TaskStatus::Completed),
+            ("complete", TaskStatus::Completed),
+            ("dead", TaskStatus::Exited),
+            ("stopped", TaskStatus::Exited),
+            ("closed", TaskStatus::Exited),
+            ("Running", TaskStatus::Running),
+            ("DONE", TaskStatus::Completed),
+        ] {
+            assert_eq!(
+                TaskStatus::from_str(synonym).unwrap(),
+                status,
+                "{}",
+                synonym
+            );
+        }
+
+        let err = TaskStatus::from_str("bogus").unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid task status: bogus (expected running, paused, completed or exited)"
+        );
+    }
 }
@@ -1,44 +1,91 @@
 //! Attention detectors for CLI process monitoring
 //!
-//! Currently not used - kept for potential future enhancement.
-//! The monitor uses a simple process-alive check instead.
+//! The monitor runs these on every poll while a task is running and records
+//! the first reason that trips as the task's `attention_reason`.
 
-use crate::models::Task;
+use crate::models::{AttentionReason, Task};
+use anyhow::{Context, Result};
+use regex_automata::meta::Regex;
+use serde::Deserialize;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
 use std::process::Command;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
-pub enum AttentionReason {
-    WaitingForInput,
-    ProcessStalled,
-    #[allow(dead_code)]
-    Custom(String),
+pub struct TaskContext {
+    /// The processes the task was started with
+    pub pids: Vec<i32>,
+    /// `pids` plus all their live descendants, refreshed every poll
+    pub tree: Vec<i32>,
+    pub last_check: SystemTime,
+    pub last_cpu_time: Option<u64>,
+    /// How long the tree has made no CPU progress, once confirmed by enough
+    /// consecutive samples (zero until then)
+    pub idle_duration: Duration,
+    /// Consecutive samples without CPU progress, and the time they span
+    pub idle_samples: u32,
+    pub idle_streak: Duration,
+    /// When some process in the tree was first seen in uninterruptible
+    /// sleep, while it stays there
+    pub blocked_since: Option<SystemTime>,
+    /// Resident memory above which the task is flagged, if enabled
+    pub mem_threshold_kb: Option<u64>,
 }
 
-impl AttentionReason {
-    #[cfg_attr(not(test), allow(dead_code))]
-    pub fn as_str(&self) -> String {
-        match self {
-            AttentionReason::WaitingForInput => "Waiting for input".to_string(),
-            AttentionReason::ProcessStalled => "Process stalled (no activity)".to_string(),
-            AttentionReason::Custom(s) => s.clone(),
+impl TaskContext {
+    /// Fold one CPU-time sample taken at `now` into the idle clock
+    ///
+    /// A single unchanged sample is often just a short network or disk wait,
+    /// so idle time is only reported once `window` samples in a row saw no
+    /// progress; from then on it covers the whole streak. Any progress
+    /// resets it.
+    pub fn record_cpu_sample(&mut self, cpu: Option<u64>, now: SystemTime, window: u32) {
+        if cpu.is_some() && cpu == self.last_cpu_time {
+            self.idle_samples += 1;
+            self.idle_streak += now.duration_since(self.last_check).unwrap_or_default();
+        } else {
+            self.idle_samples = 0;
+            self.idle_streak = Duration::ZERO;
         }
+
+        self.idle_duration = if self.idle_samples >= window.max(1) {
+            self.idle_streak
+        } else {
+            Duration::ZERO
+        };
+        self.last_cpu_time = cpu;
+        self.last_check = now;
     }
-}
 
-pub struct TaskContext {
-    pub pid: i32,
-    pub last_check: SystemTime,
-    pub last_cpu_time: Option<u64>,
-    pub idle_duration: Duration,
+    /// Fold one process-state sample into the blocked clock: it starts the
+    /// first time the tree is seen in `D` state and stops as soon as it isn't
+    pub fn record_blocked(&mut self, blocked: bool, now: SystemTime) {
+        self.blocked_since = match self.blocked_since {
+            Some(since) if blocked => Some(since),
+            None if blocked => Some(now),
+            _ => None,
+        };
+    }
+
+    /// How long the tree has been stuck in uninterruptible sleep
+    pub fn blocked_duration(&self, now: SystemTime) -> Duration {
+        self.blocked_since
+            .and_then(|since| now.duration_since(since).ok())
+            .unwrap_or_default()
+    }
 }
 
 pub trait AttentionDetector: Send {
     fn check(&self, task: &Task, context: &TaskContext) -> Option<AttentionReason>;
+
+    /// What the detector watches, named like the config's `kind`; a
+    /// configured detector replaces a default one of the same kind
+    fn kind(&self) -> &'static str;
 }
 
 /// Detector that checks if process is waiting on stdin
+#[derive(Default)]
 pub struct ProcessStateDetector;
 
 impl ProcessStateDetector {
@@ -52,16 +99,10 @@ impl ProcessStateDetector {
         let stat_path = format!("/proc/{}/stat", pid);
         let stat_content = fs::read_to_string(&stat_path).ok()?;
 
-        // Parse the stat file (format: pid (comm) state ...)
-        let parts: Vec<&str> = stat_content.split_whitespace().collect();
-        if parts.len() < 3 {
-            return None;
-        }
-
-        let state = parts[2]; // Third field is the state
+        let state = parse_state_from_stat(&stat_content)?;
 
         // Check if in 'S' (sleeping/interruptible) state
-        if state == "S" {
+        if state == 'S' {
             // Check file descriptors to see if stdin is being read
             let fd_path = format!("/proc/{}/fd/0", pid);
             if let Ok(link) = fs::read_link(&fd_path) {
@@ -80,24 +121,34 @@ impl ProcessStateDetector {
 
 impl AttentionDetector for ProcessStateDetector {
     fn check(&self, task: &Task, context: &TaskContext) -> Option<AttentionReason> {
-        if let Some(state) = self.check_process_state(context.pid) {
-            if state == "waiting_input" {
-                // Additional checks to reduce false positives:
-                // Only flag if task has been running for at least 10 seconds
-                // AND idle for at least 5 seconds
-                let task_age = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as i64
-                    - task.created_at.timestamp();
-
-                if task_age > 10 && context.idle_duration.as_secs() > 5 {
-                    return Some(AttentionReason::WaitingForInput);
-                }
+        // Only the top-level processes own the terminal the user answers in
+        let waiting = context
+            .pids
+            .iter()
+            .any(|&pid| self.check_process_state(pid).as_deref() == Some("waiting_input"));
+
+        if waiting {
+            // Additional checks to reduce false positives:
+            // Only flag if task has been running for at least 10 seconds
+            // AND idle for at least 5 seconds
+            let task_age = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64
+                - task.created_at.timestamp();
+
+            if task_age > 10 && context.idle_duration.as_secs() > 5 {
+                return Some(AttentionReason::AwaitingInput {
+                    idle_secs: context.idle_duration.as_secs(),
+                });
             }
         }
         None
     }
+
+    fn kind(&self) -> &'static str {
+        "process_state"
+    }
 }
 
 /// Detector that checks if process has been inactive for too long
@@ -109,55 +160,217 @@ impl StallDetector {
     pub fn new(timeout: Duration) -> Self {
         Self { timeout }
     }
+}
 
-    fn get_process_cpu_time(&self, pid: i32) -> Option<u64> {
-        let stat_path = format!("/proc/{}/stat", pid);
-        let stat_content = fs::read_to_string(&stat_path).ok()?;
+/// Total CPU time (user + system, in clock ticks) consumed by a process
+pub fn process_cpu_time(pid: i32) -> Option<u64> {
+    let stat_path = format!("/proc/{}/stat", pid);
+    let stat_content = fs::read_to_string(&stat_path).ok()?;
 
-        let parts: Vec<&str> = stat_content.split_whitespace().collect();
-        if parts.len() < 15 {
-            return None;
-        }
+    let parts: Vec<&str> = stat_content.split_whitespace().collect();
+    if parts.len() < 15 {
+        return None;
+    }
+
+    // Fields 13 and 14 are utime and stime (user and system CPU time)
+    let utime: u64 = parts[13].parse().ok()?;
+    let stime: u64 = parts[14].parse().ok()?;
+
+    Some(utime + stime)
+}
+
+/// Combined CPU time of a set of processes; None if none could be read
+pub fn tree_cpu_time(pids: &[i32]) -> Option<u64> {
+    pids.iter()
+        .filter_map(|&pid| process_cpu_time(pid))
+        .fold(None, |total, cpu| Some(total.unwrap_or(0) + cpu))
+}
+
+/// Parent pid from the contents of `/proc/<pid>/stat`. The command name may
+/// itself contain spaces or parentheses, so fields are counted from the last `)`.
+pub fn parse_ppid_from_stat(stat: &str) -> Option<i32> {
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    // Fields after the name: state, ppid, ...
+    after_comm.split_whitespace().nth(1)?.parse().ok()
+}
 
-        // Fields 13 and 14 are utime and stime (user and system CPU time)
-        let utime: u64 = parts[13].parse().ok()?;
-        let stime: u64 = parts[14].parse().ok()?;
+/// State letter from the contents of `/proc/<pid>/stat` (`R`, `S`, `D`, ...),
+/// the first field after the command name
+pub fn parse_state_from_stat(stat: &str) -> Option<char> {
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    after_comm.split_whitespace().next()?.chars().next()
+}
+
+/// Current state letter of a process
+pub fn process_state(pid: i32) -> Option<char> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_state_from_stat(&stat)
+}
 
-        Some(utime + stime)
+/// `roots` and every live descendant, found by walking parent links in /proc
+pub fn process_tree(roots: &[i32]) -> Vec<i32> {
+    let mut children: std::collections::HashMap<i32, Vec<i32>> = Default::default();
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<i32>().ok())
+            else {
+                continue;
+            };
+            let ppid = fs::read_to_string(entry.path().join("stat"))
+                .ok()
+                .and_then(|stat| parse_ppid_from_stat(&stat));
+            if let Some(ppid) = ppid {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
     }
+
+    let mut tree = Vec::new();
+    let mut pending: Vec<i32> = roots.to_vec();
+    while let Some(pid) = pending.pop() {
+        if tree.contains(&pid) {
+            continue;
+        }
+        tree.push(pid);
+        if let Some(kids) = children.get(&pid) {
+            pending.extend(kids);
+        }
+    }
+    tree
 }
 
 impl AttentionDetector for StallDetector {
     fn check(&self, task: &Task, context: &TaskContext) -> Option<AttentionReason> {
-        // Check if process CPU usage has changed since last check
-        if let Some(current_cpu) = self.get_process_cpu_time(context.pid) {
-            if let Some(last_cpu) = context.last_cpu_time {
-                // If CPU time hasn't changed AND we've been idle past timeout
-                if current_cpu == last_cpu && context.idle_duration > self.timeout {
-                    // Additional check: ensure task has been running long enough
-                    let task_age = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() as i64
-                        - task.created_at.timestamp();
-
-                    if task_age > 30 {
-                        return Some(AttentionReason::ProcessStalled);
-                    }
-                }
+        // The monitor only counts idle time while the tree's CPU time stands
+        // still, so this is "no CPU progress for longer than the timeout"
+        if context.idle_duration > self.timeout {
+            // Additional check: ensure task has been running long enough
+            let task_age = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64
+                - task.created_at.timestamp();
+
+            if task_age > 30 {
+                return Some(AttentionReason::Idle {
+                    idle_secs: context.idle_duration.as_secs(),
+                });
             }
         }
 
         None
     }
+
+    fn kind(&self) -> &'static str {
+        "cpu_idle"
+    }
+}
+
+/// Detector that flags a tree stuck in uninterruptible sleep (`D` state)
+///
+/// A process blocked on a hung NFS mount or a dying disk burns no CPU, so it
+/// would otherwise read as idle and look like it is waiting for the user.
+pub struct BlockedIoDetector {
+    threshold: Duration,
+}
+
+impl BlockedIoDetector {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold }
+    }
+}
+
+impl AttentionDetector for BlockedIoDetector {
+    fn check(&self, _task: &Task, context: &TaskContext) -> Option<AttentionReason> {
+        let blocked = context.blocked_duration(SystemTime::now());
+        if context.blocked_since.is_some() && blocked > self.threshold {
+            Some(AttentionReason::BlockedIo {
+                blocked_secs: blocked.as_secs(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "blocked_io"
+    }
+}
+
+/// Detector that flags processes whose resident memory exceeds a threshold
+///
+/// The threshold is the detector's own when built with one, else
+/// `TaskContext::mem_threshold_kb`; without either it never trips.
+#[derive(Default)]
+pub struct MemoryDetector {
+    threshold_kb: Option<u64>,
+}
+
+impl MemoryDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trip above `kb` regardless of the monitor's `--mem-threshold`
+    pub fn with_threshold_kb(kb: u64) -> Self {
+        Self {
+            threshold_kb: Some(kb),
+        }
+    }
+}
+
+impl AttentionDetector for MemoryDetector {
+    fn check(&self, _task: &Task, context: &TaskContext) -> Option<AttentionReason> {
+        let threshold = self.threshold_kb.or(context.mem_threshold_kb)?;
+        let rss_kb = context
+            .tree
+            .iter()
+            .filter_map(|&pid| process_rss_kb(pid))
+            .reduce(|total, kb| total + kb)?;
+
+        if rss_kb > threshold {
+            Some(AttentionReason::HighMemory { rss_kb })
+        } else {
+            None
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "rss"
+    }
+}
+
+/// Resident set size of a process in kB
+#[cfg(target_os = "linux")]
+fn process_rss_kb(pid: i32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    parse_vm_rss(&status)
+}
+
+/// Resident set size of a process in kB (no /proc on this platform)
+#[cfg(not(target_os = "linux"))]
+fn process_rss_kb(_pid: i32) -> Option<u64> {
+    None
+}
+
+/// Extract the `VmRSS` value (in kB) from the contents of `/proc/<pid>/status`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|kb| kb.parse().ok())
 }
 
 /// Detector that uses lsof to check if process is reading from stdin
-#[allow(dead_code)]
+#[derive(Default)]
 pub struct StdinDetector;
 
 impl StdinDetector {
-    #[allow(dead_code)]
     pub fn new() -> Self {
         Self
     }
@@ -183,7 +396,7 @@ impl AttentionDetector for StdinDetector {
     fn check(&self, task: &Task, context: &TaskContext) -> Option<AttentionReason> {
         // This is a more aggressive check than ProcessStateDetector
         // Only enable if lsof is available and we want detailed stdin tracking
-        if self.is_reading_stdin(context.pid) {
+        if context.pids.iter().any(|&pid| self.is_reading_stdin(pid)) {
             // Additional heuristic: check if process has been running for more than a few seconds
             let now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -193,19 +406,155 @@ impl AttentionDetector for StdinDetector {
 
             // If task is older than 30 seconds and still reading stdin, likely waiting
             if task_age > 30 {
-                return Some(AttentionReason::WaitingForInput);
+                return Some(AttentionReason::AwaitingInput {
+                    idle_secs: context.idle_duration.as_secs(),
+                });
             }
         }
         None
     }
+
+    fn kind(&self) -> &'static str {
+        "stdin"
+    }
+}
+
+/// Bytes at the end of a task's log that `LogMatchDetector` searches
+const LOG_TAIL_BYTES: u64 = 64 * 1024;
+
+/// Detector that flags a task whose log (context `log_path`) has a recent
+/// line matching a regular expression, e.g. a rate-limit or auth error
+pub struct LogMatchDetector {
+    regex: Regex,
+    pattern: String,
+    message: Option<String>,
+}
+
+impl LogMatchDetector {
+    /// Fails if `pattern` isn't a valid regular expression. The reason is
+    /// `message` when given, else the pattern and the matching line.
+    pub fn new(pattern: &str, message: Option<String>) -> Result<Self> {
+        let regex = Regex::new(pattern)
+            .with_context(|| format!("Invalid log_matches pattern '{}'", pattern))?;
+        Ok(Self {
+            regex,
+            pattern: pattern.to_string(),
+            message,
+        })
+    }
+
+    /// Last line of `text` that matches, trimmed
+    fn last_match<'a>(&self, text: &'a str) -> Option<&'a str> {
+        text.lines()
+            .rev()
+            .find(|line| self.regex.is_match(line))
+            .map(str::trim)
+    }
+}
+
+impl AttentionDetector for LogMatchDetector {
+    fn check(&self, task: &Task, _context: &TaskContext) -> Option<AttentionReason> {
+        let path = task.context.as_ref()?.log_path()?;
+        let tail = read_tail(Path::new(path), LOG_TAIL_BYTES)?;
+        let line = self.last_match(&tail)?;
+
+        let message = match &self.message {
+            Some(message) => message.clone(),
+            None => {
+                let line: String = line.chars().take(120).collect();
+                format!("Log matched /{}/: {}", self.pattern, line)
+            }
+        };
+        Some(AttentionReason::Custom(message))
+    }
+
+    fn kind(&self) -> &'static str {
+        "log_matches"
+    }
+}
+
+/// The last `max` bytes of the file at `path`, starting at a whole line
+fn read_tail(path: &Path, max: u64) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let start = len.saturating_sub(max);
+    file.seek(SeekFrom::Start(start)).ok()?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    let text = String::from_utf8_lossy(&bytes);
+    Some(match text.find('\n') {
+        // The first line was cut by the seek
+        Some(newline) if start > 0 => text[newline + 1..].to_string(),
+        _ => text.into_owned(),
+    })
+}
+
+/// A detector declared in the config file, in addition to the defaults or
+/// in place of the default of the same kind
+///
+/// ```toml
+/// [[detectors]]
+/// kind = "cpu_idle"
+/// secs = 300
+///
+/// [[detectors]]
+/// kind = "log_matches"
+/// pattern = "(?i)rate limit"
+/// message = "Rate limited"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DetectorSpec {
+    /// No CPU progress across the process tree for more than `secs`
+    CpuIdle { secs: u64 },
+    /// Resident memory of the tree above `mb`
+    Rss { mb: u64 },
+    /// In uninterruptible sleep for more than `secs`
+    BlockedIo { secs: u64 },
+    /// A recent line of the task's log matches the regex `pattern`
+    LogMatches {
+        pattern: String,
+        message: Option<String>,
+    },
+    /// Reading stdin according to `lsof` (slower, off by default)
+    Stdin,
+}
+
+impl DetectorSpec {
+    /// The detector this spec describes
+    pub fn build(&self) -> Result<Box<dyn AttentionDetector>> {
+        Ok(match self {
+            DetectorSpec::CpuIdle { secs } => {
+                Box::new(StallDetector::new(Duration::from_secs(*secs)))
+            }
+            DetectorSpec::Rss { mb } => Box::new(MemoryDetector::with_threshold_kb(mb * 1024)),
+            DetectorSpec::BlockedIo { secs } => {
+                Box::new(BlockedIoDetector::new(Duration::from_secs(*secs)))
+            }
+            DetectorSpec::LogMatches { pattern, message } => {
+                Box::new(LogMatchDetector::new(pattern, message.clone())?)
+            }
+            DetectorSpec::Stdin => Box::new(StdinDetector::new()),
+        })
+    }
+}
+
+/// Detectors for `specs`, in order; fails on the first invalid one
+pub fn build_detectors(specs: &[DetectorSpec]) -> Result<Vec<Box<dyn AttentionDetector>>> {
+    specs.iter().map(DetectorSpec::build).collect()
 }
 
 pub fn create_default_detectors() -> Vec<Box<dyn AttentionDetector>> {
     vec![
         Box::new(ProcessStateDetector::new()),
-        Box::new(StallDetector::new(Duration::from_secs(600))), // 10 minutes
-                                                                // StdinDetector is more invasive (requires lsof), so we exclude it by default
-                                                                // Box::new(StdinDetector::new()),
+        // Ahead of the stall detector: a blocked tree is idle too, but for
+        // a different reason
+        Box::new(BlockedIoDetector::new(Duration::from_secs(120))), // 2 minutes
+        Box::new(StallDetector::new(Duration::from_secs(600))),     // 10 minutes
+        Box::new(MemoryDetector::new()),
+        // StdinDetector is more invasive (requires lsof), so we exclude it by default
+        // Box::new(StdinDetector::new()),
     ]
 }
 
@@ -214,21 +563,264 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_attention_reason_display() {
+    fn test_detector_creation() {
+        let detectors = create_default_detectors();
+        assert_eq!(detectors.len(), 4); // ProcessState + BlockedIo + Stall + Memory
+    }
+
+    fn task_logging_to(path: &Path) -> Task {
+        let mut task = Task::new("t1".into(), "claude_code".into(), "Test".into(), None, None);
+        task.context = Some(crate::models::TaskContext {
+            url: None,
+            project_path: None,
+            session_id: None,
+            extra: std::collections::HashMap::from([(
+                "log_path".to_string(),
+                serde_json::json!(path.to_string_lossy()),
+            )]),
+        });
+        task
+    }
+
+    fn idle_context(idle_secs: u64) -> TaskContext {
+        TaskContext {
+            pids: vec![],
+            tree: vec![],
+            last_check: SystemTime::now(),
+            last_cpu_time: None,
+            idle_duration: Duration::from_secs(idle_secs),
+            idle_samples: 0,
+            idle_streak: Duration::ZERO,
+            blocked_since: None,
+            mem_threshold_kb: None,
+        }
+    }
+
+    #[test]
+    fn test_build_detectors_from_specs() {
+        let config = r#"
+            [[detectors]]
+            kind = "cpu_idle"
+            secs = 60
+
+            [[detectors]]
+            kind = "rss"
+            mb = 2048
+
+            [[detectors]]
+            kind = "log_matches"
+            pattern = "(?i)rate limit"
+
+            [[detectors]]
+            kind = "stdin"
+        "#;
+        #[derive(Deserialize)]
+        struct Specs {
+            detectors: Vec<DetectorSpec>,
+        }
+        let specs: Specs = toml::from_str(config).unwrap();
+        assert_eq!(specs.detectors[0], DetectorSpec::CpuIdle { secs: 60 });
         assert_eq!(
-            AttentionReason::WaitingForInput.as_str(),
-            "Waiting for input"
+            specs.detectors[2],
+            DetectorSpec::LogMatches {
+                pattern: "(?i)rate limit".to_string(),
+                message: None
+            }
         );
+        assert_eq!(build_detectors(&specs.detectors).unwrap().len(), 4);
+
+        // A custom idle threshold trips well before the default 10 minutes
+        let mut task = Task::new("t1".into(), "claude_code".into(), "Test".into(), None, None);
+        task.created_at = chrono::Utc::now() - chrono::Duration::minutes(5);
+        let idle = DetectorSpec::CpuIdle { secs: 60 }.build().unwrap();
+        assert!(idle.check(&task, &idle_context(30)).is_none());
         assert_eq!(
-            AttentionReason::ProcessStalled.as_str(),
-            "Process stalled (no activity)"
+            idle.check(&task, &idle_context(90)),
+            Some(AttentionReason::Idle { idle_secs: 90 })
         );
-        assert_eq!(AttentionReason::Custom("Test".to_string()).as_str(), "Test");
+
+        let bad = DetectorSpec::LogMatches {
+            pattern: "(unclosed".to_string(),
+            message: None,
+        };
+        assert!(build_detectors(&[bad]).is_err());
+        assert!(toml::from_str::<Specs>("[[detectors]]\nkind = \"gpu\"").is_err());
     }
 
     #[test]
-    fn test_detector_creation() {
-        let detectors = create_default_detectors();
-        assert_eq!(detectors.len(), 2); // ProcessState + Stall
+    fn test_log_match_detector() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("agent.log");
+        let task = task_logging_to(&log);
+        let context = idle_context(0);
+
+        let detector = LogMatchDetector::new("(?i)rate limit", None).unwrap();
+        // No log yet, then a log without a match
+        assert!(detector.check(&task, &context).is_none());
+        fs::write(&log, "starting\nworking\n").unwrap();
+        assert!(detector.check(&task, &context).is_none());
+
+        fs::write(&log, "starting\n  Error: Rate limit exceeded  \nretrying\n").unwrap();
+        assert_eq!(
+            detector.check(&task, &context),
+            Some(AttentionReason::Custom(
+                "Log matched /(?i)rate limit/: Error: Rate limit exceeded".to_string()
+            ))
+        );
+
+        let named = LogMatchDetector::new("limit", Some("Rate limited".to_string())).unwrap();
+        assert_eq!(
+            named.check(&task, &context),
+            Some(AttentionReason::Custom("Rate limited".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_read_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        fs::write(&path, "first line\nsecond\nthird\n").unwrap();
+        assert_eq!(
+            read_tail(&path, 1024).unwrap(),
+            "first line\nsecond\nthird\n"
+        );
+        // The partial line at the cut is dropped
+        assert_eq!(read_tail(&path, 10).unwrap(), "third\n");
+        assert!(read_tail(&dir.path().join("missing"), 10).is_none());
+    }
+
+    #[test]
+    fn test_parse_ppid_from_stat() {
+        assert_eq!(
+            parse_ppid_from_stat("1234 (claude) S 1200 1234 1200 0"),
+            Some(1200)
+        );
+        // Command names can contain spaces and parentheses
+        assert_eq!(
+            parse_ppid_from_stat("77 (node (worker) 2) R 42 77 42 0"),
+            Some(42)
+        );
+        assert_eq!(parse_ppid_from_stat("garbage"), None);
+    }
+
+    #[test]
+    fn test_parse_state_from_stat() {
+        assert_eq!(
+            parse_state_from_stat("1234 (claude) D 1200 1234 1200 0"),
+            Some('D')
+        );
+        // A name with spaces and parentheses must not shift the fields
+        assert_eq!(
+            parse_state_from_stat("77 (node (D) x) S 42 77 42 0"),
+            Some('S')
+        );
+        assert_eq!(parse_state_from_stat("78 (a) b) R 42"), Some('R'));
+        assert_eq!(parse_state_from_stat("garbage"), None);
+        assert_eq!(parse_state_from_stat("79 (truncated)"), None);
+
+        let own_pid = std::process::id() as i32;
+        assert!(process_state(own_pid).is_some());
+    }
+
+    #[test]
+    fn test_blocked_clock() {
+        let start = SystemTime::now();
+        let mut context = TaskContext {
+            pids: vec![1],
+            tree: vec![1],
+            last_check: start,
+            last_cpu_time: None,
+            idle_duration: Duration::ZERO,
+            idle_samples: 0,
+            idle_streak: Duration::ZERO,
+            blocked_since: None,
+            mem_threshold_kb: None,
+        };
+
+        context.record_blocked(true, start);
+        context.record_blocked(true, start + Duration::from_secs(5));
+        assert_eq!(context.blocked_since, Some(start));
+        assert_eq!(
+            context.blocked_duration(start + Duration::from_secs(90)),
+            Duration::from_secs(90)
+        );
+
+        // Leaving D state stops the clock; re-entering starts it over
+        context.record_blocked(false, start + Duration::from_secs(10));
+        assert_eq!(
+            context.blocked_duration(start + Duration::from_secs(90)),
+            Duration::ZERO
+        );
+        context.record_blocked(true, start + Duration::from_secs(15));
+        assert_eq!(context.blocked_since, Some(start + Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn test_process_tree_includes_children() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let own_pid = std::process::id() as i32;
+
+        let tree = process_tree(&[own_pid]);
+        assert_eq!(tree[0], own_pid);
+        assert!(tree.contains(&(child.id() as i32)));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_idle_needs_consecutive_samples() {
+        let start = SystemTime::now();
+        let mut context = TaskContext {
+            pids: vec![1],
+            tree: vec![1],
+            last_check: start,
+            last_cpu_time: Some(100),
+            idle_duration: Duration::ZERO,
+            idle_samples: 0,
+            idle_streak: Duration::ZERO,
+            blocked_since: None,
+            mem_threshold_kb: None,
+        };
+
+        // One sample every 5s; a single stalled sample (a network wait) is
+        // absorbed, three in a row start the clock with the whole streak
+        let samples = [100, 120, 120, 130, 130, 130, 130, 131];
+        let idle: Vec<u64> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &cpu)| {
+                let now = start + Duration::from_secs(5 * (i as u64 + 1));
+                context.record_cpu_sample(Some(cpu), now, 3);
+                context.idle_duration.as_secs()
+            })
+            .collect();
+        assert_eq!(idle, [0, 0, 0, 0, 0, 0, 15, 0]);
+
+        // A window of 1 counts every unchanged sample, as before smoothing
+        context.record_cpu_sample(Some(131), start + Duration::from_secs(45), 1);
+        assert_eq!(context.idle_duration, Duration::from_secs(5));
+
+        // Unreadable CPU time never counts as idle
+        context.record_cpu_sample(None, start + Duration::from_secs(50), 1);
+        assert_eq!(context.idle_duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tclaude\n\
+                      State:\tS (sleeping)\n\
+                      VmPeak:\t 1234568 kB\n\
+                      VmSize:\t 1234564 kB\n\
+                      VmHWM:\t  456792 kB\n\
+                      VmRSS:\t  456788 kB\n\
+                      Threads:\t12\n";
+        assert_eq!(parse_vm_rss(status), Some(456788));
+
+        // Kernel threads have no VmRSS line
+        assert_eq!(
+            parse_vm_rss("Name:\tkthreadd\nState:\tS (sleeping)\n"),
+            None
+        );
     }
 }
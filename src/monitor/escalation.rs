@@ -0,0 +1,192 @@
+//! Escalation of attention flags nobody acts on
+//!
+//! Each `[[escalation]]` step in the config names how long a task has been
+//! flagged before it fires. The daemon checks the flagged tasks on every
+//! pass and takes each step at most once per flag: it sends the
+//! notification again, marked as an escalation so chat targets mention the
+//! channel, and with `bump` moves the task to the top of the list. A flag
+//! that is withdrawn and raised again starts over.
+//!
+//! ```toml
+//! [[escalation]]
+//! after_secs = 900
+//!
+//! [[escalation]]
+//! after_secs = 3600
+//! bump = true
+//! ```
+
+use crate::db::{retry_busy, Database};
+use crate::models::{Task, TaskStatus};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// One `[[escalation]]` table
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationStep {
+    /// Seconds the task has been flagged when the step fires
+    pub after_secs: u64,
+    /// Send the notification again (default true)
+    #[serde(default = "default_notify")]
+    pub notify: bool,
+    /// Move the task to the top of the list by bumping `updated_at`
+    #[serde(default)]
+    pub bump: bool,
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+/// The step due for `task` at `now`, with the number of steps taken once it
+/// is: the latest step whose time has come, if the task hasn't had it yet.
+/// Steps skipped over (the daemon wasn't running) aren't taken on their own.
+pub fn due_step<'a>(
+    task: &Task,
+    steps: &'a [EscalationStep],
+    now: DateTime<Utc>,
+) -> Option<(usize, &'a EscalationStep)> {
+    // Only blocking reasons: a chat that is just ready for a reply isn't
+    // worth paging anyone for
+    if !task.needs_attention() {
+        return None;
+    }
+    let flagged_for = (now - task.attention_since?).num_seconds().max(0) as u64;
+    let passed: Vec<&EscalationStep> = steps
+        .iter()
+        .filter(|step| step.after_secs <= flagged_for)
+        .collect();
+    if passed.len() <= task.escalations() {
+        return None;
+    }
+    let step = passed.into_iter().max_by_key(|step| step.after_secs)?;
+    Some((
+        steps
+            .iter()
+            .filter(|s| s.after_secs <= step.after_secs)
+            .count(),
+        step,
+    ))
+}
+
+/// Take the due step of every flagged task. Returns the tasks escalated,
+/// as saved.
+pub fn escalate(db: &Database, steps: &[EscalationStep], now: DateTime<Utc>) -> Result<Vec<Task>> {
+    let mut escalated = Vec::new();
+    if steps.is_empty() {
+        return Ok(escalated);
+    }
+
+    for mut task in db.list_tasks(Some(TaskStatus::Running))? {
+        let Some((taken, step)) = due_step(&task, steps, now) else {
+            continue;
+        };
+        task.set_escalations(taken);
+        if step.bump {
+            task.updated_at = now;
+        }
+        retry_busy(|| db.update_task(&task))?;
+        if step.notify {
+            let since = task.attention_since.unwrap_or(now);
+            let flagged_for = (now - since).to_std().unwrap_or(Duration::ZERO);
+            db.notify_escalation(&task, flagged_for);
+        }
+        escalated.push(task);
+    }
+
+    Ok(escalated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AttentionReason;
+
+    fn step(after_secs: u64, bump: bool) -> EscalationStep {
+        EscalationStep {
+            after_secs,
+            notify: true,
+            bump,
+        }
+    }
+
+    fn flagged(id: &str, now: DateTime<Utc>, minutes_ago: i64) -> Task {
+        let mut task = Task::new(
+            id.to_string(),
+            "claude_code".to_string(),
+            id.to_string(),
+            None,
+            None,
+        );
+        task.flag_attention(AttentionReason::Custom("Approve?".to_string()));
+        task.attention_since = Some(now - chrono::Duration::minutes(minutes_ago));
+        task.updated_at = now - chrono::Duration::minutes(minutes_ago);
+        task
+    }
+
+    #[test]
+    fn test_due_step_timing() {
+        let now = Utc::now();
+        let steps = [step(3600, true), step(900, false)];
+
+        assert!(due_step(&flagged("t", now, 10), &steps, now).is_none());
+        let (taken, due) = due_step(&flagged("t", now, 15), &steps, now).unwrap();
+        assert_eq!((taken, due.after_secs), (1, 900));
+
+        // Once taken, the step doesn't come back until the next one is due
+        let mut task = flagged("t", now, 30);
+        task.set_escalations(1);
+        assert!(due_step(&task, &steps, now).is_none());
+        let later = now + chrono::Duration::minutes(30);
+        assert_eq!(due_step(&task, &steps, later).unwrap().1.after_secs, 3600);
+
+        // Both past at once: only the later one is taken, and counts for both
+        let (taken, due) = due_step(&flagged("t", now, 90), &steps, now).unwrap();
+        assert_eq!((taken, due.after_secs), (2, 3600));
+
+        // Withdrawing the flag starts over
+        task.clear_attention();
+        assert_eq!(task.escalations(), 0);
+        assert!(due_step(&task, &steps, later).is_none());
+    }
+
+    #[test]
+    fn test_ready_for_reply_never_escalates() {
+        let temp = tempfile::NamedTempFile::new().unwrap();
+        let db = Database::open(temp.path()).unwrap();
+        let now = Utc::now();
+        let mut chat = flagged("chat", now, 120);
+        chat.attention_reason = Some(AttentionReason::ReadyForReply);
+        db.insert_task(&chat).unwrap();
+        let steps = [step(60, true), step(3600, true)];
+
+        assert!(due_step(&chat, &steps, now).is_none());
+        assert!(escalate(&db, &steps, now).unwrap().is_empty());
+        assert_eq!(db.get_task_by_id("chat").unwrap().unwrap().escalations(), 0);
+    }
+
+    #[test]
+    fn test_escalate_once_per_step() {
+        let temp = tempfile::NamedTempFile::new().unwrap();
+        let db = Database::open(temp.path()).unwrap();
+        let now = Utc::now();
+        db.insert_task(&flagged("old", now, 20)).unwrap();
+        db.insert_task(&flagged("new", now, 1)).unwrap();
+        let steps = [step(600, true)];
+
+        let escalated = escalate(&db, &steps, now).unwrap();
+        assert_eq!(escalated.len(), 1);
+        let old = db.get_task_by_id("old").unwrap().unwrap();
+        assert_eq!(old.escalations(), 1);
+        assert_eq!(old.updated_at.timestamp_millis(), now.timestamp_millis());
+        assert_eq!(db.list_tasks(None).unwrap()[0].task_id, "old");
+
+        assert!(escalate(&db, &steps, now + chrono::Duration::minutes(5))
+            .unwrap()
+            .is_empty());
+        assert!(escalate(&db, &[], now).unwrap().is_empty());
+    }
+}
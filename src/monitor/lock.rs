@@ -0,0 +1,177 @@
+//! PID lockfile that keeps a second daemon from watching the same database
+//!
+//! Two daemons would both flag tasks and both send the notifications. The
+//! daemon that owns the lock file holds an advisory lock (`flock`) on it for
+//! as long as it runs, and writes its pid there for the error message. The
+//! kernel releases the lock when the process dies, so a file left behind by
+//! a crash is simply taken over, and two starters can't both win the race
+//! for it.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// `~/.agent-tasks/daemon.lock`
+pub fn default_lock_path() -> PathBuf {
+    crate::db::data_dir().join("daemon.lock")
+}
+
+/// A held lockfile, removed again when dropped
+#[derive(Debug)]
+pub struct PidLock {
+    path: PathBuf,
+    pid: u32,
+    /// Holds the advisory lock until the PidLock is dropped
+    file: File,
+}
+
+impl PidLock {
+    /// Take the lock at `path` for this process. Fails while another process
+    /// holds it; a file nobody holds (left by a crash) is taken over.
+    pub fn acquire(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let pid = std::process::id();
+
+        // A lock taken on a file that its owner removed on exit, after we
+        // opened it, guards nothing; open the new one and try again
+        for _ in 0..10 {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => match read_pid(&path)? {
+                    Some(holder) => bail!("Another daemon is already running (pid {})", holder),
+                    None => bail!("Another daemon is already running"),
+                },
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+                }
+            }
+            if !is_same_file(&file, &path)? {
+                continue;
+            }
+
+            if let Some(holder) = read_pid(&path)? {
+                tracing::warn!(holder, path = %path.display(), "Taking over stale lock");
+            }
+            file.set_len(0)
+                .and_then(|()| file.write_all(format!("{}\n", pid).as_bytes()))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            return Ok(Self { path, pid, file });
+        }
+
+        bail!("Could not take the lock {}", path.display())
+    }
+}
+
+/// Whether `path` still names the file `file` was opened from
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let opened = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) => Ok(current.dev() == opened.dev() && current.ino() == opened.ino()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to stat {}", path.display())),
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> Result<bool> {
+    Ok(path.exists())
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        // Only remove the file if it is still ours. It goes while the lock is
+        // held, so no one can lock it in between and then lose it.
+        if matches!(read_pid(&self.path), Ok(Some(pid)) if pid == self.pid as i32)
+            && is_same_file(&self.file, &self.path).unwrap_or(false)
+        {
+            if let Err(e) = fs::remove_file(&self.path) {
+                tracing::warn!(error = %e, path = %self.path.display(), "Failed to release lock");
+            }
+        }
+    }
+}
+
+/// The pid in the lock at `path`; `None` if it's missing or unreadable
+/// (e.g. a crash between creating and writing it)
+fn read_pid(path: &Path) -> Result<Option<i32>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.trim().parse().ok()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_held_and_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.lock");
+
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(read_pid(&path).unwrap(), Some(std::process::id() as i32));
+        let err = PidLock::acquire(&path).unwrap_err();
+        assert!(err.to_string().contains("already running"), "{}", err);
+
+        drop(lock);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_stale_lock_takeover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.lock");
+
+        // Left behind by a daemon that is gone
+        fs::write(&path, "999999\n").unwrap();
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(read_pid(&path).unwrap(), Some(std::process::id() as i32));
+
+        // Garbage counts as stale too
+        drop(lock);
+        fs::write(&path, "").unwrap();
+        let lock = PidLock::acquire(&path).unwrap();
+
+        // What counts is the lock, not whether the recorded pid is reused
+        drop(lock);
+        fs::write(&path, "1\n").unwrap();
+        assert!(PidLock::acquire(&path).is_ok());
+    }
+
+    #[test]
+    fn test_one_winner_among_concurrent_starters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.lock");
+        fs::write(&path, "999999\n").unwrap();
+
+        let barrier = std::sync::Barrier::new(8);
+        let locks: Vec<Option<PidLock>> = std::thread::scope(|scope| {
+            let starters: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        PidLock::acquire(&path).ok()
+                    })
+                })
+                .collect();
+            starters
+                .into_iter()
+                .map(|starter| starter.join().unwrap())
+                .collect()
+        });
+        assert_eq!(locks.iter().filter(|lock| lock.is_some()).count(), 1);
+    }
+}
@@ -1,12 +1,18 @@
-// Detectors module kept for potential future use
-#[allow(dead_code)]
 pub mod detectors;
+pub mod escalation;
+pub mod lock;
 
-use crate::db::Database;
-use crate::models::TaskStatus;
-use anyhow::Result;
+use crate::db::{retry_busy, Database};
+use crate::models::{AttentionReason, Task, TaskStatus};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+pub use detectors::{build_detectors, DetectorSpec};
+use detectors::{AttentionDetector, TaskContext as DetectorContext};
+pub use escalation::EscalationStep;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 /// Simple process monitor for CLI tools
 ///
@@ -14,58 +20,528 @@ use std::time::Duration;
 /// - CLI tools start as "Running"
 /// - When process exits → "Exited"
 ///
+/// While the process is alive, the attention detectors run on every poll and
+/// flag the task through `attention_reason` (e.g. stalled, memory blowup).
+///
 /// Note: We don't try to detect "Completed" (waiting for input) for CLI tools
 /// because it's unreliable. The wrapper script handles reporting completion
 /// with exit codes.
 pub struct TaskMonitor {
     db: Database,
     poll_interval: Duration,
+    detectors: Vec<Box<dyn AttentionDetector>>,
+    mem_threshold_kb: Option<u64>,
+    exit_on_idle: bool,
+    idle_window: u32,
+}
+
+/// Consecutive idle polls needed before idle time counts (15s at 5s polls)
+pub const DEFAULT_IDLE_WINDOW: u32 = 3;
+
+/// Time between polls; the monitor records a heartbeat on every one
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Poll intervals without a heartbeat after which a monitor is presumed dead
+pub const HEARTBEAT_MISSES: u32 = 3;
+
+/// Whether the monitor named by `task` has stopped watching it: no heartbeat
+/// within `HEARTBEAT_MISSES` poll intervals of `now`. A monitor that never
+/// beat (started by an older version) is judged by whether its process lives.
+pub fn monitor_is_stale(
+    task: &Task,
+    now: DateTime<Utc>,
+    poll_interval: Duration,
+    monitor_alive: bool,
+) -> bool {
+    if task.monitor_pid.is_none() {
+        return false;
+    }
+    match task.last_heartbeat {
+        Some(beat) => {
+            let window = poll_interval * HEARTBEAT_MISSES;
+            (now - beat).to_std().is_ok_and(|since| since > window)
+        }
+        None => !monitor_alive,
+    }
+}
+
+/// What `reap` did about a task whose monitor went away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reaped {
+    /// The agent is still alive; a new monitor was started (`None` on a dry run)
+    Restarted { monitor_pid: Option<u32> },
+    /// The agent is gone too, so the task was marked exited
+    Exited,
+}
+
+/// Find running and paused tasks whose monitor stopped beating and deal with
+/// each: watch the agent again if it still runs, otherwise mark the task
+/// exited like its monitor would have. With `dry_run` nothing is changed.
+pub fn reap(db: &Database, dry_run: bool) -> Result<Vec<(Task, Reaped)>> {
+    let now = Utc::now();
+    let mut reaped = Vec::new();
+
+    for mut task in db.monitored_tasks()? {
+        let monitor_alive = task.monitor_pid.is_some_and(is_process_alive);
+        if !monitor_is_stale(&task, now, POLL_INTERVAL, monitor_alive) {
+            continue;
+        }
+        tracing::info!(task_id = %task.task_id, monitor_pid = ?task.monitor_pid, "Stale monitor");
+
+        let agent = task.pid.filter(|&pid| is_process_alive(pid));
+        let outcome = match agent {
+            Some(_) if dry_run => Reaped::Restarted { monitor_pid: None },
+            Some(pid) => {
+                // Release the task so the new monitor can claim it
+                task.monitor_pid = None;
+                retry_busy(|| db.update_task(&task))?;
+                let monitor_pid = spawn_monitor(&task.task_id, pid)?;
+                Reaped::Restarted {
+                    monitor_pid: Some(monitor_pid),
+                }
+            }
+            None => {
+                if !dry_run {
+                    task.monitor_pid = None;
+                    task.set_exited(None);
+                    retry_busy(|| db.update_task(&task))?;
+                }
+                Reaped::Exited
+            }
+        };
+        reaped.push((task, outcome));
+    }
+
+    Ok(reaped)
+}
+
+/// Start `agent-inbox monitor <task_id> <pid>` in the background, detached
+/// from this terminal. Returns the new monitor's pid.
+pub fn spawn_monitor(task_id: &str, pid: i32) -> Result<u32> {
+    let exe = std::env::current_exe().context("Failed to locate the agent-inbox binary")?;
+    let mut cmd = Command::new(exe);
+    cmd.args(["monitor", task_id, &pid.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Its own process group, so Ctrl-C here doesn't reach it
+        cmd.process_group(0);
+    }
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to start a monitor for task {}", task_id))?;
+    Ok(child.id())
 }
 
 impl TaskMonitor {
     pub fn new(db: Database) -> Self {
         Self {
             db,
-            poll_interval: Duration::from_secs(5),
+            poll_interval: POLL_INTERVAL,
+            detectors: detectors::create_default_detectors(),
+            mem_threshold_kb: None,
+            exit_on_idle: false,
+            idle_window: DEFAULT_IDLE_WINDOW,
         }
     }
 
-    /// Monitor a process and update task status when it exits
-    pub fn monitor_task(&self, task_id: String, pid: i32) -> Result<()> {
-        loop {
-            // Check if process is still alive
-            if !is_process_alive(pid) {
-                // Process died, mark as exited
-                if let Some(mut task) = self.db.get_task_by_id(&task_id)? {
-                    // Monitor doesn't know exit code, wrapper will update with correct code
-                    task.set_exited(None);
-                    self.db.update_task(&task)?;
+    /// Run `extra` after the default detectors, e.g. ones built from the
+    /// config's `[[detectors]]`
+    ///
+    /// A default detector of the same kind as one in `extra` is dropped, so
+    /// the configured threshold applies both ways instead of only when it's
+    /// stricter than the default.
+    pub fn with_detectors(mut self, extra: Vec<Box<dyn AttentionDetector>>) -> Self {
+        self.detectors
+            .retain(|default| !extra.iter().any(|d| d.kind() == default.kind()));
+        self.detectors.extend(extra);
+        self
+    }
+
+    /// Flag the task when the process's resident memory exceeds `kb`
+    pub fn with_mem_threshold_kb(mut self, kb: Option<u64>) -> Self {
+        self.mem_threshold_kb = kb;
+        self
+    }
+
+    /// Require `samples` polls in a row without CPU progress before the
+    /// process counts as idle (at least 1)
+    pub fn with_idle_window(mut self, samples: u32) -> Self {
+        self.idle_window = samples.max(1);
+        self
+    }
+
+    /// Stop monitoring as soon as the task needs attention, not only when
+    /// it finishes
+    pub fn with_exit_on_idle(mut self, exit_on_idle: bool) -> Self {
+        self.exit_on_idle = exit_on_idle;
+        self
+    }
+
+    /// Current state of a task, retrying through transient locks
+    pub fn task(&self, task_id: &str) -> Result<Option<Task>> {
+        self.load(task_id)
+    }
+
+    /// Monitor one or more processes and update task status once all exit
+    ///
+    /// The task stays alive while any of `pids` lives, and the detectors see
+    /// the union of their process trees. The monitor records its own PID in
+    /// the task's `monitor_pid` for the duration, and refuses to start if
+    /// another live monitor owns the task.
+    pub fn monitor_task(&self, task_id: String, pids: Vec<i32>) -> Result<()> {
+        if !self.claim(&task_id)? {
+            tracing::warn!("Task {} is missing or already monitored", task_id);
+            return Ok(());
+        }
+        tracing::info!(task_id, ?pids, "Monitoring");
+
+        let result = self.watch(&task_id, pids, |interval| {
+            thread::sleep(interval);
+            true
+        });
+        self.release(&task_id)?;
+        result
+    }
+
+    /// Run `command` as a child of this process, monitor it like
+    /// `monitor_task` does, and record its exact exit status on the task.
+    /// Returns the exit code (128 + signal number if it was killed).
+    ///
+    /// The child is reaped on a separate thread as soon as it exits, which
+    /// wakes the monitor loop at once instead of on the next poll. On Linux
+    /// the child also receives SIGTERM if the monitor dies, so it can't
+    /// outlive its tracking. The command runs even if the task is missing
+    /// or already monitored; it just isn't tracked then.
+    pub fn run_child(&self, task_id: String, command: &[String]) -> Result<i32> {
+        let (program, args) = command.split_first().context("No command given")?;
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        prepare_child(&mut cmd);
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to start {}", program))?;
+        let pid = child.id() as i32;
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(child.wait());
+        });
+
+        let mut status = None;
+        if self.claim(&task_id)? {
+            if let Some(mut task) = self.load(&task_id)? {
+                task.pid = Some(pid);
+                self.save(&task)?;
+            }
+
+            let result = self.watch(&task_id, vec![pid], |interval| {
+                match rx.recv_timeout(interval) {
+                    Ok(exit) => {
+                        status = Some(exit);
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => false,
                 }
-                break;
+            });
+            self.release(&task_id)?;
+            result?;
+        } else {
+            tracing::warn!(
+                "Task {} is missing or already monitored; not tracking it",
+                task_id
+            );
+        }
+
+        // Still needed when monitoring stopped first (e.g. the task was
+        // marked completed while the agent keeps running)
+        let status = match status {
+            Some(status) => status,
+            None => rx.recv().context("Lost track of the child process")?,
+        };
+        let code = exit_code(status.context("Failed to wait for the child process")?);
+        tracing::info!(task_id, pid, code, "Child exited");
+
+        if let Some(mut task) = self.load(&task_id)? {
+            if task.monitor_pid.is_none() || task.monitor_pid == Some(std::process::id() as i32) {
+                task.set_exited(Some(code));
+                self.save(&task)?;
             }
+        }
+
+        Ok(code)
+    }
+
+    /// Record this process as the task's monitor. Returns false if the task
+    /// doesn't exist or another monitor that is still alive owns it.
+    fn claim(&self, task_id: &str) -> Result<bool> {
+        let Some(task) = self.load(task_id)? else {
+            return Ok(false);
+        };
 
-            // Get current task state
-            let task = match self.db.get_task_by_id(&task_id)? {
-                Some(t) => t,
-                None => {
-                    // Task was deleted, stop monitoring
-                    break;
+        // Liveness can't be checked in SQL, so look first and only take
+        // over the owner seen dead here; if it changes meanwhile the update
+        // matches nothing
+        let own_pid = std::process::id() as i32;
+        let stale = task
+            .monitor_pid
+            .filter(|&existing| existing != own_pid && !is_process_alive(existing));
+        retry_busy(|| self.db.claim_monitor(task_id, own_pid, stale))
+    }
+
+    /// Clear `monitor_pid` if it still points at this process
+    fn release(&self, task_id: &str) -> Result<()> {
+        retry_busy(|| self.db.release_monitor(task_id, std::process::id() as i32))
+    }
+
+    // The bridge and hooks write to the same database, so reads and writes
+    // ride out a briefly held lock instead of failing
+    fn load(&self, task_id: &str) -> Result<Option<Task>> {
+        retry_busy(|| self.db.get_task_by_id(task_id))
+    }
+
+    fn save(&self, task: &Task) -> Result<()> {
+        retry_busy(|| self.db.update_task(task))
+    }
+
+    /// Poll until there is nothing left to watch. Between polls `wait` is
+    /// handed the poll interval; it returns false to stop early.
+    fn watch(
+        &self,
+        task_id: &str,
+        pids: Vec<i32>,
+        mut wait: impl FnMut(Duration) -> bool,
+    ) -> Result<()> {
+        let tree = detectors::process_tree(&pids);
+        let mut context = DetectorContext {
+            last_cpu_time: detectors::tree_cpu_time(&tree),
+            pids,
+            tree,
+            last_check: SystemTime::now(),
+            idle_duration: Duration::ZERO,
+            idle_samples: 0,
+            idle_streak: Duration::ZERO,
+            blocked_since: None,
+            mem_threshold_kb: self.mem_threshold_kb,
+        };
+        // The reason this monitor last raised, if its flag is still up
+        let mut flagged = None;
+
+        loop {
+            match self.poll(task_id, &mut context, &mut flagged) {
+                Ok(true) => {
+                    if let Err(e) = retry_busy(|| self.db.record_heartbeat(task_id)) {
+                        tracing::warn!(task_id, "Failed to record heartbeat: {:#}", e);
+                    }
                 }
-            };
+                Ok(false) => break,
+                // Even after retries the database is unavailable; an exiting
+                // monitor would orphan the task, so try again next poll
+                Err(e) => tracing::warn!(task_id, "Monitor poll failed: {:#}", e),
+            }
 
-            // Stop monitoring if task is already completed or exited
-            if task.status == TaskStatus::Completed || task.status == TaskStatus::Exited {
+            if !wait(self.poll_interval) {
                 break;
             }
+        }
+
+        Ok(())
+    }
+
+    /// One monitoring step. Returns false once there is nothing left to watch.
+    fn poll(
+        &self,
+        task_id: &str,
+        context: &mut DetectorContext,
+        flagged: &mut Option<AttentionReason>,
+    ) -> Result<bool> {
+        // Check if any of the processes is still alive
+        if !any_alive(&context.pids) {
+            // All processes died, mark as exited
+            tracing::info!(task_id, "Monitored processes exited");
+            if let Some(mut task) = self.load(task_id)? {
+                // Monitor doesn't know exit code, wrapper will update with correct code
+                task.set_exited(None);
+                self.save(&task)?;
+            }
+            return Ok(false);
+        }
+
+        // Get current task state
+        let task = match self.load(task_id)? {
+            Some(t) => t,
+            None => {
+                // Task was deleted, stop monitoring
+                return Ok(false);
+            }
+        };
+
+        // Stop monitoring if task is already completed or exited
+        if task.status == TaskStatus::Completed || task.status == TaskStatus::Exited {
+            return Ok(false);
+        }
 
-            // Sleep before next check
-            thread::sleep(self.poll_interval);
+        // A paused task is expected to sit idle; keep watching the process
+        // but don't flag it
+        if task.status == TaskStatus::Paused {
+            return Ok(true);
+        }
+
+        let needs_attention = task.needs_attention();
+        self.run_detectors(task, context, flagged)?;
+
+        Ok(!(self.exit_on_idle && (needs_attention || flagged.is_some())))
+    }
+
+    /// Sample the process, run the detectors and persist any change in the
+    /// task's attention state
+    fn run_detectors(
+        &self,
+        mut task: Task,
+        context: &mut DetectorContext,
+        flagged: &mut Option<AttentionReason>,
+    ) -> Result<()> {
+        // Workers come and go, so rediscover the tree on every poll
+        context.tree = detectors::process_tree(&context.pids);
+        let current_cpu = detectors::tree_cpu_time(&context.tree);
+        let now = SystemTime::now();
+        context.record_cpu_sample(current_cpu, now, self.idle_window);
+        let blocked = context
+            .tree
+            .iter()
+            .any(|&pid| detectors::process_state(pid) == Some('D'));
+        context.record_blocked(blocked, now);
+        tracing::trace!(
+            task_id = %task.task_id,
+            cpu = ?current_cpu,
+            idle_samples = context.idle_samples,
+            idle_secs = context.idle_duration.as_secs(),
+            blocked,
+            "Sampled process tree"
+        );
+
+        let reason = self
+            .detectors
+            .iter()
+            .find_map(|detector| detector.check(&task, context));
+
+        match reason {
+            Some(reason) => {
+                if task.attention_reason.as_ref() != Some(&reason) {
+                    let still_raised = task.attention_reason.is_some()
+                        && flagged.as_ref().is_some_and(|prev| prev.same_kind(&reason));
+                    if still_raised {
+                        // Only the idle time grew; refresh it without
+                        // bumping updated_at so the task doesn't re-sort
+                        task.attention_reason = Some(reason.clone());
+                    } else {
+                        tracing::debug!(task_id = %task.task_id, %reason, "Flagging task");
+                        task.flag_attention(reason.clone());
+                    }
+                    self.save(&task)?;
+                }
+                *flagged = Some(reason);
+            }
+            None if flagged.is_some() => {
+                // Activity resumed, withdraw our own flag
+                tracing::debug!(task_id = %task.task_id, "Activity resumed, clearing flag");
+                task.clear_attention();
+                self.save(&task)?;
+                *flagged = None;
+            }
+            None => {}
         }
 
         Ok(())
     }
 }
 
+/// `monitor --exit-on-idle` status for an agent that exited without a code
+/// we could see: a pid that isn't our child, reaped by someone else. It may
+/// well have succeeded, so it isn't reported as a failure.
+pub const UNKNOWN_OUTCOME_EXIT_CODE: i32 = 3;
+
+/// Exit code for `monitor --exit-on-idle`, so CI scripts can branch on how
+/// the agent finished
+pub fn outcome_exit_code(task: Option<&Task>) -> i32 {
+    match task {
+        Some(task) if task.needs_attention() => 2,
+        Some(task) => match task.status {
+            TaskStatus::Completed => 0,
+            TaskStatus::Exited => match task.exit_code {
+                Some(0) => 0,
+                Some(_) => 1,
+                None => UNKNOWN_OUTCOME_EXIT_CODE,
+            },
+            // Only reachable if monitoring stopped early; treat as failure
+            TaskStatus::Running | TaskStatus::Paused => 1,
+        },
+        None => 1,
+    }
+}
+
+/// Exit code as a shell would report it: the process's own code, or
+/// 128 + the signal number that killed it
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Give the child default SIGINT/SIGQUIT handling (the caller ignores them so
+/// Ctrl-C reaches only the agent) and, on Linux, a parent-death signal
+#[cfg(unix)]
+fn prepare_child(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: only async-signal-safe calls between fork and exec
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+            #[cfg(target_os = "linux")]
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn prepare_child(_cmd: &mut Command) {}
+
+/// True while at least one of `pids` is still running
+fn any_alive(pids: &[i32]) -> bool {
+    pids.iter().any(|&pid| is_process_alive(pid))
+}
+
+/// Whether a process with `pid` exists. Signal 0 only checks: it works
+/// without /proc (macOS), and EPERM means the process is there but owned by
+/// someone else.
+#[cfg(unix)]
+fn is_process_alive(pid: i32) -> bool {
+    // 0 and negative pids would address process groups
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: kill with signal 0 sends nothing
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
 fn is_process_alive(pid: i32) -> bool {
     // Check if /proc/<pid> exists
     std::path::Path::new(&format!("/proc/{}", pid)).exists()
@@ -74,6 +550,290 @@ fn is_process_alive(pid: i32) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::process::Command;
+    use tempfile::NamedTempFile;
+
+    fn monitor_with_task(task_id: &str) -> (TaskMonitor, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::open(temp_file.path()).unwrap();
+        db.upsert_task(&Task::new(
+            task_id.to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        ))
+        .unwrap();
+        (TaskMonitor::new(db), temp_file)
+    }
+
+    #[test]
+    fn test_monitor_records_own_pid() {
+        let (monitor, _temp) = monitor_with_task("test-id");
+
+        assert!(monitor.claim("test-id").unwrap());
+        let task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        assert_eq!(task.monitor_pid, Some(std::process::id() as i32));
+
+        monitor.release("test-id").unwrap();
+        let task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        assert_eq!(task.monitor_pid, None);
+    }
+
+    #[test]
+    fn test_configured_detector_replaces_default() {
+        let (monitor, _temp) = monitor_with_task("test-id");
+        let looser = vec![DetectorSpec::CpuIdle { secs: 1200 }];
+        let monitor = monitor.with_detectors(build_detectors(&looser).unwrap());
+        assert_eq!(monitor.detectors.len(), 4);
+
+        let mut task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        task.created_at = Utc::now() - chrono::Duration::hours(1);
+        let idle_for = |secs| DetectorContext {
+            pids: vec![],
+            tree: vec![],
+            last_check: std::time::SystemTime::now(),
+            last_cpu_time: None,
+            idle_duration: Duration::from_secs(secs),
+            idle_samples: 0,
+            idle_streak: Duration::ZERO,
+            blocked_since: None,
+            mem_threshold_kb: None,
+        };
+        let reason = |context: &DetectorContext| {
+            monitor
+                .detectors
+                .iter()
+                .find_map(|d| d.check(&task, context))
+        };
+
+        // Past the built-in 10 minutes, but not the configured 20
+        assert_eq!(reason(&idle_for(700)), None);
+        assert_eq!(
+            reason(&idle_for(1300)),
+            Some(AttentionReason::Idle { idle_secs: 1300 })
+        );
+    }
+
+    #[test]
+    fn test_stale_heartbeat_window() {
+        let now = Utc::now();
+        let poll = Duration::from_secs(5);
+        let mut task = Task::new(
+            "t".to_string(),
+            "claude_code".to_string(),
+            "T".to_string(),
+            None,
+            None,
+        );
+        let beat_ago = |task: &mut Task, secs: i64| {
+            task.last_heartbeat = Some(now - chrono::Duration::seconds(secs));
+        };
+
+        // No monitor, nothing to go stale
+        beat_ago(&mut task, 3600);
+        assert!(!monitor_is_stale(&task, now, poll, false));
+
+        task.monitor_pid = Some(4242);
+        beat_ago(&mut task, 10);
+        assert!(!monitor_is_stale(&task, now, poll, true));
+        // Three missed polls are tolerated, the fourth is not
+        beat_ago(&mut task, 15);
+        assert!(!monitor_is_stale(&task, now, poll, true));
+        beat_ago(&mut task, 16);
+        assert!(monitor_is_stale(&task, now, poll, true));
+        // A heartbeat from the future (clock skew) isn't stale
+        beat_ago(&mut task, -60);
+        assert!(!monitor_is_stale(&task, now, poll, false));
+
+        // Monitors from before heartbeats: only the process tells
+        task.last_heartbeat = None;
+        assert!(!monitor_is_stale(&task, now, poll, true));
+        assert!(monitor_is_stale(&task, now, poll, false));
+    }
+
+    #[test]
+    fn test_reap_orphaned_tasks() {
+        let (monitor, _temp) = monitor_with_task("orphan");
+        let own_pid = std::process::id() as i32;
+        let mut orphan = monitor.db.get_task_by_id("orphan").unwrap().unwrap();
+        orphan.pid = Some(999999);
+        orphan.monitor_pid = Some(999998);
+        monitor.db.update_task(&orphan).unwrap();
+
+        let agent = |id: &str| {
+            Task::new(
+                id.to_string(),
+                "claude_code".to_string(),
+                id.to_string(),
+                Some(own_pid),
+                None,
+            )
+        };
+
+        // Agent alive, monitor dead: would be watched again
+        let mut stranded = agent("stranded");
+        stranded.monitor_pid = Some(999998);
+        monitor.db.insert_task(&stranded).unwrap();
+
+        // Healthy: beating and alive
+        let mut healthy = agent("healthy");
+        healthy.monitor_pid = Some(own_pid);
+        monitor.db.insert_task(&healthy).unwrap();
+        monitor.db.record_heartbeat("healthy").unwrap();
+
+        let planned = reap(&monitor.db, true).unwrap();
+        let outcomes: Vec<_> = planned
+            .iter()
+            .map(|(t, r)| (t.task_id.as_str(), r.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("orphan", Reaped::Exited),
+                ("stranded", Reaped::Restarted { monitor_pid: None })
+            ]
+        );
+        // A dry run changes nothing
+        let task = monitor.db.get_task_by_id("orphan").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Running);
+
+        monitor.db.delete_task("stranded").unwrap();
+        let reaped = reap(&monitor.db, false).unwrap();
+        assert_eq!(reaped.len(), 1);
+        let task = monitor.db.get_task_by_id("orphan").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Exited);
+        assert_eq!(task.monitor_pid, None);
+        assert!(reap(&monitor.db, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_monitor_clears_pid_when_process_exits() {
+        let (monitor, _temp) = monitor_with_task("test-id");
+
+        // A dead pid short-circuits the loop on its first iteration
+        monitor
+            .monitor_task("test-id".to_string(), vec![999999])
+            .unwrap();
+
+        let task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Exited);
+        assert_eq!(task.monitor_pid, None);
+    }
+
+    #[test]
+    fn test_run_child_records_exit_status_promptly() {
+        let (mut monitor, _temp) = monitor_with_task("test-id");
+        // Detection must not wait for the next poll
+        monitor.poll_interval = Duration::from_secs(30);
+
+        let started = std::time::Instant::now();
+        let command = ["sh", "-c", "sleep 0.2; exit 3"].map(String::from);
+        let code = monitor.run_child("test-id".to_string(), &command).unwrap();
+        assert_eq!(code, 3);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Exited);
+        assert_eq!(task.exit_code, Some(3));
+        assert_eq!(task.monitor_pid, None);
+    }
+
+    #[test]
+    fn test_run_child_reports_signals() {
+        let (monitor, _temp) = monitor_with_task("test-id");
+
+        let command = ["sh", "-c", "kill -TERM $$"].map(String::from);
+        assert_eq!(
+            monitor.run_child("test-id".to_string(), &command).unwrap(),
+            128 + 15
+        );
+
+        // Untracked commands still run and report their status
+        let command = ["true".to_string()];
+        assert_eq!(
+            monitor.run_child("missing".to_string(), &command).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_monitor_refuses_task_owned_by_live_monitor() {
+        let (monitor, _temp) = monitor_with_task("test-id");
+
+        let mut other = Command::new("sleep").arg("5").spawn().unwrap();
+        let mut task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        task.monitor_pid = Some(other.id() as i32);
+        monitor.db.update_task(&task).unwrap();
+
+        assert!(!monitor.claim("test-id").unwrap());
+
+        other.kill().unwrap();
+        other.wait().unwrap();
+
+        // A dead owner is stale and can be taken over
+        assert!(monitor.claim("test-id").unwrap());
+    }
+
+    #[test]
+    fn test_monitor_waits_out_a_transient_lock() {
+        let (monitor, temp) = monitor_with_task("test-id");
+
+        // Another writer holds the lock briefly, as the bridge might
+        let other = rusqlite::Connection::open(temp.path()).unwrap();
+        other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let holder = std::thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            other.execute_batch("COMMIT").unwrap();
+        });
+
+        assert!(monitor.claim("test-id").unwrap());
+        holder.join().unwrap();
+
+        let task = monitor.db.get_task_by_id("test-id").unwrap().unwrap();
+        assert_eq!(task.monitor_pid, Some(std::process::id() as i32));
+    }
+
+    #[test]
+    fn test_outcome_exit_code() {
+        let mut task = Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        );
+
+        task.complete();
+        assert_eq!(outcome_exit_code(Some(&task)), 0);
+
+        task.set_exited(Some(0));
+        assert_eq!(outcome_exit_code(Some(&task)), 0);
+        task.set_exited(Some(3));
+        assert_eq!(outcome_exit_code(Some(&task)), 1);
+        task.set_exited(None);
+        assert_eq!(outcome_exit_code(Some(&task)), UNKNOWN_OUTCOME_EXIT_CODE);
+
+        task.set_running();
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        assert_eq!(outcome_exit_code(Some(&task)), 2);
+
+        assert_eq!(outcome_exit_code(None), 1);
+    }
+
+    #[test]
+    fn test_alive_while_any_pid_lives() {
+        let mut worker = Command::new("sleep").arg("5").spawn().unwrap();
+        let worker_pid = worker.id() as i32;
+
+        assert!(any_alive(&[999999, worker_pid]));
+        assert!(!any_alive(&[999999, 999998]));
+        assert!(!any_alive(&[]));
+
+        worker.kill().unwrap();
+        worker.wait().unwrap();
+        assert!(!any_alive(&[999999, worker_pid]));
+    }
 
     #[test]
     fn test_is_process_alive() {
@@ -83,5 +843,11 @@ mod tests {
 
         // PID 999999 very unlikely to exist
         assert!(!is_process_alive(999999));
+
+        // Init is there even when it belongs to another user
+        assert!(is_process_alive(1));
+        // Not process groups
+        assert!(!is_process_alive(0));
+        assert!(!is_process_alive(-1));
     }
 }
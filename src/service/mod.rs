@@ -0,0 +1,275 @@
+//! Background service definitions for `agent-inbox daemon`
+//!
+//! Linux gets a systemd user unit and macOS a launchd agent. Both run the
+//! daemon from the absolute path of this binary and restart it when it
+//! fails, but not when it was stopped on purpose.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// systemd unit name
+pub const UNIT_NAME: &str = "agent-inbox.service";
+
+/// launchd job label, also the plist's file name
+pub const LAUNCHD_LABEL: &str = "com.agent_tasks.daemon";
+
+/// Service manager the definition is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceKind {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceKind {
+    /// The service manager of `os` (`std::env::consts::OS`)
+    pub fn for_os(os: &str) -> Result<Self> {
+        match os {
+            "linux" => Ok(ServiceKind::Systemd),
+            "macos" => Ok(ServiceKind::Launchd),
+            _ => anyhow::bail!("install-service is not supported on {}", os),
+        }
+    }
+
+    /// Where the definition goes for a user with `home`. systemd reads user
+    /// units from `$XDG_CONFIG_HOME/systemd/user`, `config_home` here.
+    pub fn path(self, home: &Path, config_home: Option<&Path>) -> PathBuf {
+        match self {
+            ServiceKind::Systemd => config_home
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("systemd/user")
+                .join(UNIT_NAME),
+            ServiceKind::Launchd => home
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+        }
+    }
+
+    /// File contents running `agent-inbox daemon` from `exe`, logging to
+    /// `log` where the manager doesn't keep logs itself
+    pub fn definition(self, exe: &Path, log: &Path) -> String {
+        match self {
+            ServiceKind::Systemd => systemd_unit(exe),
+            ServiceKind::Launchd => launchd_plist(exe, log),
+        }
+    }
+
+    /// Command that starts the service now and at every login
+    pub fn enable_command(self, path: &Path) -> String {
+        match self {
+            ServiceKind::Systemd => format!(
+                "systemctl --user daemon-reload && systemctl --user enable --now {}",
+                UNIT_NAME
+            ),
+            ServiceKind::Launchd => {
+                format!("launchctl bootstrap gui/$(id -u) '{}'", path.display())
+            }
+        }
+    }
+
+    /// Stop the service and keep it from starting again, while its
+    /// definition still exists. Fails if it wasn't loaded.
+    pub fn disable(self, path: &Path) -> Result<()> {
+        let mut command = match self {
+            ServiceKind::Systemd => {
+                let mut command = Command::new("systemctl");
+                command.args(["--user", "disable", "--now", UNIT_NAME]);
+                command
+            }
+            ServiceKind::Launchd => {
+                let mut command = Command::new("launchctl");
+                command
+                    .arg("bootout")
+                    .arg(format!("gui/{}", uid()))
+                    .arg(path);
+                command
+            }
+        };
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run {:?}", command))?;
+        if !status.success() {
+            anyhow::bail!("{:?} failed ({})", command, status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn uid() -> u32 {
+    0
+}
+
+fn systemd_unit(exe: &Path) -> String {
+    // Quoted so spaces survive; `%` would start a unit specifier
+    let exe = exe
+        .display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!(
+        "[Unit]
+Description=Agent Inbox daemon (recovers dead monitors, applies retention)
+
+[Service]
+ExecStart=\"{}\" daemon
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+",
+        exe
+    )
+}
+
+fn launchd_plist(exe: &Path, log: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>daemon</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL,
+        xml_escape(&exe.display().to_string()),
+        xml_escape(&log.display().to_string())
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Kind and path of the service definition for the current user
+pub fn current() -> Result<(ServiceKind, PathBuf)> {
+    let kind = ServiceKind::for_os(std::env::consts::OS)?;
+    let home = std::env::var_os("HOME").context("HOME is not set")?;
+    let config_home = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty());
+    let path = kind.path(Path::new(&home), config_home.as_deref().map(Path::new));
+    Ok((kind, path))
+}
+
+/// The running `agent-inbox`, with symlinks resolved so the service keeps
+/// working when a package manager swaps the link
+pub fn current_exe() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Failed to locate the agent-inbox binary")?;
+    Ok(exe.canonicalize().unwrap_or(exe))
+}
+
+/// Write `contents` to `path`, creating the directory if needed
+pub fn install(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Remove the definition at `path`. Returns false if there was none.
+pub fn uninstall(path: &Path) -> Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_paths() {
+        let home = Path::new("/home/me");
+        assert_eq!(ServiceKind::for_os("linux").unwrap(), ServiceKind::Systemd);
+        assert_eq!(ServiceKind::for_os("macos").unwrap(), ServiceKind::Launchd);
+        assert!(ServiceKind::for_os("windows").is_err());
+
+        assert_eq!(
+            ServiceKind::Systemd.path(home, None),
+            Path::new("/home/me/.config/systemd/user/agent-inbox.service")
+        );
+        assert_eq!(
+            ServiceKind::Systemd.path(home, Some(Path::new("/xdg"))),
+            Path::new("/xdg/systemd/user/agent-inbox.service")
+        );
+        assert_eq!(
+            ServiceKind::Launchd.path(home, Some(Path::new("/xdg"))),
+            Path::new("/home/me/Library/LaunchAgents/com.agent_tasks.daemon.plist")
+        );
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        let log = Path::new("/unused");
+        let unit = ServiceKind::Systemd.definition(Path::new("/opt/bin/agent-inbox"), log);
+        assert!(
+            unit.contains("\nExecStart=\"/opt/bin/agent-inbox\" daemon\n"),
+            "{}",
+            unit
+        );
+        assert!(unit.contains("\nRestart=on-failure\n"));
+        assert!(unit.contains("\nWantedBy=default.target\n"));
+
+        let odd = ServiceKind::Systemd.definition(Path::new("/My Tools/100%/agent-inbox"), log);
+        assert!(
+            odd.contains("ExecStart=\"/My Tools/100%%/agent-inbox\" daemon"),
+            "{}",
+            odd
+        );
+    }
+
+    #[test]
+    fn test_launchd_plist() {
+        let plist = ServiceKind::Launchd.definition(
+            Path::new("/Apps/R&D/agent-inbox"),
+            Path::new("/Users/me/.agent-tasks/daemon.log"),
+        );
+        assert!(plist.contains("<string>com.agent_tasks.daemon</string>"));
+        assert!(plist.contains(
+            "<string>/Apps/R&amp;D/agent-inbox</string>\n        <string>daemon</string>"
+        ));
+        assert!(plist.contains("<key>SuccessfulExit</key>\n        <false/>"));
+        assert!(plist.contains("<string>/Users/me/.agent-tasks/daemon.log</string>"));
+    }
+
+    #[test]
+    fn test_install_and_uninstall() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ServiceKind::Systemd.path(dir.path(), None);
+        let unit = ServiceKind::Systemd.definition(Path::new("/bin/agent-inbox"), dir.path());
+
+        install(&path, &unit).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), unit);
+        assert!(uninstall(&path).unwrap());
+        assert!(!uninstall(&path).unwrap());
+    }
+}
@@ -0,0 +1,227 @@
+//! Live JSON-lines feed of task changes for editor and tooling integrations
+//!
+//! Each line is one event: `{"event":"changed","task":{...}}` when a task is
+//! created or updated, `{"event":"removed","task_id":"..."}` when it is
+//! deleted. A new reader first receives every current task as `changed`.
+
+use crate::db::Database;
+use crate::models::{Task, TaskStatus};
+use crate::watcher::DbWatcher;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StreamEvent<'a> {
+    Changed { task: &'a Task },
+    Removed { task_id: String },
+}
+
+/// What the reader has been told about each task so far
+type Snapshot = HashMap<String, (TaskStatus, DateTime<Utc>)>;
+
+fn snapshot(tasks: &[Task]) -> Snapshot {
+    tasks
+        .iter()
+        .map(|t| (t.task_id.clone(), (t.status.clone(), t.updated_at)))
+        .collect()
+}
+
+/// Events that bring a reader who saw `previous` up to date with `tasks`.
+/// Without a previous snapshot every task is reported.
+fn diff<'a>(previous: Option<&Snapshot>, tasks: &'a [Task]) -> Vec<StreamEvent<'a>> {
+    let mut events: Vec<StreamEvent> = tasks
+        .iter()
+        .filter(|task| {
+            previous.and_then(|p| p.get(&task.task_id))
+                != Some(&(task.status.clone(), task.updated_at))
+        })
+        .map(|task| StreamEvent::Changed { task })
+        .collect();
+
+    if let Some(previous) = previous {
+        let mut removed: Vec<_> = previous
+            .keys()
+            .filter(|id| !tasks.iter().any(|t| &t.task_id == *id))
+            .cloned()
+            .collect();
+        removed.sort();
+        events.extend(
+            removed
+                .into_iter()
+                .map(|task_id| StreamEvent::Removed { task_id }),
+        );
+    }
+
+    events
+}
+
+/// Write change events to stdout, or to the FIFO at `fifo` (created if
+/// needed). When a FIFO reader goes away the pipe is reopened for the next
+/// one; on stdout a closed pipe ends the stream.
+pub fn run(db: &Database, db_path: &Path, fifo: Option<&Path>) -> Result<()> {
+    if let Some(path) = fifo {
+        ensure_fifo(path)?;
+    }
+
+    let db_watcher = DbWatcher::new(db_path).ok();
+
+    loop {
+        let mut out: Box<dyn Write> = match fifo {
+            // Blocks until a reader opens the other end
+            Some(path) => Box::new(
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open FIFO {}", path.display()))?,
+            ),
+            None => Box::new(io::stdout()),
+        };
+
+        match feed(db, db_watcher.as_ref(), &mut out) {
+            Err(e) if is_broken_pipe(&e) => {
+                if fifo.is_none() {
+                    return Ok(());
+                }
+                // Reader disconnected; wait for the next one
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Stream events to `out` until writing fails
+fn feed(db: &Database, db_watcher: Option<&DbWatcher>, out: &mut dyn Write) -> Result<()> {
+    let mut previous: Option<Snapshot> = None;
+
+    loop {
+        let tasks = db.list_tasks(None)?;
+        for event in diff(previous.as_ref(), &tasks) {
+            serde_json::to_writer(&mut *out, &event)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        previous = Some(snapshot(&tasks));
+
+        match db_watcher {
+            Some(w) => {
+                w.wait(Duration::from_secs(30));
+            }
+            None => thread::sleep(Duration::from_secs(2)),
+        }
+    }
+}
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        let kind = cause
+            .downcast_ref::<io::Error>()
+            .map(io::Error::kind)
+            .or_else(|| cause.downcast_ref::<serde_json::Error>()?.io_error_kind());
+        kind == Some(io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Create a FIFO at `path` unless one already exists there
+#[cfg(unix)]
+fn ensure_fifo(path: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(meta) = std::fs::metadata(path) {
+        if meta.file_type().is_fifo() {
+            return Ok(());
+        }
+        anyhow::bail!("{} exists and is not a FIFO", path.display());
+    }
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: c_path is a valid NUL-terminated string for the whole call
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to create FIFO {}", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ensure_fifo(_path: &Path) -> Result<()> {
+    anyhow::bail!("--fifo is only supported on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str) -> Task {
+        Task::new(
+            id.to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_first_diff_reports_everything() {
+        let tasks = vec![task("a"), task("b")];
+        let events = diff(None, &tasks);
+        assert_eq!(events.len(), 2);
+
+        let line = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(line["event"], "changed");
+        assert_eq!(line["task"]["task_id"], "a");
+    }
+
+    #[test]
+    fn test_diff_reports_changes_and_removals() {
+        let mut tasks = vec![task("a"), task("b")];
+        let previous = snapshot(&tasks);
+        assert!(diff(Some(&previous), &tasks).is_empty());
+
+        tasks[0].complete();
+        tasks.remove(1);
+        let events: Vec<_> = diff(Some(&previous), &tasks)
+            .iter()
+            .map(|e| serde_json::to_value(e).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["task"]["status"], "completed");
+        assert_eq!(
+            events[1],
+            serde_json::json!({"event": "removed", "task_id": "b"})
+        );
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let err = anyhow::Error::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(is_broken_pipe(&err));
+        assert!(is_broken_pipe(&err.context("Failed to write event")));
+        assert!(!is_broken_pipe(&anyhow::anyhow!("database is locked")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ensure_fifo() {
+        use std::os::unix::fs::FileTypeExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inbox.fifo");
+        ensure_fifo(&path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        // Reusing an existing FIFO is fine, clobbering a regular file is not
+        ensure_fifo(&path).unwrap();
+        let file = dir.path().join("plain");
+        std::fs::write(&file, b"").unwrap();
+        assert!(ensure_fifo(&file).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Push-based change notification for the task database
+//!
+//! Watches the SQLite file and its `-wal`/`-shm` siblings so live views can
+//! redraw when something writes, instead of polling on a timer.
+
+use anyhow::Result;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Quiet period that ends a burst of change events
+pub const DEBOUNCE: Duration = Duration::from_millis(150);
+
+pub struct DbWatcher {
+    rx: Receiver<notify::Result<Event>>,
+    db_file_name: String,
+    // Dropping the watcher stops event delivery
+    _watcher: RecommendedWatcher,
+}
+
+impl DbWatcher {
+    /// Watch the directory holding `db_path`, keeping only events for the
+    /// database and its journal files
+    pub fn new(db_path: &Path) -> Result<Self> {
+        let dir: PathBuf = match db_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let db_file_name = db_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            rx,
+            db_file_name,
+            _watcher: watcher,
+        })
+    }
+
+    /// Block until the database changes or `timeout` passes. A burst of
+    /// writes is collapsed into one wake-up by waiting for `DEBOUNCE` of
+    /// quiet. Returns whether a change was seen.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(remaining) {
+                Ok(event) if self.is_db_event(&event) => break,
+                // Something else in the directory changed; keep waiting
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return false
+                }
+            }
+        }
+
+        // Drain the rest of the burst
+        while self.rx.recv_timeout(DEBOUNCE).is_ok() {}
+        true
+    }
+
+    fn is_db_event(&self, event: &notify::Result<Event>) -> bool {
+        match event {
+            Ok(event) => event.paths.iter().any(|path| {
+                path.file_name()
+                    .map(|n| n.to_string_lossy().starts_with(&self.db_file_name))
+                    .unwrap_or(false)
+            }),
+            // Surface watcher errors as a change so the caller re-reads
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_burst_of_writes_wakes_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("tasks.db");
+        fs::write(&db_path, b"").unwrap();
+
+        let watcher = DbWatcher::new(&db_path).unwrap();
+
+        for i in 0..5 {
+            fs::write(&db_path, format!("write {}", i)).unwrap();
+        }
+        fs::write(dir.path().join("tasks.db-wal"), b"wal").unwrap();
+
+        assert!(watcher.wait(Duration::from_secs(5)));
+        // The whole burst was consumed by the first wake-up
+        assert!(!watcher.wait(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn test_unrelated_files_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("tasks.db");
+        fs::write(&db_path, b"").unwrap();
+
+        let watcher = DbWatcher::new(&db_path).unwrap();
+        fs::write(dir.path().join("config.toml"), b"x = 1").unwrap();
+
+        assert!(!watcher.wait(Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,725 @@
+//! Outgoing HTTP notifications for task status changes
+//!
+//! Deliveries run on background threads so they never stall a database
+//! write. Pending deliveries are joined when the `Webhook` is dropped, which
+//! keeps short-lived CLI invocations from exiting before the POST is sent.
+//!
+//! Transient failures are retried with exponential backoff. A delivery that
+//! still fails is appended to a dead-letter file (JSON lines) from which
+//! `agent-inbox webhook replay` can re-send it later.
+//!
+//! A task flapping in and out of attention would send a burst of the same
+//! notification; a `Throttle` holds those back for a cooldown.
+
+use crate::models::attention::format_idle;
+use crate::models::Task;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where undeliverable payloads are kept
+pub fn default_dead_letter_path() -> PathBuf {
+    crate::db::data_dir().join("webhook-deadletter.jsonl")
+}
+
+/// How hard to try before giving up on a delivery
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first
+    pub attempts: u32,
+    /// Wait before the first retry; doubled for each one after
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Body layout expected by the receiving end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The `StatusChange` fields as a flat JSON object
+    Generic,
+    /// Slack incoming webhook message with a colored attachment
+    Slack,
+    /// Discord webhook message with a colored embed
+    Discord,
+}
+
+impl WebhookFormat {
+    /// Request body for `change`
+    pub fn body(&self, change: &StatusChange) -> serde_json::Value {
+        match self {
+            WebhookFormat::Generic => serde_json::to_value(change).unwrap_or_default(),
+            WebhookFormat::Slack => serde_json::json!({
+                "text": format!("{}{}", mention(change, "<!here> "), summary_line(change)),
+                "attachments": [{
+                    "color": format!("#{:06x}", status_color(change)),
+                    "title": change.title,
+                    "text": change.reason,
+                    "fields": [
+                        {"title": "Agent", "value": change.agent_type, "short": true},
+                        {"title": "Status", "value": change.status, "short": true},
+                    ],
+                    "footer": change.task_id,
+                }],
+            }),
+            WebhookFormat::Discord => serde_json::json!({
+                "content": format!("{}{}", mention(change, "@here "), summary_line(change)),
+                "embeds": [{
+                    "title": change.title,
+                    "description": change.reason,
+                    "color": status_color(change),
+                    "fields": [
+                        {"name": "Agent", "value": change.agent_type, "inline": true},
+                        {"name": "Status", "value": change.status, "inline": true},
+                    ],
+                    "footer": {"text": change.task_id},
+                }],
+            }),
+        }
+    }
+}
+
+/// `tag` for escalations, so the channel is pinged; nothing otherwise
+fn mention<'a>(change: &StatusChange, tag: &'a str) -> &'a str {
+    if change.escalated_after_secs.is_some() {
+        tag
+    } else {
+        ""
+    }
+}
+
+/// One-line message text for the chat targets
+fn summary_line(change: &StatusChange) -> String {
+    if let Some(secs) = change.escalated_after_secs {
+        format!(
+            "[{}] still needs attention after {}: {}",
+            change.agent_type,
+            format_idle(secs),
+            change.title
+        )
+    } else if change.reason.is_some() {
+        format!("[{}] needs attention: {}", change.agent_type, change.title)
+    } else {
+        format!(
+            "[{}] {}: {}",
+            change.agent_type, change.status, change.title
+        )
+    }
+}
+
+/// RGB sidebar color: red when escalated, yellow when flagged, otherwise
+/// by status
+fn status_color(change: &StatusChange) -> u32 {
+    if change.escalated_after_secs.is_some() {
+        return 0xe01e5a;
+    }
+    if change.reason.is_some() {
+        return 0xecb22e;
+    }
+    match change.status.as_str() {
+        "running" => 0x1d9bd1,
+        "paused" => 0x17a2b8,
+        "completed" => 0x2eb67d,
+        _ => 0xe01e5a,
+    }
+}
+
+/// One line of the dead-letter file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub url: String,
+    pub payload: serde_json::Value,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// JSON body POSTed on every status change
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusChange {
+    pub task_id: String,
+    pub title: String,
+    pub agent_type: String,
+    pub status: String,
+    pub reason: Option<String>,
+    /// Machine-readable `AttentionReason::kind`, for routing
+    pub reason_kind: Option<&'static str>,
+    /// Set when re-sent because the flag has been up this long
+    /// (`[[escalation]]`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub escalated_after_secs: Option<u64>,
+}
+
+impl StatusChange {
+    pub fn from_task(task: &Task) -> Self {
+        Self {
+            task_id: task.task_id.clone(),
+            title: task.title.clone(),
+            agent_type: task.agent_type.clone(),
+            status: task.status.as_str().to_string(),
+            reason: task.attention_reason.as_ref().map(|r| r.to_string()),
+            reason_kind: task.attention_reason.as_ref().map(|r| r.kind()),
+            escalated_after_secs: None,
+        }
+    }
+}
+
+/// Per-task cooldown on notifications that repeat the status last sent for
+/// the task. Reasons seen while held back are combined into the next
+/// notification that goes out with a reason of its own.
+#[derive(Debug)]
+pub struct Throttle {
+    cooldown: Duration,
+    last: HashMap<String, Sent>,
+}
+
+#[derive(Debug)]
+struct Sent {
+    at: Instant,
+    status: String,
+    held_reasons: Vec<String>,
+}
+
+impl Throttle {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last: HashMap::new(),
+        }
+    }
+
+    /// `change`, possibly with combined reasons, if it should be sent at
+    /// `now`; None while it repeats its task's last sent status within the
+    /// cooldown. A new status always goes out.
+    pub fn admit(&mut self, mut change: StatusChange, now: Instant) -> Option<StatusChange> {
+        if let Some(sent) = self.last.get_mut(&change.task_id) {
+            let repeat = sent.status == change.status;
+            if repeat && now.saturating_duration_since(sent.at) < self.cooldown {
+                if let Some(reason) = change.reason {
+                    if !sent.held_reasons.contains(&reason) {
+                        sent.held_reasons.push(reason);
+                    }
+                }
+                return None;
+            }
+        }
+
+        let mut reasons = self
+            .last
+            .remove(&change.task_id)
+            .map(|sent| sent.held_reasons)
+            .unwrap_or_default();
+        if let Some(reason) = change.reason.as_ref().filter(|_| !reasons.is_empty()) {
+            if !reasons.contains(reason) {
+                reasons.push(reason.clone());
+            }
+            change.reason = Some(reasons.join("; "));
+        }
+        self.last.insert(
+            change.task_id.clone(),
+            Sent {
+                at: now,
+                status: change.status.clone(),
+                held_reasons: Vec::new(),
+            },
+        );
+        Some(change)
+    }
+}
+
+pub struct Webhook {
+    url: String,
+    format: WebhookFormat,
+    retry: RetryPolicy,
+    dead_letter: Option<PathBuf>,
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Webhook {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            format: WebhookFormat::Generic,
+            retry: RetryPolicy::default(),
+            dead_letter: None,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_format(mut self, format: WebhookFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Append deliveries that exhaust their retries to `path`
+    pub fn with_dead_letter(mut self, path: PathBuf) -> Self {
+        self.dead_letter = Some(path);
+        self
+    }
+
+    /// POST `change` to the webhook URL in the background. Failures are
+    /// logged and dead-lettered, never returned.
+    pub fn send(&self, change: StatusChange) {
+        let url = self.url.clone();
+        let retry = self.retry;
+        let dead_letter = self.dead_letter.clone();
+        let payload = self.format.body(&change);
+        let handle = thread::spawn(move || {
+            let result = deliver(&url, &payload, retry);
+            if result.is_ok() {
+                tracing::debug!(task_id = %change.task_id, %url, "Webhook delivered");
+            }
+            if let Err(e) = result {
+                tracing::warn!(task_id = %change.task_id, error = %e, "Webhook delivery failed");
+                if let Some(path) = dead_letter {
+                    let letter = DeadLetter {
+                        url,
+                        payload,
+                        error: e.to_string(),
+                        failed_at: Utc::now(),
+                    };
+                    if let Err(e) = append_dead_letters(&path, &[letter]) {
+                        tracing::warn!(error = %e, "Failed to write webhook dead letter");
+                    }
+                }
+            }
+        });
+
+        if let Ok(mut pending) = self.pending.lock() {
+            // The bridge and the daemon live for days; don't keep a handle
+            // per delivery ever sent
+            pending.retain(|handle| !handle.is_finished());
+            pending.push(handle);
+        }
+    }
+
+    /// Wait for all in-flight deliveries to finish
+    pub fn flush(&self) {
+        let handles: Vec<_> = match self.pending.lock() {
+            Ok(mut pending) => pending.drain(..).collect(),
+            Err(_) => return,
+        };
+
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// POST `payload` to `url`, retrying connection errors, 429 and 5xx
+/// responses per `retry`. Returns the last error once attempts run out.
+pub fn deliver(url: &str, payload: &serde_json::Value, retry: RetryPolicy) -> Result<()> {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let mut delay = retry.base_delay;
+    let mut attempt = 1;
+    loop {
+        match agent.post(url).send_json(payload) {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < retry.attempts && is_transient(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn is_transient(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+fn append_dead_letters(path: &Path, letters: &[DeadLetter]) -> Result<()> {
+    let mut lines = String::new();
+    for letter in letters {
+        lines.push_str(&serde_json::to_string(letter)?);
+        lines.push('\n');
+    }
+    // One write per call so concurrent appenders don't interleave lines
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// What `replay` managed to re-send
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub delivered: usize,
+    pub failed: usize,
+}
+
+/// Re-send every dead letter in `path`, to `url` when given and otherwise to
+/// the URL each was originally meant for. Letters that fail again are kept.
+pub fn replay(path: &Path, url: Option<&str>, retry: RetryPolicy) -> Result<ReplaySummary> {
+    // Take the queue aside so deliveries failing meanwhile append to a new file
+    let claimed = path.with_extension("jsonl.replaying");
+    match fs::rename(path, &claimed) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ReplaySummary::default()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to claim {}", path.display()));
+        }
+    }
+
+    let file = fs::File::open(&claimed)?;
+    let mut summary = ReplaySummary::default();
+    let mut still_failing = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut letter: DeadLetter = serde_json::from_str(&line)
+            .with_context(|| format!("Malformed dead letter in {}", path.display()))?;
+
+        let target = url.unwrap_or(&letter.url).to_string();
+        match deliver(&target, &letter.payload, retry) {
+            Ok(()) => summary.delivered += 1,
+            Err(e) => {
+                letter.error = e.to_string();
+                letter.failed_at = Utc::now();
+                still_failing.push(letter);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    append_dead_letters(path, &still_failing)?;
+    fs::remove_file(&claimed)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AttentionReason;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    const FAST_RETRY: RetryPolicy = RetryPolicy {
+        attempts: 3,
+        base_delay: Duration::from_millis(1),
+    };
+
+    /// Accept one HTTP request, reply 200 and hand back the request body
+    fn mock_server() -> (String, mpsc::Receiver<String>) {
+        mock_server_with(vec![200])
+    }
+
+    /// Answer one request per entry of `statuses`, in order, handing back
+    /// each request body
+    fn mock_server_with(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .get_mut()
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        )
+                        .as_bytes(),
+                    )
+                    .unwrap();
+                tx.send(String::from_utf8(body).unwrap()).unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn task() -> Task {
+        Task::new(
+            "test-id".to_string(),
+            "claude_code".to_string(),
+            "Test task".to_string(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_throttle_cooldown() {
+        let mut task = task();
+        let mut throttle = Throttle::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        task.flag_attention(AttentionReason::Idle { idle_secs: 600 });
+        assert!(throttle
+            .admit(StatusChange::from_task(&task), at(0))
+            .is_some());
+
+        // Flapping within the cooldown is held back, reasons remembered
+        task.set_running();
+        assert!(throttle
+            .admit(StatusChange::from_task(&task), at(10))
+            .is_none());
+        task.flag_attention(AttentionReason::Custom("Approve?".to_string()));
+        assert!(throttle
+            .admit(StatusChange::from_task(&task), at(20))
+            .is_none());
+
+        // Other tasks have their own window
+        let mut other = task.clone();
+        other.task_id = "t2".to_string();
+        assert!(throttle
+            .admit(StatusChange::from_task(&other), at(20))
+            .is_some());
+
+        // After the cooldown the held reasons go out with the new one
+        task.flag_attention(AttentionReason::Idle { idle_secs: 900 });
+        let change = throttle
+            .admit(StatusChange::from_task(&task), at(61))
+            .unwrap();
+        assert_eq!(
+            change.reason.as_deref(),
+            Some("Approve?; No CPU activity for 15m")
+        );
+
+        // A new status is never held back
+        task.complete();
+        let change = throttle
+            .admit(StatusChange::from_task(&task), at(62))
+            .unwrap();
+        assert_eq!(change.status, "completed");
+    }
+
+    #[test]
+    fn test_webhook_posts_status_change() {
+        let (url, rx) = mock_server();
+
+        let mut task = task();
+        task.complete();
+
+        let webhook = Webhook::new(url);
+        webhook.send(StatusChange::from_task(&task));
+        webhook.flush();
+
+        let body: serde_json::Value =
+            serde_json::from_str(&rx.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "task_id": "test-id",
+                "title": "Test task",
+                "agent_type": "claude_code",
+                "status": "completed",
+                "reason": null,
+                "reason_kind": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_finished_deliveries_are_dropped() {
+        let (url, rx) = mock_server_with(vec![200, 200]);
+        let webhook = Webhook::new(url);
+        webhook.send(StatusChange::from_task(&task()));
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        while !webhook.pending.lock().unwrap()[0].is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        webhook.send(StatusChange::from_task(&task()));
+        assert_eq!(webhook.pending.lock().unwrap().len(), 1);
+        webhook.flush();
+    }
+
+    #[test]
+    fn test_webhook_failure_does_not_panic() {
+        // Nothing listens on port 9 (discard) in the test environment
+        let webhook = Webhook::new("http://127.0.0.1:9/hook".to_string()).with_retry(FAST_RETRY);
+        webhook.send(StatusChange::from_task(&task()));
+        webhook.flush();
+    }
+
+    #[test]
+    fn test_transient_failures_are_retried() {
+        let (url, rx) = mock_server_with(vec![503, 500, 200]);
+
+        let payload = serde_json::to_value(StatusChange::from_task(&task())).unwrap();
+        deliver(&url, &payload, FAST_RETRY).unwrap();
+        assert_eq!(rx.iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let (url, rx) = mock_server_with(vec![404, 200]);
+
+        let payload = serde_json::to_value(StatusChange::from_task(&task())).unwrap();
+        assert!(deliver(&url, &payload, FAST_RETRY).is_err());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn test_final_failure_is_dead_lettered_and_replayed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("webhook-deadletter.jsonl");
+
+        let webhook = Webhook::new("http://127.0.0.1:9/hook".to_string())
+            .with_retry(FAST_RETRY)
+            .with_dead_letter(path.clone());
+        webhook.send(StatusChange::from_task(&task()));
+        webhook.flush();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let letter: DeadLetter = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(letter.url, "http://127.0.0.1:9/hook");
+        assert_eq!(letter.payload["task_id"], "test-id");
+
+        // Still unreachable: the letter stays queued
+        let summary = replay(&path, None, FAST_RETRY).unwrap();
+        assert_eq!(
+            summary,
+            ReplaySummary {
+                delivered: 0,
+                failed: 1
+            }
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        let (url, rx) = mock_server();
+        let summary = replay(&path, Some(&url), FAST_RETRY).unwrap();
+        assert_eq!(
+            summary,
+            ReplaySummary {
+                delivered: 1,
+                failed: 0
+            }
+        );
+        let body: serde_json::Value =
+            serde_json::from_str(&rx.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap();
+        assert_eq!(body["task_id"], "test-id");
+        assert!(fs::read_to_string(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replay_without_queue_is_noop() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = replay(&dir.path().join("none.jsonl"), None, FAST_RETRY).unwrap();
+        assert_eq!(summary, ReplaySummary::default());
+    }
+
+    #[test]
+    fn test_slack_body() {
+        let mut task = task();
+        task.complete();
+        let body = WebhookFormat::Slack.body(&StatusChange::from_task(&task));
+
+        assert_eq!(body["text"], "[claude_code] completed: Test task");
+        let attachment = &body["attachments"][0];
+        assert_eq!(attachment["color"], "#2eb67d");
+        assert_eq!(attachment["title"], "Test task");
+        assert_eq!(
+            attachment["fields"][0],
+            serde_json::json!({"title": "Agent", "value": "claude_code", "short": true})
+        );
+        assert_eq!(attachment["fields"][1]["value"], "completed");
+        assert_eq!(attachment["footer"], "test-id");
+    }
+
+    #[test]
+    fn test_discord_body() {
+        let mut task = task();
+        task.flag_attention(AttentionReason::Custom("Approve deploy".to_string()));
+        let body = WebhookFormat::Discord.body(&StatusChange::from_task(&task));
+
+        assert_eq!(body["content"], "[claude_code] needs attention: Test task");
+        let embed = &body["embeds"][0];
+        assert_eq!(embed["color"], 0xecb22e);
+        assert_eq!(embed["description"], "Approve deploy");
+        assert_eq!(
+            embed["fields"][1],
+            serde_json::json!({"name": "Status", "value": "running", "inline": true})
+        );
+        assert_eq!(embed["footer"]["text"], "test-id");
+    }
+
+    #[test]
+    fn test_escalated_body() {
+        let mut task = task();
+        task.flag_attention(AttentionReason::Custom("Approve deploy".to_string()));
+        let mut change = StatusChange::from_task(&task);
+        assert!(serde_json::to_value(&change)
+            .unwrap()
+            .get("escalated_after_secs")
+            .is_none());
+        change.escalated_after_secs = Some(3600);
+
+        let body = WebhookFormat::Slack.body(&change);
+        let text = "<!here> [claude_code] still needs attention after 1h: Test task";
+        assert_eq!(body["text"], text);
+        assert_eq!(body["attachments"][0]["color"], "#e01e5a");
+        let body = WebhookFormat::Discord.body(&change);
+        assert!(body["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("@here [claude_code] still"));
+        assert_eq!(
+            WebhookFormat::Generic.body(&change)["escalated_after_secs"],
+            3600
+        );
+    }
+
+    #[test]
+    fn test_generic_body_is_status_change() {
+        let change = StatusChange::from_task(&task());
+        assert_eq!(
+            WebhookFormat::Generic.body(&change),
+            serde_json::to_value(&change).unwrap()
+        );
+    }
+}
@@ -0,0 +1,656 @@
+//! End-to-end tests for the `report` subcommands, run against the built
+//! binary with `HOME` pointed at a scratch directory
+
+use serde_json::Value;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn agent_inbox(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_agent-inbox"))
+        .args(args)
+        .env("HOME", home)
+        .output()
+        .expect("failed to run agent-inbox")
+}
+
+/// Run a command that must succeed and return its stdout
+fn run(home: &Path, args: &[&str]) -> String {
+    let output = agent_inbox(home, args);
+    assert!(
+        output.status.success(),
+        "agent-inbox {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn show(home: &Path, task_id: &str) -> Value {
+    serde_json::from_str(&run(home, &["show", task_id, "--json"])).unwrap()
+}
+
+fn start(home: &Path, task_id: &str) {
+    let out = run(
+        home,
+        &[
+            "report",
+            "start",
+            task_id,
+            "claude_code",
+            "/repo",
+            "Fix the build",
+        ],
+    );
+    assert_eq!(out.trim(), format!("Task started: {}", task_id));
+}
+
+#[test]
+fn test_report_start() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let task = show(home.path(), "t1");
+    assert_eq!(task["status"], "running");
+    assert_eq!(task["agent_type"], "claude_code");
+    assert_eq!(task["title"], "Fix the build");
+    assert_eq!(task["context"]["project_path"], "/repo");
+}
+
+#[test]
+fn test_report_start_twice() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    // A wrapper firing start again must not fail
+    let args = [
+        "report",
+        "start",
+        "t1",
+        "claude_code",
+        "/repo",
+        "Fix the build",
+    ];
+    let output = agent_inbox(home.path(), &args);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "Task started: t1"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Task t1 already existed"), "{}", stderr);
+    assert_eq!(show(home.path(), "t1")["status"], "running");
+}
+
+#[test]
+fn test_show_context_only() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let out = run(home.path(), &["show", "t1", "--context-only", "--require"]);
+    let context: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(context["project_path"], "/repo");
+    assert!(context.get("status").is_none());
+}
+
+#[test]
+fn test_report_running_and_complete() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    run(home.path(), &["report", "complete", "t1"]);
+    assert_eq!(show(home.path(), "t1")["status"], "completed");
+
+    run(home.path(), &["report", "running", "t1"]);
+    assert_eq!(show(home.path(), "t1")["status"], "running");
+
+    // A non-zero exit code on complete means the agent failed
+    run(
+        home.path(),
+        &["report", "complete", "t1", "--exit-code", "3"],
+    );
+    let task = show(home.path(), "t1");
+    assert_eq!(task["status"], "exited");
+    assert_eq!(task["exit_code"], 3);
+}
+
+#[test]
+fn test_report_update() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+    let before = show(home.path(), "t1");
+    run(home.path(), &["report", "complete", "t1"]);
+
+    let out = run(
+        home.path(),
+        &["report", "update", "t1", "--title", "Fix the tests too"],
+    );
+    assert_eq!(out.trim(), "Task updated: t1");
+    let task = show(home.path(), "t1");
+    assert_eq!(task["title"], "Fix the tests too");
+    assert_eq!(task["status"], "completed");
+    assert_eq!(task["created_at"], before["created_at"]);
+    assert_eq!(task["context"]["project_path"], "/repo");
+
+    run(
+        home.path(),
+        &[
+            "report",
+            "update",
+            "t1",
+            "--cwd",
+            "/other",
+            "--url",
+            "https://x.test",
+        ],
+    );
+    let task = show(home.path(), "t1");
+    assert_eq!(task["context"]["project_path"], "/other");
+    assert_eq!(task["context"]["url"], "https://x.test");
+    assert_eq!(task["title"], "Fix the tests too");
+
+    // Nothing to change is a usage error
+    assert!(!agent_inbox(home.path(), &["report", "update", "t1"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_ttl() {
+    let home = tempfile::tempdir().unwrap();
+    let args = [
+        "report",
+        "start",
+        "t1",
+        "claude_code",
+        "/repo",
+        "Try something",
+        "--ttl",
+        "1h",
+    ];
+    run(home.path(), &args);
+    assert!(show(home.path(), "t1")["expires_at"].is_string());
+    let detail = run(home.path(), &["show", "t1"]);
+    let left = ["Expires:  in 59m", "Expires:  in 1h"];
+    assert!(left.iter().any(|text| detail.contains(text)), "{}", detail);
+
+    // A TTL of zero runs out at once; the next invocation removes the task
+    run(home.path(), &["report", "update", "t1", "--ttl", "0s"]);
+    let out = run(home.path(), &["list", "--all", "--format", "json"]);
+    assert_eq!(
+        serde_json::from_str::<Value>(&out).unwrap(),
+        serde_json::json!([])
+    );
+}
+
+#[test]
+fn test_report_exited() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    run(home.path(), &["report", "exited", "t1", "--exit-code", "0"]);
+    let task = show(home.path(), "t1");
+    assert_eq!(task["status"], "exited");
+    assert_eq!(task["exit_code"], 0);
+}
+
+#[test]
+fn test_report_needs_attention() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+    run(home.path(), &["report", "complete", "t1"]);
+
+    let out = run(
+        home.path(),
+        &["report", "needs-attention", "t1", "Waiting for approval"],
+    );
+    assert_eq!(out.trim(), "Task needs attention: t1");
+
+    // Attention brings the task back to running so it is shown flagged
+    let task = show(home.path(), "t1");
+    assert_eq!(task["status"], "running");
+    assert_eq!(task["attention_reason"]["kind"], "custom");
+    assert_eq!(task["attention_reason"]["message"], "Waiting for approval");
+}
+
+#[test]
+fn test_report_failed() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let out = run(home.path(), &["report", "failed", "t1"]);
+    assert_eq!(out.trim(), "Task failed: t1");
+    let task = show(home.path(), "t1");
+    assert_eq!(task["status"], "exited");
+    assert_eq!(task["exit_code"], 1);
+
+    run(
+        home.path(),
+        &["report", "failed", "t1", "--exit-code", "137"],
+    );
+    assert_eq!(show(home.path(), "t1")["exit_code"], 137);
+}
+
+#[test]
+fn test_list_exit_code() {
+    let home = tempfile::tempdir().unwrap();
+    for (id, code) in [
+        ("ok", "0"),
+        ("killed", "137"),
+        ("segv", "139"),
+        ("err", "1"),
+    ] {
+        start(home.path(), id);
+        run(home.path(), &["report", "exited", id, "--exit-code", code]);
+    }
+    start(home.path(), "running");
+
+    let ids = |args: &[&str]| -> Vec<String> {
+        let mut list = vec!["list", "--format", "json"];
+        list.extend(args);
+        let tasks: Value = serde_json::from_str(&run(home.path(), &list)).unwrap();
+        let tasks = tasks.as_array().unwrap().iter();
+        let mut ids: Vec<String> = tasks
+            .map(|t| t["task_id"].as_str().unwrap().into())
+            .collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(&["--exit-code", "137"]), ["killed"]);
+    assert_eq!(
+        ids(&["--status", "exited", "--exit-code", "137"]),
+        ["killed"]
+    );
+    assert_eq!(
+        ids(&["--exit-code", "1", "--exit-code", "137"]),
+        ["err", "killed"]
+    );
+    assert_eq!(ids(&["--exit-code", "0,128-159"]), ["killed", "ok", "segv"]);
+    assert!(ids(&["--status", "running", "--exit-code", "137"]).is_empty());
+
+    let count = run(home.path(), &["list", "--count", "--exit-code", "128-255"]);
+    assert_eq!(count.trim(), "2");
+    let out = agent_inbox(home.path(), &["list", "--exit-code", "159-128"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_clear_by_status() {
+    let home = tempfile::tempdir().unwrap();
+    // One task per state: running, flagged, paused, completed, exited
+    for id in ["running", "flagged", "paused", "completed", "exited"] {
+        start(home.path(), id);
+    }
+    run(
+        home.path(),
+        &["report", "needs-attention", "flagged", "Approve?"],
+    );
+    run(home.path(), &["pause", "paused"]);
+    run(home.path(), &["report", "complete", "completed"]);
+    run(home.path(), &["report", "exited", "exited"]);
+
+    let remaining = || -> Vec<String> {
+        let out = run(home.path(), &["list", "--all", "--format", "json"]);
+        let tasks: Vec<Value> = serde_json::from_str(&out).unwrap();
+        let mut ids: Vec<String> = tasks
+            .iter()
+            .map(|t| t["task_id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    let out = run(home.path(), &["clear", "--status", "attention"]);
+    assert_eq!(out.trim(), "Cleared 1 tasks");
+    assert_eq!(remaining(), ["completed", "exited", "paused", "running"]);
+
+    assert_eq!(
+        run(home.path(), &["clear", "--status", "paused"]).trim(),
+        "Cleared 1 tasks"
+    );
+    assert_eq!(
+        run(home.path(), &["clear", "--all-terminal"]).trim(),
+        "Cleared 2 tasks"
+    );
+    assert_eq!(remaining(), ["running"]);
+
+    assert_eq!(
+        run(home.path(), &["clear", "--status", "running"]).trim(),
+        "Cleared 1 tasks"
+    );
+    assert!(remaining().is_empty());
+
+    // A task and a selector, or neither, is a usage error
+    assert!(!agent_inbox(home.path(), &["clear"]).status.success());
+    assert!(
+        !agent_inbox(home.path(), &["clear", "t1", "--status", "exited"])
+            .status
+            .success()
+    );
+    assert!(!agent_inbox(home.path(), &["clear", "--status", "bogus"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_json_errors() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let error = |args: &[&str]| -> Value {
+        let output = agent_inbox(home.path(), args);
+        assert!(!output.status.success(), "{:?} should fail", args);
+        serde_json::from_slice(&output.stderr).unwrap()
+    };
+
+    let missing = error(&["show", "missing", "--format", "json"]);
+    assert_eq!(missing["code"], "task_not_found");
+    assert_eq!(missing["error"], "Task not found: missing");
+    // show --json counts as asking for JSON
+    assert_eq!(
+        error(&["show", "missing", "--json"])["code"],
+        "task_not_found"
+    );
+    assert_eq!(
+        error(&["list", "--status", "bogus", "--format", "json"])["code"],
+        "invalid_status"
+    );
+    assert_eq!(
+        error(&["resume", "t1", "--format", "json"])["code"],
+        "invalid_state"
+    );
+
+    // Without JSON the message stays human
+    let output = agent_inbox(home.path(), &["show", "missing"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Error: Task not found: missing"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_fields() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let out = run(
+        home.path(),
+        &["list", "--format", "json", "--fields", "task_id,status"],
+    );
+    let tasks: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        tasks,
+        serde_json::json!([{"task_id": "t1", "status": "running"}])
+    );
+
+    let out = run(home.path(), &["show", "t1", "--json", "--fields", "title"]);
+    let task: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(task, serde_json::json!({"title": "Fix the build"}));
+
+    let output = agent_inbox(
+        home.path(),
+        &["list", "--format", "json", "--fields", "task_id,nme"],
+    );
+    assert!(!output.status.success());
+    let error: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("valid fields: id, task_id"));
+    // Text views have no fields to pick
+    assert!(!agent_inbox(home.path(), &["list", "--fields", "task_id"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_list_quiet() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+
+    let full = run(home.path(), &["list"]);
+    assert!(
+        full.contains("Agent Inbox") && full.contains("agent-inbox show"),
+        "{}",
+        full
+    );
+
+    let quiet = run(home.path(), &["list", "--quiet"]);
+    assert!(
+        quiet.contains("1 running") && quiet.contains("Fix the build"),
+        "{}",
+        quiet
+    );
+    assert!(
+        !quiet.contains("Agent Inbox") && !quiet.contains("auto-clear"),
+        "{}",
+        quiet
+    );
+}
+
+#[test]
+fn test_title_lengths() {
+    let home = tempfile::tempdir().unwrap();
+    let title = format!("Refactor {}", "the parser ".repeat(20));
+    let title = title.trim_end();
+    run(
+        home.path(),
+        &["report", "start", "t1", "claude_code", "/repo", title],
+    );
+    assert_eq!(
+        show(home.path(), "t1")["title"].as_str().unwrap().len(),
+        100
+    );
+    let list = run(home.path(), &["list", "--quiet"]);
+    assert!(
+        list.contains(&format!("\"{}...\"", &title[..57])),
+        "{}",
+        list
+    );
+
+    std::fs::write(
+        home.path().join(".agent-tasks/config.toml"),
+        "max_title_len = 300\nlist_title_width = 40\n",
+    )
+    .unwrap();
+    run(home.path(), &["report", "update", "t1", "--title", title]);
+    assert_eq!(show(home.path(), "t1")["title"], title);
+    let list = run(home.path(), &["list", "--quiet"]);
+    assert!(
+        list.contains(&format!("\"{}...\"", &title[..37])),
+        "{}",
+        list
+    );
+}
+
+#[test]
+fn test_merge() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "keep");
+    run(home.path(), &["report", "complete", "keep"]);
+    run(home.path(), &["meta", "set", "keep", "branch", "\"main\""]);
+    start(home.path(), "web");
+    run(home.path(), &["link", "web", "keep"]);
+    run(
+        home.path(),
+        &[
+            "report",
+            "start",
+            "dup",
+            "claude_code",
+            "/elsewhere",
+            "Fix the build",
+        ],
+    );
+    run(home.path(), &["meta", "set", "dup", "branch", "\"fix\""]);
+    run(home.path(), &["meta", "set", "dup", "pr", "7"]);
+    run(home.path(), &["link", "web", "dup"]);
+
+    let out = run(home.path(), &["merge", "keep", "dup"]);
+    assert_eq!(out.trim(), "Merged dup into keep");
+    let keep = show(home.path(), "keep");
+    assert_eq!(keep["status"], "running");
+    assert_eq!(keep["context"]["project_path"], "/repo");
+    assert_eq!(keep["metadata"]["branch"], "main");
+    assert_eq!(keep["metadata"]["pr"], 7);
+    assert!(!agent_inbox(home.path(), &["show", "dup"]).status.success());
+    assert_eq!(
+        show(home.path(), "web")["metadata"]["related"],
+        serde_json::json!(["keep"])
+    );
+
+    // The duplicate went to the trash
+    run(home.path(), &["undo"]);
+    assert_eq!(show(home.path(), "dup")["metadata"]["branch"], "fix");
+    assert!(!agent_inbox(home.path(), &["merge", "keep", "keep"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_version() {
+    let home = tempfile::tempdir().unwrap();
+    let info: Value = serde_json::from_str(&run(home.path(), &["version", "--json"])).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!info["git_sha"].as_str().unwrap().is_empty());
+    assert!(info["schema_version"].as_i64().unwrap() > 1);
+    let db_path = home.path().join(".agent-tasks/tasks.db");
+    assert_eq!(info["db_path"], db_path.to_str().unwrap());
+
+    let text = run(home.path(), &["version"]);
+    assert!(
+        text.starts_with(&format!("agent-inbox {} (", env!("CARGO_PKG_VERSION"))),
+        "{}",
+        text
+    );
+    let flag = run(home.path(), &["--version"]);
+    assert_eq!(
+        flag.trim(),
+        format!("agent-inbox {}", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn test_import_processes() {
+    let home = tempfile::tempdir().unwrap();
+    let pid = std::process::id();
+    let list = home.path().join("agents.csv");
+    std::fs::write(
+        &list,
+        format!(
+            "task_id,agent_type,title,pid\n\
+             pane-1,claude_code,Fix the build,{pid}\n\
+             pane-2,aider,\"Review, then merge\",{pid}\n"
+        ),
+    )
+    .unwrap();
+
+    let args = ["import-processes", list.to_str().unwrap(), "--no-monitor"];
+    assert_eq!(
+        run(home.path(), &args).trim(),
+        "Imported 2 tasks (0 already existed)"
+    );
+    let task = show(home.path(), "pane-2");
+    assert_eq!(task["status"], "running");
+    assert_eq!(task["agent_type"], "aider");
+    assert_eq!(task["title"], "Review, then merge");
+    assert_eq!(task["pid"], pid);
+    assert_eq!(
+        run(home.path(), &args).trim(),
+        "Imported 2 tasks (2 already existed)"
+    );
+
+    // Nothing is recorded when a row is invalid
+    std::fs::write(
+        &list,
+        "[{\"task_id\": \"pane-3\", \"agent_type\": \"x\", \"title\": \"T\"}]",
+    )
+    .unwrap();
+    assert!(!agent_inbox(home.path(), &args).status.success());
+    assert!(!agent_inbox(home.path(), &["show", "pane-3"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_last_selector() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "t1");
+    start(home.path(), "t2");
+    assert_eq!(show(home.path(), "last")["task_id"], "t2");
+
+    run(home.path(), &["report", "failed", "t1"]);
+    assert_eq!(show(home.path(), "last-failed")["task_id"], "t1");
+    let output = agent_inbox(home.path(), &["show", "last-attention"]);
+    assert!(!output.status.success());
+
+    run(home.path(), &["clear", "last-failed"]);
+    assert_eq!(show(home.path(), "last")["task_id"], "t2");
+}
+
+#[test]
+fn test_list_number_selector() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "running");
+    start(home.path(), "done");
+    run(home.path(), &["report", "complete", "done"]);
+
+    // Numbers refer to the view last printed, with its filters
+    let out = run(home.path(), &["list", "--status", "completed"]);
+    assert!(out.contains(" 1."));
+    assert_eq!(show(home.path(), "1")["task_id"], "done");
+    assert!(!agent_inbox(home.path(), &["show", "2"]).status.success());
+
+    run(home.path(), &["list"]);
+    assert_eq!(show(home.path(), "1")["task_id"], "running");
+    run(
+        home.path(),
+        &["list", "--all", "--sort", "created", "--group-by", "none"],
+    );
+    assert_eq!(show(home.path(), "1")["task_id"], "done");
+
+    // Structured output prints no numbers and leaves them as they were
+    run(home.path(), &["list", "--format", "json"]);
+    assert_eq!(show(home.path(), "1")["task_id"], "done");
+}
+
+#[test]
+fn test_link() {
+    let home = tempfile::tempdir().unwrap();
+    start(home.path(), "web");
+    start(home.path(), "code");
+
+    let out = run(home.path(), &["link", "web", "code"]);
+    assert_eq!(out.trim(), "Linked web to code");
+    assert_eq!(
+        show(home.path(), "web")["metadata"]["related"],
+        serde_json::json!(["code"])
+    );
+    assert_eq!(
+        show(home.path(), "code")["metadata"]["related"],
+        serde_json::json!(["web"])
+    );
+
+    let detail = run(home.path(), &["show", "code"]);
+    assert!(detail.contains("Related:"), "{}", detail);
+    assert!(!agent_inbox(home.path(), &["link", "web", "web"])
+        .status
+        .success());
+}
+
+#[test]
+fn test_report_unknown_task_fails() {
+    let home = tempfile::tempdir().unwrap();
+
+    for args in [
+        &["report", "complete", "missing"][..],
+        &["report", "needs-attention", "missing", "reason"],
+        &["report", "failed", "missing"],
+    ] {
+        let output = agent_inbox(home.path(), args);
+        assert!(!output.status.success(), "{:?} should fail", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Task not found: missing"));
+    }
+}